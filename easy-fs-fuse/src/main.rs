@@ -1,9 +1,10 @@
-use clap::{App, Arg};
-use easy_fs::{BlockDevice, EasyFileSystem};
+use clap::{App, Arg, ArgMatches, SubCommand};
+use easy_fs::{set_clock, BlockDevice, EasyFileSystem, Inode};
 use std::fs::{read_dir, File, OpenOptions};
 use std::io::{Read, Seek, SeekFrom, Write};
 use std::sync::Arc;
 use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 const BLOCK_SZ: usize = 4096;
 
@@ -33,10 +34,11 @@ impl BlockDevice for BlockFile {
 }
 
 fn main() {
-    easy_fs_pack().expect("Error when packing easy-fs!");
-}
-
-fn easy_fs_pack() -> std::io::Result<()> {
+    set_clock(|| {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_secs() as u32)
+    });
     let matches = App::new("EasyFileSystem packer")
         .arg(
             Arg::with_name("source")
@@ -59,7 +61,95 @@ fn easy_fs_pack() -> std::io::Result<()> {
                 .takes_value(true)
                 .help("File system image volume in MiB"),
         )
+        .subcommand(
+            SubCommand::with_name("fsck")
+                .about("Check the consistency of a file system image")
+                .arg(
+                    Arg::with_name("image")
+                        .required(true)
+                        .help("File system image to check"),
+                )
+                .arg(
+                    Arg::with_name("repair")
+                        .short("r")
+                        .long("repair")
+                        .help("Repair the problems which can be repaired"),
+                ),
+        )
         .get_matches();
+    if let Some(matches) = matches.subcommand_matches("fsck") {
+        let clean = easy_fs_check(matches).expect("Error when checking easy-fs!");
+        std::process::exit(if clean { 0 } else { 1 });
+    }
+    easy_fs_pack(&matches).expect("Error when packing easy-fs!");
+}
+
+/// Check an image and print its problems.
+/// Return whether no problem is left in it.
+fn easy_fs_check(matches: &ArgMatches) -> std::io::Result<bool> {
+    let image = matches.value_of("image").unwrap();
+    let repair = matches.is_present("repair");
+    // opening replays the journal, so the image is written even without `repair`
+    let block_file = Arc::new(BlockFile(Mutex::new(
+        OpenOptions::new().read(true).write(true).open(image)?,
+    )));
+    let problems = EasyFileSystem::check_device(block_file, repair);
+    for problem in problems.iter() {
+        let repaired = repair && problem.is_repairable();
+        println!("{}{}", problem, if repaired { " (repaired)" } else { "" });
+    }
+    let left = problems
+        .iter()
+        .filter(|problem| !repair || !problem.is_repairable())
+        .count();
+    println!("{}: {} problems, {} left", image, problems.len(), left);
+    Ok(left == 0)
+}
+
+/// Type and permissions of `inode` as `ls -l` shows them, e.g. `-rwxr-xr-x`
+fn mode_string(inode: &Inode) -> String {
+    let file_type = if inode.is_dir() {
+        'd'
+    } else if inode.is_symlink() {
+        'l'
+    } else {
+        '-'
+    };
+    let mode = inode.mode();
+    let mut s = String::from(file_type);
+    for shift in [6, 3, 0] {
+        for (bit, c) in [(4, 'r'), (2, 'w'), (1, 'x')] {
+            s.push(if (mode >> shift) & bit != 0 { c } else { '-' });
+        }
+    }
+    s
+}
+
+/// Print the entries of directory `dir` with their mode, links, owner,
+/// size and modification time in seconds since the Unix epoch
+fn list(dir: &Inode) {
+    for name in dir.ls() {
+        let inode = dir.find(name.as_str()).unwrap();
+        let (uid, gid) = inode.owner();
+        let (_, mtime, _) = inode.times();
+        print!(
+            "{} {:>2} {:>4} {:>4} {:>8} {:>10} {}",
+            mode_string(&inode),
+            inode.nlink(),
+            uid,
+            gid,
+            inode.size(),
+            mtime,
+            name
+        );
+        if inode.is_symlink() {
+            print!(" -> {}", inode.read_link());
+        }
+        println!();
+    }
+}
+
+fn easy_fs_pack(matches: &ArgMatches) -> std::io::Result<()> {
     let src_path = matches.value_of("source").unwrap();
     let target_path = matches.value_of("target").unwrap();
     let volume = matches
@@ -109,27 +199,63 @@ fn easy_fs_pack() -> std::io::Result<()> {
         let inode = root_inode.create(app.as_str()).unwrap();
         // write data to easy-fs
         inode.write_at(0, all_data.as_slice());
+        inode.set_mode(0o755);
     }
+    // a stable name for the shell
+    root_inode.symlink("sh", "user_shell").unwrap();
     // list apps
-    for app in root_inode.ls() {
-        println!("{}", app);
-    }
+    list(&root_inode);
     Ok(())
 }
 
-#[test]
-fn efs_test() -> std::io::Result<()> {
-    let block_file = Arc::new(BlockFile(Mutex::new({
+/// An image file of a test, removed once the test is done with it
+#[cfg(test)]
+struct TestImage(&'static str);
+
+#[cfg(test)]
+impl TestImage {
+    /// Create an image of `blocks` zeroed blocks at `path`
+    fn create(path: &'static str, blocks: usize) -> std::io::Result<(Self, BlockFile)> {
         let f = OpenOptions::new()
             .read(true)
             .write(true)
             .create(true)
-            .open("target/fs.img")?;
-        f.set_len(8192 * BLOCK_SZ as u64).unwrap();
-        f
-    })));
-    EasyFileSystem::create(block_file.clone(), 4096, 1);
-    let efs = EasyFileSystem::open(block_file.clone());
+            .truncate(true)
+            .open(path)?;
+        let image = Self(path);
+        f.set_len((blocks * BLOCK_SZ) as u64)?;
+        Ok((image, BlockFile(Mutex::new(f))))
+    }
+
+    /// Open the image again, as a device of its own
+    fn open(&self) -> std::io::Result<Arc<BlockFile>> {
+        let f = OpenOptions::new().read(true).write(true).open(self.0)?;
+        Ok(Arc::new(BlockFile(Mutex::new(f))))
+    }
+}
+
+#[cfg(test)]
+impl Drop for TestImage {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(self.0);
+    }
+}
+
+/// A new file system of `blocks` blocks on an image at `path`, which is
+/// removed when the returned [`TestImage`] is dropped
+#[cfg(test)]
+fn test_fs(
+    path: &'static str,
+    blocks: u32,
+) -> std::io::Result<(TestImage, Arc<spin::Mutex<EasyFileSystem>>)> {
+    let (image, block_file) = TestImage::create(path, blocks as usize)?;
+    let efs = EasyFileSystem::create(Arc::new(block_file), blocks, 1);
+    Ok((image, efs))
+}
+
+#[test]
+fn efs_test() -> std::io::Result<()> {
+    let (_image, efs) = test_fs("target/fs.img", 4096)?;
     let root_inode = EasyFileSystem::root_inode(&efs);
     root_inode.create("filea");
     root_inode.create("fileb");
@@ -141,12 +267,12 @@ fn efs_test() -> std::io::Result<()> {
     filea.write_at(0, greet_str.as_bytes());
     //let mut buffer = [0u8; 512];
     let mut buffer = [0u8; 233];
-    let len = filea.read_at(0, &mut buffer);
+    let len = filea.read_at(0, &mut buffer).unwrap();
     assert_eq!(greet_str, core::str::from_utf8(&buffer[..len]).unwrap(),);
 
     let mut random_str_test = |len: usize| {
         filea.clear();
-        assert_eq!(filea.read_at(0, &mut buffer), 0,);
+        assert_eq!(filea.read_at(0, &mut buffer), Some(0));
         let mut str = String::new();
         use rand;
         // random digit
@@ -158,7 +284,7 @@ fn efs_test() -> std::io::Result<()> {
         let mut offset = 0usize;
         let mut read_str = String::new();
         loop {
-            let len = filea.read_at(offset, &mut read_buffer);
+            let len = filea.read_at(offset, &mut read_buffer).unwrap();
             if len == 0 {
                 break;
             }
@@ -180,6 +306,487 @@ fn efs_test() -> std::io::Result<()> {
     Ok(())
 }
 
+#[test]
+fn efs_dir_test() -> std::io::Result<()> {
+    let (_image, efs) = test_fs("target/fs_dir.img", 4096)?;
+    let root_inode = EasyFileSystem::root_inode(&efs);
+    // root is its own parent
+    assert_eq!(root_inode.ls(), vec![".", ".."]);
+    assert_eq!(root_inode.find("..").unwrap().inode_id(), 0);
+
+    let dira = root_inode.mkdir("dira").unwrap();
+    assert!(dira.is_dir());
+    assert!(root_inode.mkdir("dira").is_none());
+    assert!(root_inode.create("dira").is_none());
+    assert_eq!(dira.ls(), vec![".", ".."]);
+    assert_eq!(dira.find(".").unwrap().inode_id(), dira.inode_id());
+    assert_eq!(dira.find("..").unwrap().inode_id(), 0);
+
+    let dirb = dira.mkdir("dirb").unwrap();
+    let filec = dirb.create("filec").unwrap();
+    assert!(filec.is_file());
+    filec.write_at(0, b"nested");
+    let filec = root_inode
+        .find("dira")
+        .and_then(|dira| dira.find("dirb"))
+        .and_then(|dirb| dirb.find("filec"))
+        .unwrap();
+    let mut buffer = [0u8; 16];
+    let len = filec.read_at(0, &mut buffer).unwrap();
+    assert_eq!(&buffer[..len], b"nested");
+    assert_eq!(
+        dirb.find("..").unwrap().find("..").unwrap().inode_id(),
+        root_inode.inode_id()
+    );
+
+    // invalid names
+    assert!(dira.create("").is_none());
+    assert!(dira.create("a/b").is_none());
+    assert!(dira.create(&"x".repeat(28)).is_none());
+    Ok(())
+}
+
+#[test]
+fn efs_unlink_test() -> std::io::Result<()> {
+    let (_image, efs) = test_fs("target/fs_unlink.img", 2048)?;
+    let root_inode = EasyFileSystem::root_inode(&efs);
+
+    // each round takes more than half of the data area, so blocks must be reclaimed
+    let data = vec![0x5au8; 640 * BLOCK_SZ];
+    let mut inode_id = None;
+    for _ in 0..16 {
+        let file = root_inode.create("big").unwrap();
+        assert_eq!(*inode_id.get_or_insert(file.inode_id()), file.inode_id());
+        assert_eq!(file.write_at(0, &data), Some(data.len()));
+        assert!(root_inode.unlink("big"));
+        assert!(root_inode.find("big").is_none());
+    }
+    assert!(!root_inode.unlink("big"));
+    assert_eq!(root_inode.ls(), vec![".", ".."]);
+
+    // only empty directories can be removed, `.` and `..` never
+    let dira = root_inode.mkdir("dira").unwrap();
+    dira.create("filea").unwrap();
+    assert!(!root_inode.unlink("dira"));
+    assert!(!dira.unlink("."));
+    assert!(!dira.unlink(".."));
+    assert!(dira.unlink("filea"));
+    assert!(root_inode.unlink("dira"));
+
+    // freed entries are reused
+    root_inode.create("a").unwrap();
+    root_inode.create("b").unwrap();
+    assert!(root_inode.unlink("a"));
+    root_inode.create("c").unwrap();
+    assert_eq!(root_inode.ls(), vec![".", "..", "c", "b"]);
+    Ok(())
+}
+
+#[test]
+fn efs_link_rename_test() -> std::io::Result<()> {
+    let (_image, efs) = test_fs("target/fs_link.img", 2048)?;
+    let root_inode = EasyFileSystem::root_inode(&efs);
+    assert_eq!(root_inode.nlink(), 2);
+
+    // the data stays until the last link is gone
+    let filea = root_inode.create("filea").unwrap();
+    filea.write_at(0, b"hello");
+    assert_eq!(filea.nlink(), 1);
+    assert!(root_inode.link("fileb", &filea));
+    assert!(!root_inode.link("fileb", &filea));
+    assert_eq!(filea.nlink(), 2);
+    assert!(root_inode.unlink("filea"));
+    let fileb = root_inode.find("fileb").unwrap();
+    assert_eq!(fileb.inode_id(), filea.inode_id());
+    assert_eq!(fileb.nlink(), 1);
+    let mut buf = [0u8; 5];
+    assert_eq!(fileb.read_at(0, &mut buf), Some(5));
+    assert_eq!(&buf, b"hello");
+
+    // directories cannot be linked, and count the `..` of their subdirectories
+    let dira = root_inode.mkdir("dira").unwrap();
+    assert!(!root_inode.link("dirb", &dira));
+    assert_eq!(dira.nlink(), 2);
+    assert_eq!(root_inode.nlink(), 3);
+    let dirb = root_inode.mkdir("dirb").unwrap();
+    assert_eq!(root_inode.nlink(), 4);
+
+    // moving a directory moves its `..` too
+    assert!(root_inode.rename("dirb", &dira, "dirc"));
+    assert_eq!(root_inode.nlink(), 3);
+    assert_eq!(dira.nlink(), 3);
+    assert_eq!(dirb.find("..").unwrap().inode_id(), dira.inode_id());
+    assert!(root_inode.find("dirb").is_none());
+
+    // a file replaces a file, keeping the name of the target
+    dira.create("filec").unwrap();
+    assert!(root_inode.rename("fileb", &dira, "filec"));
+    assert_eq!(dira.find("filec").unwrap().inode_id(), fileb.inode_id());
+    assert_eq!(dira.ls(), vec![".", "..", "dirc", "filec"]);
+    let filec = root_inode.create("filec").unwrap();
+
+    // a directory only replaces an empty directory, and never a file
+    assert!(!dira.rename("dirc", &root_inode, "filec"));
+    assert!(!dira.rename("filec", &root_inode, "dira"));
+    root_inode.mkdir("empty").unwrap();
+    assert!(!root_inode.rename("empty", &root_inode, "dira"));
+    assert!(root_inode.rename("dira", &root_inode, "empty"));
+    assert_eq!(root_inode.nlink(), 3);
+    assert_eq!(root_inode.ls(), vec![".", "..", "filec", "empty"]);
+
+    // two names of the same file are left alone
+    assert!(root_inode.link("filed", &filec));
+    assert!(root_inode.rename("filec", &root_inode, "filed"));
+    assert_eq!(filec.nlink(), 2);
+    assert!(!root_inode.rename(".", &root_inode, "x"));
+    assert!(!root_inode.rename("missing", &root_inode, "x"));
+
+    // exchange swaps a directory and a file
+    assert!(root_inode.exchange("filed", &dira, "dirc"));
+    assert_eq!(
+        root_inode.find("filed").unwrap().inode_id(),
+        dirb.inode_id()
+    );
+    assert_eq!(dira.find("dirc").unwrap().inode_id(), filec.inode_id());
+    assert_eq!(dirb.find("..").unwrap().inode_id(), root_inode.inode_id());
+    assert_eq!(root_inode.nlink(), 4);
+    assert_eq!(dira.nlink(), 2);
+    Ok(())
+}
+
+#[test]
+fn efs_orphan_test() -> std::io::Result<()> {
+    let (_image, efs) = test_fs("target/fs_orphan.img", 2048)?;
+    let root_inode = EasyFileSystem::root_inode(&efs);
+
+    // an unlinked file stays usable, and its inode taken, while it is open
+    let filea = root_inode.create("filea").unwrap();
+    filea.write_at(0, b"hello");
+    assert!(root_inode.unlink("filea"));
+    let dira = root_inode.mkdir("dira").unwrap();
+    assert_ne!(dira.inode_id(), filea.inode_id());
+    assert_eq!(filea.write_at(5, b" world"), Some(6));
+    let mut buf = [0u8; 11];
+    assert_eq!(filea.read_at(0, &mut buf), Some(11));
+    assert_eq!(&buf, b"hello world");
+    drop(filea);
+    assert_eq!(EasyFileSystem::check(&efs, false), []);
+
+    // so does a file replaced by a rename
+    let fileb = root_inode.create("fileb").unwrap();
+    fileb.write_at(0, b"old");
+    root_inode.create("filec").unwrap();
+    assert!(root_inode.rename("filec", &root_inode, "fileb"));
+    let mut buf = [0u8; 3];
+    assert_eq!(fileb.read_at(0, &mut buf), Some(3));
+    assert_eq!(&buf, b"old");
+    drop(fileb);
+    assert_eq!(EasyFileSystem::check(&efs, false), []);
+
+    // a removed directory takes no new entries
+    let filed = root_inode.create("filed").unwrap();
+    assert!(root_inode.unlink("dira"));
+    assert!(dira.create("filee").is_none());
+    assert!(dira.mkdir("dire").is_none());
+    assert!(!dira.link("filed", &filed));
+    assert!(!root_inode.rename("filed", &dira, "filed"));
+    assert_eq!(dira.ls(), vec![".", ".."]);
+    drop(dira);
+    assert_eq!(EasyFileSystem::check(&efs, false), []);
+
+    // reading or writing a directory, or looking up in a file, fails
+    assert_eq!(root_inode.read_at(0, &mut buf), None);
+    assert_eq!(root_inode.write_at(0, b"x"), None);
+    assert!(filed.find("x").is_none());
+    Ok(())
+}
+
+#[test]
+fn efs_full_test() -> std::io::Result<()> {
+    use easy_fs::MAX_FILE_SIZE;
+    let (_image, efs) = test_fs("target/fs_full.img", 2048)?;
+    let root_inode = EasyFileSystem::root_inode(&efs);
+
+    // a write takes what is left and no more
+    let filea = root_inode.create("filea").unwrap();
+    let data = vec![7u8; 2048 * BLOCK_SZ];
+    let len = filea.write_at(0, &data).unwrap();
+    assert!(len > 0 && len < data.len());
+    assert_eq!(filea.size(), len);
+    assert_eq!(filea.write_at(len, b"x"), Some(0));
+    let mut buf = [0u8; BLOCK_SZ];
+    assert_eq!(filea.read_at(len - BLOCK_SZ, &mut buf), Some(BLOCK_SZ));
+    assert_eq!(buf, [7u8; BLOCK_SZ]);
+
+    // creating what needs a block fails without leaking the inode
+    assert!(root_inode.mkdir("dira").is_none());
+    assert!(root_inode.symlink("link", "filea").is_none());
+    assert_eq!(root_inode.ls(), vec![".", "..", "filea"]);
+    assert_eq!(EasyFileSystem::check(&efs, false), []);
+
+    // no file grows past the largest size
+    assert_eq!(filea.write_at(MAX_FILE_SIZE as usize, b"x"), Some(0));
+    assert_eq!(filea.write_at(usize::MAX, b"x"), Some(0));
+
+    // the space is back once the file is gone
+    drop(filea);
+    assert!(root_inode.unlink("filea"));
+    assert!(root_inode.mkdir("dira").is_some());
+    assert_eq!(EasyFileSystem::check(&efs, false), []);
+    Ok(())
+}
+
+#[test]
+fn efs_symlink_test() -> std::io::Result<()> {
+    let (_image, efs) = test_fs("target/fs_symlink.img", 2048)?;
+    let root_inode = EasyFileSystem::root_inode(&efs);
+
+    // the target is kept as is, whether it exists or not
+    let link = root_inode.symlink("sh", "user_shell").unwrap();
+    assert!(link.is_symlink() && !link.is_file() && !link.is_dir());
+    assert_eq!(link.read_link(), "user_shell");
+    assert_eq!(link.size(), "user_shell".len());
+    assert!(root_inode.symlink("sh", "other").is_none());
+    let long_target = "d/".repeat(BLOCK_SZ);
+    let long_link = root_inode.symlink("long", &long_target).unwrap();
+    assert_eq!(long_link.read_link(), long_target);
+
+    // removing a link frees it, but not its target
+    let file = root_inode.create("user_shell").unwrap();
+    assert!(root_inode.unlink("sh"));
+    assert!(root_inode.unlink("long"));
+    assert!(root_inode.find("user_shell").is_some());
+    assert_eq!(file.nlink(), 1);
+    let link = root_inode.symlink("sh", "/user_shell").unwrap();
+    assert_eq!(link.read_link(), "/user_shell");
+    Ok(())
+}
+
+#[test]
+fn efs_metadata_test() -> std::io::Result<()> {
+    use std::sync::atomic::{AtomicU32, Ordering};
+    static NOW: AtomicU32 = AtomicU32::new(1000);
+    let set_now = |now| NOW.store(now, Ordering::Relaxed);
+    set_clock(|| NOW.load(Ordering::Relaxed));
+    let (_image, efs) = test_fs("target/fs_metadata.img", 2048)?;
+    let root_inode = EasyFileSystem::root_inode(&efs);
+    assert_eq!(root_inode.times(), (1000, 1000, 1000));
+    assert_eq!(root_inode.mode(), 0o755);
+    assert_eq!(root_inode.owner(), (0, 0));
+
+    // creating a file changes its directory
+    set_now(2000);
+    let file = root_inode.create("file").unwrap();
+    assert_eq!(file.times(), (2000, 2000, 2000));
+    assert_eq!(file.mode(), 0o644);
+    assert_eq!(mode_string(&file), "-rw-r--r--");
+    assert_eq!(root_inode.times(), (1000, 2000, 2000));
+
+    // reads stamp the access time, writes the modification time
+    set_now(3000);
+    file.write_at(0, b"data");
+    set_now(4000);
+    let mut buf = [0u8; 4];
+    file.read_at(0, &mut buf);
+    assert_eq!(file.times(), (4000, 3000, 3000));
+
+    // a new link or mode only changes the status
+    set_now(5000);
+    assert!(root_inode.link("link", &file));
+    file.set_mode(0o4755);
+    assert_eq!(file.times(), (4000, 3000, 5000));
+    assert_eq!(mode_string(&file), "-rwxr-xr-x");
+    assert_eq!(file.mode(), 0o4755);
+    let dir = root_inode.mkdir("dir").unwrap();
+    assert_eq!(mode_string(&dir), "drwxr-xr-x");
+    let link = root_inode.symlink("sym", "file").unwrap();
+    assert_eq!(mode_string(&link), "lrwxrwxrwx");
+
+    // everything is still there after opening the image again
+    let block_device = Arc::clone(&efs.lock().block_device);
+    let efs = EasyFileSystem::open(block_device);
+    let file = EasyFileSystem::root_inode(&efs).find("file").unwrap();
+    assert_eq!(file.times(), (4000, 3000, 5000));
+    assert_eq!(file.nlink(), 2);
+    Ok(())
+}
+
+/// A device which stops writing at the commit of a transaction, as if the
+/// machine went down there
+#[cfg(test)]
+struct CrashingFile {
+    file: BlockFile,
+    armed: std::sync::atomic::AtomicBool,
+    crashed: std::sync::atomic::AtomicBool,
+    /// Whether the journal header still makes it to disk
+    keep_header: bool,
+}
+
+#[cfg(test)]
+impl BlockDevice for CrashingFile {
+    fn read_block(&self, block_id: usize, buf: &mut [u8]) {
+        self.file.read_block(block_id, buf);
+    }
+
+    fn write_block(&self, block_id: usize, buf: &[u8]) {
+        use std::sync::atomic::Ordering;
+        if self.crashed.load(Ordering::Relaxed) {
+            return;
+        }
+        // the journal header follows the super block
+        if self.armed.load(Ordering::Relaxed) && block_id == 1 {
+            self.crashed.store(true, Ordering::Relaxed);
+            if !self.keep_header {
+                return;
+            }
+        }
+        self.file.write_block(block_id, buf);
+    }
+}
+
+#[test]
+fn efs_journal_test() -> std::io::Result<()> {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    for keep_header in [true, false] {
+        let (image, file) = TestImage::create("target/fs_journal.img", 2048)?;
+        let crashing_file = Arc::new(CrashingFile {
+            file,
+            armed: AtomicBool::new(false),
+            crashed: AtomicBool::new(false),
+            keep_header,
+        });
+        let efs = EasyFileSystem::create(crashing_file.clone(), 2048, 1);
+        let root_inode = EasyFileSystem::root_inode(&efs);
+        root_inode.create("old").unwrap();
+        crashing_file.armed.store(true, Ordering::Relaxed);
+        root_inode.mkdir("dir").unwrap();
+        assert!(crashing_file.crashed.load(Ordering::Relaxed));
+
+        // the disk as the crash left it, behind caches of its own
+        let efs = EasyFileSystem::open(image.open()?);
+        let root_inode = EasyFileSystem::root_inode(&efs);
+        if keep_header {
+            // a committed transaction is replayed as a whole
+            assert_eq!(root_inode.ls(), [".", "..", "old", "dir"]);
+            assert_eq!(root_inode.nlink(), 3);
+            let dir = root_inode.find("dir").unwrap();
+            assert_eq!(dir.ls(), [".", ".."]);
+            assert!(dir.create("new").is_some());
+        } else {
+            // a transaction without its header is dropped as a whole
+            assert_eq!(root_inode.ls(), [".", "..", "old"]);
+            assert_eq!(root_inode.nlink(), 2);
+            assert!(root_inode.mkdir("dir").is_some());
+        }
+        root_inode.find("old").unwrap().write_at(0, b"still here");
+
+        // nothing is replayed twice
+        let efs = EasyFileSystem::open(image.open()?);
+        let root_inode = EasyFileSystem::root_inode(&efs);
+        assert_eq!(root_inode.ls(), [".", "..", "old", "dir"]);
+        let mut buf = [0u8; 10];
+        root_inode.find("old").unwrap().read_at(0, &mut buf);
+        assert_eq!(&buf, b"still here");
+    }
+    Ok(())
+}
+
+#[test]
+fn efs_fsck_test() -> std::io::Result<()> {
+    use easy_fs::{Problem, JOURNAL_BLOCKS};
+    let (image, efs) = test_fs("target/fs_fsck.img", 2048)?;
+    let root_inode = EasyFileSystem::root_inode(&efs);
+    let file = root_inode.create("a").unwrap();
+    file.write_at(0, &[7u8; 100 * BLOCK_SZ]);
+    let dir = root_inode.mkdir("d").unwrap();
+    dir.create("b").unwrap();
+    assert!(dir.link("c", &file));
+    assert_eq!(EasyFileSystem::check(&efs, false), []);
+
+    // a leaked block, an orphan inode and a used block marked free
+    let (leaked, orphan, root_block) = {
+        let mut fs = efs.lock();
+        let leaked = fs.alloc_data().unwrap();
+        let orphan = fs.alloc_inode().unwrap();
+        let root_block = fs.get_data_block_id(0);
+        fs.dealloc_data(root_block);
+        fs.commit();
+        (leaked, orphan, root_block)
+    };
+    // "a" of the root pointing to a free inode
+    let block_file = image.open()?;
+    let mut block = [0u8; BLOCK_SZ];
+    block_file.read_block(root_block as usize, &mut block);
+    block[2 * 32 + 28..3 * 32].copy_from_slice(&500u32.to_le_bytes());
+    block_file.write_block(root_block as usize, &block);
+
+    let efs = EasyFileSystem::open(image.open()?);
+    let problems = [
+        Problem::BadEntry { dir: 0, index: 2 },
+        Problem::WrongLinkCount {
+            inode: file.inode_id(),
+            recorded: 2,
+            found: 1,
+        },
+        Problem::OrphanInode { inode: orphan },
+        Problem::UnmarkedBlock { block: root_block },
+        Problem::LeakedBlock { block: leaked },
+    ];
+    assert_eq!(EasyFileSystem::check(&efs, false), problems);
+    assert_eq!(EasyFileSystem::check(&efs, true), problems);
+    assert_eq!(EasyFileSystem::check(&efs, false), []);
+
+    // the repairs are on disk
+    let efs = EasyFileSystem::open(image.open()?);
+    assert_eq!(EasyFileSystem::check(&efs, false), []);
+
+    // a size too large to count the blocks of is cut down, then to the blocks it has
+    let (block_id, offset) = efs.lock().get_disk_inode_pos(orphan);
+    let big = EasyFileSystem::root_inode(&efs).create("big").unwrap();
+    assert_eq!(big.inode_id(), orphan);
+    let block_file = image.open()?;
+    block_file.read_block(block_id as usize, &mut block);
+    block[offset..offset + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+    block_file.write_block(block_id as usize, &block);
+    let efs = EasyFileSystem::open(image.open()?);
+    let bad_size = [
+        Problem::BadSize {
+            inode: orphan,
+            size: u32::MAX,
+        },
+        Problem::BadBlock {
+            inode: orphan,
+            block: 0,
+        },
+    ];
+    assert_eq!(EasyFileSystem::check(&efs, false), bad_size);
+    assert_eq!(EasyFileSystem::check(&efs, true), bad_size);
+    assert_eq!(EasyFileSystem::check(&efs, false), []);
+    assert!(EasyFileSystem::root_inode(&efs).unlink("big"));
+
+    // a super block which cannot be trusted is not opened
+    let block_file = image.open()?;
+    block_file.read_block(0, &mut block);
+    block[8..12].copy_from_slice(&0u32.to_le_bytes());
+    block_file.write_block(0, &block);
+    assert_eq!(
+        EasyFileSystem::check_device(image.open()?, true),
+        [Problem::BadSuperBlock]
+    );
+    block[8..12].copy_from_slice(&JOURNAL_BLOCKS.to_le_bytes());
+    block_file.write_block(0, &block);
+
+    let efs = EasyFileSystem::open(image.open()?);
+    let root_inode = EasyFileSystem::root_inode(&efs);
+    assert_eq!(root_inode.ls(), [".", "..", "d"]);
+    let file = root_inode.find("d").unwrap().find("c").unwrap();
+    assert_eq!(file.nlink(), 1);
+    let mut buf = [0u8; BLOCK_SZ];
+    assert_eq!(file.read_at(99 * BLOCK_SZ, &mut buf), Some(BLOCK_SZ));
+    assert!(buf.iter().all(|&byte| byte == 7));
+    Ok(())
+}
+
 #[test]
 fn test_packed() -> std::io::Result<()> {
     const FS_IMG_PATH: &'static str = "../user/target/riscv64gc-unknown-none-elf/release/fs.img";
@@ -191,6 +798,6 @@ fn test_packed() -> std::io::Result<()> {
     })));
     let efs = EasyFileSystem::open(block_file);
     let root_inode = EasyFileSystem::root_inode(&efs);
-    println!("{:#?}", root_inode.ls());
+    list(&root_inode);
     Ok(())
 }
@@ -2,6 +2,7 @@ use crate::{BlockDevice, BLOCK_CACHE_NUM, BLOCK_SIZE};
 use alloc::boxed::Box;
 use alloc::collections::VecDeque;
 use alloc::sync::Arc;
+use alloc::vec::Vec;
 use lazy_static::lazy_static;
 use spin::Mutex;
 
@@ -10,6 +11,7 @@ pub struct BlockCache {
     block_id: usize,                    // which block does this cache come from
     block_device: Arc<dyn BlockDevice>, // reference to low level block device for reading and writing.
     modified: bool,                     // modified in memory ?
+    journaled: bool,                    // modified as metadata, waiting for the journal ?
 }
 
 impl BlockCache {
@@ -22,8 +24,17 @@ impl BlockCache {
             block_id,
             block_device,
             modified: false,
+            journaled: false,
         }
     }
+    /// Which block this cache holds
+    pub fn block_id(&self) -> usize {
+        self.block_id
+    }
+    /// Contents of the block in memory
+    pub fn data(&self) -> &[u8] {
+        &self.cache[..]
+    }
     // turn the offset of cache into memory address
     fn addr_of_offset(&self, offset: usize) -> usize {
         &self.cache[offset] as *const _ as usize
@@ -39,8 +50,18 @@ impl BlockCache {
         unsafe { &*(addr as *const T) }
     }
     /// Reinterpret data in cache memory from `offset` as `T` type object and return its mutable reference.
-    /// Using [`BlockCache::get_mut`] instead of [`BlockCache::get_ref`] implies modification.
+    /// Using [`BlockCache::get_mut`] instead of [`BlockCache::get_ref`] implies modification,
+    /// which is metadata kept in memory until the journal commits it.
     pub fn get_mut<T>(&mut self, offset: usize) -> &mut T
+    where
+        T: Sized,
+    {
+        self.journaled = true;
+        self.get_mut_unjournaled(offset)
+    }
+    /// [`BlockCache::get_mut`] for changes which bypass the journal and may be
+    /// written back at any time.
+    pub fn get_mut_unjournaled<T>(&mut self, offset: usize) -> &mut T
     where
         T: Sized,
     {
@@ -58,14 +79,29 @@ impl BlockCache {
     pub fn modify<T, V>(&mut self, offset: usize, f: impl FnOnce(&mut T) -> V) -> V {
         f(self.get_mut(offset))
     }
-    /// Sync cache with block device.
+    /// Bind closure `f` to a certain block cache and execute it, bypassing the journal.
+    /// Used for file contents and other changes which are fine to lose in a crash.
+    pub fn modify_unjournaled<T, V>(&mut self, offset: usize, f: impl FnOnce(&mut T) -> V) -> V {
+        f(self.get_mut_unjournaled(offset))
+    }
+    /// Whether there are metadata changes the journal has not committed yet
+    pub fn is_journaled(&self) -> bool {
+        self.journaled
+    }
+    /// Sync cache with block device, unless it holds metadata which must go
+    /// through the journal first.
     /// There should be a background process write cache back to block device every 30 seconds.
     pub fn sync(&mut self) {
-        if self.modified {
+        if self.modified && !self.journaled {
             self.modified = false;
             self.block_device.write_block(self.block_id, &*self.cache);
         }
     }
+    /// Write the block in place once the journal holds a copy of it.
+    pub fn checkpoint(&mut self) {
+        self.journaled = false;
+        self.sync();
+    }
 }
 
 /// RAII: write back to block device when dropping [`BlockCache`]
@@ -76,8 +112,8 @@ impl Drop for BlockCache {
 }
 
 pub struct BlockCacheManager {
-    // (cache-id, cache-ref)
-    queue: VecDeque<(usize, Arc<Mutex<BlockCache>>)>,
+    // (cache-id, device address, cache-ref)
+    queue: VecDeque<(usize, usize, Arc<Mutex<BlockCache>>)>,
 }
 
 impl BlockCacheManager {
@@ -91,18 +127,21 @@ impl BlockCacheManager {
         block_id: usize,
         block_device: Arc<dyn BlockDevice>,
     ) -> Arc<Mutex<BlockCache>> {
-        if let Some(pair) = self.queue.iter().find(|pair| pair.0 == block_id) {
-            Arc::clone(&pair.1)
+        // caches of different devices must not be mixed up
+        let device = Arc::as_ptr(&block_device) as *const () as usize;
+        if let Some(pair) = self
+            .queue
+            .iter()
+            .find(|pair| pair.0 == block_id && pair.1 == device)
+        {
+            Arc::clone(&pair.2)
         } else {
             // substitute, FIFO
             if self.queue.len() == BLOCK_CACHE_NUM {
-                // from front to tail
-                if let Some((idx, _)) = self
-                    .queue
-                    .iter()
-                    .enumerate()
-                    .find(|(_, pair)| Arc::strong_count(&pair.1) == 1)
-                {
+                // from front to tail, metadata stays until the journal commits it
+                if let Some((idx, _)) = self.queue.iter().enumerate().find(|(_, pair)| {
+                    Arc::strong_count(&pair.2) == 1 && !pair.2.lock().is_journaled()
+                }) {
                     self.queue.drain(idx..=idx); // evacuate selected cache
                 } else {
                     panic!("Run out of BlockCache!");
@@ -113,7 +152,8 @@ impl BlockCacheManager {
                 block_id,
                 Arc::clone(&block_device),
             )));
-            self.queue.push_back((block_id, Arc::clone(&block_cache)));
+            self.queue
+                .push_back((block_id, device, Arc::clone(&block_cache)));
             block_cache
         }
     }
@@ -133,10 +173,16 @@ pub fn get_block_cache(
         .get_block_cache(block_id, block_device)
 }
 
-/// Sync all block cache to block device
-pub fn block_cache_sync_all() {
-    let manager = BLOCK_CACHE_MANAGER.lock();
-    for (_, cache) in manager.queue.iter() {
-        cache.lock().sync();
-    }
+/// Caches of `block_device` with changes not on the device yet, sorted by block id
+pub fn dirty_block_caches(block_device: &Arc<dyn BlockDevice>) -> Vec<Arc<Mutex<BlockCache>>> {
+    let device = Arc::as_ptr(block_device) as *const () as usize;
+    let mut caches: Vec<_> = BLOCK_CACHE_MANAGER
+        .lock()
+        .queue
+        .iter()
+        .filter(|pair| pair.1 == device && pair.2.lock().modified)
+        .map(|pair| (pair.0, Arc::clone(&pair.2)))
+        .collect();
+    caches.sort_by_key(|pair| pair.0);
+    caches.into_iter().map(|pair| pair.1).collect()
 }
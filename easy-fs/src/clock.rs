@@ -0,0 +1,16 @@
+use spin::Mutex;
+
+/// Source of the current time in seconds since the Unix epoch,
+/// stamped on inodes when they are created, read or written
+static CLOCK: Mutex<fn() -> u32> = Mutex::new(|| 0);
+
+/// Set the clock for inode timestamps, which all read 0 until it is set
+pub fn set_clock(clock: fn() -> u32) {
+    *CLOCK.lock() = clock;
+}
+
+/// Current time in seconds since the Unix epoch
+pub(crate) fn now() -> u32 {
+    let clock = *CLOCK.lock();
+    clock()
+}
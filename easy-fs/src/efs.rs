@@ -1,9 +1,10 @@
+use alloc::collections::BTreeMap;
 use alloc::sync::Arc;
 use spin::Mutex;
 
 use crate::{
-    block_cache_sync_all, get_block_cache, Bitmap, BlockDevice, DataBlock, DiskInode,
-    DiskInodeType, Inode, SuperBlock, BLOCK_SIZE,
+    get_block_cache, now, Bitmap, BlockDevice, DataBlock, DiskInode, DiskInodeType, Inode, Journal,
+    SuperBlock, BLOCK_SIZE, JOURNAL_BLOCKS,
 };
 
 /// How many blocks could a block in bitmap maps
@@ -17,8 +18,12 @@ pub struct EasyFileSystem {
     pub inode_bitmap: Bitmap,
     /// Data blocks bitmap
     pub data_bitmap: Bitmap,
+    journal: Journal,
+    /// Number of [`Inode`]s in memory for each inode number
+    open_inodes: BTreeMap<u32, usize>,
     inode_area_start_block: u32,
     data_area_start_block: u32,
+    data_area_blocks: u32,
 }
 
 impl EasyFileSystem {
@@ -29,32 +34,36 @@ impl EasyFileSystem {
         inode_bitmap_blocks: u32,
     ) -> Arc<Mutex<Self>> {
         // calculate block size of areas & create bitmaps
-        let inode_bitmap = Bitmap::new(1, inode_bitmap_blocks as usize);
+        let journal_blocks = JOURNAL_BLOCKS;
+        let inode_bitmap = Bitmap::new(1 + journal_blocks as usize, inode_bitmap_blocks as usize);
         // how many inode structs should this fs contains
         let inode_num = inode_bitmap.maximum();
         let inode_area_blocks =
             ((inode_num * core::mem::size_of::<DiskInode>() + BLOCK_SIZE - 1) / BLOCK_SIZE) as u32;
         let inode_total_blocks = inode_bitmap_blocks + inode_area_blocks;
-        let data_total_blocks = total_blocks - 1 - inode_total_blocks;
+        let data_total_blocks = total_blocks - 1 - journal_blocks - inode_total_blocks;
         let data_bitmap_blocks = (data_total_blocks + PER_BITMAP_BLOCK_MAPS_BLOCK_NUM)
             / (PER_BITMAP_BLOCK_MAPS_BLOCK_NUM + 1);
         let data_area_blocks = data_total_blocks - data_bitmap_blocks;
         let data_bitmap = Bitmap::new(
-            (1 + inode_bitmap_blocks + inode_area_blocks) as usize,
+            (1 + journal_blocks + inode_total_blocks) as usize,
             data_bitmap_blocks as usize,
         );
         let mut efs = Self {
             block_device: Arc::clone(&block_device),
             inode_bitmap,
             data_bitmap,
-            inode_area_start_block: 1 + inode_bitmap_blocks,
-            data_area_start_block: 1 + inode_total_blocks + data_bitmap_blocks,
+            journal: Journal::new(1, journal_blocks),
+            open_inodes: BTreeMap::new(),
+            inode_area_start_block: 1 + journal_blocks + inode_bitmap_blocks,
+            data_area_start_block: 1 + journal_blocks + inode_total_blocks + data_bitmap_blocks,
+            data_area_blocks,
         };
         // clear all blocks
         for i in 0..total_blocks {
             get_block_cache(i as usize, Arc::clone(&block_device))
                 .lock()
-                .modify(0, |data_block: &mut DataBlock| {
+                .modify_unjournaled(0, |data_block: &mut DataBlock| {
                     for byte in data_block.iter_mut() {
                         *byte = 0;
                     }
@@ -66,6 +75,7 @@ impl EasyFileSystem {
             |super_block: &mut SuperBlock| {
                 super_block.initialize(
                     total_blocks,
+                    journal_blocks,
                     inode_bitmap_blocks,
                     inode_area_blocks,
                     data_bitmap_blocks,
@@ -73,49 +83,104 @@ impl EasyFileSystem {
                 );
             },
         );
-        // write back immediately
         // create a inode for root node "/"
-        assert_eq!(efs.alloc_inode(), 0);
+        assert_eq!(efs.alloc_inode(), Some(0));
         let (root_inode_block_id, root_inode_offset) = efs.get_disk_inode_pos(0);
         get_block_cache(root_inode_block_id as usize, Arc::clone(&block_device))
             .lock()
             .modify(root_inode_offset, |disk_inode: &mut DiskInode| {
-                disk_inode.initialize(DiskInodeType::Directory);
+                disk_inode.initialize(DiskInodeType::Directory, now());
             });
-        block_cache_sync_all();
-        Arc::new(Mutex::new(efs))
+        let efs = Arc::new(Mutex::new(efs));
+        // "." and ".." of root both refer to root itself
+        let root_inode = Self::root_inode(&efs);
+        {
+            let mut fs = efs.lock();
+            get_block_cache(root_inode_block_id as usize, Arc::clone(&block_device))
+                .lock()
+                .modify(root_inode_offset, |disk_inode: &mut DiskInode| {
+                    assert!(root_inode.write_dot_entries(disk_inode, 0, 0, &mut fs));
+                });
+        }
+        efs.lock().commit();
+        efs
     }
-    /// Open an [`EasyFileSystem`] on `block_device`
+    /// Open an [`EasyFileSystem`] on `block_device`, replaying the metadata
+    /// changes a crash kept from being written in place
     pub fn open(block_device: Arc<dyn BlockDevice>) -> Arc<Mutex<Self>> {
         // read SuperBlock
-        // kprintln!("called EasyFileSystem::open");
-        get_block_cache(0, Arc::clone(&block_device))
-            .lock()
-            .read(0, |super_block: &SuperBlock| {
-                // kprintln!("inside read");
+        let efs = get_block_cache(0, Arc::clone(&block_device)).lock().read(
+            0,
+            |super_block: &SuperBlock| {
                 assert!(super_block.is_valid(), "Error loading EFS!");
+                let journal_total_blocks = 1 + super_block.journal_blocks;
                 let inode_total_blocks =
                     super_block.inode_bitmap_blocks + super_block.inode_area_blocks;
-                let efs = Self {
-                    block_device,
-                    inode_bitmap: Bitmap::new(1, super_block.inode_bitmap_blocks as usize),
+                Self {
+                    block_device: Arc::clone(&block_device),
+                    inode_bitmap: Bitmap::new(
+                        journal_total_blocks as usize,
+                        super_block.inode_bitmap_blocks as usize,
+                    ),
                     data_bitmap: Bitmap::new(
-                        (1 + inode_total_blocks) as usize,
+                        (journal_total_blocks + inode_total_blocks) as usize,
                         super_block.data_bitmap_blocks as usize,
                     ),
-                    inode_area_start_block: 1 + super_block.inode_bitmap_blocks,
-                    data_area_start_block: 1 + inode_total_blocks + super_block.data_bitmap_blocks,
-                };
-                Arc::new(Mutex::new(efs))
-            })
+                    journal: Journal::new(1, super_block.journal_blocks),
+                    open_inodes: BTreeMap::new(),
+                    inode_area_start_block: journal_total_blocks + super_block.inode_bitmap_blocks,
+                    data_area_start_block: journal_total_blocks
+                        + inode_total_blocks
+                        + super_block.data_bitmap_blocks,
+                    data_area_blocks: super_block.data_area_blocks,
+                }
+            },
+        );
+        efs.journal.replay(&block_device);
+        Arc::new(Mutex::new(efs))
+    }
+    /// Write back the changes made since the last commit, metadata as one
+    /// transaction through the journal. Every operation changing the file
+    /// system commits before releasing the lock on it, so the transaction
+    /// never holds half of an operation.
+    pub fn commit(&self) {
+        self.journal.commit(&self.block_device);
     }
     /// Get the root inode of the filesystem
     pub fn root_inode(efs: &Arc<Mutex<Self>>) -> Inode {
-        let block_device = Arc::clone(&efs.lock().block_device);
         // acquire efs lock temporarily
-        let (block_id, block_offset) = efs.lock().get_disk_inode_pos(0);
-        // release efs lock
-        Inode::new(block_id, block_offset, Arc::clone(efs), block_device)
+        Inode::new(0, &mut efs.lock(), Arc::clone(efs))
+    }
+    /// Count one more [`Inode`] in memory for `inode_id`
+    pub(crate) fn open_inode(&mut self, inode_id: u32) {
+        *self.open_inodes.entry(inode_id).or_insert(0) += 1;
+    }
+    /// Count one [`Inode`] in memory for `inode_id` less, return whether it was the last one
+    pub(crate) fn close_inode(&mut self, inode_id: u32) -> bool {
+        let count = self.open_inodes.get_mut(&inode_id).unwrap();
+        *count -= 1;
+        if *count > 0 {
+            return false;
+        }
+        self.open_inodes.remove(&inode_id);
+        true
+    }
+    /// Whether there is an [`Inode`] in memory for `inode_id`
+    pub(crate) fn is_open(&self, inode_id: u32) -> bool {
+        self.open_inodes.contains_key(&inode_id)
+    }
+    /// Free the data blocks of inode `inode_id` and the inode itself
+    pub(crate) fn free_inode(&mut self, inode_id: u32) {
+        let (block_id, block_offset) = self.get_disk_inode_pos(inode_id);
+        let data_blocks = get_block_cache(block_id as usize, Arc::clone(&self.block_device))
+            .lock()
+            .modify(block_offset, |disk_inode: &mut DiskInode| {
+                disk_inode.clear_size(&self.block_device)
+            });
+        for data_block in data_blocks {
+            self.dealloc_data(data_block);
+        }
+        self.dealloc_inode(inode_id);
     }
     /// Get inode by id.
     /// Return: (block id, offset of inode in the block)
@@ -130,27 +195,34 @@ impl EasyFileSystem {
     pub fn get_data_block_id(&self, data_block_id: u32) -> u32 {
         self.data_area_start_block + data_block_id
     }
-    /// Allocate a new inode
-    pub fn alloc_inode(&mut self) -> u32 {
-        self.inode_bitmap.alloc(&self.block_device).unwrap() as u32
+    /// Allocate a new inode, `None` if all are in use
+    pub fn alloc_inode(&mut self) -> Option<u32> {
+        self.inode_bitmap
+            .alloc(&self.block_device)
+            .map(|inode_id| inode_id as u32)
     }
     /// Deallocate an inode
-    pub fn dealloc_inode(&mut self, _inode_id: u32) {
-        todo!()
+    pub fn dealloc_inode(&mut self, inode_id: u32) {
+        self.inode_bitmap
+            .dealloc(&self.block_device, inode_id as usize)
     }
-    /// Allocate a data block
-    pub fn alloc_data(&mut self) -> u32 {
-        self.data_bitmap.alloc(&self.block_device).unwrap() as u32 + self.data_area_start_block
+    /// Allocate a data block, cleared to zero before any metadata refers to it.
+    /// Return `None` if the data area is full.
+    pub fn alloc_data(&mut self) -> Option<u32> {
+        let bit = self.data_bitmap.alloc(&self.block_device)?;
+        // the last bitmap block has bits beyond the end of the data area
+        if bit >= self.data_area_blocks as usize {
+            self.data_bitmap.dealloc(&self.block_device, bit);
+            return None;
+        }
+        let block_id = bit as u32 + self.data_area_start_block;
+        get_block_cache(block_id as usize, Arc::clone(&self.block_device))
+            .lock()
+            .modify_unjournaled(0, |data_block: &mut DataBlock| data_block.fill(0));
+        Some(block_id)
     }
     /// Deallocate a data block
     pub fn dealloc_data(&mut self, block_id: u32) {
-        get_block_cache(block_id as usize, Arc::clone(&self.block_device))
-            .lock()
-            .modify(0, |data_block: &mut DataBlock| {
-                data_block.iter_mut().for_each(|p| {
-                    *p = 0;
-                })
-            });
         self.data_bitmap.dealloc(
             &self.block_device,
             (block_id - self.data_area_start_block) as usize,
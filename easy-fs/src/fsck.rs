@@ -0,0 +1,429 @@
+//! Consistency check of a file system.
+//!
+//! Every inode reachable from the root is visited once, its blocks are
+//! claimed in a map of the data area and the directory entries leading to it
+//! are counted. The bitmaps and link counts are then compared to what the walk
+//! found.
+
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt::{self, Display, Formatter};
+use spin::Mutex;
+
+use crate::{
+    get_block_cache, BlockDevice, DirEntry, DiskInode, EasyFileSystem, SuperBlock, BLOCK_SIZE,
+    DIRENT_SIZE, MAX_FILE_SIZE,
+};
+
+/// An inconsistency found by [`EasyFileSystem::check`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Problem {
+    /// The super block is not one of easy-fs, or its areas do not add up to the file system
+    BadSuperBlock,
+    /// The root is not an allocated directory
+    BadRoot,
+    /// `inode` is larger than the data area, or a directory not made of whole entries
+    BadSize {
+        /// The inode
+        inode: u32,
+        /// Its size in bytes
+        size: u32,
+    },
+    /// `inode` refers to `block` outside of the data area
+    BadBlock {
+        /// The inode
+        inode: u32,
+        /// The block it refers to
+        block: u32,
+    },
+    /// `block` is used by two inodes, or twice by one
+    DoubleAllocated {
+        /// The block
+        block: u32,
+        /// The inode which claimed it first and the one which claimed it again
+        inodes: (u32, u32),
+    },
+    /// Entry `index` of directory `dir` has a bad name, refers to a free or broken
+    /// inode, links a directory a second time, or is a `.` or `..` pointing elsewhere
+    BadEntry {
+        /// The directory
+        dir: u32,
+        /// Index of the entry in it
+        index: u32,
+    },
+    /// The link count of `inode` is not the number of entries referring to it
+    WrongLinkCount {
+        /// The inode
+        inode: u32,
+        /// Its link count
+        recorded: u32,
+        /// Number of entries referring to it
+        found: u32,
+    },
+    /// `inode` is allocated, but no entry leads to it
+    OrphanInode {
+        /// The inode
+        inode: u32,
+    },
+    /// `block` is allocated, but no inode uses it
+    LeakedBlock {
+        /// The block
+        block: u32,
+    },
+    /// `block` is used, but free in the data bitmap
+    UnmarkedBlock {
+        /// The block
+        block: u32,
+    },
+}
+
+impl Problem {
+    /// Whether [`EasyFileSystem::check`] can repair it.
+    /// Bad sizes and blocks are repaired by truncating the inode, bad entries by
+    /// removing them and orphan inodes by freeing them.
+    pub fn is_repairable(&self) -> bool {
+        !matches!(
+            self,
+            Problem::BadSuperBlock | Problem::BadRoot | Problem::DoubleAllocated { .. }
+        )
+    }
+}
+
+impl Display for Problem {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match *self {
+            Problem::BadSuperBlock => write!(f, "super block: not easy-fs or areas do not add up"),
+            Problem::BadRoot => write!(f, "root: not an allocated directory"),
+            Problem::BadSize { inode, size } => write!(f, "inode {}: bad size {}", inode, size),
+            Problem::BadBlock { inode, block } => {
+                write!(
+                    f,
+                    "inode {}: block {} outside of the data area",
+                    inode, block
+                )
+            }
+            Problem::DoubleAllocated { block, inodes } => write!(
+                f,
+                "block {}: used by inode {} and inode {}",
+                block, inodes.0, inodes.1
+            ),
+            Problem::BadEntry { dir, index } => write!(f, "inode {}: bad entry {}", dir, index),
+            Problem::WrongLinkCount {
+                inode,
+                recorded,
+                found,
+            } => write!(
+                f,
+                "inode {}: link count {}, {} entries refer to it",
+                inode, recorded, found
+            ),
+            Problem::OrphanInode { inode } => {
+                write!(f, "inode {}: allocated but unreachable", inode)
+            }
+            Problem::LeakedBlock { block } => write!(f, "block {}: allocated but unused", block),
+            Problem::UnmarkedBlock { block } => write!(f, "block {}: used but free", block),
+        }
+    }
+}
+
+impl EasyFileSystem {
+    /// Check the file system on `block_device` like [`EasyFileSystem::check`],
+    /// making sure its super block can be trusted before opening it
+    pub fn check_device(block_device: Arc<dyn BlockDevice>, repair: bool) -> Vec<Problem> {
+        let consistent = get_block_cache(0, Arc::clone(&block_device))
+            .lock()
+            .read(0, |super_block: &SuperBlock| super_block.is_consistent());
+        if !consistent {
+            return vec![Problem::BadSuperBlock];
+        }
+        Self::check(&Self::open(block_device), repair)
+    }
+    /// Check the consistency of the file system and return the problems found.
+    /// With `repair`, every problem [`Problem::is_repairable`] is repaired as it is found.
+    pub fn check(efs: &Arc<Mutex<Self>>, repair: bool) -> Vec<Problem> {
+        let fs = efs.lock();
+        let mut checker = match Checker::new(&fs, repair) {
+            Some(checker) => checker,
+            None => return vec![Problem::BadSuperBlock],
+        };
+        checker.check();
+        checker.problems
+    }
+}
+
+/// State of one check
+struct Checker<'a> {
+    fs: &'a EasyFileSystem,
+    repair: bool,
+    inode_count: u32,
+    data_area_blocks: u32,
+    /// Inode owning each block of the data area
+    owners: Vec<Option<u32>>,
+    /// Number of entries referring to each inode
+    links: Vec<u32>,
+    visited: Vec<bool>,
+    problems: Vec<Problem>,
+}
+
+impl<'a> Checker<'a> {
+    fn new(fs: &'a EasyFileSystem, repair: bool) -> Option<Self> {
+        let super_block = get_block_cache(0, Arc::clone(&fs.block_device))
+            .lock()
+            .read(0, |super_block: &SuperBlock| {
+                super_block
+                    .is_consistent()
+                    .then_some((super_block.inode_area_blocks, super_block.data_area_blocks))
+            });
+        let (inode_area, data_area) = super_block?;
+        let inodes_per_block = (BLOCK_SIZE / core::mem::size_of::<DiskInode>()) as u32;
+        let inode_count = (inode_area * inodes_per_block).min(fs.inode_bitmap.maximum() as u32);
+        Some(Self {
+            fs,
+            repair,
+            inode_count,
+            data_area_blocks: data_area,
+            owners: vec![None; data_area as usize],
+            links: vec![0; inode_count as usize],
+            visited: vec![false; inode_count as usize],
+            problems: Vec::new(),
+        })
+    }
+    fn check(&mut self) {
+        if !self.fs.inode_bitmap.is_allocated(&self.fs.block_device, 0)
+            || !self.read_inode(0, |disk_inode| {
+                disk_inode.has_valid_type() && disk_inode.is_dir()
+            })
+        {
+            self.problems.push(Problem::BadRoot);
+            return;
+        }
+        // depth first from the root, which is its own parent
+        self.visited[0] = true;
+        let mut stack = vec![(0, 0)];
+        while let Some((inode_id, parent_id)) = stack.pop() {
+            let data_blocks = self.check_blocks(inode_id);
+            if self.read_inode(inode_id, |disk_inode| disk_inode.is_dir()) {
+                self.check_entries(inode_id, parent_id, data_blocks, &mut stack);
+            }
+        }
+        self.check_inodes();
+        self.check_data_bitmap();
+    }
+    /// Claim the blocks of `inode_id`, truncating it where they go wrong.
+    /// Return the number of its data blocks which can be read.
+    fn check_blocks(&mut self, inode_id: u32) -> u32 {
+        let (size, is_dir) = self.read_inode(inode_id, |disk_inode| {
+            (disk_inode.size, disk_inode.is_dir())
+        });
+        // checked before counting its blocks, which could overflow
+        let max_size = (self.data_area_blocks as u64 * BLOCK_SIZE as u64).min(MAX_FILE_SIZE as u64);
+        let mut size = size as u64;
+        if size > max_size || (is_dir && size as usize % DIRENT_SIZE != 0) {
+            self.problems.push(Problem::BadSize {
+                inode: inode_id,
+                size: size as u32,
+            });
+            size = size.min(max_size);
+            self.truncate(inode_id, size as u32);
+        }
+        let data_blocks = ((size + BLOCK_SIZE as u64 - 1) / BLOCK_SIZE as u64) as u32;
+        let data_area =
+            self.fs.get_data_block_id(0)..self.fs.get_data_block_id(self.data_area_blocks);
+        let (blocks, rejected) = self.read_inode(inode_id, |disk_inode| {
+            disk_inode.walk_blocks(data_blocks, &self.fs.block_device, |block| {
+                data_area.contains(&block)
+            })
+        });
+        for block in blocks {
+            let owner = &mut self.owners[(block - data_area.start) as usize];
+            match *owner {
+                Some(first) => self.problems.push(Problem::DoubleAllocated {
+                    block,
+                    inodes: (first, inode_id),
+                }),
+                None => *owner = Some(inode_id),
+            }
+        }
+        match rejected {
+            Some((block, data_blocks)) => {
+                self.problems.push(Problem::BadBlock {
+                    inode: inode_id,
+                    block,
+                });
+                self.truncate(inode_id, data_blocks * BLOCK_SIZE as u32);
+                data_blocks
+            }
+            None => data_blocks,
+        }
+    }
+    /// Check the entries in the first `data_blocks` blocks of directory `dir_id`,
+    /// and push the directories and files they lead to for the first time
+    fn check_entries(
+        &mut self,
+        dir_id: u32,
+        parent_id: u32,
+        data_blocks: u32,
+        stack: &mut Vec<(u32, u32)>,
+    ) {
+        let size = self.read_inode(dir_id, |disk_inode| disk_inode.size);
+        let file_count = (size as usize).min(data_blocks as usize * BLOCK_SIZE) / DIRENT_SIZE;
+        let mut dirent = DirEntry::empty();
+        for index in 0..file_count {
+            self.read_inode(dir_id, |disk_inode| {
+                disk_inode.read_at(
+                    index * DIRENT_SIZE,
+                    dirent.as_bytes_mut(),
+                    &self.fs.block_device,
+                )
+            });
+            if dirent.is_free() {
+                continue;
+            }
+            let name = if dirent.has_valid_name() {
+                dirent.name()
+            } else {
+                ""
+            };
+            let inode_id = dirent.inode_number();
+            // dots point to this directory and its parent, other entries to live
+            // inodes, and only files can be reached twice
+            let expected = match name {
+                "" => None,
+                "." => Some(dir_id),
+                ".." => Some(parent_id),
+                _ if self.is_live_inode(inode_id)
+                    && !(self.visited[inode_id as usize]
+                        && self.read_inode(inode_id, |disk_inode| disk_inode.is_dir())) =>
+                {
+                    Some(inode_id)
+                }
+                _ => None,
+            };
+            if expected != Some(inode_id) {
+                self.problems.push(Problem::BadEntry {
+                    dir: dir_id,
+                    index: index as u32,
+                });
+                if !self.repair {
+                    continue;
+                }
+                // dots are pointed back, other entries removed
+                let dirent = match expected {
+                    Some(expected) => DirEntry::new(name, expected),
+                    None => DirEntry::empty(),
+                };
+                self.modify_inode(dir_id, |disk_inode| {
+                    disk_inode.write_at(
+                        index * DIRENT_SIZE,
+                        dirent.as_bytes(),
+                        &self.fs.block_device,
+                    );
+                });
+                if expected.is_none() {
+                    continue;
+                }
+            }
+            let inode_id = expected.unwrap();
+            self.links[inode_id as usize] += 1;
+            if !self.visited[inode_id as usize] {
+                self.visited[inode_id as usize] = true;
+                stack.push((inode_id, dir_id));
+            }
+        }
+    }
+    /// Compare the link counts and the inode bitmap to the walk, freeing
+    /// orphan inodes, whose blocks then turn up as leaked
+    fn check_inodes(&mut self) {
+        for inode_id in 0..self.inode_count {
+            if self.visited[inode_id as usize] {
+                let recorded = self.read_inode(inode_id, |disk_inode| disk_inode.nlink);
+                let found = self.links[inode_id as usize];
+                if recorded != found {
+                    self.problems.push(Problem::WrongLinkCount {
+                        inode: inode_id,
+                        recorded,
+                        found,
+                    });
+                    self.modify_inode(inode_id, |disk_inode| disk_inode.nlink = found);
+                }
+            } else if self
+                .fs
+                .inode_bitmap
+                .is_allocated(&self.fs.block_device, inode_id as usize)
+            {
+                self.problems.push(Problem::OrphanInode { inode: inode_id });
+                if self.repair {
+                    self.fs
+                        .inode_bitmap
+                        .dealloc(&self.fs.block_device, inode_id as usize);
+                    self.fs.commit();
+                }
+            }
+        }
+    }
+    /// Compare the data bitmap to the blocks claimed by the walk
+    fn check_data_bitmap(&mut self) {
+        for bit in 0..self.data_area_blocks {
+            let block = self.fs.get_data_block_id(bit);
+            let used = self.owners[bit as usize].is_some();
+            let allocated = self
+                .fs
+                .data_bitmap
+                .is_allocated(&self.fs.block_device, bit as usize);
+            if used && !allocated {
+                self.problems.push(Problem::UnmarkedBlock { block });
+                if self.repair {
+                    self.fs
+                        .data_bitmap
+                        .mark(&self.fs.block_device, bit as usize);
+                    self.fs.commit();
+                }
+            } else if !used && allocated {
+                self.problems.push(Problem::LeakedBlock { block });
+                if self.repair {
+                    self.fs
+                        .data_bitmap
+                        .dealloc(&self.fs.block_device, bit as usize);
+                    self.fs.commit();
+                }
+            }
+        }
+    }
+    /// Whether `inode_id` is allocated and of a known type
+    fn is_live_inode(&self, inode_id: u32) -> bool {
+        inode_id < self.inode_count
+            && self
+                .fs
+                .inode_bitmap
+                .is_allocated(&self.fs.block_device, inode_id as usize)
+            && self.read_inode(inode_id, |disk_inode| disk_inode.has_valid_type())
+    }
+    /// Cut `inode_id` down to `size`, leaving the blocks past it to be found leaked
+    fn truncate(&self, inode_id: u32, size: u32) {
+        self.modify_inode(inode_id, |disk_inode| {
+            disk_inode.size = if disk_inode.is_dir() {
+                size - size % DIRENT_SIZE as u32
+            } else {
+                size
+            };
+        });
+    }
+    fn read_inode<V>(&self, inode_id: u32, f: impl FnOnce(&DiskInode) -> V) -> V {
+        let (block_id, block_offset) = self.fs.get_disk_inode_pos(inode_id);
+        get_block_cache(block_id as usize, Arc::clone(&self.fs.block_device))
+            .lock()
+            .read(block_offset, f)
+    }
+    /// Apply a repair to `inode_id` and commit it, unless only checking
+    fn modify_inode(&self, inode_id: u32, f: impl FnOnce(&mut DiskInode)) {
+        if !self.repair {
+            return;
+        }
+        let (block_id, block_offset) = self.fs.get_disk_inode_pos(inode_id);
+        get_block_cache(block_id as usize, Arc::clone(&self.fs.block_device))
+            .lock()
+            .modify(block_offset, f);
+        self.fs.commit();
+    }
+}
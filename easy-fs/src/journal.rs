@@ -0,0 +1,135 @@
+//! Write-ahead journal of metadata blocks.
+//!
+//! The superblock, bitmaps, inodes, directory contents and index blocks an
+//! operation changes stay in the block cache until the operation commits.
+//! Committing copies them into the journal area, then writes a header listing
+//! their home blocks, and only then writes them in place. A crash before the
+//! header is on disk loses the operation as a whole, a crash after it is
+//! repaired by replaying the copies when the file system is opened.
+//!
+//! File contents bypass the journal, but are written before the metadata that
+//! refers to them, so a committed inode never points to stale data.
+
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+use crate::{dirty_block_caches, get_block_cache, BlockDevice, DataBlock, BLOCK_SIZE};
+
+/// Magic number of a journal header holding a transaction
+const JOURNAL_MAGIC: u32 = 0x6a726e6c;
+/// Words of the header before the list of home blocks
+const HEADER_WORDS: usize = 3;
+/// Number of blocks of the journal area of a new file system
+pub const JOURNAL_BLOCKS: u32 = 64;
+
+/// First block of the journal area: magic, number of blocks in the
+/// transaction, checksum, then the home block of each copy that follows
+type HeaderBlock = [u32; BLOCK_SIZE / 4];
+
+/// The journal area of a file system
+pub struct Journal {
+    start_block: usize,
+    blocks: usize,
+}
+
+impl Journal {
+    /// The journal taking `blocks` blocks from `start_block`
+    pub fn new(start_block: u32, blocks: u32) -> Self {
+        assert!(blocks >= 2, "the journal needs a header and a copy");
+        Self {
+            start_block: start_block as usize,
+            blocks: blocks as usize,
+        }
+    }
+    /// Most blocks one transaction can change
+    pub fn capacity(&self) -> usize {
+        (self.blocks - 1).min(BLOCK_SIZE / 4 - HEADER_WORDS)
+    }
+    /// Commit all metadata changed in the block cache since the last commit as
+    /// one transaction, after writing back the file contents.
+    /// Panic if the transaction does not fit in the journal.
+    pub fn commit(&self, block_device: &Arc<dyn BlockDevice>) {
+        let mut transaction = Vec::new();
+        for cache in dirty_block_caches(block_device) {
+            let mut locked = cache.lock();
+            if locked.is_journaled() {
+                drop(locked);
+                transaction.push(cache);
+            } else {
+                locked.sync();
+            }
+        }
+        if transaction.is_empty() {
+            return;
+        }
+        assert!(
+            transaction.len() <= self.capacity(),
+            "transaction of {} blocks outgrows the journal",
+            transaction.len()
+        );
+        let mut header: HeaderBlock = [0; BLOCK_SIZE / 4];
+        header[0] = JOURNAL_MAGIC;
+        header[1] = transaction.len() as u32;
+        for (i, cache) in transaction.iter().enumerate() {
+            let cache = cache.lock();
+            header[HEADER_WORDS + i] = cache.block_id() as u32;
+            header[2] = checksum(header[2], cache.data());
+            block_device.write_block(self.start_block + 1 + i, cache.data());
+        }
+        // the transaction is committed once its header is on disk
+        block_device.write_block(self.start_block, header_bytes(&header));
+        for cache in transaction.iter() {
+            cache.lock().checkpoint();
+        }
+        self.clear(block_device);
+    }
+    /// Write back a transaction committed before a crash, or drop one whose
+    /// header was not written completely. Return the number of blocks replayed.
+    pub fn replay(&self, block_device: &Arc<dyn BlockDevice>) -> usize {
+        let mut header: HeaderBlock = [0; BLOCK_SIZE / 4];
+        block_device.read_block(self.start_block, header_bytes_mut(&mut header));
+        let count = header[1] as usize;
+        if header[0] != JOURNAL_MAGIC || count == 0 || count > self.capacity() {
+            return 0;
+        }
+        let mut copies = Vec::with_capacity(count);
+        let mut sum = 0;
+        for i in 0..count {
+            let mut copy: DataBlock = [0; BLOCK_SIZE];
+            block_device.read_block(self.start_block + 1 + i, &mut copy);
+            sum = checksum(sum, &copy);
+            copies.push(copy);
+        }
+        if sum != header[2] {
+            self.clear(block_device);
+            return 0;
+        }
+        // committing the copies again writes them in place and clears the journal
+        for (i, copy) in copies.iter().enumerate() {
+            get_block_cache(header[HEADER_WORDS + i] as usize, Arc::clone(block_device))
+                .lock()
+                .modify(0, |block: &mut DataBlock| block.copy_from_slice(copy));
+        }
+        self.commit(block_device);
+        count
+    }
+    /// Mark the journal as empty
+    fn clear(&self, block_device: &Arc<dyn BlockDevice>) {
+        let header: HeaderBlock = [0; BLOCK_SIZE / 4];
+        block_device.write_block(self.start_block, header_bytes(&header));
+    }
+}
+
+/// Fold the contents of a block into a running checksum
+fn checksum(sum: u32, data: &[u8]) -> u32 {
+    data.iter()
+        .fold(sum, |sum, &byte| sum.rotate_left(5) ^ byte as u32)
+}
+
+fn header_bytes(header: &HeaderBlock) -> &[u8] {
+    unsafe { core::slice::from_raw_parts(header.as_ptr() as *const u8, BLOCK_SIZE) }
+}
+
+fn header_bytes_mut(header: &mut HeaderBlock) -> &mut [u8] {
+    unsafe { core::slice::from_raw_parts_mut(header.as_mut_ptr() as *mut u8, BLOCK_SIZE) }
+}
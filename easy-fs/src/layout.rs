@@ -2,12 +2,14 @@ use alloc::{sync::Arc, vec::Vec};
 
 use crate::{get_block_cache, BlockDevice, BLOCK_SIZE};
 
-/// Magic number for sanity check
-const EFS_MAGIC: u32 = 0x3b800001;
+/// Magic number for sanity check, counting up with every change of the on-disk
+/// layout so that older images are rejected instead of misread:
+/// 2 added link counts, 3 times, modes and owners, 4 the journal
+const EFS_MAGIC: u32 = 0x3b800004;
 /// The max number of direct inodes
-const INODE_DIRECT_COUNT: usize = 28;
+const INODE_DIRECT_COUNT: usize = 22;
 /// The max length of inode name
-const NAME_LENGTH_LIMIT: usize = 27;
+pub const NAME_LENGTH_LIMIT: usize = 27;
 /// The max number of indirect1 inodes
 const INODE_INDIRECT1_COUNT: usize = BLOCK_SIZE / 4;
 /// The max number of indirect2 inodes
@@ -17,8 +19,17 @@ const DIRECT_BOUND: usize = INODE_DIRECT_COUNT;
 /// The upper bound of indirect1 inode index
 const INDIRECT1_BOUND: usize = DIRECT_BOUND + INODE_INDIRECT1_COUNT;
 /// The upper bound of indirect2 inode indexs
-#[allow(unused)]
 const INDIRECT2_BOUND: usize = INDIRECT1_BOUND + INODE_INDIRECT2_COUNT;
+/// The largest size the blocks of an inode can be indexed and counted for
+pub const MAX_FILE_SIZE: u32 = {
+    let indexed = INDIRECT2_BOUND as u64 * BLOCK_SIZE as u64;
+    let countable = u32::MAX as u64 + 1 - BLOCK_SIZE as u64;
+    if indexed < countable {
+        indexed as u32
+    } else {
+        countable as u32
+    }
+};
 /// The max length of directory entry
 pub const DIRENT_SIZE: usize = core::mem::size_of::<DirEntry>();
 
@@ -30,6 +41,8 @@ pub struct SuperBlock {
     magic: u32,
     /// Total blocks in this file system
     pub total_blocks: u32,
+    /// Total journal blocks in this file system, right after the super block
+    pub journal_blocks: u32,
     /// Total inode bitmap blocks in this file system
     pub inode_bitmap_blocks: u32,
     /// Total inode area blocks in this file system
@@ -45,6 +58,7 @@ impl SuperBlock {
     pub fn initialize(
         &mut self,
         total_blocks: u32,
+        journal_blocks: u32,
         inode_bitmap_blocks: u32,
         inode_area_blocks: u32,
         data_bitmap_blocks: u32,
@@ -53,6 +67,7 @@ impl SuperBlock {
         *self = Self {
             magic: EFS_MAGIC,
             total_blocks,
+            journal_blocks,
             inode_bitmap_blocks,
             inode_area_blocks,
             data_bitmap_blocks,
@@ -63,6 +78,29 @@ impl SuperBlock {
     pub fn is_valid(&self) -> bool {
         self.magic == EFS_MAGIC
     }
+    /// Check whether the block is valid [`SuperBlock`] whose areas add up to the
+    /// file system and have room for a journal, the root inode and a data bitmap
+    /// covering the data area, so that it can be opened
+    pub fn is_consistent(&self) -> bool {
+        let areas: u64 = [
+            1,
+            self.journal_blocks,
+            self.inode_bitmap_blocks,
+            self.inode_area_blocks,
+            self.data_bitmap_blocks,
+            self.data_area_blocks,
+        ]
+        .iter()
+        .map(|&blocks| blocks as u64)
+        .sum();
+        self.is_valid()
+            && areas == self.total_blocks as u64
+            && self.journal_blocks >= 2
+            && self.inode_bitmap_blocks > 0
+            && self.inode_area_blocks > 0
+            && self.data_bitmap_blocks as u64 * BLOCK_SIZE as u64 * 8
+                >= self.data_area_blocks as u64
+    }
 }
 
 #[repr(C)]
@@ -71,13 +109,30 @@ pub struct DiskInode {
     pub direct: [u32; INODE_DIRECT_COUNT],
     pub indirect1: u32,
     pub indirect2: u32,
+    /// Number of directory entries referring to this inode
+    pub nlink: u32,
+    /// Owner user id
+    pub uid: u32,
+    /// Owner group id
+    pub gid: u32,
+    /// Last access in seconds since the Unix epoch
+    pub atime: u32,
+    /// Last change of the contents in seconds since the Unix epoch
+    pub mtime: u32,
+    /// Last change of the contents or the metadata in seconds since the Unix epoch
+    pub ctime: u32,
+    /// Permission bits, `0o7777` at most
+    pub mode: u16,
     type_: DiskInodeType,
 }
 
 #[derive(PartialEq)]
+#[repr(u8)]
 pub enum DiskInodeType {
     File,
     Directory,
+    /// Holds the path it points to in its data blocks
+    Symlink,
 }
 
 pub type IndirectBlock = [u32; BLOCK_SIZE / 4];
@@ -85,19 +140,44 @@ pub type DataBlock = [u8; BLOCK_SIZE];
 
 impl DiskInode {
     /// indirect1 and indirect2 block are allocated only when they are needed.
-    pub fn initialize(&mut self, type_: DiskInodeType) {
+    /// A new directory is linked from its parent and from its own `.`.
+    /// The inode is owned by root and all its times are `now`.
+    pub fn initialize(&mut self, type_: DiskInodeType, now: u32) {
         self.size = 0;
         self.direct.iter_mut().for_each(|v| *v = 0);
         self.indirect1 = 0;
         self.indirect2 = 0;
+        (self.nlink, self.mode) = match type_ {
+            DiskInodeType::File => (1, 0o644),
+            DiskInodeType::Directory => (2, 0o755),
+            DiskInodeType::Symlink => (1, 0o777),
+        };
+        self.uid = 0;
+        self.gid = 0;
+        self.atime = now;
+        self.mtime = now;
+        self.ctime = now;
         self.type_ = type_;
     }
+    /// Record a change of the contents at `now`
+    pub fn touch(&mut self, now: u32) {
+        self.mtime = now;
+        self.ctime = now;
+    }
     pub fn is_dir(&self) -> bool {
         self.type_ == DiskInodeType::Directory
     }
     pub fn is_file(&self) -> bool {
         self.type_ == DiskInodeType::File
     }
+    pub fn is_symlink(&self) -> bool {
+        self.type_ == DiskInodeType::Symlink
+    }
+    /// Whether the type of an inode read from disk is one of [`DiskInodeType`]
+    pub fn has_valid_type(&self) -> bool {
+        let type_ = unsafe { *(core::ptr::addr_of!(self.type_) as *const u8) };
+        type_ <= DiskInodeType::Symlink as u8
+    }
     pub fn get_block_id(&self, inner_id: u32, block_device: &Arc<dyn BlockDevice>) -> u32 {
         let inner_id = inner_id as usize;
         if inner_id < INODE_DIRECT_COUNT {
@@ -248,7 +328,7 @@ impl DiskInode {
         // indirect1
         get_block_cache(self.indirect1 as usize, Arc::clone(block_device))
             .lock()
-            .modify(0, |indirect1: &mut IndirectBlock| {
+            .read(0, |indirect1: &IndirectBlock| {
                 while current_blocks < data_blocks.min(INODE_INDIRECT1_COUNT) {
                     v.push(indirect1[current_blocks]);
                     //indirect1[current_blocks] = 0;
@@ -269,13 +349,13 @@ impl DiskInode {
         let b1 = data_blocks % INODE_INDIRECT1_COUNT;
         get_block_cache(self.indirect2 as usize, Arc::clone(block_device))
             .lock()
-            .modify(0, |indirect2: &mut IndirectBlock| {
+            .read(0, |indirect2: &IndirectBlock| {
                 // full indirect1 blocks
-                for entry in indirect2.iter_mut().take(a1) {
+                for entry in indirect2.iter().take(a1) {
                     v.push(*entry);
                     get_block_cache(*entry as usize, Arc::clone(block_device))
                         .lock()
-                        .modify(0, |indirect1: &mut IndirectBlock| {
+                        .read(0, |indirect1: &IndirectBlock| {
                             for entry in indirect1.iter() {
                                 v.push(*entry);
                             }
@@ -286,7 +366,7 @@ impl DiskInode {
                     v.push(indirect2[a1]);
                     get_block_cache(indirect2[a1] as usize, Arc::clone(block_device))
                         .lock()
-                        .modify(0, |indirect1: &mut IndirectBlock| {
+                        .read(0, |indirect1: &IndirectBlock| {
                             for entry in indirect1.iter().take(b1) {
                                 v.push(*entry);
                             }
@@ -297,6 +377,56 @@ impl DiskInode {
         self.indirect2 = 0;
         v
     }
+    /// Data and index blocks of the first `data_blocks` data blocks of this inode,
+    /// stopping at the first block `valid` rejects.
+    /// Return the blocks before it and, if there is one, the rejected block and the
+    /// number of data blocks before it.
+    pub fn walk_blocks(
+        &self,
+        data_blocks: u32,
+        block_device: &Arc<dyn BlockDevice>,
+        valid: impl Fn(u32) -> bool,
+    ) -> (Vec<u32>, Option<(u32, u32)>) {
+        let entry = |block: u32, index: usize| {
+            get_block_cache(block as usize, Arc::clone(block_device))
+                .lock()
+                .read(0, |indirect: &IndirectBlock| indirect[index])
+        };
+        let check = |block: u32, data_blocks: usize| {
+            if valid(block) {
+                Ok(block)
+            } else {
+                Err((block, data_blocks as u32))
+            }
+        };
+        let mut v: Vec<u32> = Vec::new();
+        let mut walk = || {
+            for i in 0..(data_blocks as usize).min(INDIRECT2_BOUND) {
+                let block = if i < DIRECT_BOUND {
+                    self.direct[i]
+                } else if i < INDIRECT1_BOUND {
+                    if i == DIRECT_BOUND {
+                        v.push(check(self.indirect1, i)?);
+                    }
+                    entry(self.indirect1, i - DIRECT_BOUND)
+                } else {
+                    if i == INDIRECT1_BOUND {
+                        v.push(check(self.indirect2, i)?);
+                    }
+                    let last = i - INDIRECT1_BOUND;
+                    let indirect1 = entry(self.indirect2, last / INODE_INDIRECT1_COUNT);
+                    if last % INODE_INDIRECT1_COUNT == 0 {
+                        v.push(check(indirect1, i)?);
+                    }
+                    entry(indirect1, last % INODE_INDIRECT1_COUNT)
+                };
+                v.push(check(block, i)?);
+            }
+            Ok(())
+        };
+        let rejected = walk().err();
+        (v, rejected)
+    }
     /// Read data from current disk inode
     pub fn read_at(
         &self,
@@ -348,6 +478,11 @@ impl DiskInode {
         let mut start = offset;
         let end = (offset + buf.len()).min(self.size as usize);
         assert!(start <= end);
+        if start == end {
+            return 0;
+        }
+        // file contents bypass the journal, directory entries and links do not
+        let journaled = !self.is_file();
         let mut start_block = start / BLOCK_SIZE;
         let mut write_size = 0usize;
         loop {
@@ -356,17 +491,20 @@ impl DiskInode {
             end_current_block = end_current_block.min(end);
             // write and update write size
             let block_write_size = end_current_block - start;
-            get_block_cache(
+            let block_cache = get_block_cache(
                 self.get_block_id(start_block as u32, block_device) as usize,
                 Arc::clone(block_device),
-            )
-            .lock()
-            .modify(0, |data_block: &mut DataBlock| {
-                let src = &buf[write_size..write_size + block_write_size];
-                let dst =
-                    &mut data_block[start % BLOCK_SIZE..start % BLOCK_SIZE + block_write_size];
-                dst.copy_from_slice(src);
-            });
+            );
+            let mut block_cache = block_cache.lock();
+            let data_block: &mut DataBlock = if journaled {
+                block_cache.get_mut(0)
+            } else {
+                block_cache.get_mut_unjournaled(0)
+            };
+            let src = &buf[write_size..write_size + block_write_size];
+            data_block[start % BLOCK_SIZE..start % BLOCK_SIZE + block_write_size]
+                .copy_from_slice(src);
+            drop(block_cache);
             write_size += block_write_size;
             // move to next block
             if end_current_block == end {
@@ -406,6 +544,17 @@ impl DirEntry {
     pub fn as_bytes_mut(&mut self) -> &mut [u8] {
         unsafe { core::slice::from_raw_parts_mut(self as *mut _ as usize as *mut u8, DIRENT_SIZE) }
     }
+    /// A free slot left behind by a removed entry
+    pub fn is_free(&self) -> bool {
+        self.name[0] == 0
+    }
+    /// Whether the name is non-empty, terminated and valid UTF-8
+    pub fn has_valid_name(&self) -> bool {
+        match self.name.iter().position(|&byte| byte == 0) {
+            Some(len) => len > 0 && core::str::from_utf8(&self.name[..len]).is_ok(),
+            None => false,
+        }
+    }
     pub fn name(&self) -> &str {
         let len = (0usize..).find(|i| self.name[*i] == 0).unwrap();
         core::str::from_utf8(&self.name[..len]).unwrap() // invalid utf-8 name causes panic
@@ -7,7 +7,10 @@ extern crate alloc;
 mod bitmap;
 mod block_cache;
 mod block_dev;
+mod clock;
 mod efs;
+mod fsck;
+mod journal;
 mod layout;
 mod vfs;
 // #[macro_use]
@@ -20,9 +23,14 @@ pub const SECTOR_SIZE: usize = 0x200; // 512 Bytes
 /// Number of block caches that should stay in memory
 const BLOCK_CACHE_NUM: usize = 128;
 use bitmap::Bitmap;
-use block_cache::{block_cache_sync_all, get_block_cache};
+use block_cache::{dirty_block_caches, get_block_cache};
 pub use block_dev::BlockDevice;
+use clock::now;
+pub use clock::set_clock;
 pub use efs::EasyFileSystem;
-pub use layout::SuperBlock;
+pub use fsck::Problem;
+use journal::Journal;
+pub use journal::JOURNAL_BLOCKS;
+pub use layout::{SuperBlock, MAX_FILE_SIZE};
 use layout::*;
 pub use vfs::Inode;
@@ -1,15 +1,20 @@
 use core::fmt::Debug;
 
-use alloc::{string::String, sync::Arc, vec::Vec};
+use alloc::{string::String, sync::Arc, vec, vec::Vec};
 use spin::{Mutex, MutexGuard};
 
 use crate::{
-    block_cache_sync_all, get_block_cache, BlockDevice, DirEntry, DiskInode, DiskInodeType,
-    EasyFileSystem, DIRENT_SIZE,
+    get_block_cache, now, BlockDevice, DirEntry, DiskInode, DiskInodeType, EasyFileSystem,
+    BLOCK_SIZE, DIRENT_SIZE, MAX_FILE_SIZE, NAME_LENGTH_LIMIT,
 };
 
+/// Most bytes a file grows by in one transaction, which keeps the index and
+/// bitmap blocks it changes well within the journal
+const GROW_CHUNK: usize = 256 * BLOCK_SIZE;
+
 /// Virtual filesystem layer over easy-fs
 pub struct Inode {
+    inode_id: u32,
     block_id: usize,
     block_offset: usize,
     fs: Arc<Mutex<EasyFileSystem>>,
@@ -20,25 +25,28 @@ impl Debug for Inode {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         write!(
             f,
-            "Inode {{ block_id: {}, block_offset: {}}}",
-            self.block_id, self.block_offset
+            "Inode {{ inode_id: {}, block_id: {}, block_offset: {}}}",
+            self.inode_id, self.block_id, self.block_offset
         )
     }
 }
 
 impl Inode {
-    /// Create a new [`Inode`] at (`block_id`, `block_offset`) in `fs` on `block_device`
-    pub fn new(
-        block_id: u32,
-        block_offset: usize,
-        fs: Arc<Mutex<EasyFileSystem>>,
-        block_device: Arc<dyn BlockDevice>,
+    /// Create a new [`Inode`] numbered `inode_id` in `fs`, locked from `efs`.
+    /// The inode stays allocated while it is in memory, even with no links left.
+    pub(crate) fn new(
+        inode_id: u32,
+        fs: &mut EasyFileSystem,
+        efs: Arc<Mutex<EasyFileSystem>>,
     ) -> Self {
+        let (block_id, block_offset) = fs.get_disk_inode_pos(inode_id);
+        fs.open_inode(inode_id);
         Self {
+            inode_id,
             block_id: block_id as usize,
             block_offset,
-            fs,
-            block_device,
+            fs: efs,
+            block_device: Arc::clone(&fs.block_device),
         }
     }
     fn read_disk_inode<V>(&self, f: impl FnOnce(&DiskInode) -> V) -> V {
@@ -51,40 +59,115 @@ impl Inode {
             .lock()
             .modify(self.block_offset, f)
     }
-    fn is_dir(&self) -> bool {
+    fn read_other_disk_inode<V>(
+        &self,
+        fs: &EasyFileSystem,
+        inode_id: u32,
+        f: impl FnOnce(&DiskInode) -> V,
+    ) -> V {
+        let (block_id, block_offset) = fs.get_disk_inode_pos(inode_id);
+        get_block_cache(block_id as usize, Arc::clone(&self.block_device))
+            .lock()
+            .read(block_offset, f)
+    }
+    /// Modify the disk inode numbered `inode_id`, which may be another one than `self`.
+    /// Must not be called while the disk inode of `self` is borrowed, as both may share a block.
+    fn modify_other_disk_inode<V>(
+        &self,
+        fs: &EasyFileSystem,
+        inode_id: u32,
+        f: impl FnOnce(&mut DiskInode) -> V,
+    ) -> V {
+        let (block_id, block_offset) = fs.get_disk_inode_pos(inode_id);
+        get_block_cache(block_id as usize, Arc::clone(&self.block_device))
+            .lock()
+            .modify(block_offset, f)
+    }
+    /// Inode number of this inode
+    pub fn inode_id(&self) -> u32 {
+        self.inode_id
+    }
+    /// Whether this inode is a directory
+    pub fn is_dir(&self) -> bool {
         get_block_cache(self.block_id, Arc::clone(&self.block_device))
             .lock()
             .read(self.block_offset, |disk_inode: &DiskInode| {
                 disk_inode.is_dir()
             })
     }
-    fn is_file(&self) -> bool {
+    /// Whether this inode is a regular file
+    pub fn is_file(&self) -> bool {
         get_block_cache(self.block_id, Arc::clone(&self.block_device))
             .lock()
             .read(self.block_offset, |disk_inode: &DiskInode| {
                 disk_inode.is_file()
             })
     }
+    /// Whether this inode is a symbolic link
+    pub fn is_symlink(&self) -> bool {
+        let _fs = self.fs.lock();
+        self.read_disk_inode(|disk_inode| disk_inode.is_symlink())
+    }
+    /// Size of this inode in bytes
+    pub fn size(&self) -> usize {
+        let _fs = self.fs.lock();
+        self.read_disk_inode(|disk_inode| disk_inode.size as usize)
+    }
+    /// Number of directory entries referring to this inode
+    pub fn nlink(&self) -> u32 {
+        let _fs = self.fs.lock();
+        self.read_disk_inode(|disk_inode| disk_inode.nlink)
+    }
+    /// Permission bits of this inode
+    pub fn mode(&self) -> u16 {
+        let _fs = self.fs.lock();
+        self.read_disk_inode(|disk_inode| disk_inode.mode)
+    }
+    /// Change the permission bits of this inode to `mode & 0o7777`
+    pub fn set_mode(&self, mode: u16) {
+        let fs = self.fs.lock();
+        self.modify_disk_inode(|disk_inode| {
+            disk_inode.mode = mode & 0o7777;
+            disk_inode.ctime = now();
+        });
+        fs.commit();
+    }
+    /// User and group id of the owner of this inode
+    pub fn owner(&self) -> (u32, u32) {
+        let _fs = self.fs.lock();
+        self.read_disk_inode(|disk_inode| (disk_inode.uid, disk_inode.gid))
+    }
+    /// Last access, modification and status change of this inode,
+    /// in seconds since the Unix epoch
+    pub fn times(&self) -> (u32, u32, u32) {
+        let _fs = self.fs.lock();
+        self.read_disk_inode(|disk_inode| (disk_inode.atime, disk_inode.mtime, disk_inode.ctime))
+    }
+    /// Number of blocks occupied by this inode, including the index blocks
+    pub fn blocks(&self) -> usize {
+        let _fs = self.fs.lock();
+        self.read_disk_inode(|disk_inode| DiskInode::total_blocks(disk_inode.size) as usize)
+    }
     /// Find a directory entry with `name`.
-    /// Note: must assure that `self` correspond to a [`DiskInode`] with directory type.
+    /// Return `None` if there is none, or `self` is not a directory.
     pub fn find(&self, name: &str) -> Option<Arc<Inode>> {
-        assert!(self.is_dir());
         // lock to assure block cache exclusive accessing
         // avoid multiple cores accessing the filesystem concurrently
-        let fs = self.fs.lock();
-        self.read_disk_inode(|disk_inode| {
-            self.find_inode_id(name, disk_inode).map(|inode_id| {
-                let (block_id, block_offset) = fs.get_disk_inode_pos(inode_id);
-                Arc::new(Self::new(
-                    block_id,
-                    block_offset,
-                    self.fs.clone(),
-                    self.block_device.clone(),
-                ))
-            })
-        })
+        let mut fs = self.fs.lock();
+        let inode_id = self.read_disk_inode(|disk_inode| {
+            if !disk_inode.is_dir() {
+                return None;
+            }
+            self.find_inode_id(name, disk_inode)
+        })?;
+        Some(Arc::new(Self::new(inode_id, &mut fs, Arc::clone(&self.fs))))
     }
     fn find_inode_id(&self, name: &str, disk_inode: &DiskInode) -> Option<u32> {
+        self.find_dirent(name, disk_inode)
+            .map(|(_, inode_id)| inode_id)
+    }
+    /// Find the entry `name`, return its index in the directory and its inode number
+    fn find_dirent(&self, name: &str, disk_inode: &DiskInode) -> Option<(usize, u32)> {
         // assert it is a directory
         assert!(disk_inode.is_dir());
         let file_count = (disk_inode.size as usize) / DIRENT_SIZE;
@@ -93,12 +176,36 @@ impl Inode {
             let dirent_read_bytes =
                 disk_inode.read_at(DIRENT_SIZE * i, dirent.as_bytes_mut(), &self.block_device);
             assert_eq!(dirent_read_bytes, DIRENT_SIZE);
-            if dirent.name() == name {
-                return Some(dirent.inode_number() as u32);
+            if !dirent.is_free() && dirent.name() == name {
+                return Some((i, dirent.inode_number()));
             }
         }
         None
     }
+    /// Find the name under which `inode_id` is linked in this directory,
+    /// ignoring `.` and `..`.
+    /// Note: must assure that `self` correspond to a [`DiskInode`] with directory type.
+    pub fn find_name(&self, inode_id: u32) -> Option<String> {
+        assert!(self.is_dir());
+        let _fs = self.fs.lock();
+        self.read_disk_inode(|disk_inode| {
+            let file_count = (disk_inode.size as usize) / DIRENT_SIZE;
+            let mut dirent = DirEntry::empty();
+            for i in 0..file_count {
+                let dirent_read_bytes =
+                    disk_inode.read_at(DIRENT_SIZE * i, dirent.as_bytes_mut(), &self.block_device);
+                assert_eq!(dirent_read_bytes, DIRENT_SIZE);
+                let name = dirent.name();
+                if dirent.is_free() || name == "." || name == ".." {
+                    continue;
+                }
+                if dirent.inode_number() == inode_id {
+                    return Some(String::from(name));
+                }
+            }
+            None
+        })
+    }
     /// Get all directory enties name.
     /// /// Note: must assure that `self` correspond to a [`DiskInode`] with directory type.
     pub fn ls(&self) -> Vec<String> {
@@ -112,7 +219,9 @@ impl Inode {
                 let dirent_read_bytes =
                     disk_inode.read_at(DIRENT_SIZE * i, dirent.as_bytes_mut(), &self.block_device);
                 assert_eq!(dirent_read_bytes, DIRENT_SIZE);
-                v.push(String::from(dirent.name()));
+                if !dirent.is_free() {
+                    v.push(String::from(dirent.name()));
+                }
             }
             v
         })
@@ -120,54 +229,351 @@ impl Inode {
     /// Create a new file as `name`.
     /// /// Note: must assure that `self` correspond to a [`DiskInode`] with directory type.
     pub fn create(&self, name: &str) -> Option<Arc<Inode>> {
+        self.create_inode(name, DiskInodeType::File, &[])
+    }
+    /// Create a new directory as `name`, with `.` and `..` entries in it.
+    /// Note: must assure that `self` correspond to a [`DiskInode`] with directory type.
+    pub fn mkdir(&self, name: &str) -> Option<Arc<Inode>> {
+        self.create_inode(name, DiskInodeType::Directory, &[])
+    }
+    /// Create a symbolic link as `name` pointing to `target`, which is not checked.
+    /// Note: must assure that `self` correspond to a [`DiskInode`] with directory type.
+    pub fn symlink(&self, name: &str, target: &str) -> Option<Arc<Inode>> {
+        self.create_inode(name, DiskInodeType::Symlink, target.as_bytes())
+    }
+    /// Path a symbolic link points to.
+    /// Note: must assure that `self` correspond to a [`DiskInode`] with symlink type.
+    pub fn read_link(&self) -> String {
+        let _fs = self.fs.lock();
+        self.read_disk_inode(|disk_inode| {
+            assert!(disk_inode.is_symlink());
+            let mut target = vec![0u8; disk_inode.size as usize];
+            disk_inode.read_at(0, &mut target, &self.block_device);
+            String::from_utf8_lossy(&target).into_owned()
+        })
+    }
+    /// Create an inode of `type_` holding `content` and add it as `name`
+    fn create_inode(&self, name: &str, type_: DiskInodeType, content: &[u8]) -> Option<Arc<Inode>> {
         assert!(self.is_dir());
+        if !valid_name(name) {
+            return None;
+        }
         let mut fs = self.fs.lock();
-        if self
-            .modify_disk_inode(|root_inode| {
-                // assert it is a directory
-                assert!(root_inode.is_dir());
-                // has the file been created?
-                self.find_inode_id(name, root_inode)
-            })
-            .is_some()
+        if self.is_removed()
+            || self
+                .modify_disk_inode(|root_inode| {
+                    // assert it is a directory
+                    assert!(root_inode.is_dir());
+                    // has the file been created?
+                    self.find_inode_id(name, root_inode)
+                })
+                .is_some()
         {
             return None;
         }
         // create a new file
         // alloc a inode with an indirect block
-        let new_inode_id = fs.alloc_inode();
+        let new_inode_id = fs.alloc_inode()?;
         // initialize inode
         let (new_inode_block_id, new_inode_block_offset) = fs.get_disk_inode_pos(new_inode_id);
-        get_block_cache(new_inode_block_id as usize, Arc::clone(&self.block_device))
-            .lock()
-            .modify(new_inode_block_offset, |new_inode: &mut DiskInode| {
-                new_inode.initialize(DiskInodeType::File);
-            });
-        self.modify_disk_inode(|root_inode| {
-            // append file in the dirent
-            let file_count = (root_inode.size as usize) / DIRENT_SIZE;
-            let new_size = (file_count + 1) * DIRENT_SIZE;
-            // increase size
-            self.increase_size(new_size as u32, root_inode, &mut fs);
-            // write dirent
-            let dirent = DirEntry::new(name, new_inode_id);
-            root_inode.write_at(
-                file_count * DIRENT_SIZE,
-                dirent.as_bytes(),
-                &self.block_device,
-            );
-        });
-        let (block_id, block_offset) = fs.get_disk_inode_pos(new_inode_id);
-        block_cache_sync_all();
+        let is_dir = type_ == DiskInodeType::Directory;
+        let initialized =
+            get_block_cache(new_inode_block_id as usize, Arc::clone(&self.block_device))
+                .lock()
+                .modify(new_inode_block_offset, |new_inode: &mut DiskInode| {
+                    new_inode.initialize(type_, now());
+                    if is_dir
+                        && !self.write_dot_entries(new_inode, new_inode_id, self.inode_id, &mut fs)
+                    {
+                        return false;
+                    }
+                    if !content.is_empty() {
+                        if !self.increase_size(content.len() as u32, new_inode, &mut fs) {
+                            return false;
+                        }
+                        new_inode.write_at(0, content, &self.block_device);
+                    }
+                    true
+                });
+        if !initialized
+            || !self.modify_disk_inode(|root_inode| {
+                self.add_dirent(root_inode, name, new_inode_id, &mut fs)
+            })
+        {
+            // out of space, and nothing refers to the new inode yet
+            fs.free_inode(new_inode_id);
+            fs.commit();
+            return None;
+        }
+        if is_dir {
+            // `..` of the new directory
+            self.change_nlink(&fs, self.inode_id, 1);
+        }
+        fs.commit();
         // return inode
         Some(Arc::new(Self::new(
-            block_id,
-            block_offset,
+            new_inode_id,
+            &mut fs,
             Arc::clone(&self.fs),
-            Arc::clone(&self.block_device),
         )))
         // release efs lock automatically by compiler
     }
+    /// Write `.` and `..` entries into an empty directory.
+    /// The root directory is its own parent.
+    /// Return false if there is no free block for them.
+    pub(crate) fn write_dot_entries(
+        &self,
+        dir_inode: &mut DiskInode,
+        inode_id: u32,
+        parent_inode_id: u32,
+        fs: &mut MutexGuard<EasyFileSystem>,
+    ) -> bool {
+        assert!(dir_inode.is_dir() && dir_inode.size == 0);
+        // both entries share the first block
+        self.add_dirent(dir_inode, ".", inode_id, fs)
+            && self.add_dirent(dir_inode, "..", parent_inode_id, fs)
+    }
+    /// Write a new entry into the first free slot of the directory,
+    /// growing it when there is none.
+    /// Return false, leaving the directory as it was, if it cannot grow.
+    fn add_dirent(
+        &self,
+        dir_inode: &mut DiskInode,
+        name: &str,
+        inode_id: u32,
+        fs: &mut MutexGuard<EasyFileSystem>,
+    ) -> bool {
+        let file_count = (dir_inode.size as usize) / DIRENT_SIZE;
+        let mut dirent = DirEntry::empty();
+        let free_slot = (0..file_count).find(|&i| {
+            dir_inode.read_at(DIRENT_SIZE * i, dirent.as_bytes_mut(), &self.block_device);
+            dirent.is_free()
+        });
+        let slot = match free_slot {
+            Some(slot) => slot,
+            None => {
+                // append file in the dirent
+                let new_size = (file_count + 1) * DIRENT_SIZE;
+                if !self.increase_size(new_size as u32, dir_inode, fs) {
+                    return false;
+                }
+                file_count
+            }
+        };
+        // write dirent
+        let dirent = DirEntry::new(name, inode_id);
+        dir_inode.write_at(slot * DIRENT_SIZE, dirent.as_bytes(), &self.block_device);
+        dir_inode.touch(now());
+        true
+    }
+    /// Whether a directory contains nothing but `.` and `..`
+    fn dir_is_empty(&self, dir_inode: &DiskInode) -> bool {
+        let file_count = (dir_inode.size as usize) / DIRENT_SIZE;
+        let mut dirent = DirEntry::empty();
+        (0..file_count).all(|i| {
+            dir_inode.read_at(DIRENT_SIZE * i, dirent.as_bytes_mut(), &self.block_device);
+            dirent.is_free() || dirent.name() == "." || dirent.name() == ".."
+        })
+    }
+    /// Add `delta` to the link count of `inode_id`, return the new count
+    fn change_nlink(&self, fs: &EasyFileSystem, inode_id: u32, delta: i32) -> u32 {
+        self.modify_other_disk_inode(fs, inode_id, |disk_inode| {
+            disk_inode.nlink = disk_inode.nlink.checked_add_signed(delta).unwrap();
+            disk_inode.ctime = now();
+            disk_inode.nlink
+        })
+    }
+    /// Whether this directory was removed while still in memory
+    fn is_removed(&self) -> bool {
+        self.read_disk_inode(|disk_inode| disk_inode.nlink == 0)
+    }
+    /// Count the link from a removed entry of directory `parent_id` to `inode_id` no more,
+    /// and free the data blocks and the inode once nothing refers to it.
+    /// A directory only has one entry, and its `..` no longer links to the parent.
+    fn drop_link(&self, fs: &mut MutexGuard<EasyFileSystem>, parent_id: u32, inode_id: u32) {
+        let nlink = if self.read_other_disk_inode(fs, inode_id, |disk_inode| disk_inode.is_dir()) {
+            self.change_nlink(fs, parent_id, -1);
+            0
+        } else {
+            self.change_nlink(fs, inode_id, -1)
+        };
+        if nlink > 0 {
+            return;
+        }
+        if fs.is_open(inode_id) {
+            // freed with its last Inode, a removed directory takes no entries till then
+            self.modify_other_disk_inode(fs, inode_id, |disk_inode| disk_inode.nlink = 0);
+            return;
+        }
+        fs.free_inode(inode_id);
+    }
+    /// Point `..` of directory `dir_id` to `parent_id`
+    fn set_parent(&self, fs: &EasyFileSystem, dir_id: u32, parent_id: u32) {
+        self.modify_other_disk_inode(fs, dir_id, |dir_inode| {
+            let (index, _) = self.find_dirent("..", dir_inode).unwrap();
+            let dirent = DirEntry::new("..", parent_id);
+            dir_inode.write_at(index * DIRENT_SIZE, dirent.as_bytes(), &self.block_device);
+            dir_inode.touch(now());
+        });
+    }
+    /// Overwrite the entry at `index` of this directory
+    fn write_dirent(&self, index: usize, dirent: &DirEntry) {
+        self.modify_disk_inode(|dir_inode| {
+            dir_inode.write_at(index * DIRENT_SIZE, dirent.as_bytes(), &self.block_device);
+            dir_inode.touch(now());
+        });
+    }
+    /// Remove the entry `name`. The data blocks and the inode it refers to are freed
+    /// when it was the last link to a file, or when it is a directory, which can only
+    /// be removed when it is empty.
+    /// Return whether the entry was removed.
+    /// Note: must assure that `self` correspond to a [`DiskInode`] with directory type.
+    pub fn unlink(&self, name: &str) -> bool {
+        assert!(self.is_dir());
+        if name == "." || name == ".." {
+            return false;
+        }
+        let mut fs = self.fs.lock();
+        let (index, inode_id) =
+            match self.read_disk_inode(|dir_inode| self.find_dirent(name, dir_inode)) {
+                Some(dirent) => dirent,
+                None => return false,
+            };
+        let removable = self.read_other_disk_inode(&fs, inode_id, |disk_inode| {
+            !disk_inode.is_dir() || self.dir_is_empty(disk_inode)
+        });
+        if !removable {
+            return false;
+        }
+        // drop the entry, leaving a free slot for later entries
+        self.write_dirent(index, &DirEntry::empty());
+        self.drop_link(&mut fs, self.inode_id, inode_id);
+        fs.commit();
+        true
+    }
+    /// Add the entry `name` for `inode`, which must be a file of the same file system.
+    /// Return false if `name` is invalid or taken, `inode` is a directory, or `self` was removed.
+    /// Note: must assure that `self` correspond to a [`DiskInode`] with directory type.
+    pub fn link(&self, name: &str, inode: &Inode) -> bool {
+        assert!(self.is_dir());
+        if !valid_name(name) {
+            return false;
+        }
+        let mut fs = self.fs.lock();
+        if self.is_removed()
+            || inode.read_disk_inode(|disk_inode| disk_inode.is_dir())
+            || self
+                .read_disk_inode(|dir_inode| self.find_inode_id(name, dir_inode))
+                .is_some()
+        {
+            return false;
+        }
+        if !self.modify_disk_inode(|dir_inode| {
+            self.add_dirent(dir_inode, name, inode.inode_id, &mut fs)
+        }) {
+            return false;
+        }
+        self.change_nlink(&fs, inode.inode_id, 1);
+        fs.commit();
+        true
+    }
+    /// Move the entry `old_name` of this directory to `new_name` in `new_dir`.
+    /// An existing `new_name` is replaced in place, so that the name never goes missing:
+    /// a file can only replace a file, and a directory an empty directory.
+    /// When both names refer to the same inode, nothing is done.
+    /// Moving a directory below itself must be prevented by the caller.
+    /// Return whether the entry was moved.
+    /// Note: `self` and `new_dir` must correspond to [`DiskInode`]s with directory type.
+    pub fn rename(&self, old_name: &str, new_dir: &Inode, new_name: &str) -> bool {
+        assert!(self.is_dir() && new_dir.is_dir());
+        if is_dot(old_name) || is_dot(new_name) || !valid_name(new_name) {
+            return false;
+        }
+        let mut fs = self.fs.lock();
+        let Some((old_index, inode_id)) =
+            self.read_disk_inode(|dir_inode| self.find_dirent(old_name, dir_inode))
+        else {
+            return false;
+        };
+        if new_dir.is_removed() {
+            return false;
+        }
+        let target = new_dir.read_disk_inode(|dir_inode| new_dir.find_dirent(new_name, dir_inode));
+        let is_dir = self.read_other_disk_inode(&fs, inode_id, |disk_inode| disk_inode.is_dir());
+        if let Some((_, target_id)) = target {
+            if target_id == inode_id {
+                return true;
+            }
+            let replaceable = self.read_other_disk_inode(&fs, target_id, |disk_inode| {
+                disk_inode.is_dir() == is_dir && (!is_dir || self.dir_is_empty(disk_inode))
+            });
+            if !replaceable {
+                return false;
+            }
+        }
+        // the new name is in place before the old one goes away
+        match target {
+            Some((index, _)) => new_dir.write_dirent(index, &DirEntry::new(new_name, inode_id)),
+            None => {
+                if !new_dir.modify_disk_inode(|dir_inode| {
+                    new_dir.add_dirent(dir_inode, new_name, inode_id, &mut fs)
+                }) {
+                    return false;
+                }
+            }
+        }
+        self.write_dirent(old_index, &DirEntry::empty());
+        if is_dir && new_dir.inode_id != self.inode_id {
+            self.set_parent(&fs, inode_id, new_dir.inode_id);
+            self.change_nlink(&fs, self.inode_id, -1);
+            self.change_nlink(&fs, new_dir.inode_id, 1);
+        }
+        if let Some((_, target_id)) = target {
+            self.drop_link(&mut fs, new_dir.inode_id, target_id);
+        }
+        fs.commit();
+        true
+    }
+    /// Swap the inodes which the entry `name` of this directory and `other_name`
+    /// of `other_dir` refer to. Both entries must exist.
+    /// Moving a directory below itself must be prevented by the caller.
+    /// Return whether the entries were swapped.
+    /// Note: `self` and `other_dir` must correspond to [`DiskInode`]s with directory type.
+    pub fn exchange(&self, name: &str, other_dir: &Inode, other_name: &str) -> bool {
+        assert!(self.is_dir() && other_dir.is_dir());
+        if is_dot(name) || is_dot(other_name) {
+            return false;
+        }
+        let fs = self.fs.lock();
+        let (Some((index, inode_id)), Some((other_index, other_id))) = (
+            self.read_disk_inode(|dir_inode| self.find_dirent(name, dir_inode)),
+            other_dir.read_disk_inode(|dir_inode| other_dir.find_dirent(other_name, dir_inode)),
+        ) else {
+            return false;
+        };
+        self.write_dirent(index, &DirEntry::new(name, other_id));
+        other_dir.write_dirent(other_index, &DirEntry::new(other_name, inode_id));
+        if self.inode_id != other_dir.inode_id {
+            let is_dir =
+                self.read_other_disk_inode(&fs, inode_id, |disk_inode| disk_inode.is_dir());
+            let other_is_dir =
+                self.read_other_disk_inode(&fs, other_id, |disk_inode| disk_inode.is_dir());
+            if is_dir {
+                self.set_parent(&fs, inode_id, other_dir.inode_id);
+            }
+            if other_is_dir {
+                self.set_parent(&fs, other_id, self.inode_id);
+            }
+            // a directory moving alone takes its `..` link along
+            if is_dir != other_is_dir {
+                let delta = if is_dir { 1 } else { -1 };
+                self.change_nlink(&fs, other_dir.inode_id, delta);
+                self.change_nlink(&fs, self.inode_id, -delta);
+            }
+        }
+        fs.commit();
+        true
+    }
     /// Clear contents of this inode.
     /// Note: must assure that `self` correspond to a [`DiskInode`] with directory type.
     pub fn clear(&self) {
@@ -179,40 +585,109 @@ impl Inode {
             for data_block in data_blocks_dealloc.into_iter() {
                 fs.dealloc_data(data_block);
             }
+            disk_inode.touch(now());
         });
-        block_cache_sync_all();
+        fs.commit();
     }
-    /// Read data from current inode
-    pub fn read_at(&self, offset: usize, buf: &mut [u8]) -> usize {
-        assert!(self.is_file());
+    /// Read data from current inode.
+    /// Return `None` if it is not a regular file.
+    pub fn read_at(&self, offset: usize, buf: &mut [u8]) -> Option<usize> {
         let _fs = self.fs.lock();
-        self.read_disk_inode(|disk_inode| disk_inode.read_at(offset, buf, &self.block_device))
+        // the access time is not worth a transaction
+        get_block_cache(self.block_id, Arc::clone(&self.block_device))
+            .lock()
+            .modify_unjournaled(self.block_offset, |disk_inode: &mut DiskInode| {
+                if !disk_inode.is_file() {
+                    return None;
+                }
+                disk_inode.atime = now();
+                Some(disk_inode.read_at(offset, buf, &self.block_device))
+            })
     }
-    /// Write data to current inode
-    pub fn write_at(&self, offset: usize, buf: &[u8]) -> usize {
-        assert!(self.is_file());
+    /// Write data to current inode, cut short at [`MAX_FILE_SIZE`] or when
+    /// the file system runs out of free blocks.
+    /// Return `None` if it is not a regular file.
+    pub fn write_at(&self, offset: usize, buf: &[u8]) -> Option<usize> {
         let mut fs = self.fs.lock();
+        if !self.read_disk_inode(|disk_inode| disk_inode.is_file()) {
+            return None;
+        }
+        let end = offset.saturating_add(buf.len()).min(MAX_FILE_SIZE as usize);
+        let mut size = self.read_disk_inode(|disk_inode| disk_inode.size as usize);
+        let mut chunk = GROW_CHUNK;
+        while size < end {
+            let new_size = (size / BLOCK_SIZE * BLOCK_SIZE + chunk).min(end);
+            if self.modify_disk_inode(|disk_inode| {
+                self.increase_size(new_size as u32, disk_inode, &mut fs)
+            }) {
+                size = new_size;
+                fs.commit();
+            } else if chunk > BLOCK_SIZE {
+                // out of space, grow block by block as far as it goes
+                chunk = BLOCK_SIZE;
+            } else {
+                break;
+            }
+        }
+        // the file may not even reach `offset` when it stopped growing
+        let len = end.min(size).saturating_sub(offset);
         let size = self.modify_disk_inode(|disk_inode| {
-            self.increase_size((offset + buf.len()) as u32, disk_inode, &mut fs);
-            disk_inode.write_at(offset, buf, &self.block_device)
+            disk_inode.touch(now());
+            if len == 0 {
+                return 0;
+            }
+            disk_inode.write_at(offset, &buf[..len], &self.block_device)
         });
-        block_cache_sync_all();
-        size
+        fs.commit();
+        Some(size)
     }
+    /// Grow `disk_inode` to `new_size`.
+    /// Return false, leaving it as it was, if there are not enough free blocks.
     fn increase_size(
         &self,
         new_size: u32,
         disk_inode: &mut DiskInode,
         fs: &mut MutexGuard<EasyFileSystem>,
-    ) {
+    ) -> bool {
         if new_size < disk_inode.size {
-            return;
+            return true;
         }
         let block_needed = disk_inode.blocks_num_needed(new_size);
         let mut v: Vec<u32> = Vec::new();
         for _ in 0..block_needed {
-            v.push(fs.alloc_data());
+            match fs.alloc_data() {
+                Some(block_id) => v.push(block_id),
+                None => {
+                    for block_id in v {
+                        fs.dealloc_data(block_id);
+                    }
+                    return false;
+                }
+            }
         }
         disk_inode.increase_size(new_size, v, &self.block_device);
+        true
+    }
+}
+
+/// Free the inode with its last [`Inode`] in memory if it has no links left
+impl Drop for Inode {
+    fn drop(&mut self) {
+        let mut fs = self.fs.lock();
+        if fs.close_inode(self.inode_id) && self.read_disk_inode(|disk_inode| disk_inode.nlink) == 0
+        {
+            fs.free_inode(self.inode_id);
+            fs.commit();
+        }
     }
 }
+
+/// Whether `name` can be given to a new directory entry
+fn valid_name(name: &str) -> bool {
+    !name.is_empty() && name.len() <= NAME_LENGTH_LIMIT && !name.contains('/')
+}
+
+/// Whether `name` is `.` or `..`
+fn is_dot(name: &str) -> bool {
+    name == "." || name == ".."
+}
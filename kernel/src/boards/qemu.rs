@@ -6,4 +6,10 @@ pub const MMIO: &[(usize, usize)] = &[
     (0x1000_1000, 0x00_1000), // Virtio Block in virt machine
 ];
 
+/// Goldfish RTC in virt machine, inside the VIRT_TEST/RTC range of [`MMIO`]
+pub const RTC_BASE: usize = 0x0010_1000;
+
 pub type BlockDeviceImpl = crate::drivers::block::VirtIOBlock;
+
+/// Harts the kernel can run on, QEMU virt machine supports up to 8 with `-smp`
+pub const MAX_HARTS: usize = 8;
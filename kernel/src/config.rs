@@ -8,4 +8,7 @@ pub const POINTER_SIZE: usize = core::mem::size_of::<usize>() * 8;
 pub const PAGE_SIZE_BITS: usize = 0xc; // 4KiB
 pub const PAGE_SIZE: usize = 0x1000; // 4KiB
 pub const TRAMPOLINE: usize = usize::MAX - PAGE_SIZE + 1; // Top most page in virtual space
-pub const TRAP_CONTEXT: usize = TRAMPOLINE - PAGE_SIZE; // Second top most page in virtual space
+pub const TRAP_CONTEXT_BASE: usize = TRAMPOLINE - PAGE_SIZE; // Trap context of thread 0, thread `tid` has the page `tid` pages below
+pub const USER_SPACE_TOP: usize = 0x40_0000_0000; // End of the lower half of SV39 virtual space
+pub const SIGRETURN_TRAMPOLINE: usize = USER_SPACE_TOP - PAGE_SIZE; // Code signal handlers return to, in every user space
+pub const MMAP_BASE: usize = 0x10_0000_0000; // Lowest address picked for `mmap`
@@ -1,6 +1,7 @@
 use core::fmt::{self, Write};
 
 use crate::sbi::console_putchar;
+use crate::sync::SpinLock;
 
 struct Kout;
 
@@ -13,8 +14,11 @@ impl Write for Kout {
     }
 }
 
+/// Keeps the output of different harts from interleaving
+static KOUT: SpinLock<Kout> = SpinLock::new(Kout);
+
 pub fn print(args: fmt::Arguments) {
-    Kout.write_fmt(args).unwrap()
+    KOUT.exclusive_access().write_fmt(args).unwrap()
 }
 
 /// Kernel print macro
@@ -1,12 +1,30 @@
     .section .text.entry
     .globl _start
 _start:
-    la sp, boot_stack_top
+    # boot hart, a0 = hartid
+    call set_boot_stack
     call rust_main
 
+    .globl _start_secondary
+_start_secondary:
+    # started by the boot hart through SBI HSM, a0 = hartid
+    call set_boot_stack
+    call rust_main_secondary
+
+# keep hartid in tp, and switch to the boot stack of this hart
+set_boot_stack:
+    mv tp, a0
+    addi t0, a0, 1
+    # each stack is 4096 * 16 = 1 << 16 bytes
+    slli t0, t0, 16
+    la sp, boot_stack_lower_bound
+    add sp, sp, t0
+    ret
+
     .section .bss.stack
     .globl boot_stack_lower_bound
 boot_stack_lower_bound:
-    .space 4096 * 16
+    # one stack per hart, MAX_HARTS in total
+    .space 4096 * 16 * 8
     .globl boot_stack_top
-boot_stack_top:
\ No newline at end of file
+boot_stack_top:
@@ -1,9 +1,14 @@
-use alloc::{sync::Arc, vec::Vec};
-use easy_fs::{EasyFileSystem, Inode, SECTOR_SIZE};
+use alloc::{string::String, sync::Arc, vec::Vec};
+use easy_fs::{EasyFileSystem, Inode, BLOCK_SIZE, MAX_FILE_SIZE, SECTOR_SIZE};
 use lazy_static::lazy_static;
 use spin::Mutex;
 
-use crate::{drivers::BLOCK_DEVICE, mm::UserBuffer};
+use crate::{
+    drivers::BLOCK_DEVICE,
+    errno::{Errno, SysResult},
+    mm::UserBuffer,
+    timer::get_wall_time_sec,
+};
 
 use super::File;
 
@@ -35,7 +40,7 @@ impl OSInode {
         crate::mm::inspect_heap();
         crate::task::inspect_kernel_stack();
         loop {
-            let len = inner.inode.read_at(inner.offset, &mut buffer);
+            let len = inner.inode.read_at(inner.offset, &mut buffer).unwrap_or(0);
             if len == 0 {
                 break;
             }
@@ -46,6 +51,44 @@ impl OSInode {
     }
 }
 
+/// Read from `offset` of `inode` into `buf`, return the number of bytes read
+fn read_buffer(inode: &Inode, mut offset: usize, mut buf: UserBuffer) -> SysResult<usize> {
+    let mut total_read_size = 0usize;
+    for slice in buf.buffers.iter_mut() {
+        let read_size = inode.read_at(offset, slice).ok_or(Errno::EISDIR)?;
+        if read_size == 0 {
+            break;
+        }
+        offset += read_size;
+        total_read_size += read_size;
+    }
+    Ok(total_read_size)
+}
+
+/// Write `buf` to `offset` of `inode`, return the number of bytes written,
+/// which falls short when the file system runs out of space
+fn write_buffer(inode: &Inode, mut offset: usize, buf: UserBuffer) -> SysResult<usize> {
+    if offset
+        .checked_add(buf.len())
+        .map_or(true, |end| end > MAX_FILE_SIZE as usize)
+    {
+        return Err(Errno::EFBIG);
+    }
+    let mut total_write_size = 0usize;
+    for slice in buf.buffers.iter() {
+        let write_size = inode.write_at(offset, slice).ok_or(Errno::EISDIR)?;
+        offset += write_size;
+        total_write_size += write_size;
+        if write_size < slice.len() {
+            break;
+        }
+    }
+    if total_write_size == 0 && buf.len() > 0 {
+        return Err(Errno::ENOSPC);
+    }
+    Ok(total_write_size)
+}
+
 impl File for OSInode {
     fn readable(&self) -> bool {
         self.readable
@@ -53,34 +96,49 @@ impl File for OSInode {
     fn writable(&self) -> bool {
         self.writable
     }
-    fn read(&self, mut buf: UserBuffer) -> usize {
+    fn read(&self, buf: UserBuffer) -> SysResult<usize> {
         let mut inner = self.inner.lock();
-        let mut total_read_size = 0usize;
-        for slice in buf.buffers.iter_mut() {
-            let read_size = inner.inode.read_at(inner.offset, *slice);
-            if read_size == 0 {
-                break;
-            }
-            inner.offset += read_size;
-            total_read_size += read_size;
-        }
-        total_read_size
+        let read_size = read_buffer(&inner.inode, inner.offset, buf)?;
+        inner.offset += read_size;
+        Ok(read_size)
+    }
+    fn write(&self, buf: UserBuffer) -> SysResult<usize> {
+        let mut inner = self.inner.lock();
+        let write_size = write_buffer(&inner.inode, inner.offset, buf)?;
+        inner.offset += write_size;
+        Ok(write_size)
     }
-    fn write(&self, buf: UserBuffer) -> usize {
+    fn seek(&self, offset: isize, whence: usize) -> SysResult<usize> {
         let mut inner = self.inner.lock();
-        let mut total_write_size = 0usize;
-        for slice in buf.buffers.iter() {
-            let write_size = inner.inode.write_at(inner.offset, *slice);
-            assert_eq!(write_size, slice.len());
-            inner.offset += write_size;
-            total_write_size += write_size;
+        let base = match whence {
+            SEEK_SET => 0,
+            SEEK_CUR => inner.offset,
+            SEEK_END => inner.inode.size(),
+            _ => return Err(Errno::EINVAL),
+        };
+        let offset = base.checked_add_signed(offset).ok_or(Errno::EINVAL)?;
+        if offset > MAX_FILE_SIZE as usize {
+            return Err(Errno::EFBIG);
         }
-        total_write_size
+        inner.offset = offset;
+        Ok(offset)
+    }
+    fn read_at(&self, offset: usize, buf: UserBuffer) -> SysResult<usize> {
+        let inner = self.inner.lock();
+        read_buffer(&inner.inode, offset, buf)
+    }
+    fn write_at(&self, offset: usize, buf: UserBuffer) -> SysResult<usize> {
+        let inner = self.inner.lock();
+        write_buffer(&inner.inode, offset, buf)
+    }
+    fn inode(&self) -> Option<Arc<Inode>> {
+        Some(self.inner.lock().inode.clone())
     }
 }
 
 lazy_static! {
     pub static ref ROOT_INODE: Arc<Inode> = {
+        easy_fs::set_clock(|| get_wall_time_sec() as u32);
         let efs = EasyFileSystem::open(BLOCK_DEVICE.clone());
         Arc::new(EasyFileSystem::root_inode(&efs))
     };
@@ -93,6 +151,7 @@ bitflags! {
         const RDWR = 1 << 1;   // read write
         const CREATE = 1 << 9; // create
         const TRUNC = 1 << 10; // truncate
+        const NOFOLLOW = 1 << 17; // fail on a symbolic link
     }
 }
 
@@ -108,32 +167,352 @@ impl OpenFlags {
     }
 }
 
-pub fn open_file(name: &str, flags: OpenFlags) -> Option<Arc<OSInode>> {
-    let (readable, writable) = flags.read_write();
-    if flags.contains(OpenFlags::CREATE) {
-        if let Some(inode) = ROOT_INODE.find(name) {
-            // clear size
-            inode.clear();
-            Some(Arc::new(OSInode::new(readable, writable, inode)))
+/// `whence` of `lseek`: set the offset to `offset`
+pub const SEEK_SET: usize = 0;
+/// `whence` of `lseek`: add `offset` to the current offset
+pub const SEEK_CUR: usize = 1;
+/// `whence` of `lseek`: add `offset` to the file size
+pub const SEEK_END: usize = 2;
+
+bitflags! {
+    /// File type and permission bits of [`Stat`]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct StatMode: u32 {
+        const S_IFMT = 0o170_000;
+        const S_IFDIR = 0o040_000;
+        const S_IFREG = 0o100_000;
+        const S_IFLNK = 0o120_000;
+        const S_IRWXU = 0o700;
+        const S_IRWXG = 0o070;
+        const S_IRWXO = 0o007;
+    }
+}
+
+/// File status returned by `fstat`, laid out as `struct stat` of riscv64 Linux
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Stat {
+    pub st_dev: u64,
+    pub st_ino: u64,
+    pub st_mode: u32,
+    pub st_nlink: u32,
+    pub st_uid: u32,
+    pub st_gid: u32,
+    pub st_rdev: u64,
+    __pad1: u64,
+    pub st_size: i64,
+    pub st_blksize: i32,
+    __pad2: i32,
+    /// Number of 512-byte units allocated
+    pub st_blocks: i64,
+    pub st_atime: i64,
+    pub st_atime_nsec: i64,
+    pub st_mtime: i64,
+    pub st_mtime_nsec: i64,
+    pub st_ctime: i64,
+    pub st_ctime_nsec: i64,
+    __unused: [u32; 2],
+}
+
+impl Stat {
+    /// Status of `inode`, timestamps only have whole seconds.
+    pub fn from_inode(inode: &Inode) -> Self {
+        let file_type = if inode.is_dir() {
+            StatMode::S_IFDIR
+        } else if inode.is_symlink() {
+            StatMode::S_IFLNK
+        } else {
+            StatMode::S_IFREG
+        };
+        let (uid, gid) = inode.owner();
+        let (atime, mtime, ctime) = inode.times();
+        Self {
+            st_ino: inode.inode_id() as u64,
+            st_mode: file_type.bits() | inode.mode() as u32,
+            st_nlink: inode.nlink(),
+            st_size: inode.size() as i64,
+            st_blksize: BLOCK_SIZE as i32,
+            st_blocks: (inode.blocks() * BLOCK_SIZE / 512) as i64,
+            st_uid: uid,
+            st_gid: gid,
+            st_atime: atime as i64,
+            st_mtime: mtime as i64,
+            st_ctime: ctime as i64,
+            ..Default::default()
+        }
+    }
+}
+
+/// Special `dirfd` value: resolve relative paths against the working directory
+pub const AT_FDCWD: isize = -100;
+/// Flag of `unlinkat`: remove a directory instead of a file
+pub const AT_REMOVEDIR: u32 = 0x200;
+/// Flag of `linkat`: follow a symbolic link at the old path
+pub const AT_SYMLINK_FOLLOW: u32 = 0x400;
+/// Flag of `renameat2`: fail with `EEXIST` instead of replacing the new path
+pub const RENAME_NOREPLACE: u32 = 1;
+/// Flag of `renameat2`: swap the old and the new path, both of which must exist
+pub const RENAME_EXCHANGE: u32 = 2;
+
+/// Longest chain of symbolic links followed while resolving one path
+const MAX_SYMLINK_FOLLOWS: usize = 40;
+
+/// Walk `path` component by component, starting at the root for absolute
+/// paths and at `base` otherwise. `.` and `..` are ordinary directory entries.
+/// Symbolic links on the way are followed, and so is the last component if
+/// `follow` is set.
+pub fn find_inode(base: &Arc<Inode>, path: &str, follow: bool) -> SysResult<Arc<Inode>> {
+    walk_path(base, path, follow, &mut 0)
+}
+
+/// [`find_inode`] counting the symbolic links followed in `follows`, so that
+/// link loops end in `ELOOP`
+fn walk_path(
+    base: &Arc<Inode>,
+    path: &str,
+    follow: bool,
+    follows: &mut usize,
+) -> SysResult<Arc<Inode>> {
+    let mut inode = if path.starts_with('/') {
+        ROOT_INODE.clone()
+    } else {
+        base.clone()
+    };
+    let mut names = path.split('/').filter(|name| !name.is_empty()).peekable();
+    while let Some(name) = names.next() {
+        if !inode.is_dir() {
+            return Err(Errno::ENOENT);
+        }
+        let next = inode.find(name).ok_or(Errno::ENOENT)?;
+        inode = if next.is_symlink() && (follow || names.peek().is_some()) {
+            *follows += 1;
+            if *follows > MAX_SYMLINK_FOLLOWS {
+                return Err(Errno::ELOOP);
+            }
+            // a relative target starts at the directory holding the link
+            walk_path(&inode, next.read_link().as_str(), true, follows)?
         } else {
+            next
+        };
+    }
+    Ok(inode)
+}
+
+/// Split `path` into its parent directory and the last component, e.g.
+/// `/a/b/c` into (inode of `/a/b`, `c`).
+/// Fail with `ENOENT` if the parent does not exist or the last component is empty.
+pub fn find_parent<'a>(base: &Arc<Inode>, path: &'a str) -> SysResult<(Arc<Inode>, &'a str)> {
+    let path = path.trim_end_matches('/');
+    let (parent_path, name) = match path.rfind('/') {
+        Some(0) => ("/", &path[1..]),
+        Some(idx) => (&path[..idx], &path[idx + 1..]),
+        None => ("", path),
+    };
+    if name.is_empty() {
+        return Err(Errno::ENOENT);
+    }
+    let parent = find_inode(base, parent_path, true)?;
+    if !parent.is_dir() {
+        return Err(Errno::ENOENT);
+    }
+    Ok((parent, name))
+}
+
+/// Open the file at `path`, relative paths are resolved against `cwd`.
+/// A symbolic link is opened as its target unless `NOFOLLOW` is set, in
+/// which case it fails with `ELOOP`.
+pub fn open_file(cwd: &Arc<Inode>, path: &str, flags: OpenFlags) -> SysResult<Arc<OSInode>> {
+    let (readable, writable) = flags.read_write();
+    let follow = !flags.contains(OpenFlags::NOFOLLOW);
+    let inode = if flags.contains(OpenFlags::CREATE) {
+        let (parent, name) = find_parent(cwd, path)?;
+        if parent.find(name).is_none() {
             // create file
-            ROOT_INODE
-                .create(name)
-                .map(|inode| Arc::new(OSInode::new(readable, writable, inode)))
+            let inode = parent.create(name).ok_or(Errno::ENOENT)?;
+            return Ok(Arc::new(OSInode::new(readable, writable, inode)));
         }
+        find_inode(&parent, name, follow)?
+    } else {
+        find_inode(cwd, path, follow)?
+    };
+    if inode.is_symlink() {
+        return Err(Errno::ELOOP);
+    }
+    // directories cannot be read or written as files
+    if inode.is_dir() {
+        return Err(Errno::ENOENT);
+    }
+    if flags.intersects(OpenFlags::CREATE | OpenFlags::TRUNC) {
+        // clear size
+        inode.clear();
+    }
+    Ok(Arc::new(OSInode::new(readable, writable, inode)))
+}
+
+/// Create a directory at `path`. Fail if `path` already exists.
+pub fn make_dir(cwd: &Arc<Inode>, path: &str) -> SysResult<Arc<Inode>> {
+    let (parent, name) = find_parent(cwd, path)?;
+    parent.mkdir(name).ok_or(Errno::ENOENT)
+}
+
+/// Remove the file at `path`, or the empty directory if `remove_dir` is set.
+/// A symbolic link is removed itself, not its target.
+pub fn unlink_file(cwd: &Arc<Inode>, path: &str, remove_dir: bool) -> SysResult<()> {
+    let (parent, name) = find_parent(cwd, path)?;
+    if name == "." || name == ".." {
+        return Err(Errno::EINVAL);
+    }
+    let inode = parent.find(name).ok_or(Errno::ENOENT)?;
+    match (inode.is_dir(), remove_dir) {
+        (true, false) => Err(Errno::EISDIR),
+        (false, true) => Err(Errno::ENOTDIR),
+        _ if parent.unlink(name) => Ok(()),
+        // only a directory with entries other than "." and ".." is refused
+        _ => Err(Errno::ENOTEMPTY),
+    }
+}
+
+/// Give the file at `old_path` the additional name `new_path`. A symbolic
+/// link at `old_path` is linked itself unless `follow` is set.
+pub fn link_file(cwd: &Arc<Inode>, old_path: &str, new_path: &str, follow: bool) -> SysResult<()> {
+    let inode = find_inode(cwd, old_path, follow)?;
+    if inode.is_dir() {
+        return Err(Errno::EPERM);
+    }
+    let (parent, name) = find_parent(cwd, new_path)?;
+    if parent.find(name).is_some() {
+        return Err(Errno::EEXIST);
+    }
+    if parent.link(name, &inode) {
+        Ok(())
     } else {
-        ROOT_INODE.find(name).map(|inode| {
-            if flags.contains(OpenFlags::TRUNC) {
-                inode.clear();
+        Err(Errno::EINVAL)
+    }
+}
+
+/// Create a symbolic link at `link_path` pointing to `target`, which need not exist.
+pub fn symlink_file(cwd: &Arc<Inode>, target: &str, link_path: &str) -> SysResult<()> {
+    if target.is_empty() {
+        return Err(Errno::ENOENT);
+    }
+    let (parent, name) = find_parent(cwd, link_path)?;
+    if parent.find(name).is_some() {
+        return Err(Errno::EEXIST);
+    }
+    parent.symlink(name, target).ok_or(Errno::EINVAL)?;
+    Ok(())
+}
+
+/// Target of the symbolic link at `path`, `EINVAL` if it is something else.
+pub fn read_link(cwd: &Arc<Inode>, path: &str) -> SysResult<String> {
+    let inode = find_inode(cwd, path, false)?;
+    if !inode.is_symlink() {
+        return Err(Errno::EINVAL);
+    }
+    Ok(inode.read_link())
+}
+
+/// Move `old_path` to `new_path`, replacing what is there unless `flags` says otherwise.
+pub fn rename_file(cwd: &Arc<Inode>, old_path: &str, new_path: &str, flags: u32) -> SysResult<()> {
+    if flags & !(RENAME_NOREPLACE | RENAME_EXCHANGE) != 0
+        || flags == RENAME_NOREPLACE | RENAME_EXCHANGE
+    {
+        return Err(Errno::EINVAL);
+    }
+    let (old_parent, old_name) = find_parent(cwd, old_path)?;
+    let (new_parent, new_name) = find_parent(cwd, new_path)?;
+    if [old_name, new_name]
+        .iter()
+        .any(|name| *name == "." || *name == "..")
+    {
+        return Err(Errno::EINVAL);
+    }
+    let inode = old_parent.find(old_name).ok_or(Errno::ENOENT)?;
+    let target = new_parent.find(new_name);
+    // a directory cannot be moved below itself
+    if inode.is_dir() && is_ancestor(&inode, &new_parent) {
+        return Err(Errno::EINVAL);
+    }
+    if flags == RENAME_EXCHANGE {
+        let target = target.ok_or(Errno::ENOENT)?;
+        if target.is_dir() && is_ancestor(&target, &old_parent) {
+            return Err(Errno::EINVAL);
+        }
+        old_parent.exchange(old_name, &new_parent, new_name);
+        return Ok(());
+    }
+    match target {
+        Some(_) if flags == RENAME_NOREPLACE => return Err(Errno::EEXIST),
+        Some(target) if target.inode_id() != inode.inode_id() => {
+            match (inode.is_dir(), target.is_dir()) {
+                (true, false) => return Err(Errno::ENOTDIR),
+                (false, true) => return Err(Errno::EISDIR),
+                _ => {}
             }
-            Arc::new(OSInode::new(readable, writable, inode))
-        })
+        }
+        _ => {}
+    }
+    if old_parent.rename(old_name, &new_parent, new_name) {
+        Ok(())
+    } else if inode.is_dir() {
+        // only a directory with entries other than "." and ".." is refused
+        Err(Errno::ENOTEMPTY)
+    } else {
+        Err(Errno::EINVAL)
+    }
+}
+
+/// Whether directory `dir` is `inode` or one of the directories above it.
+fn is_ancestor(dir: &Arc<Inode>, inode: &Arc<Inode>) -> bool {
+    let mut current = inode.clone();
+    while current.inode_id() != dir.inode_id() {
+        if current.inode_id() == ROOT_INODE.inode_id() || is_removed_dir(&current) {
+            return false;
+        }
+        match current.find("..") {
+            Some(parent) => current = parent,
+            None => return false,
+        }
+    }
+    true
+}
+
+/// Whether `inode` is not a directory still linked into the tree,
+/// so that there is no way up from it.
+fn is_removed_dir(inode: &Inode) -> bool {
+    !inode.is_dir() || inode.nlink() == 0
+}
+
+/// Absolute path of directory `dir`, found by walking up through `..`.
+/// Return `None` if `dir` or a directory above it has been removed.
+pub fn dir_path(dir: &Arc<Inode>) -> Option<String> {
+    let mut names: Vec<String> = Vec::new();
+    let mut current = dir.clone();
+    while current.inode_id() != ROOT_INODE.inode_id() {
+        if is_removed_dir(&current) {
+            return None;
+        }
+        let parent = current.find("..")?;
+        names.push(parent.find_name(current.inode_id())?);
+        current = parent;
     }
+    let mut path = String::new();
+    for name in names.iter().rev() {
+        path.push('/');
+        path.push_str(name);
+    }
+    if path.is_empty() {
+        path.push('/');
+    }
+    Some(path)
 }
 
 pub fn list_apps() {
     kprintln!("/**** APPS ****");
     for app in ROOT_INODE.ls() {
+        if app == "." || app == ".." {
+            continue;
+        }
         kprintln!("{}", app);
     }
     kprintln!("**************/")
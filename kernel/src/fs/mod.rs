@@ -1,17 +1,54 @@
 use core::fmt::Debug;
 
+use alloc::sync::Arc;
+use easy_fs::Inode;
+
+use crate::errno::{Errno, SysResult};
 use crate::mm::UserBuffer;
 
 pub mod inode;
 pub mod pipe;
 pub mod stdio;
 
-pub use inode::{list_apps, open_file, OpenFlags};
-pub use stdio::{Stderr, Stdin, Stdout};
+pub use inode::{
+    dir_path, find_inode, link_file, list_apps, make_dir, open_file, read_link, rename_file,
+    symlink_file, unlink_file, OpenFlags, Stat, AT_FDCWD, AT_REMOVEDIR, AT_SYMLINK_FOLLOW,
+};
+pub use pipe::Pipe;
+pub use stdio::{console_foreground, poll_console, set_console_foreground, Stderr, Stdin, Stdout};
 
 pub trait File: Send + Sync + Debug {
     fn readable(&self) -> bool;
     fn writable(&self) -> bool;
-    fn read(&self, buf: UserBuffer) -> usize;
-    fn write(&self, buf: UserBuffer) -> usize;
+    fn read(&self, buf: UserBuffer) -> SysResult<usize>;
+    fn write(&self, buf: UserBuffer) -> SysResult<usize>;
+    /// Move the offset used by `read` and `write`, see `SEEK_*`. Return the new offset
+    fn seek(&self, _offset: isize, _whence: usize) -> SysResult<usize> {
+        Err(Errno::ESPIPE)
+    }
+    /// Read at `offset` without using or changing the file offset
+    fn read_at(&self, _offset: usize, _buf: UserBuffer) -> SysResult<usize> {
+        Err(Errno::ESPIPE)
+    }
+    /// Write at `offset` without using or changing the file offset
+    fn write_at(&self, _offset: usize, _buf: UserBuffer) -> SysResult<usize> {
+        Err(Errno::ESPIPE)
+    }
+    /// The on-disk inode behind this file, if any, e.g. for `mmap`
+    fn inode(&self) -> Option<Arc<Inode>> {
+        None
+    }
+    /// The pipe behind this file, if any, e.g. for `fcntl`
+    fn pipe(&self) -> Option<&Pipe> {
+        None
+    }
+    /// Whether this file is the console, e.g. for `ioctl`
+    fn is_tty(&self) -> bool {
+        false
+    }
+    /// Whether `read` or `write` may block, so that they must not be given
+    /// user memory, which may be unmapped or copied on write meanwhile
+    fn blocking(&self) -> bool {
+        false
+    }
 }
@@ -1,11 +1,22 @@
 use alloc::sync::{Arc, Weak};
+use alloc::vec;
+use alloc::vec::Vec;
 use spin::Mutex;
 
-use crate::{mm::UserBuffer, task::suspend_current_and_run_next};
+use crate::{
+    config::PAGE_SIZE,
+    errno::{Errno, SysResult},
+    mm::UserBuffer,
+    sync::WaitQueue,
+    task::current_interrupted,
+};
 
 use super::File;
 
-const RING_BUFFER_SIZE: usize = 32;
+/// Default capacity of a pipe
+const PIPE_DEFAULT_SIZE: usize = PAGE_SIZE;
+/// Largest capacity `F_SETPIPE_SZ` may ask for
+const PIPE_MAX_SIZE: usize = 1 << 20;
 
 #[derive(Debug)]
 pub struct Pipe {
@@ -34,6 +45,35 @@ impl Pipe {
             buffer,
         }
     }
+    /// Capacity of the pipe in bytes
+    pub fn capacity(&self) -> usize {
+        self.buffer.lock().capacity()
+    }
+    /// Resize the pipe to hold at least `size` bytes, rounded up to whole pages.
+    /// Return the new capacity. Fail with `EPERM` if `size` is too large, or with
+    /// `EBUSY` if the content currently in the pipe would not fit.
+    pub fn set_capacity(&self, size: usize) -> SysResult {
+        if size > PIPE_MAX_SIZE {
+            return Err(Errno::EPERM);
+        }
+        let size = size.max(1).div_ceil(PAGE_SIZE) * PAGE_SIZE;
+        let mut ring_buffer = self.buffer.lock();
+        if !ring_buffer.resize(size) {
+            return Err(Errno::EBUSY);
+        }
+        // more space might be available now
+        ring_buffer.write_wait.wake_all();
+        Ok(size)
+    }
+}
+
+/// Result of a read or write interrupted by a signal after `done` bytes
+fn interrupted(done: usize) -> SysResult<usize> {
+    if done == 0 {
+        Err(Errno::EINTR)
+    } else {
+        Ok(done)
+    }
 }
 
 impl File for Pipe {
@@ -43,98 +83,110 @@ impl File for Pipe {
     fn writable(&self) -> bool {
         self.writable
     }
-    fn read(&self, buf: UserBuffer) -> usize {
+    fn read(&self, buf: UserBuffer) -> SysResult<usize> {
         assert!(self.readable());
-        let want_to_read = buf.len();
-        let mut buf_iter = buf.into_iter();
         let mut already_read = 0usize;
-        loop {
-            let mut ring_buffer = self.buffer.lock();
-            let loop_read = ring_buffer.available_read_bytes();
-            if loop_read == 0 {
-                // fail to fetch content from the pipe.
-                // first check whether all write ends closed.
-                // if so, then there will not be any content to read.
-                if ring_buffer.all_write_ends_closed() {
-                    return already_read;
-                }
-                // if not, then wait until there's content to fetch.
-                // drop the lock to avoid dead lock.
-                drop(ring_buffer);
-                suspend_current_and_run_next();
-                continue;
-            }
-            for _ in 0..loop_read {
-                if let Some(byte_ref) = buf_iter.next() {
-                    // there's still space to fill
-                    unsafe { *byte_ref = ring_buffer.read_byte() };
-                    already_read += 1;
-                    if already_read == want_to_read {
-                        return want_to_read;
+        for slice in buf.buffers {
+            let mut filled = 0usize;
+            while filled < slice.len() {
+                let mut ring_buffer = self.buffer.lock();
+                if ring_buffer.is_empty() {
+                    // fail to fetch content from the pipe.
+                    // first check whether all write ends closed.
+                    // if so, then there will not be any content to read.
+                    if ring_buffer.all_write_ends_closed() {
+                        return Ok(already_read);
                     }
-                } else {
-                    // no more buffer to be filled
-                    return already_read;
+                    // if not, then wait until there's content to fetch.
+                    // drop the lock to avoid dead lock.
+                    let read_wait = ring_buffer.read_wait.clone();
+                    let ticket = read_wait.prepare_to_wait();
+                    drop(ring_buffer);
+                    // a signal ends the read, which fails if nothing is read yet
+                    if current_interrupted() {
+                        return interrupted(already_read);
+                    }
+                    read_wait.wait_interruptible(ticket);
+                    continue;
                 }
+                let n = ring_buffer.read(&mut slice[filled..]);
+                // space is freed for the writers
+                ring_buffer.write_wait.wake_all();
+                filled += n;
+                already_read += n;
             }
         }
+        Ok(already_read)
     }
-    fn write(&self, buf: UserBuffer) -> usize {
+    fn write(&self, buf: UserBuffer) -> SysResult<usize> {
         assert!(self.writable());
-        let want_to_write = buf.len();
-        let mut buf_iter = buf.into_iter();
         let mut already_write = 0usize;
-        loop {
-            let mut ring_buffer = self.buffer.lock();
-            let loop_write = ring_buffer.available_write_bytes();
-            if loop_write == 0 {
-                // if there's no space to write, then wait
-                drop(ring_buffer);
-                suspend_current_and_run_next();
-                continue;
-            }
-            // write at most loop_write bytes
-            for _ in 0..loop_write {
-                if let Some(byte_ref) = buf_iter.next() {
-                    ring_buffer.write_byte(unsafe { *byte_ref });
-                    already_write += 1;
-                    if already_write == want_to_write {
-                        return want_to_write;
+        for slice in buf.buffers {
+            let mut sent = 0usize;
+            while sent < slice.len() {
+                let mut ring_buffer = self.buffer.lock();
+                if ring_buffer.is_full() {
+                    // if there's no space to write, then wait
+                    let write_wait = ring_buffer.write_wait.clone();
+                    let ticket = write_wait.prepare_to_wait();
+                    drop(ring_buffer);
+                    if current_interrupted() {
+                        return interrupted(already_write);
                     }
-                } else {
-                    return already_write;
+                    write_wait.wait_interruptible(ticket);
+                    continue;
                 }
+                let n = ring_buffer.write(&slice[sent..]);
+                // content is coming for the readers
+                ring_buffer.read_wait.wake_all();
+                sent += n;
+                already_write += n;
             }
         }
+        Ok(already_write)
+    }
+    fn pipe(&self) -> Option<&Pipe> {
+        Some(self)
+    }
+    fn blocking(&self) -> bool {
+        true
     }
 }
 
-#[derive(Copy, Clone, PartialEq, Debug)]
-enum RingBufferStatus {
-    FULL,
-    EMPTY,
-    NORMAL,
+impl Drop for Pipe {
+    fn drop(&mut self) {
+        if self.writable {
+            // readers blocked on an empty pipe should see the end of file
+            self.buffer.lock().read_wait.wake_all();
+        }
+    }
 }
 
 /// Actual body of Pipe.
 #[derive(Debug)]
 pub struct PipeRingBuffer {
-    arr: [u8; RING_BUFFER_SIZE],
+    arr: Vec<u8>,
+    /// Index of the first byte to read
     head: usize,
-    tail: usize,
-    status: RingBufferStatus,
+    /// Number of bytes in the buffer
+    len: usize,
     // Weak reference to avoid memory leak.
     write_end: Option<Weak<Pipe>>,
+    /// Readers waiting for content
+    read_wait: Arc<WaitQueue>,
+    /// Writers waiting for free space
+    write_wait: Arc<WaitQueue>,
 }
 
 impl PipeRingBuffer {
     pub fn new() -> Self {
         Self {
-            arr: [0; RING_BUFFER_SIZE],
+            arr: vec![0; PIPE_DEFAULT_SIZE],
             head: 0,
-            tail: 0,
-            status: RingBufferStatus::EMPTY,
+            len: 0,
             write_end: None,
+            read_wait: Arc::new(WaitQueue::new()),
+            write_wait: Arc::new(WaitQueue::new()),
         }
     }
     /// Bind the write end to a `Pipe` struct
@@ -142,40 +194,48 @@ impl PipeRingBuffer {
         // downgrade to weak reference to avoid cyclic reference
         self.write_end = Some(Arc::downgrade(write_end));
     }
-    pub fn read_byte(&mut self) -> u8 {
-        self.status = RingBufferStatus::NORMAL;
-        let c = self.arr[self.head];
-        self.head = (self.head + 1) % RING_BUFFER_SIZE;
-        if self.head == self.tail {
-            self.status = RingBufferStatus::EMPTY;
-        }
-        c
-    }
-    pub fn write_byte(&mut self, byte: u8) {
-        self.status = RingBufferStatus::NORMAL;
-        self.arr[self.tail] = byte;
-        self.tail = (self.tail + 1) % RING_BUFFER_SIZE;
-        if self.tail == self.head {
-            self.status = RingBufferStatus::FULL;
-        }
+    pub fn capacity(&self) -> usize {
+        self.arr.len()
     }
-    pub fn available_read_bytes(&self) -> usize {
-        if self.status == RingBufferStatus::EMPTY {
-            0
-        } else {
-            if self.tail > self.head {
-                self.tail - self.head
-            } else {
-                self.tail + RING_BUFFER_SIZE - self.head
-            }
-        }
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+    pub fn is_full(&self) -> bool {
+        self.len == self.capacity()
+    }
+    /// Move as many bytes as possible into `buf`, return the number of bytes moved.
+    pub fn read(&mut self, buf: &mut [u8]) -> usize {
+        let n = buf.len().min(self.len);
+        // the content may wrap around the end of `arr`
+        let first = n.min(self.capacity() - self.head);
+        buf[..first].copy_from_slice(&self.arr[self.head..self.head + first]);
+        buf[first..n].copy_from_slice(&self.arr[..n - first]);
+        self.head = (self.head + n) % self.capacity();
+        self.len -= n;
+        n
+    }
+    /// Move as many bytes as possible from `buf`, return the number of bytes moved.
+    pub fn write(&mut self, buf: &[u8]) -> usize {
+        let n = buf.len().min(self.capacity() - self.len);
+        let tail = (self.head + self.len) % self.capacity();
+        // the free space may wrap around the end of `arr`
+        let first = n.min(self.capacity() - tail);
+        self.arr[tail..tail + first].copy_from_slice(&buf[..first]);
+        self.arr[..n - first].copy_from_slice(&buf[first..n]);
+        self.len += n;
+        n
     }
-    pub fn available_write_bytes(&self) -> usize {
-        if self.status == RingBufferStatus::FULL {
-            0
-        } else {
-            RING_BUFFER_SIZE - self.available_read_bytes()
+    /// Change the capacity to `size`, fail if the content would not fit.
+    pub fn resize(&mut self, size: usize) -> bool {
+        if size < self.len {
+            return false;
         }
+        let mut arr = vec![0; size];
+        let len = self.read(&mut arr);
+        self.arr = arr;
+        self.head = 0;
+        self.len = len;
+        true
     }
     pub fn all_write_ends_closed(&self) -> bool {
         self.write_end.as_ref().unwrap().upgrade().is_none()
@@ -1,7 +1,102 @@
+use alloc::collections::VecDeque;
+use alloc::string::String;
+use alloc::vec::Vec;
+use lazy_static::*;
+
 use super::File;
+use crate::errno::{Errno, SysResult};
 use crate::mm::UserBuffer;
 use crate::sbi::console_getchar;
-use crate::task::suspend_current_and_run_next;
+use crate::sync::SpinLock;
+use crate::task::{
+    current_interrupted, current_task, process_group, suspend_current_and_run_next, SignalFlags,
+    SignalInfo, SIG_IGN,
+};
+
+/// Typed at the console to send `SIGINT` to the foreground process group
+const CTRL_C: u8 = 0x03;
+/// Typed at the console to send `SIGTSTP` to the foreground process group
+const CTRL_Z: u8 = 0x1a;
+
+/// The console, which is the controlling terminal of every session
+struct Console {
+    /// Typed characters not read yet
+    input: VecDeque<u8>,
+    /// The process group which may read the console and gets the signals
+    /// typed at it
+    foreground: usize,
+}
+
+lazy_static! {
+    static ref CONSOLE: SpinLock<Console> = SpinLock::new(Console {
+        input: VecDeque::new(),
+        // the group of initproc, the first process
+        foreground: 0,
+    });
+}
+
+/// Move the characters typed at the console into its input. ^C and ^Z are
+/// not input but send `SIGINT` and `SIGTSTP` to the foreground process group.
+pub fn poll_console() {
+    let mut console = CONSOLE.exclusive_access();
+    let mut signals = Vec::new();
+    loop {
+        // depending on the SBI implementation, 0 or -1 if nothing is typed
+        let c = console_getchar();
+        if c == 0 || c == usize::MAX {
+            break;
+        }
+        match c as u8 {
+            CTRL_C => signals.push((SignalFlags::SIGINT, "^C")),
+            CTRL_Z => signals.push((SignalFlags::SIGTSTP, "^Z")),
+            ch => console.input.push_back(ch),
+        }
+    }
+    let foreground = console.foreground;
+    drop(console);
+    for (signal, echo) in signals {
+        kprint!("{}", echo);
+        for process in process_group(foreground) {
+            process.send_signal(SignalInfo::kernel(signal));
+        }
+    }
+}
+
+/// The foreground process group of the console
+pub fn console_foreground() -> usize {
+    CONSOLE.exclusive_access().foreground
+}
+
+/// Make `pgid` the foreground process group of the console
+pub fn set_console_foreground(pgid: usize) {
+    CONSOLE.exclusive_access().foreground = pgid;
+}
+
+/// Stop the process group of the current process by `SIGTTIN` if it is not
+/// in the foreground, so that it may read the console once it is continued
+/// there. Fail with `EIO` instead if the signal is blocked or ignored.
+fn check_foreground() -> SysResult<()> {
+    let task = current_task().unwrap();
+    let sig = SignalFlags::SIGTTIN.bits().trailing_zeros() as usize;
+    let blocked = task
+        .inner_exclusive_access()
+        .signal_mask
+        .contains(SignalFlags::SIGTTIN);
+    let process = task.process();
+    let inner = process.inner_exclusive_access();
+    let pgid = inner.pgid;
+    if pgid == console_foreground() {
+        return Ok(());
+    }
+    if blocked || inner.signal_actions.table[sig].handler == SIG_IGN {
+        return Err(Errno::EIO);
+    }
+    drop(inner);
+    for process in process_group(pgid) {
+        process.send_signal(SignalInfo::kernel(SignalFlags::SIGTTIN));
+    }
+    Err(Errno::EINTR)
+}
 
 /// Standard input
 #[derive(Debug)]
@@ -20,27 +115,35 @@ impl File for Stdin {
     fn writable(&self) -> bool {
         false
     }
-    fn read(&self, mut buf: UserBuffer) -> usize {
-        assert_eq!(buf.len(), 1);
+    fn read(&self, mut buf: UserBuffer) -> SysResult<usize> {
+        if buf.len() == 0 {
+            return Ok(0);
+        }
         // busy loop
-        let mut c: usize;
-        loop {
-            c = console_getchar();
-            if c == 0 {
-                suspend_current_and_run_next();
-                continue;
-            } else {
-                break;
+        let ch = loop {
+            check_foreground()?;
+            poll_console();
+            if let Some(ch) = CONSOLE.exclusive_access().input.pop_front() {
+                break ch;
             }
-        }
-        let ch = c as u8;
+            if current_interrupted() {
+                return Err(Errno::EINTR);
+            }
+            suspend_current_and_run_next();
+        };
         unsafe {
             buf.buffers[0].as_mut_ptr().write_volatile(ch);
         }
-        1
+        Ok(1)
     }
-    fn write(&self, _buf: UserBuffer) -> usize {
-        panic!("Cannot write to stdin!"); // FIXME: better error handling
+    fn write(&self, _buf: UserBuffer) -> SysResult<usize> {
+        Err(Errno::EBADF)
+    }
+    fn is_tty(&self) -> bool {
+        true
+    }
+    fn blocking(&self) -> bool {
+        true
     }
 }
 
@@ -51,14 +154,17 @@ impl File for Stdout {
     fn writable(&self) -> bool {
         true
     }
-    fn read(&self, _buf: UserBuffer) -> usize {
-        panic!("Cannot read from stdout!"); // FIXME: better error handling
+    fn read(&self, _buf: UserBuffer) -> SysResult<usize> {
+        Err(Errno::EBADF)
     }
-    fn write(&self, buf: UserBuffer) -> usize {
+    fn write(&self, buf: UserBuffer) -> SysResult<usize> {
         for buffer in buf.buffers.iter() {
-            kprint!("{}", core::str::from_utf8(*buffer).unwrap());
+            kprint!("{}", String::from_utf8_lossy(buffer));
         }
-        buf.len()
+        Ok(buf.len())
+    }
+    fn is_tty(&self) -> bool {
+        true
     }
 }
 
@@ -69,13 +175,16 @@ impl File for Stderr {
     fn writable(&self) -> bool {
         true
     }
-    fn read(&self, _buf: UserBuffer) -> usize {
-        panic!("Cannot read from stderr!"); // FIXME: better error handling
+    fn read(&self, _buf: UserBuffer) -> SysResult<usize> {
+        Err(Errno::EBADF)
     }
-    fn write(&self, buf: UserBuffer) -> usize {
+    fn write(&self, buf: UserBuffer) -> SysResult<usize> {
         for buffer in buf.buffers.iter() {
-            kprint!("{}", core::str::from_utf8(*buffer).unwrap());
+            kprint!("{}", String::from_utf8_lossy(buffer));
         }
-        buf.len()
+        Ok(buf.len())
+    }
+    fn is_tty(&self) -> bool {
+        true
     }
 }
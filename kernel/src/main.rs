@@ -23,6 +23,7 @@ mod console;
 #[macro_use]
 mod debug;
 mod drivers;
+mod errno;
 mod fs;
 mod loader;
 mod logging;
@@ -49,9 +50,9 @@ pub fn clear_bss() {
     })
 }
 
-/// rust entry-point
+/// rust entry-point of the boot hart
 #[no_mangle]
-pub fn rust_main() -> ! {
+pub fn rust_main(hart_id: usize) -> ! {
     clear_bss();
     kprintln!("[kernel] Hello, world!");
     mm::init();
@@ -61,6 +62,33 @@ pub fn rust_main() -> ! {
     timer::set_next_trigger();
     fs::list_apps();
     task::add_initproc();
+    start_other_harts(hart_id);
     task::run_tasks();
     panic!("Unreachable in rust_main!");
 }
+
+/// Start all the other harts with SBI HSM, they enter [`rust_main_secondary`]
+fn start_other_harts(boot_hart_id: usize) {
+    extern "C" {
+        fn _start_secondary();
+    }
+    assert!(boot_hart_id < board::MAX_HARTS);
+    for hart_id in (0..board::MAX_HARTS).filter(|&id| id != boot_hart_id) {
+        // harts which do not exist fail to start
+        if sbi::hart_start(hart_id, _start_secondary as usize) {
+            kprintln!("[kernel] Starting hart {}", hart_id);
+        }
+    }
+}
+
+/// rust entry-point of the other harts, the kernel has been initialized by the boot hart
+#[no_mangle]
+pub fn rust_main_secondary(hart_id: usize) -> ! {
+    mm::init_secondary();
+    trap::init();
+    trap::enable_timer_interrupt();
+    timer::set_next_trigger();
+    kprintln!("[kernel] Hart {} is running", hart_id);
+    task::run_tasks();
+    panic!("Unreachable in rust_main_secondary!");
+}
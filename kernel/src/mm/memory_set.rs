@@ -1,13 +1,20 @@
 use core::arch::asm;
 
 use alloc::{collections::btree_map::BTreeMap, sync::Arc, vec::Vec};
+use easy_fs::Inode;
 use lazy_static::lazy_static;
 use riscv::register::satp;
 
 use crate::{
     board::{MEMORY_END, MMIO},
-    config::{PAGE_SIZE, TRAMPOLINE, TRAP_CONTEXT, USER_STACK_SIZE},
-    sync::UPSafeCell,
+    config::{
+        MMAP_BASE, PAGE_SIZE, SIGRETURN_TRAMPOLINE, TRAMPOLINE, TRAP_CONTEXT_BASE, USER_SPACE_TOP,
+        USER_STACK_SIZE,
+    },
+    errno::{Errno, SysResult},
+    sbi::remote_sfence_vma_all,
+    sync::SpinLock,
+    trap::sigreturn_trampoline,
 };
 
 use super::{
@@ -28,8 +35,8 @@ extern "C" {
     fn strampoline();
 }
 lazy_static! {
-    pub static ref KERNEL_SPACE: Arc<UPSafeCell<MemorySet>> =
-        Arc::new(unsafe { UPSafeCell::new(MemorySet::new_kernel()) });
+    pub static ref KERNEL_SPACE: Arc<SpinLock<MemorySet>> =
+        Arc::new(SpinLock::new(MemorySet::new_kernel()));
 }
 
 /// Get kernel space root ppn
@@ -37,6 +44,12 @@ pub fn kernel_token() -> usize {
     KERNEL_SPACE.exclusive_access().token()
 }
 
+/// Drop stale translations on every hart.
+/// Threads of a process may run on other harts while its memory set changes.
+pub fn flush_tlb() {
+    remote_sfence_vma_all();
+}
+
 /// Memory set structure, controls virtual-memory space.
 /// `page_table`: how address is mapped in this process.
 /// `areas`: memory areas for this process, e.g. code area, data area, etc.
@@ -70,6 +83,12 @@ impl MemorySet {
             None, // we do not need any initial data
         );
     }
+    /// Whether none of the pages in `[start, end)` is mapped
+    pub fn is_unmapped(&self, start: VirtPageNum, end: VirtPageNum) -> bool {
+        !self.areas.iter().any(|area| {
+            area.vpn_range.get_start() < end && start < area.vpn_range.get_end()
+        })
+    }
     /// Remove `MapArea` that starts with `start_vpn`
     pub fn remove_area_with_start_vpn(&mut self, start_vpn: VirtPageNum) {
         if let Some((idx, area)) = self
@@ -179,24 +198,32 @@ impl MemorySet {
         }
         memory_set
     }
-    pub fn from_elf(elf_data: &[u8]) -> (Self, usize, usize) {
+    /// Return (memory set, user stack top, entry point), or `None` if `elf_data` is not a valid ELF.
+    pub fn from_elf(elf_data: &[u8]) -> Option<(Self, usize, usize)> {
         // Create a new memory set for the application
         let mut memory_set = Self::new_bare();
         // map trampoline
         memory_set.map_trampoline();
         // map program headers of elf, with U flag
-        let elf = xmas_elf::ElfFile::new(elf_data).unwrap();
+        let elf = xmas_elf::ElfFile::new(elf_data).ok()?;
         let elf_header = elf.header;
         let magic = elf_header.pt1.magic;
-        assert_eq!(magic, [0x7f, 0x45, 0x4c, 0x46], "invalid elf!");
+        if magic != [0x7f, 0x45, 0x4c, 0x46] {
+            return None;
+        }
         let ph_count = elf_header.pt2.ph_count();
         let mut max_end_vpn = VirtPageNum(0);
         for i in 0..ph_count {
-            let ph = elf.program_header(i).unwrap();
+            let ph = elf.program_header(i).ok()?;
             // Loadable section
-            if ph.get_type().unwrap() == xmas_elf::program::Type::Load {
+            if ph.get_type().ok()? == xmas_elf::program::Type::Load {
+                let end = ph.virtual_addr().checked_add(ph.mem_size())?;
+                // segments must stay below the area of mmap
+                if end > MMAP_BASE as u64 || ph.file_size() > ph.mem_size() {
+                    return None;
+                }
                 let start_va: VirtAddr = (ph.virtual_addr() as usize).into();
-                let end_va: VirtAddr = ((ph.virtual_addr() + ph.mem_size()) as usize).into();
+                let end_va: VirtAddr = (end as usize).into();
                 let mut map_perm = MapPermission::U; // User mode accessible
                 let ph_flags = ph.flags();
                 if ph_flags.is_read() {
@@ -208,16 +235,22 @@ impl MemorySet {
                 if ph_flags.is_execute() {
                     map_perm |= MapPermission::X;
                 }
-                // create a map area for this section
-                let map_area = MapArea::new(start_va, end_va, MapType::Framed, map_perm);
+                // create a map area for this section, loaded page by page on first access
+                let data = elf
+                    .input
+                    .get(ph.offset() as usize..ph.offset().checked_add(ph.file_size())? as usize)?;
+                let map_area = MapArea::new_lazy(
+                    start_va,
+                    end_va,
+                    map_perm,
+                    MapBacking::Data {
+                        data: Arc::from(data),
+                        offset: start_va.page_offset() as isize,
+                    },
+                );
                 // accumulate end VPN of this application
                 max_end_vpn = map_area.vpn_range.get_end();
-                // copy data into memory directly.
-                // actually we need implement lazy load later.
-                memory_set.push(
-                    map_area,
-                    Some(&elf.input[ph.offset() as usize..(ph.offset() + ph.file_size()) as usize]),
-                )
+                memory_set.push(map_area, None)
             }
         }
         // map user stack with U flags
@@ -237,21 +270,41 @@ impl MemorySet {
             ),
             None,
         );
-        // map TrapContext
+        // empty heap right above the user stack, grown by `sbrk`
+        memory_set.push(
+            MapArea::new_lazy(
+                user_stack_top.into(),
+                user_stack_top.into(),
+                MapPermission::R | MapPermission::W | MapPermission::U,
+                MapBacking::Zero,
+            ),
+            None,
+        );
+        // map the code signal handlers return to
+        memory_set.push(
+            MapArea::new(
+                SIGRETURN_TRAMPOLINE.into(),
+                (SIGRETURN_TRAMPOLINE + PAGE_SIZE).into(),
+                MapType::Framed,
+                MapPermission::R | MapPermission::X | MapPermission::U,
+            ),
+            Some(sigreturn_trampoline()),
+        );
+        // map TrapContext of the main thread
         memory_set.push(
             MapArea::new(
-                TRAP_CONTEXT.into(),
+                TRAP_CONTEXT_BASE.into(),
                 TRAMPOLINE.into(),
                 MapType::Framed,
                 MapPermission::R | MapPermission::W,
             ),
             None,
         );
-        (
+        Some((
             memory_set,
             user_stack_top,
             elf.header.pt2.entry_point() as usize,
-        )
+        ))
     }
     pub fn activate(&self) {
         let satp = self.page_table.token();
@@ -271,6 +324,7 @@ impl MemorySet {
             .find(|area| area.vpn_range.get_start() == start_va.floor())
         {
             area.shrink_to(&mut self.page_table, new_end.ceil());
+            flush_tlb();
             true
         } else {
             false
@@ -288,29 +342,178 @@ impl MemorySet {
             false
         }
     }
-    pub fn from_existed_user(user_space: &MemorySet) -> MemorySet {
+    /// Copy `user_space` for a forked child.
+    /// Frames of user areas are shared rather than copied, and writable ones
+    /// of private areas become copy-on-write in both spaces. Trap contexts
+    /// are left out, the caller maps one for the thread of the child.
+    pub fn from_existed_user(user_space: &mut MemorySet) -> MemorySet {
         let mut memory_set = Self::new_bare();
         // map trampoline
         memory_set.map_trampoline();
-        // copy data sections / trap_context / user_stack
-        for area in user_space.areas.iter() {
-            let new_area = MapArea::from_another(area);
-            memory_set.push(new_area, None);
-            // copy data from another space
-            // FIXME: lazy mapping
-            for vpn in area.vpn_range {
-                let src_ppn = user_space.translate(vpn).unwrap().ppn();
-                let dst_ppn = memory_set.translate(vpn).unwrap().ppn();
-                // when kernel code running, all kernel virtual addresses are translated
-                // into identical physical addresses.
-                // so just access corresponding virtual address.
-                dst_ppn
-                    .get_bytes_array()
-                    .copy_from_slice(&src_ppn.get_bytes_array());
+        // trap contexts live above user space and are mapped per thread by the caller,
+        // every area below it is copied, whatever its permissions
+        let user_space_top: VirtPageNum = VirtAddr::from(USER_SPACE_TOP).floor();
+        for area in user_space
+            .areas
+            .iter_mut()
+            .filter(|area| area.vpn_range.get_start() < user_space_top)
+        {
+            let mut new_area = MapArea::from_another(area);
+            // share data sections / user stack / heap / mmap areas.
+            // pages of private areas not touched yet stay lazy, while shared
+            // areas are populated so that both spaces see the same frames.
+            if area.shared {
+                area.populate(&mut user_space.page_table);
             }
+            let mut flags = area.pte_flags();
+            if !area.shared {
+                flags -= PTEFlags::W;
+            }
+            for (&vpn, frame) in area.data_frames.iter() {
+                user_space.page_table.remap(vpn, frame.ppn, flags);
+                memory_set.page_table.map(vpn, frame.ppn, flags);
+                new_area.data_frames.insert(vpn, frame.clone());
+            }
+            memory_set.areas.push(new_area);
         }
+        // writable pages of the parent have just become read-only
+        flush_tlb();
         memory_set
     }
+    /// Resolve a page fault of an `access` (one of R, W and X) at `vpn`: load a
+    /// lazily mapped page, or on a write copy a page that is still shared with
+    /// another space.
+    /// Fail with `EFAULT` if the access is not allowed at all, or with `ENOMEM`
+    /// if there is no frame left for the page.
+    pub fn handle_page_fault(&mut self, vpn: VirtPageNum, access: MapPermission) -> SysResult<()> {
+        let Some(area) = self
+            .areas
+            .iter_mut()
+            .find(|area| area.vpn_range.get_start() <= vpn && vpn < area.vpn_range.get_end())
+        else {
+            return Err(Errno::EFAULT);
+        };
+        if area.map_type == MapType::Identical || !area.map_perm.contains(MapPermission::U | access)
+        {
+            return Err(Errno::EFAULT);
+        }
+        match self.page_table.translate(vpn) {
+            Some(pte) if pte.is_valid() => {
+                // otherwise another thread resolved the fault first, or the
+                // TLB of this hart still held the entry from before
+                if access.contains(MapPermission::W) && !pte.writable() {
+                    area.copy_on_write(&mut self.page_table, vpn)?;
+                }
+            }
+            _ if area.map_type == MapType::Lazy => area.map_one(&mut self.page_table, vpn)?,
+            _ => return Err(Errno::EFAULT),
+        }
+        unsafe { asm!("sfence.vma {}", in(reg) usize::from(VirtAddr::from(vpn))) };
+        Ok(())
+    }
+    /// Map a new area of `len` bytes with `perm` and `backing` for `mmap`,
+    /// at `start` replacing whatever is mapped there, or at a free range when
+    /// `start` is `None`. Return the start address of the new area.
+    pub fn mmap(
+        &mut self,
+        start: Option<VirtAddr>,
+        len: usize,
+        perm: MapPermission,
+        backing: MapBacking,
+        shared: bool,
+    ) -> Option<VirtAddr> {
+        let pages = len.div_ceil(PAGE_SIZE);
+        let start_vpn = match start {
+            Some(start) => {
+                let start_vpn = start.floor();
+                let end_vpn = VirtPageNum(start_vpn.0 + pages);
+                if end_vpn > VirtAddr::from(USER_SPACE_TOP).floor() {
+                    return None;
+                }
+                self.munmap(start_vpn, end_vpn);
+                start_vpn
+            }
+            None => self.find_free_range(pages)?,
+        };
+        let end_vpn = VirtPageNum(start_vpn.0 + pages);
+        let mut area = MapArea::new_lazy(start_vpn.into(), end_vpn.into(), perm, backing);
+        area.shared = shared;
+        self.push(area, None);
+        Some(start_vpn.into())
+    }
+    /// Lowest range of `pages` pages above [`MMAP_BASE`] that is not mapped yet
+    fn find_free_range(&self, pages: usize) -> Option<VirtPageNum> {
+        let mut start = VirtAddr::from(MMAP_BASE).floor().0;
+        loop {
+            let end = start + pages;
+            if end > VirtAddr::from(USER_SPACE_TOP).floor().0 {
+                return None;
+            }
+            match self.areas.iter().find(|area| {
+                area.vpn_range.get_start().0 < end && start < area.vpn_range.get_end().0
+            }) {
+                Some(area) => start = area.vpn_range.get_end().0,
+                None => return Some(VirtPageNum(start)),
+            }
+        }
+    }
+    /// Split areas so that `start` and `end` fall on area boundaries.
+    /// Return the indices of the areas inside `[start, end)`.
+    fn split_areas(&mut self, start: VirtPageNum, end: VirtPageNum) -> Vec<usize> {
+        let mut i = 0;
+        while i < self.areas.len() {
+            let area = &mut self.areas[i];
+            let (area_start, area_end) = (area.vpn_range.get_start(), area.vpn_range.get_end());
+            if area_start < start && start < area_end {
+                // the tail is checked against `end` later on
+                let tail = area.split_off(start);
+                self.areas.push(tail);
+            } else if area_start < end && end < area_end {
+                let tail = area.split_off(end);
+                self.areas.push(tail);
+            }
+            i += 1;
+        }
+        (0..self.areas.len())
+            .filter(|&i| {
+                let range = self.areas[i].vpn_range;
+                start <= range.get_start()
+                    && range.get_end() <= end
+                    && range.get_start() < range.get_end()
+            })
+            .collect()
+    }
+    /// Unmap the pages in `[start, end)`, which may cover several areas or
+    /// only parts of them
+    pub fn munmap(&mut self, start: VirtPageNum, end: VirtPageNum) {
+        let mut indices = self.split_areas(start, end);
+        indices.sort_unstable();
+        for idx in indices.into_iter().rev() {
+            let mut area = self.areas.remove(idx);
+            area.unmap(&mut self.page_table);
+        }
+        flush_tlb();
+    }
+    /// Change the permission of the pages in `[start, end)` to `perm`.
+    /// Return `false` if part of the range is not mapped.
+    pub fn mprotect(&mut self, start: VirtPageNum, end: VirtPageNum, perm: MapPermission) -> bool {
+        let indices = self.split_areas(start, end);
+        let mapped_pages: usize = indices
+            .iter()
+            .map(|&i| {
+                let range = self.areas[i].vpn_range;
+                range.get_end().0 - range.get_start().0
+            })
+            .sum();
+        if mapped_pages != end.0 - start.0 {
+            return false;
+        }
+        for idx in indices {
+            self.areas[idx].set_perm(&mut self.page_table, perm);
+        }
+        flush_tlb();
+        true
+    }
     pub fn recycle_data_pages(&mut self) {
         self.areas.clear()
     }
@@ -319,9 +522,14 @@ impl MemorySet {
 #[derive(Debug)]
 pub struct MapArea {
     vpn_range: VPNRange,
-    data_frames: BTreeMap<VirtPageNum, FrameTracker>,
+    /// Frames shared with other spaces after `fork` are copied on write
+    data_frames: BTreeMap<VirtPageNum, Arc<FrameTracker>>,
     map_type: MapType,
     map_perm: MapPermission,
+    backing: MapBacking,
+    /// Shared areas keep sharing frames after `fork` instead of copying on write,
+    /// and write file pages back when they are unmapped
+    shared: bool,
 }
 
 impl MapArea {
@@ -338,28 +546,135 @@ impl MapArea {
             data_frames: BTreeMap::new(),
             map_type,
             map_perm,
+            backing: MapBacking::Zero,
+            shared: false,
         }
     }
-    /// Map one virtual page into page table
-    fn map_one(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) {
+    /// Create an area whose pages are only allocated and filled from
+    /// `backing` when they are first accessed
+    pub fn new_lazy(
+        start_va: VirtAddr,
+        end_va: VirtAddr,
+        map_perm: MapPermission,
+        backing: MapBacking,
+    ) -> Self {
+        let mut area = Self::new(start_va, end_va, MapType::Lazy, map_perm);
+        area.backing = backing;
+        area
+    }
+    /// Map one virtual page into page table.
+    /// Fail with `ENOMEM` if there is no frame left for it.
+    fn map_one(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) -> SysResult<()> {
         let ppn: PhysPageNum;
         match self.map_type {
             MapType::Identical => {
                 // do not allocate frame
                 ppn = PhysPageNum(vpn.0);
             }
-            MapType::Framed => {
+            MapType::Framed | MapType::Lazy => {
                 // allocate a new frame from global frame allocator
-                let frame = frame_alloc().unwrap();
+                let frame = frame_alloc().ok_or(Errno::ENOMEM)?;
+                self.fill_page(vpn, frame.ppn.get_bytes_array());
                 ppn = frame.ppn;
                 // hold this frame.
-                self.data_frames.insert(vpn, frame);
+                self.data_frames.insert(vpn, Arc::new(frame));
             }
         }
-        // all pages' permission bits are same in an area.
-        let pte_flags = PTEFlags::from_bits(self.map_perm.bits()).unwrap();
         // map this virtual page in page table
-        page_table.map(vpn, ppn, pte_flags);
+        if !page_table.try_map(vpn, ppn, self.pte_flags()) {
+            self.data_frames.remove(&vpn);
+            return Err(Errno::ENOMEM);
+        }
+        Ok(())
+    }
+    /// Initialize the zeroed page at `vpn` from the backing of this area.
+    fn fill_page(&self, vpn: VirtPageNum, page: &mut [u8]) {
+        let page_idx = vpn.0 - self.vpn_range.get_start().0;
+        match &self.backing {
+            MapBacking::Zero => {}
+            MapBacking::Data { data, offset } => {
+                // offsets from the start of the first page of this area
+                let page_start = (page_idx * PAGE_SIZE) as isize;
+                let begin = page_start.max(*offset);
+                let end = (page_start + PAGE_SIZE as isize).min(offset + data.len() as isize);
+                if begin < end {
+                    page[(begin - page_start) as usize..(end - page_start) as usize]
+                        .copy_from_slice(&data[(begin - offset) as usize..(end - offset) as usize]);
+                }
+            }
+            MapBacking::File { inode, offset } => {
+                // reading past the end of file leaves the rest zeroed
+                inode.read_at(offset + page_idx * PAGE_SIZE, page);
+            }
+        }
+    }
+    /// Write the page at `vpn` of a shared file mapping back to the file,
+    /// without growing the file.
+    fn write_back_page(&self, vpn: VirtPageNum, frame: &FrameTracker) {
+        if let (true, MapBacking::File { inode, offset }) = (self.shared, &self.backing) {
+            let pos = offset + (vpn.0 - self.vpn_range.get_start().0) * PAGE_SIZE;
+            let file_size = inode.size();
+            if pos < file_size {
+                let len = PAGE_SIZE.min(file_size - pos);
+                inode.write_at(pos, &frame.ppn.get_bytes_array()[..len]);
+            }
+        }
+    }
+    /// Map the pages of a lazy area that have not been touched yet
+    fn populate(&mut self, page_table: &mut PageTable) {
+        for vpn in self.vpn_range {
+            if !self.data_frames.contains_key(&vpn) {
+                self.map_one(page_table, vpn).unwrap();
+            }
+        }
+    }
+    /// Split this area at `at`, keeping `[start, at)` and returning `[at, end)`
+    fn split_off(&mut self, at: VirtPageNum) -> MapArea {
+        let pages = at.0 - self.vpn_range.get_start().0;
+        let tail = MapArea {
+            vpn_range: VPNRange::new(at, self.vpn_range.get_end()),
+            data_frames: self.data_frames.split_off(&at),
+            map_type: self.map_type,
+            map_perm: self.map_perm,
+            backing: self.backing.advance(pages),
+            shared: self.shared,
+        };
+        self.vpn_range = VPNRange::new(self.vpn_range.get_start(), at);
+        tail
+    }
+    /// Change the permission of the whole area
+    fn set_perm(&mut self, page_table: &mut PageTable, perm: MapPermission) {
+        self.map_perm = perm;
+        let pte_flags = self.pte_flags();
+        for (&vpn, frame) in self.data_frames.iter() {
+            // pages still shared copy-on-write stay read-only
+            if !self.shared && Arc::strong_count(frame) > 1 {
+                page_table.remap(vpn, frame.ppn, pte_flags - PTEFlags::W);
+            } else {
+                page_table.remap(vpn, frame.ppn, pte_flags);
+            }
+        }
+    }
+    /// All pages' permission bits are same in an area.
+    fn pte_flags(&self) -> PTEFlags {
+        PTEFlags::from_bits(self.map_perm.bits()).unwrap()
+    }
+    /// Give this area a private, writable copy of the page at `vpn`.
+    /// Fail with `ENOMEM` if there is no frame left for the copy.
+    fn copy_on_write(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) -> SysResult<()> {
+        let pte_flags = self.pte_flags();
+        let frame = self.data_frames.get_mut(&vpn).unwrap();
+        // the last owner can simply take the page over
+        if Arc::strong_count(frame) > 1 {
+            let new_frame = frame_alloc().ok_or(Errno::ENOMEM)?;
+            new_frame
+                .ppn
+                .get_bytes_array()
+                .copy_from_slice(frame.ppn.get_bytes_array());
+            *frame = Arc::new(new_frame);
+        }
+        page_table.remap(vpn, frame.ppn, pte_flags);
+        Ok(())
     }
     /// Unmap one virtual page from page table
     fn unmap_one(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) {
@@ -367,15 +682,23 @@ impl MapArea {
             MapType::Framed => {
                 self.data_frames.remove(&vpn);
             }
+            MapType::Lazy => match self.data_frames.remove(&vpn) {
+                Some(frame) => self.write_back_page(vpn, &frame),
+                // never touched, so never mapped
+                None => return,
+            },
             _ => { /* do nothing */ }
         }
         // unmap this virtual page in page table
         page_table.unmap(vpn);
     }
-    /// Map every page in this area.
+    /// Map every page in this area. Lazy areas are mapped on page faults instead.
     pub fn map(&mut self, page_table: &mut PageTable) {
+        if self.map_type == MapType::Lazy {
+            return;
+        }
         for vpn in self.vpn_range {
-            self.map_one(page_table, vpn);
+            self.map_one(page_table, vpn).unwrap();
         }
     }
     #[allow(unused)]
@@ -394,8 +717,10 @@ impl MapArea {
     }
     /// Grow the area
     pub fn append_to(&mut self, page_table: &mut PageTable, new_end: VirtPageNum) {
-        for vpn in VPNRange::new(self.vpn_range.get_start(), new_end) {
-            self.map_one(page_table, vpn);
+        if self.map_type != MapType::Lazy {
+            for vpn in VPNRange::new(self.vpn_range.get_end(), new_end) {
+                self.map_one(page_table, vpn).unwrap();
+            }
         }
         self.vpn_range = VPNRange::new(self.vpn_range.get_start(), new_end);
     }
@@ -428,6 +753,16 @@ impl MapArea {
             data_frames: BTreeMap::new(),
             map_type: another.map_type,
             map_perm: another.map_perm,
+            backing: another.backing.clone(),
+            shared: another.shared,
+        }
+    }
+}
+
+impl Drop for MapArea {
+    fn drop(&mut self) {
+        for (&vpn, frame) in self.data_frames.iter() {
+            self.write_back_page(vpn, frame);
         }
     }
 }
@@ -436,6 +771,38 @@ impl MapArea {
 pub enum MapType {
     Identical,
     Framed,
+    /// Framed, but each frame is allocated on the first access to its page
+    Lazy,
+}
+
+/// Initial contents of the pages of a [`MapArea`]
+#[derive(Clone, Debug)]
+pub enum MapBacking {
+    /// Zero-filled pages
+    Zero,
+    /// `data` surrounded by zeros, starting `offset` bytes after the start of
+    /// the first page
+    Data { data: Arc<[u8]>, offset: isize },
+    /// Contents of `inode`, starting at byte `offset` of the file
+    File { inode: Arc<Inode>, offset: usize },
+}
+
+impl MapBacking {
+    /// Backing of the part of an area starting `pages` pages later
+    fn advance(&self, pages: usize) -> Self {
+        let bytes = pages * PAGE_SIZE;
+        match self {
+            Self::Zero => Self::Zero,
+            Self::Data { data, offset } => Self::Data {
+                data: data.clone(),
+                offset: offset - bytes as isize,
+            },
+            Self::File { inode, offset } => Self::File {
+                inode: inode.clone(),
+                offset: offset + bytes,
+            },
+        }
+    }
 }
 
 bitflags! {
@@ -449,6 +816,48 @@ bitflags! {
     }
 }
 
+bitflags! {
+    /// `prot` argument of `mmap` and `mprotect`
+    #[derive(Clone, Copy, Debug)]
+    pub struct MmapProt: u32 {
+        const READ = 1 << 0;
+        const WRITE = 1 << 1;
+        const EXEC = 1 << 2;
+    }
+}
+
+bitflags! {
+    /// `flags` argument of `mmap`
+    #[derive(Clone, Copy, Debug)]
+    pub struct MmapFlags: u32 {
+        const SHARED = 1 << 0;
+        const PRIVATE = 1 << 1;
+        const FIXED = 1 << 4;
+        const ANONYMOUS = 1 << 5;
+    }
+}
+
+impl From<MmapProt> for MapPermission {
+    fn from(prot: MmapProt) -> Self {
+        if prot.is_empty() {
+            // PROT_NONE: present to the kernel only
+            return Self::R;
+        }
+        let mut perm = Self::U;
+        // writable pages have to be readable as well on RISC-V
+        if prot.intersects(MmapProt::READ | MmapProt::WRITE) {
+            perm |= Self::R;
+        }
+        if prot.contains(MmapProt::WRITE) {
+            perm |= Self::W;
+        }
+        if prot.contains(MmapProt::EXEC) {
+            perm |= Self::X;
+        }
+        perm
+    }
+}
+
 #[allow(unused)]
 pub fn remap_test() {
     let mut kernel_space = KERNEL_SPACE.exclusive_access();
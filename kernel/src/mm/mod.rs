@@ -17,11 +17,12 @@ pub use address::{StepByOne, VPNRange};
 pub use frame_allocator::{frame_alloc, frame_dealloc, FrameTracker};
 #[allow(unused)]
 pub use heap_allocator::inspect_heap;
-pub use memory_set::{kernel_token, remap_test};
-pub use memory_set::{MapPermission, MemorySet, KERNEL_SPACE};
+pub use memory_set::{flush_tlb, kernel_token, remap_test};
+pub use memory_set::{MapBacking, MapPermission, MemorySet, MmapFlags, MmapProt, KERNEL_SPACE};
 pub use page_table::{
-    translated_byte_buffer, translated_ref, translated_refmut, translated_str, PageTableEntry,
-    UserBuffer,
+    copy_bytes_from_user, copy_bytes_to_user, copy_from_user, copy_to_user,
+    translated_byte_buffer, translated_byte_buffer_mut, translated_ref, translated_refmut,
+    translated_str, PageTableEntry, UserBuffer,
 };
 pub use page_table::{PTEFlags, PageTable};
 
@@ -31,3 +32,8 @@ pub fn init() {
     frame_allocator::init_frame_allocator();
     KERNEL_SPACE.exclusive_access().activate();
 }
+
+/// Enable paging on a hart other than the boot hart, memory management is ready
+pub fn init_secondary() {
+    KERNEL_SPACE.exclusive_access().activate();
+}
@@ -4,7 +4,10 @@ use bitflags::*;
 
 use super::address::{PhysPageNum, StepByOne, VirtPageNum, PPN_WIDTH_SV39};
 use super::frame_allocator::{frame_alloc, FrameTracker};
-use super::{PhysAddr, VirtAddr};
+use super::{MapPermission, PhysAddr, VirtAddr};
+use crate::config::PAGE_SIZE;
+use crate::errno::{Errno, SysResult};
+use crate::task::current_handle_page_fault;
 
 const PTE_PPN_OFFSET: usize = 10;
 
@@ -58,6 +61,9 @@ impl PageTableEntry {
     pub fn executable(&self) -> bool {
         (self.flags() & PTEFlags::X) != PTEFlags::empty()
     }
+    pub fn is_user(&self) -> bool {
+        (self.flags() & PTEFlags::U) != PTEFlags::empty()
+    }
 }
 
 /// Page table structure
@@ -89,7 +95,7 @@ impl PageTable {
                 break;
             }
             if !pte.is_valid() {
-                let frame = frame_alloc().unwrap();
+                let frame = frame_alloc()?;
                 *pte = PageTableEntry::new(frame.ppn, PTEFlags::V);
                 self.frames.push(frame);
             }
@@ -116,9 +122,27 @@ impl PageTable {
         result
     }
     pub fn map(&mut self, vpn: VirtPageNum, ppn: PhysPageNum, flags: PTEFlags) {
-        let pte = self.find_pte_create(vpn).unwrap();
+        assert!(
+            self.try_map(vpn, ppn, flags),
+            "no frame left to map vpn {:?}",
+            vpn
+        );
+    }
+    /// Map `vpn` like [`PageTable::map`].
+    /// Return `false` if there is no frame left for the page tables on the way.
+    pub fn try_map(&mut self, vpn: VirtPageNum, ppn: PhysPageNum, flags: PTEFlags) -> bool {
+        let Some(pte) = self.find_pte_create(vpn) else {
+            return false;
+        };
         assert!(!pte.is_valid(), "vpn {:?} is mapped before mapping", vpn);
         *pte = PageTableEntry::new(ppn, flags | PTEFlags::V);
+        true
+    }
+    /// Point the mapped `vpn` at `ppn` with new `flags`
+    pub fn remap(&mut self, vpn: VirtPageNum, ppn: PhysPageNum, flags: PTEFlags) {
+        let pte = self.find_pte(vpn).unwrap();
+        assert!(pte.is_valid(), "vpn: {:?} is invalid before remapping", vpn);
+        *pte = PageTableEntry::new(ppn, flags | PTEFlags::V);
     }
     pub fn unmap(&mut self, vpn: VirtPageNum) {
         let pte = self.find_pte(vpn).unwrap();
@@ -152,15 +176,70 @@ impl PageTable {
     }
 }
 
-pub fn translated_byte_buffer(token: usize, ptr: *const u8, len: usize) -> Vec<&'static mut [u8]> {
+/// Longest string [`translated_str`] accepts, including the terminating NUL
+const USER_STR_MAX: usize = PAGE_SIZE;
+
+/// Physical page behind the user page `vpn`.
+/// The kernel accesses user pages through their physical addresses, bypassing
+/// the MMU, so lazily mapped and copy-on-write pages have to be resolved by hand.
+/// Fail with `EFAULT` if the user itself may not access the page this way,
+/// or with `ENOMEM` if there is no frame left for it.
+fn user_ppn(page_table: &PageTable, vpn: VirtPageNum, write: bool) -> SysResult<PhysPageNum> {
+    let access = if write {
+        MapPermission::W
+    } else {
+        MapPermission::R
+    };
+    match page_table.translate(vpn) {
+        Some(pte) if pte.is_valid() && pte.is_user() && (!write || pte.writable()) => Ok(pte.ppn()),
+        _ => {
+            current_handle_page_fault(vpn.into(), access)?;
+            page_table
+                .translate(vpn)
+                .map(|pte| pte.ppn())
+                .ok_or(Errno::EFAULT)
+        }
+    }
+}
+
+/// Physical address behind the user address `va`, see [`user_ppn`].
+fn user_pa(page_table: &PageTable, va: VirtAddr, write: bool) -> SysResult<PhysAddr> {
+    let aligned_pa: PhysAddr = user_ppn(page_table, va.floor(), write)?.into();
+    Ok((usize::from(aligned_pa) + va.page_offset()).into())
+}
+
+/// Translate a user buffer the kernel is only going to read
+pub fn translated_byte_buffer(
+    token: usize,
+    ptr: *const u8,
+    len: usize,
+) -> SysResult<Vec<&'static mut [u8]>> {
+    translate_byte_buffer(token, ptr, len, false)
+}
+
+/// Translate a user buffer the kernel is going to write
+pub fn translated_byte_buffer_mut(
+    token: usize,
+    ptr: *mut u8,
+    len: usize,
+) -> SysResult<Vec<&'static mut [u8]>> {
+    translate_byte_buffer(token, ptr, len, true)
+}
+
+fn translate_byte_buffer(
+    token: usize,
+    ptr: *const u8,
+    len: usize,
+    write: bool,
+) -> SysResult<Vec<&'static mut [u8]>> {
     let page_table = PageTable::from_token(token); // temporary page table, does not hold resources
     let mut start = ptr as usize;
-    let end = start + len;
+    let end = start.checked_add(len).ok_or(Errno::EFAULT)?;
     let mut v = Vec::new();
     while start < end {
         let start_va = VirtAddr::from(start);
         let mut vpn = start_va.floor();
-        let ppn = page_table.translate(vpn).unwrap().ppn();
+        let ppn = user_ppn(&page_table, vpn, write)?;
         vpn.step();
         let mut end_va: VirtAddr = vpn.into();
         end_va = end_va.min(VirtAddr::from(end));
@@ -171,48 +250,96 @@ pub fn translated_byte_buffer(token: usize, ptr: *const u8, len: usize) -> Vec<&
         }
         start = end_va.into();
     }
-    v
+    Ok(v)
 }
 
-/// Translate a str in user space into kernel space
-pub fn translated_str(token: usize, ptr: *const u8) -> String {
+/// View `value` as raw bytes
+fn bytes_of_mut<T>(value: &mut T) -> &mut [u8] {
+    unsafe {
+        core::slice::from_raw_parts_mut(value as *mut T as *mut u8, core::mem::size_of::<T>())
+    }
+}
+
+/// Copy `bytes` to `ptr` in user space, which may cross a page boundary
+pub fn copy_bytes_to_user(token: usize, ptr: *mut u8, bytes: &[u8]) -> SysResult<()> {
+    let mut copied = 0;
+    for buf in translated_byte_buffer_mut(token, ptr, bytes.len())? {
+        buf.copy_from_slice(&bytes[copied..copied + buf.len()]);
+        copied += buf.len();
+    }
+    Ok(())
+}
+
+/// Fill `bytes` from `ptr` in user space, which may cross a page boundary
+pub fn copy_bytes_from_user(token: usize, ptr: *const u8, bytes: &mut [u8]) -> SysResult<()> {
+    let mut copied = 0;
+    for buf in translated_byte_buffer(token, ptr, bytes.len())? {
+        bytes[copied..copied + buf.len()].copy_from_slice(buf);
+        copied += buf.len();
+    }
+    Ok(())
+}
+
+/// Copy `value` to `ptr` in user space, which may cross a page boundary
+pub fn copy_to_user<T: Copy>(token: usize, ptr: *mut T, value: &T) -> SysResult<()> {
+    let mut value = *value;
+    copy_bytes_to_user(token, ptr as *mut u8, bytes_of_mut(&mut value))
+}
+
+/// Copy a `T` from `ptr` in user space, which may cross a page boundary.
+/// `T` must be valid for any bit pattern.
+pub fn copy_from_user<T: Copy + Default>(token: usize, ptr: *const T) -> SysResult<T> {
+    let mut value = T::default();
+    copy_bytes_from_user(token, ptr as *const u8, bytes_of_mut(&mut value))?;
+    Ok(value)
+}
+
+/// Translate a str in user space into kernel space.
+/// Fail with `ENAMETOOLONG` if it is longer than [`USER_STR_MAX`].
+pub fn translated_str(token: usize, ptr: *const u8) -> SysResult<String> {
     // build a temporary page table
     let page_table = PageTable::from_token(token);
-    let mut string = String::new();
+    let mut bytes = Vec::new();
     let mut va = ptr as usize;
     loop {
-        let ch: u8 = *(page_table
-            .translate_va(VirtAddr::from(va))
-            .unwrap()
-            .get_mut());
+        let ch: u8 = *(user_pa(&page_table, VirtAddr::from(va), false)?.get_ref());
         if ch == 0 {
             break;
-        } else {
-            string.push(ch as char);
-            va += 1;
         }
+        if bytes.len() + 1 >= USER_STR_MAX {
+            return Err(Errno::ENAMETOOLONG);
+        }
+        bytes.push(ch);
+        va = va.checked_add(1).ok_or(Errno::EFAULT)?;
     }
-    string
+    String::from_utf8(bytes).map_err(|_| Errno::EINVAL)
+}
+
+/// Check that a `T` at `va` is aligned, which also keeps it within one page
+/// since every type passed by reference is no larger than its alignment.
+fn check_ref<T>(va: usize) -> SysResult<()> {
+    if va % core::mem::align_of::<T>() != 0
+        || va % PAGE_SIZE + core::mem::size_of::<T>() > PAGE_SIZE
+    {
+        return Err(Errno::EFAULT);
+    }
+    Ok(())
 }
 
 /// Translate a generic through page table and return a immutable reference
-pub fn translated_ref<T>(token: usize, ptr: *const T) -> &'static T {
+pub fn translated_ref<T>(token: usize, ptr: *const T) -> SysResult<&'static T> {
     let page_table = PageTable::from_token(token);
     let va = ptr as usize;
-    page_table
-        .translate_va(VirtAddr::from(va))
-        .unwrap()
-        .get_ref()
+    check_ref::<T>(va)?;
+    Ok(user_pa(&page_table, VirtAddr::from(va), false)?.get_ref())
 }
 
 /// Translate a generic through page table and return a mutable reference
-pub fn translated_refmut<T>(token: usize, ptr: *mut T) -> &'static mut T {
+pub fn translated_refmut<T>(token: usize, ptr: *mut T) -> SysResult<&'static mut T> {
     let page_table = PageTable::from_token(token);
     let va = ptr as usize;
-    page_table
-        .translate_va(VirtAddr::from(va))
-        .unwrap()
-        .get_mut()
+    check_ref::<T>(va)?;
+    Ok(user_pa(&page_table, VirtAddr::from(va), true)?.get_mut())
 }
 
 /// User buffer is continuous in user space, but could be separate in kernel space.
@@ -226,6 +353,16 @@ impl UserBuffer {
     pub fn new(buffers: Vec<&'static mut [u8]>) -> Self {
         Self { buffers }
     }
+    /// Wrap a buffer in kernel memory, e.g. to read or write a file which
+    /// may block through it, see [`crate::syscall::fs::sys_read`].
+    ///
+    /// # Safety
+    ///
+    /// `buf` must outlive the returned buffer.
+    pub unsafe fn from_kernel(buf: &mut [u8]) -> Self {
+        let buf = core::slice::from_raw_parts_mut(buf.as_mut_ptr(), buf.len());
+        Self::new(vec![buf])
+    }
     /// Length of `UserBuffer`
     pub fn len(&self) -> usize {
         let mut total: usize = 0;
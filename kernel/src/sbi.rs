@@ -17,6 +17,17 @@ pub fn set_timer(timer: usize) {
     sbi_rt::set_timer(timer as _);
 }
 
+/// use sbi call to start hart `hartid` at physical address `start_addr`,
+/// return `false` if the hart does not exist or is already started
+pub fn hart_start(hartid: usize, start_addr: usize) -> bool {
+    sbi_rt::hart_start(hartid, start_addr, 0).is_ok()
+}
+
+/// use sbi call to flush the whole TLB of every hart
+pub fn remote_sfence_vma_all() {
+    sbi_rt::remote_sfence_vma(sbi_rt::HartMask::from_mask_base(0, usize::MAX), 0, usize::MAX);
+}
+
 /// use sbi call to shutdown the kernel
 pub fn shutdown(failure: bool) -> ! {
     use sbi_rt::{system_reset, NoReason, Shutdown, SystemFailure};
@@ -0,0 +1,141 @@
+//! Futexes, on which user threads block until a word in their memory changes
+//!
+//! A futex is identified by the physical address of its word, so processes
+//! sharing a page (with `MAP_SHARED`) share the futexes in it as well. A
+//! copy-on-write page gets a new frame on its first write after `fork`, so
+//! threads should not be blocked on a private futex across a `fork`.
+
+use super::SpinLock;
+use crate::errno::{Errno, SysResult};
+use crate::task::{
+    block_current_and_run_next, current_task, wakeup_task, ProcessControlBlock, TaskControlBlock,
+};
+use crate::timer::{add_futex_timer, remove_futex_timer};
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU32, Ordering};
+use lazy_static::*;
+
+/// A task blocked on a futex
+pub struct FutexWaiter {
+    /// Physical address of the futex word
+    key: usize,
+    task: Arc<TaskControlBlock>,
+    /// What `futex_wait` returns, set before the task is woken up
+    result: SpinLock<SysResult>,
+}
+
+type FutexQueues = BTreeMap<usize, VecDeque<Arc<FutexWaiter>>>;
+
+lazy_static! {
+    /// Waiters of every futex with waiters, keyed by the physical address of its word
+    static ref FUTEXES: SpinLock<FutexQueues> = SpinLock::new(BTreeMap::new());
+}
+
+/// Take `waiter` off its queue, return `false` if someone else did already
+fn take_waiter(futexes: &mut FutexQueues, waiter: &Arc<FutexWaiter>) -> bool {
+    let Some(queue) = futexes.get_mut(&waiter.key) else {
+        return false;
+    };
+    let Some(pos) = queue.iter().position(|other| Arc::ptr_eq(other, waiter)) else {
+        return false;
+    };
+    queue.remove(pos);
+    if queue.is_empty() {
+        futexes.remove(&waiter.key);
+    }
+    true
+}
+
+/// Block the current task on the futex of `word` if it still holds `expected`,
+/// until it is woken by [`futex_wake`] or `get_time()` reaches `deadline`.
+///
+/// Fail with `EAGAIN` if `word` holds something else, `ETIMEDOUT` when the
+/// deadline passes, or `EINTR` if a signal comes or the process is exiting.
+pub fn futex_wait(word: &AtomicU32, expected: u32, deadline: Option<usize>) -> SysResult {
+    let key = word as *const AtomicU32 as usize;
+    let task = current_task().unwrap();
+    let mut futexes = FUTEXES.exclusive_access();
+    // the waker changes the word before taking the lock, so it cannot be missed
+    if word.load(Ordering::SeqCst) != expected {
+        return Err(Errno::EAGAIN);
+    }
+    // the exiting thread and the sender of a signal change these before
+    // taking the lock, see `futex_wake_process`
+    let process = task.process();
+    let process_inner = process.inner_exclusive_access();
+    let signal_mask = task.inner_exclusive_access().signal_mask;
+    if process_inner.exiting || !process_inner.interrupting_signals(signal_mask).is_empty() {
+        return Err(Errno::EINTR);
+    }
+    drop(process_inner);
+    drop(process);
+    let waiter = Arc::new(FutexWaiter {
+        key,
+        task,
+        result: SpinLock::new(Ok(0)),
+    });
+    futexes.entry(key).or_default().push_back(waiter.clone());
+    if let Some(deadline) = deadline {
+        add_futex_timer(deadline, waiter.clone());
+    }
+    drop(futexes);
+    block_current_and_run_next();
+    let result = *waiter.result.exclusive_access();
+    result
+}
+
+/// Wake up at most `count` tasks blocked on the futex of `word`, the ones
+/// waiting longest first. Return how many were woken up.
+pub fn futex_wake(word: &AtomicU32, count: usize) -> usize {
+    let key = word as *const AtomicU32 as usize;
+    let mut futexes = FUTEXES.exclusive_access();
+    let Some(queue) = futexes.get_mut(&key) else {
+        return 0;
+    };
+    let woken: Vec<_> = queue.drain(..count.min(queue.len())).collect();
+    if queue.is_empty() {
+        futexes.remove(&key);
+    }
+    // cancel the timeouts before another hart can handle them
+    woken.iter().for_each(remove_futex_timer);
+    drop(futexes);
+    let count = woken.len();
+    for waiter in woken {
+        wakeup_task(waiter.task.clone());
+    }
+    count
+}
+
+/// Wake up `waiter` whose timeout expired, unless it has been woken already
+pub fn futex_timeout(waiter: Arc<FutexWaiter>) {
+    let mut futexes = FUTEXES.exclusive_access();
+    if !take_waiter(&mut futexes, &waiter) {
+        return;
+    }
+    *waiter.result.exclusive_access() = Err(Errno::ETIMEDOUT);
+    drop(futexes);
+    wakeup_task(waiter.task.clone());
+}
+
+/// Wake up every thread of `process` blocked on a futex, so that they
+/// notice a new signal or that the process is exiting.
+pub fn futex_wake_process(process: &ProcessControlBlock) {
+    let mut futexes = FUTEXES.exclusive_access();
+    let woken: Vec<_> = futexes
+        .values()
+        .flatten()
+        .filter(|waiter| core::ptr::eq(waiter.task.process.as_ptr(), process))
+        .cloned()
+        .collect();
+    for waiter in woken.iter() {
+        take_waiter(&mut futexes, waiter);
+        remove_futex_timer(waiter);
+        *waiter.result.exclusive_access() = Err(Errno::EINTR);
+    }
+    drop(futexes);
+    for waiter in woken {
+        wakeup_task(waiter.task.clone());
+    }
+}
@@ -1,4 +1,10 @@
 #![allow(unused)]
+mod futex;
+mod spinlock;
 mod up;
+mod wait_queue;
 
+pub use futex::{futex_timeout, futex_wait, futex_wake, futex_wake_process, FutexWaiter};
+pub use spinlock::SpinLock;
 pub use up::UPSafeCell;
+pub use wait_queue::{WaitQueue, Waiter};
@@ -0,0 +1,30 @@
+//! Spinlock shared by all harts
+
+use spin::{Mutex, MutexGuard};
+
+/// Wrap a data structure shared by all harts, so that only one hart
+/// accesses it at a time.
+///
+/// The kernel never enables interrupts in S mode, so a hart holding the
+/// lock cannot be interrupted and spin on it again. Still, the lock is
+/// not reentrant: accessing it twice on the same hart deadlocks.
+///
+/// In order to get mutable reference of inner data, call
+/// `exclusive_access`.
+#[derive(Debug)]
+pub struct SpinLock<T> {
+    /// inner data
+    inner: Mutex<T>,
+}
+
+impl<T> SpinLock<T> {
+    pub const fn new(value: T) -> Self {
+        Self {
+            inner: Mutex::new(value),
+        }
+    }
+    /// Exclusive access inner data, spin until no other hart holds it.
+    pub fn exclusive_access(&self) -> MutexGuard<'_, T> {
+        self.inner.lock()
+    }
+}
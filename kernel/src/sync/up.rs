@@ -5,7 +5,9 @@ use core::cell::{RefCell, RefMut};
 /// Wrap a static data structure inside it so that we are
 /// able to access it without any `unsafe`.
 ///
-/// We should only use it in uniprocessor.
+/// We should only use it for data that a single hart accesses,
+/// such as the per-hart `Processor`. Data shared among
+/// harts goes into a [`super::SpinLock`].
 ///
 /// In order to get mutable reference of inner data, call
 /// `exclusive_access`.
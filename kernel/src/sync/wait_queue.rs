@@ -0,0 +1,143 @@
+//! Tasks blocked until some event happens
+
+use super::SpinLock;
+use crate::task::{
+    block_current_and_run_next, current_interrupted, current_task, wakeup_task, TaskControlBlock,
+};
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// A blocked task, woken up by the event it waits for or, if it is
+/// interruptible, by a signal or the exit of its process as well (see
+/// `ProcessControlBlock::interrupt_threads`). Only the first of them to claim
+/// the waiter wakes the task up.
+#[derive(Debug)]
+pub struct Waiter {
+    task: Arc<TaskControlBlock>,
+    interruptible: bool,
+    /// Set by whoever wakes the task up
+    woken: AtomicBool,
+}
+
+impl Waiter {
+    /// A waiter for the current task. An interruptible one is recorded in the
+    /// task until it wakes up, so that it can be found and woken.
+    pub fn new(interruptible: bool) -> Arc<Self> {
+        let task = current_task().unwrap();
+        let waiter = Arc::new(Self {
+            task: task.clone(),
+            interruptible,
+            woken: AtomicBool::new(false),
+        });
+        if interruptible {
+            task.inner_exclusive_access().waiter = Some(waiter.clone());
+        }
+        waiter
+    }
+    /// Wake the task up unless someone else has, return whether this did
+    pub fn wake(&self) -> bool {
+        if self.woken.swap(true, Ordering::AcqRel) {
+            return false;
+        }
+        wakeup_task(self.task.clone());
+        true
+    }
+    /// Block the current task until it is woken up. An interruptible waiter
+    /// returns at once if the task has been interrupted before it was recorded.
+    pub fn wait(&self) {
+        // the interrupter changes the process before looking for the waiter
+        let interrupted = self.interruptible && current_interrupted() && !self.cancel();
+        if !interrupted {
+            block_current_and_run_next();
+        }
+        if self.interruptible {
+            self.task.inner_exclusive_access().waiter = None;
+        }
+    }
+    /// Claim the waiter without waking the task up, return `false` if
+    /// someone else has claimed it and is waking the task up
+    fn cancel(&self) -> bool {
+        !self.woken.swap(true, Ordering::AcqRel)
+    }
+}
+
+/// A queue of blocked tasks waiting for the same event.
+///
+/// The waiter takes a ticket with [`WaitQueue::prepare_to_wait`], checks its
+/// condition, releases every lock protecting it and then calls
+/// [`WaitQueue::wait`] with the ticket. Another hart may change the condition
+/// and wake the queue in between, in which case `wait` returns at once instead
+/// of sleeping forever. Either way the waiter must check the condition again.
+#[derive(Debug)]
+pub struct WaitQueue {
+    inner: SpinLock<WaitQueueInner>,
+}
+
+#[derive(Debug)]
+struct WaitQueueInner {
+    /// Bumped on every wakeup
+    seq: usize,
+    queue: VecDeque<Arc<Waiter>>,
+}
+
+impl WaitQueue {
+    pub fn new() -> Self {
+        Self {
+            inner: SpinLock::new(WaitQueueInner {
+                seq: 0,
+                queue: VecDeque::new(),
+            }),
+        }
+    }
+    /// Take a ticket for [`WaitQueue::wait`], before checking the condition
+    pub fn prepare_to_wait(&self) -> usize {
+        self.inner.exclusive_access().seq
+    }
+    /// Block current task until it is woken up, or return at once
+    /// if the queue has been woken since `ticket` was taken.
+    pub fn wait(&self, ticket: usize) {
+        self.block(ticket, false);
+    }
+    /// Like [`WaitQueue::wait`], but a signal or the exit of the process wakes
+    /// the task up as well. The caller checks [`current_interrupted`] after
+    /// its condition, and fails with `EINTR` if it is set.
+    pub fn wait_interruptible(&self, ticket: usize) {
+        self.block(ticket, true);
+    }
+    fn block(&self, ticket: usize, interruptible: bool) {
+        let mut inner = self.inner.exclusive_access();
+        if inner.seq != ticket {
+            return;
+        }
+        let waiter = Waiter::new(interruptible);
+        inner.queue.push_back(waiter.clone());
+        drop(inner);
+        waiter.wait();
+        // an interrupted waiter is still in the queue
+        let mut inner = self.inner.exclusive_access();
+        inner.queue.retain(|other| !Arc::ptr_eq(other, &waiter));
+    }
+    /// Wake up the task waiting longest, return `false` if there is none
+    pub fn wake_one(&self) -> bool {
+        let mut inner = self.inner.exclusive_access();
+        inner.seq = inner.seq.wrapping_add(1);
+        // skip the waiters which have been interrupted
+        while let Some(waiter) = inner.queue.pop_front() {
+            if waiter.wake() {
+                return true;
+            }
+        }
+        false
+    }
+    /// Wake up all the waiting tasks
+    pub fn wake_all(&self) {
+        let mut inner = self.inner.exclusive_access();
+        inner.seq = inner.seq.wrapping_add(1);
+        let waiters = core::mem::take(&mut inner.queue);
+        drop(inner);
+        for waiter in waiters {
+            waiter.wake();
+        }
+    }
+}
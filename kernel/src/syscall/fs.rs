@@ -1,110 +1,370 @@
-use alloc::sync::Arc;
+use alloc::vec;
 
 use crate::{
-    fs::{open_file, pipe::make_pipe, OpenFlags},
-    mm::{translated_byte_buffer, translated_refmut, translated_str, UserBuffer},
-    task::{current_task, current_user_token},
+    config::PAGE_SIZE,
+    errno::{Errno, SysResult},
+    fs::{
+        console_foreground, dir_path, find_inode, link_file, make_dir, open_file, pipe::make_pipe,
+        read_link, rename_file, set_console_foreground, symlink_file, unlink_file, OpenFlags, Stat,
+        AT_FDCWD, AT_REMOVEDIR, AT_SYMLINK_FOLLOW,
+    },
+    mm::{
+        copy_bytes_from_user, copy_bytes_to_user, copy_from_user, copy_to_user,
+        translated_byte_buffer, translated_byte_buffer_mut, translated_refmut, translated_str,
+        UserBuffer,
+    },
+    task::{current_process, current_user_token, process_group},
 };
 
 const __STDIN: usize = 0;
 const __STDOUT: usize = 1;
 const __STDERR: usize = 2;
 
-pub fn sys_dup(fd: usize) -> isize {
-    let task = current_task().unwrap();
-    let mut inner = task.inner_exclusive_access();
-    if fd >= inner.fd_table.len() {
-        // fd not allocated yet
-        return -1;
+pub fn sys_dup(fd: usize) -> SysResult {
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    let file = inner.get_file(fd)?;
+    let new_fd = inner.alloc_fd();
+    inner.fd_table[new_fd] = Some(file);
+    Ok(new_fd)
+}
+
+/// `cmd` of [`sys_fcntl`]: resize a pipe to at least `arg` bytes
+const F_SETPIPE_SZ: u32 = 1031;
+/// `cmd` of [`sys_fcntl`]: get the capacity of a pipe
+const F_GETPIPE_SZ: u32 = 1032;
+
+/// Manipulate an open file. Only the pipe size commands are supported.
+pub fn sys_fcntl(fd: usize, cmd: u32, arg: usize) -> SysResult {
+    let file = current_process().inner_exclusive_access().get_file(fd)?;
+    let pipe = file.pipe().ok_or(Errno::EBADF)?;
+    match cmd {
+        F_SETPIPE_SZ => pipe.set_capacity(arg),
+        F_GETPIPE_SZ => Ok(pipe.capacity()),
+        _ => Err(Errno::EINVAL),
     }
-    if inner.fd_table[fd].is_none() {
-        // correspond file not found
-        return -1;
+}
+
+/// `request` of [`sys_ioctl`]: get the foreground process group of the terminal
+const TIOCGPGRP: usize = 0x540f;
+/// `request` of [`sys_ioctl`]: set the foreground process group of the terminal
+const TIOCSPGRP: usize = 0x5410;
+
+/// Control a device. Only getting and setting the foreground process group of
+/// the console is supported, through the `i32` which `argp` points to. The new
+/// foreground group must be in the session of the caller.
+pub fn sys_ioctl(fd: usize, request: usize, argp: usize) -> SysResult {
+    let process = current_process();
+    let file = process.inner_exclusive_access().get_file(fd)?;
+    if !file.is_tty() {
+        return Err(Errno::ENOTTY);
+    }
+    let token = current_user_token();
+    match request {
+        TIOCGPGRP => {
+            copy_to_user(token, argp as *mut i32, &(console_foreground() as i32))?;
+            Ok(0)
+        }
+        TIOCSPGRP => {
+            let pgid = copy_from_user(token, argp as *const i32)?;
+            let pgid = usize::try_from(pgid).map_err(|_| Errno::EINVAL)?;
+            let sid = process.inner_exclusive_access().sid;
+            if !process_group(pgid)
+                .iter()
+                .any(|member| member.inner_exclusive_access().sid == sid)
+            {
+                return Err(Errno::EPERM);
+            }
+            set_console_foreground(pgid);
+            Ok(0)
+        }
+        _ => Err(Errno::EINVAL),
     }
-    let new_fd = inner.alloc_fd();
-    inner.fd_table[new_fd] = Some(Arc::clone(inner.fd_table[fd].as_ref().unwrap()));
-    new_fd as isize
 }
 
-pub fn sys_open(path: *const u8, flags: u32) -> isize {
-    let task = current_task().unwrap();
+/// Create a directory with permission bits `mode`. Only `AT_FDCWD` is
+/// accepted as `dirfd`.
+pub fn sys_mkdirat(dirfd: isize, path: *const u8, mode: u32) -> SysResult {
+    if dirfd != AT_FDCWD {
+        return Err(Errno::EBADF);
+    }
     let token = current_user_token();
-    let path = translated_str(token, path);
-    if let Some(inode) = open_file(path.as_str(), OpenFlags::from_bits(flags).unwrap()) {
-        let mut inner = task.inner_exclusive_access();
-        let fd = inner.alloc_fd();
-        inner.fd_table[fd] = Some(inode);
-        fd as isize
-    } else {
-        -1
+    let path = translated_str(token, path)?;
+    let cwd = current_process().inner_exclusive_access().cwd.clone();
+    if find_inode(&cwd, path.as_str(), false).is_ok() {
+        return Err(Errno::EEXIST);
     }
+    make_dir(&cwd, path.as_str())?.set_mode(mode as u16);
+    Ok(0)
 }
 
-pub fn sys_close(fd: usize) -> isize {
-    let task = current_task().unwrap();
-    let mut inner = task.inner_exclusive_access();
-    if fd >= inner.fd_table.len() {
-        return -1;
+pub fn sys_unlinkat(dirfd: isize, path: *const u8, flags: u32) -> SysResult {
+    if dirfd != AT_FDCWD {
+        return Err(Errno::EBADF);
     }
-    if inner.fd_table[fd].is_none() {
-        return -1;
+    let token = current_user_token();
+    let path = translated_str(token, path)?;
+    let cwd = current_process().inner_exclusive_access().cwd.clone();
+    unlink_file(&cwd, path.as_str(), flags & AT_REMOVEDIR != 0)?;
+    Ok(0)
+}
+
+/// Give the file at `old_path` another name `new_path`. Only `AT_FDCWD` is
+/// accepted as the directory fds, and `AT_SYMLINK_FOLLOW` as `flags`.
+pub fn sys_linkat(
+    old_dirfd: isize,
+    old_path: *const u8,
+    new_dirfd: isize,
+    new_path: *const u8,
+    flags: u32,
+) -> SysResult {
+    if old_dirfd != AT_FDCWD || new_dirfd != AT_FDCWD {
+        return Err(Errno::EBADF);
+    }
+    if flags & !AT_SYMLINK_FOLLOW != 0 {
+        return Err(Errno::EINVAL);
+    }
+    let token = current_user_token();
+    let old_path = translated_str(token, old_path)?;
+    let new_path = translated_str(token, new_path)?;
+    let cwd = current_process().inner_exclusive_access().cwd.clone();
+    let follow = flags & AT_SYMLINK_FOLLOW != 0;
+    link_file(&cwd, old_path.as_str(), new_path.as_str(), follow)?;
+    Ok(0)
+}
+
+/// Create a symbolic link at `link_path` pointing to `target`. Only `AT_FDCWD`
+/// is accepted as `new_dirfd`.
+pub fn sys_symlinkat(target: *const u8, new_dirfd: isize, link_path: *const u8) -> SysResult {
+    if new_dirfd != AT_FDCWD {
+        return Err(Errno::EBADF);
+    }
+    let token = current_user_token();
+    let target = translated_str(token, target)?;
+    let link_path = translated_str(token, link_path)?;
+    let cwd = current_process().inner_exclusive_access().cwd.clone();
+    symlink_file(&cwd, target.as_str(), link_path.as_str())?;
+    Ok(0)
+}
+
+/// Copy the target of the symbolic link at `path` into `buf`, truncated to
+/// `len` bytes and without a terminating NUL. Return the length copied.
+/// Only `AT_FDCWD` is accepted as `dirfd`.
+pub fn sys_readlinkat(dirfd: isize, path: *const u8, buf: *mut u8, len: usize) -> SysResult {
+    if dirfd != AT_FDCWD {
+        return Err(Errno::EBADF);
+    }
+    if len == 0 {
+        return Err(Errno::EINVAL);
+    }
+    let token = current_user_token();
+    let path = translated_str(token, path)?;
+    let cwd = current_process().inner_exclusive_access().cwd.clone();
+    let target = read_link(&cwd, path.as_str())?;
+    let len = target.len().min(len);
+    let user_buf = UserBuffer::new(translated_byte_buffer_mut(token, buf, len)?);
+    for (byte_ref, byte) in user_buf.into_iter().zip(target.bytes()) {
+        unsafe { *byte_ref = byte };
+    }
+    Ok(len)
+}
+
+/// Move `old_path` to `new_path`, replacing it at once if it exists.
+/// `RENAME_NOREPLACE` and `RENAME_EXCHANGE` are supported in `flags`,
+/// and only `AT_FDCWD` is accepted as the directory fds.
+pub fn sys_renameat2(
+    old_dirfd: isize,
+    old_path: *const u8,
+    new_dirfd: isize,
+    new_path: *const u8,
+    flags: u32,
+) -> SysResult {
+    if old_dirfd != AT_FDCWD || new_dirfd != AT_FDCWD {
+        return Err(Errno::EBADF);
     }
+    let token = current_user_token();
+    let old_path = translated_str(token, old_path)?;
+    let new_path = translated_str(token, new_path)?;
+    let cwd = current_process().inner_exclusive_access().cwd.clone();
+    rename_file(&cwd, old_path.as_str(), new_path.as_str(), flags)?;
+    Ok(0)
+}
+
+/// Write the absolute path of the working directory into `buf`, including
+/// the terminating NUL. Return the length written, or `ERANGE` if `buf` is too small.
+pub fn sys_getcwd(buf: *mut u8, len: usize) -> SysResult {
+    let token = current_user_token();
+    let cwd = current_process().inner_exclusive_access().cwd.clone();
+    // the working directory has been removed
+    let path = dir_path(&cwd).ok_or(Errno::ENOENT)?;
+    if path.len() + 1 > len {
+        return Err(Errno::ERANGE);
+    }
+    let user_buf = UserBuffer::new(translated_byte_buffer_mut(token, buf, path.len() + 1)?);
+    for (byte_ref, byte) in user_buf.into_iter().zip(path.bytes().chain([0])) {
+        unsafe { *byte_ref = byte };
+    }
+    Ok(path.len() + 1)
+}
+
+pub fn sys_chdir(path: *const u8) -> SysResult {
+    let token = current_user_token();
+    let process = current_process();
+    let path = translated_str(token, path)?;
+    let cwd = process.inner_exclusive_access().cwd.clone();
+    let dir = find_inode(&cwd, path.as_str(), true)?;
+    if !dir.is_dir() {
+        return Err(Errno::ENOTDIR);
+    }
+    process.inner_exclusive_access().cwd = dir;
+    Ok(0)
+}
+
+pub fn sys_open(path: *const u8, flags: u32) -> SysResult {
+    let process = current_process();
+    let token = current_user_token();
+    let path = translated_str(token, path)?;
+    let flags = OpenFlags::from_bits(flags).ok_or(Errno::EINVAL)?;
+    let cwd = process.inner_exclusive_access().cwd.clone();
+    let inode = open_file(&cwd, path.as_str(), flags)?;
+    let mut inner = process.inner_exclusive_access();
+    let fd = inner.alloc_fd();
+    inner.fd_table[fd] = Some(inode);
+    Ok(fd)
+}
+
+pub fn sys_close(fd: usize) -> SysResult {
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    inner.get_file(fd)?;
     inner.fd_table[fd].take(); // substract the strong counter in `Arc`
-    0
+    Ok(0)
 }
 
-pub fn sys_pipe(pipe: *mut usize) -> isize {
-    let task = current_task().unwrap();
+pub fn sys_pipe(pipe: *mut usize) -> SysResult {
+    let process = current_process();
     let token = current_user_token();
-    let mut inner = task.inner_exclusive_access();
+    // check the array before any fd is allocated
+    translated_refmut(token, pipe)?;
+    translated_refmut(token, pipe.wrapping_add(1))?;
+    let mut inner = process.inner_exclusive_access();
     let (pipe_read, pipe_write) = make_pipe();
     let read_fd = inner.alloc_fd();
     inner.fd_table[read_fd] = Some(pipe_read);
     let write_fd = inner.alloc_fd();
     inner.fd_table[write_fd] = Some(pipe_write);
+    // accessing user memory may need to access the memory set
+    drop(inner);
     // Translate pointer in user space to kernel space.
     // Then write fd numbers into the array.
-    *translated_refmut(token, pipe) = read_fd;
-    *translated_refmut(token, unsafe { pipe.add(1) }) = write_fd;
-    0
+    *translated_refmut(token, pipe)? = read_fd;
+    *translated_refmut(token, pipe.wrapping_add(1))? = write_fd;
+    Ok(0)
 }
 
-pub fn sys_read(fd: usize, buf: *const u8, len: usize) -> isize {
+/// Read up to `len` bytes from `fd` into `buf`. A file which may block is
+/// read through a kernel buffer, one chunk at a time, and each chunk is copied
+/// out once it is read, so no user page is held while the reader sleeps.
+pub fn sys_read(fd: usize, buf: *const u8, len: usize) -> SysResult {
     let token = current_user_token();
-    let task = current_task().unwrap();
-    let inner = task.inner_exclusive_access();
-    if fd >= inner.fd_table.len() {
-        return -1;
+    // release current PCB at once to avoid multi-borrow
+    let file = current_process().inner_exclusive_access().get_file(fd)?;
+    if !file.readable() {
+        return Err(Errno::EBADF);
     }
-    if let Some(file) = &inner.fd_table[fd] {
-        let file = file.clone();
-        if !file.readable() {
-            return -1;
+    if !file.blocking() {
+        return file.read(UserBuffer::new(translated_byte_buffer_mut(
+            token,
+            buf as *mut u8,
+            len,
+        )?));
+    }
+    let mut kernel_buf = vec![0u8; len.min(PAGE_SIZE)];
+    let mut read = 0;
+    while read < len {
+        let chunk = &mut kernel_buf[..(len - read).min(PAGE_SIZE)];
+        let chunk_len = chunk.len();
+        // the chunk lives until the read returns
+        let n = match file.read(unsafe { UserBuffer::from_kernel(chunk) }) {
+            Ok(n) => n,
+            Err(_) if read > 0 => break,
+            Err(err) => return Err(err),
+        };
+        copy_bytes_to_user(token, (buf as *mut u8).wrapping_add(read), &chunk[..n])?;
+        read += n;
+        // at the end of the file, or nothing more has come
+        if n < chunk_len {
+            break;
         }
-        // release current task TCB manually to avoid multi-borrow
-        drop(inner);
-        file.read(UserBuffer::new(translated_byte_buffer(token, buf, len))) as isize
-    } else {
-        -1
     }
+    Ok(read)
 }
 
-pub fn sys_write(fd: usize, buf: *const u8, len: usize) -> isize {
+/// Write `len` bytes from `buf` to `fd`, see [`sys_read`] for files which
+/// may block.
+pub fn sys_write(fd: usize, buf: *const u8, len: usize) -> SysResult {
     let token = current_user_token();
-    let task = current_task().unwrap();
-    let inner = task.inner_exclusive_access();
-    if fd >= inner.fd_table.len() {
-        return -1;
+    // release current PCB at once to avoid multi-borrow
+    let file = current_process().inner_exclusive_access().get_file(fd)?;
+    if !file.writable() {
+        return Err(Errno::EBADF);
+    }
+    if !file.blocking() {
+        return file.write(UserBuffer::new(translated_byte_buffer(token, buf, len)?));
     }
-    if let Some(file) = &inner.fd_table[fd] {
-        let file = file.clone();
-        if !file.writable() {
-            return -1;
+    let mut kernel_buf = vec![0u8; len.min(PAGE_SIZE)];
+    let mut written = 0;
+    while written < len {
+        let chunk = &mut kernel_buf[..(len - written).min(PAGE_SIZE)];
+        let chunk_len = chunk.len();
+        copy_bytes_from_user(token, buf.wrapping_add(written), chunk)?;
+        // the chunk lives until the write returns
+        let n = match file.write(unsafe { UserBuffer::from_kernel(chunk) }) {
+            Ok(n) => n,
+            Err(_) if written > 0 => break,
+            Err(err) => return Err(err),
+        };
+        written += n;
+        if n < chunk_len {
+            break;
         }
-        // release current task TCB manually to avoid multi-borrow
-        drop(inner);
-        file.write(UserBuffer::new(translated_byte_buffer(token, buf, len))) as isize
-    } else {
-        -1
     }
+    Ok(written)
+}
+
+/// Reposition the offset of `fd`, return the new offset.
+pub fn sys_lseek(fd: usize, offset: isize, whence: usize) -> SysResult {
+    let file = current_process().inner_exclusive_access().get_file(fd)?;
+    file.seek(offset, whence)
+}
+
+/// Read from `offset` of `fd` without changing its offset.
+pub fn sys_pread64(fd: usize, buf: *mut u8, len: usize, offset: usize) -> SysResult {
+    let token = current_user_token();
+    let file = current_process().inner_exclusive_access().get_file(fd)?;
+    if !file.readable() {
+        return Err(Errno::EBADF);
+    }
+    let buf = UserBuffer::new(translated_byte_buffer_mut(token, buf, len)?);
+    file.read_at(offset, buf)
+}
+
+/// Write to `offset` of `fd` without changing its offset.
+pub fn sys_pwrite64(fd: usize, buf: *const u8, len: usize, offset: usize) -> SysResult {
+    let token = current_user_token();
+    let file = current_process().inner_exclusive_access().get_file(fd)?;
+    if !file.writable() {
+        return Err(Errno::EBADF);
+    }
+    let buf = UserBuffer::new(translated_byte_buffer(token, buf, len)?);
+    file.write_at(offset, buf)
+}
+
+/// Get the status of the file behind `fd`, only files on disk are supported.
+pub fn sys_fstat(fd: usize, st: *mut Stat) -> SysResult {
+    let token = current_user_token();
+    let file = current_process().inner_exclusive_access().get_file(fd)?;
+    let inode = file.inode().ok_or(Errno::EINVAL)?;
+    copy_to_user(token, st, &Stat::from_inode(&inode))?;
+    Ok(0)
 }
@@ -2,202 +2,568 @@ use alloc::string::String;
 use alloc::sync::Arc;
 use alloc::vec::Vec;
 
+use crate::config::{PAGE_SIZE, USER_SPACE_TOP};
+use crate::errno::{Errno, SysResult};
 use crate::fs::{open_file, OpenFlags};
-use crate::mm::{translated_ref, translated_refmut, translated_str};
+use crate::mm::{
+    copy_from_user, copy_to_user, translated_ref, translated_str, MapBacking, MmapFlags, MmapProt,
+    VirtAddr,
+};
 use crate::sbi::shutdown;
+use crate::sync::Waiter;
+use crate::task::{
+    add_task, current_process, current_task, current_trap_cx, current_user_token, pid2process,
+    process_group, ProcessControlBlock, RUsage, SignalAction, SignalFlags, SignalFrame, SignalInfo,
+    CONTINUED_STATUS, MAX_SIG, NICE_MAX, NICE_MIN, SIG_IGN,
+};
 use crate::task::{
-    add_task, current_task, current_user_token, pid2task, SignalAction, SignalFlags, MAX_SIG,
+    current_interrupted, exit_current_thread_and_run_next, suspend_current_and_run_next,
 };
-use crate::task::{exit_current_and_run_next, suspend_current_and_run_next};
-use crate::timer::get_time_ms;
+use crate::timer::{add_timer, get_time, get_time_ms, remove_timer, ITimerVal, TimeSpec};
+use crate::timer::{ITIMER_PROF, ITIMER_REAL};
 
-/// Batch Kernel: batched app exits and schedule the next one
-/// Multiprogramming Kernel: task exits and submit and exit code
+/// Exit the calling thread, and the whole process if it is the main thread
 pub fn sys_exit(exit_code: i32) -> ! {
-    exit_current_and_run_next(exit_code);
+    exit_current_thread_and_run_next(exit_code);
     panic!("Unreachable in sys_exit!");
 }
 
 /// Current task gives up resources for other tasks
 /// Syscall ID: 124
-pub fn sys_yield() -> isize {
+pub fn sys_yield() -> SysResult {
     suspend_current_and_run_next();
-    0
+    Ok(0)
 }
 
-pub fn sys_kill(pid: usize, signum: i32) -> isize {
-    if let Some(task) = pid2task(pid) {
-        if let Some(flag) = SignalFlags::from_bits(1 << signum) {
-            // insert the signal if legal
-            let mut task_ref = task.inner_exclusive_access();
-            if task_ref.signals.contains(flag) {
-                return -1;
+/// Block current task for the interval in `req`. If a signal comes first, fail
+/// with `EINTR` and write the time left into `*rem` unless it is null.
+/// Syscall ID: 101
+pub fn sys_nanosleep(req: *const TimeSpec, rem: *mut TimeSpec) -> SysResult {
+    let token = current_user_token();
+    let ticks = copy_from_user(token, req)?
+        .to_ticks()
+        .ok_or(Errno::EINVAL)?;
+    let deadline = get_time().saturating_add(ticks);
+    loop {
+        let now = get_time();
+        if now >= deadline {
+            return Ok(0);
+        }
+        if current_interrupted() {
+            if !rem.is_null() {
+                copy_to_user(token, rem, &TimeSpec::from_ticks(deadline - now))?;
             }
-            task_ref.signals.insert(flag);
-            0
-        } else {
-            -1
+            return Err(Errno::EINTR);
         }
-    } else {
-        -1
+        // an ignored signal wakes the task up as well
+        let waiter = Waiter::new(true);
+        add_timer(deadline, waiter.clone());
+        waiter.wait();
+        remove_timer(&waiter);
     }
 }
 
-fn check_sigaction_error(signal: SignalFlags, action: usize, old_action: usize) -> bool {
-    if action == 0
-        || old_action == 0
-        || signal == SignalFlags::SIGKILL
-        || signal == SignalFlags::SIGSTOP
-    {
-        true
-    } else {
-        false
+/// Turn `signum` into its flag, `EINVAL` if it is out of range
+fn signal_flag(signum: i32) -> SysResult<SignalFlags> {
+    if !(0..=MAX_SIG as i32).contains(&signum) {
+        return Err(Errno::EINVAL);
+    }
+    SignalFlags::from_bits(1 << signum).ok_or(Errno::EINVAL)
+}
+
+/// Send signal `signum` to process `pid`, to every process in the process
+/// group of the caller if `pid` is 0, or in group `-pid` if it is below -1.
+/// Signal 0 is not sent, it only checks that a process exists.
+pub fn sys_kill(pid: isize, signum: i32) -> SysResult {
+    let flag = signal_flag(signum)?;
+    let current = current_process();
+    let targets = match pid {
+        0 => {
+            let pgid = current.inner_exclusive_access().pgid;
+            process_group(pgid)
+        }
+        ..=-2 => process_group(pid.unsigned_abs()),
+        -1 => return Err(Errno::EINVAL),
+        _ => pid2process(pid as usize).into_iter().collect(),
+    };
+    if targets.is_empty() {
+        return Err(Errno::ESRCH);
+    }
+    if flag == SignalFlags::SIGDEF {
+        return Ok(0);
     }
+    let info = SignalInfo::user(flag, current.getpid());
+    for process in targets {
+        process.send_signal(info);
+    }
+    Ok(0)
 }
 
+/// Set the action of `signum` to `*action`, and save the previous one into `*old_action`.
+/// Either pointer may be null. The actions of `SIGKILL` and `SIGSTOP` cannot be changed.
 pub fn sys_sigaction(
     signum: i32,
     action: *const SignalAction,
     old_action: *mut SignalAction,
-) -> isize {
+) -> SysResult {
     let token = current_user_token();
+    let process = current_process();
+    let flag = signal_flag(signum)?;
+    if flag == SignalFlags::SIGKILL || flag == SignalFlags::SIGSTOP {
+        return Err(Errno::EINVAL);
+    }
+    // read the new action before anything is changed
+    let new_action = if action.is_null() {
+        None
+    } else {
+        Some(copy_from_user(token, action)?)
+    };
+    let prev_action = process.inner_exclusive_access().signal_actions.table[signum as usize];
+    if !old_action.is_null() {
+        copy_to_user(token, old_action, &prev_action)?;
+    }
+    if let Some(new_action) = new_action {
+        let mut inner = process.inner_exclusive_access();
+        inner.signal_actions.table[signum as usize] = new_action;
+        if new_action.handler == SIG_IGN {
+            // a pending signal is discarded as well
+            inner.signals.remove(flag);
+        }
+    }
+    Ok(0)
+}
+
+/// Set the signal mask of the calling thread, return the previous one.
+/// `SIGKILL` and `SIGSTOP` cannot be blocked and are left out silently.
+pub fn sys_sigprocmask(mask: u32) -> SysResult {
     let task = current_task().unwrap();
     let mut inner = task.inner_exclusive_access();
-    if signum as usize > MAX_SIG {
-        return -1;
+    let old_mask = inner.signal_mask;
+    inner.signal_mask = SignalFlags::from_bits(mask).ok_or(Errno::EINVAL)?
+        - (SignalFlags::SIGKILL | SignalFlags::SIGSTOP);
+    Ok(old_mask.bits() as usize)
+}
+
+/// Return from a signal handler to the context saved in the signal frame at
+/// the top of the user stack, and restore the signal mask saved there.
+/// Only the trampoline at `SIGRETURN_TRAMPOLINE` is supposed to call it.
+pub fn sys_sigreturn() -> SysResult {
+    let token = current_user_token();
+    let trap_ctx = current_trap_cx();
+    let sp = trap_ctx.x[2];
+    let Ok(frame) = copy_from_user(token, sp as *const SignalFrame) else {
+        // there is nowhere to return to
+        let info = SignalInfo::fault(SignalFlags::SIGSEGV, sp);
+        current_process().inner_exclusive_access().add_signal(info);
+        return Err(Errno::EFAULT);
+    };
+    frame.ucontext.mcontext.restore(trap_ctx);
+    current_task().unwrap().inner_exclusive_access().signal_mask =
+        frame.ucontext.sigmask - (SignalFlags::SIGKILL | SignalFlags::SIGSTOP);
+    // returned into a0 again
+    Ok(trap_ctx.x[10])
+}
+
+/// `which` of [`sys_setpriority`] and [`sys_getpriority`], only processes are supported
+const PRIO_PROCESS: usize = 0;
+
+/// Set nice value of all threads of process `who` (0 for the calling one),
+/// clamped into `NICE_MIN..=NICE_MAX`.
+pub fn sys_setpriority(which: usize, who: usize, nice: i32) -> SysResult {
+    let process = priority_target(which, who)?;
+    let inner = process.inner_exclusive_access();
+    for task in inner.tasks.iter().flatten() {
+        task.inner_exclusive_access().sched.nice = nice.clamp(NICE_MIN, NICE_MAX);
     }
-    if let Some(flag) = SignalFlags::from_bits(1 << signum) {
-        if check_sigaction_error(flag, action as usize, old_action as usize) {
-            return -1;
-        }
-        let prev_action = inner.signal_actions.table[signum as usize];
-        *translated_refmut(token, old_action) = prev_action;
-        inner.signal_actions.table[signum as usize] = *translated_ref(token, action);
-        0
+    Ok(0)
+}
+
+/// Get nice value of process `who` (0 for the calling one), which is the one
+/// of its first thread.
+/// As in Linux, `20 - nice` is returned so that a valid result is never negative.
+pub fn sys_getpriority(which: usize, who: usize) -> SysResult {
+    let process = priority_target(which, who)?;
+    let inner = process.inner_exclusive_access();
+    let task = inner.tasks.iter().flatten().next().ok_or(Errno::ESRCH)?;
+    let nice = task.inner_exclusive_access().sched.nice;
+    Ok((20 - nice) as usize)
+}
+
+/// Find the process named by `which` and `who` of [`sys_setpriority`] and [`sys_getpriority`]
+fn priority_target(which: usize, who: usize) -> SysResult<Arc<ProcessControlBlock>> {
+    if which != PRIO_PROCESS {
+        return Err(Errno::EINVAL);
+    }
+    if who == 0 {
+        Ok(current_process())
     } else {
-        -1
+        pid2process(who).ok_or(Errno::ESRCH)
     }
 }
 
-pub fn sys_sigprocmask(mask: u32) -> isize {
-    if let Some(task) = current_task() {
-        let mut inner = task.inner_exclusive_access();
-        let old_mask = inner.signal_mask;
-        if let Some(flag) = SignalFlags::from_bits(mask) {
-            inner.signal_mask = flag;
-            old_mask.bits() as isize
-        } else {
-            -1
-        }
+/// Get time in milliseconds
+pub fn sys_get_time() -> SysResult {
+    Ok(get_time_ms())
+}
+
+/// Interval timer `which` of the current process and the time on its clock
+fn current_itimer(which: usize) -> Result<(Arc<ProcessControlBlock>, usize), Errno> {
+    if which > ITIMER_PROF {
+        return Err(Errno::EINVAL);
+    }
+    // the cpu time clocks count the running time slice as well
+    let task = current_task().unwrap();
+    task.inner_exclusive_access().sched.charge(get_time());
+    let process = task.process();
+    let now = process.inner_exclusive_access().itimer_clock(which);
+    Ok((process, now))
+}
+
+/// Save the time left on interval timer `which` and its period into `*curr_value`
+pub fn sys_getitimer(which: usize, curr_value: *mut ITimerVal) -> SysResult {
+    let (process, now) = current_itimer(which)?;
+    let value = process.inner_exclusive_access().itimers[which].get(now);
+    copy_to_user(current_user_token(), curr_value, &value)?;
+    Ok(0)
+}
+
+/// Set interval timer `which` to `*new_value`, and save its previous value
+/// into `*old_value` unless it is null. A zero `it_value` disarms the timer.
+pub fn sys_setitimer(
+    which: usize,
+    new_value: *const ITimerVal,
+    old_value: *mut ITimerVal,
+) -> SysResult {
+    let token = current_user_token();
+    let new_value = copy_from_user(token, new_value)?;
+    let value = new_value.it_value.to_ticks().ok_or(Errno::EINVAL)?;
+    let interval = new_value.it_interval.to_ticks().ok_or(Errno::EINVAL)?;
+    let (process, now) = current_itimer(which)?;
+    let mut inner = process.inner_exclusive_access();
+    let old = inner.itimers[which].get(now);
+    inner.itimers[which].set(now, value, interval);
+    let expire = inner.itimers[which].expire;
+    drop(inner);
+    if which == ITIMER_REAL && expire != 0 {
+        process.add_real_timer(expire);
+    }
+    if !old_value.is_null() {
+        copy_to_user(token, old_value, &old)?;
+    }
+    Ok(0)
+}
+
+pub fn sys_getpid() -> SysResult {
+    Ok(current_process().getpid())
+}
+
+/// Process `pid`, or the caller if it is 0
+fn process_or_current(pid: usize) -> SysResult<Arc<ProcessControlBlock>> {
+    if pid == 0 {
+        Ok(current_process())
     } else {
-        -1
+        pid2process(pid).ok_or(Errno::ESRCH)
     }
 }
 
-pub fn sys_sigreturn() -> isize {
-    if let Some(task) = current_task() {
-        let mut inner = task.inner_exclusive_access();
-        inner.handling_sig = -1;
-        // restore the trap context
-        let trap_ctx = inner.get_trap_cx();
-        *trap_ctx = inner.trap_ctx_backup.unwrap();
-        trap_ctx.x[10] as isize
+/// Move process `pid` (the caller if 0) into process group `pgid` (a new one
+/// led by `pid` if 0). The process must be the caller or one of its children,
+/// and the group must be in the session of the caller.
+pub fn sys_setpgid(pid: usize, pgid: usize) -> SysResult {
+    let current = current_process();
+    let target = if pid == 0 || pid == current.getpid() {
+        current.clone()
     } else {
-        -1
+        let inner = current.inner_exclusive_access();
+        let child = inner.children.iter().find(|child| child.getpid() == pid);
+        child.cloned().ok_or(Errno::ESRCH)?
+    };
+    let pgid = if pgid == 0 { target.getpid() } else { pgid };
+    let sid = current.inner_exclusive_access().sid;
+    if pgid != target.getpid()
+        && !process_group(pgid)
+            .iter()
+            .any(|process| process.inner_exclusive_access().sid == sid)
+    {
+        return Err(Errno::EPERM);
     }
+    let mut target_inner = target.inner_exclusive_access();
+    // a session leader cannot leave its group
+    if target_inner.sid != sid || target_inner.sid == target.getpid() {
+        return Err(Errno::EPERM);
+    }
+    target_inner.pgid = pgid;
+    Ok(0)
 }
 
-/// Get time in microseconds
-pub fn sys_get_time() -> isize {
-    get_time_ms() as isize
+/// Process group of process `pid`, or of the caller if it is 0
+pub fn sys_getpgid(pid: usize) -> SysResult {
+    Ok(process_or_current(pid)?.inner_exclusive_access().pgid)
 }
 
-pub fn sys_getpid() -> isize {
-    current_task().unwrap().pid.0 as isize
+/// Session of process `pid`, or of the caller if it is 0
+pub fn sys_getsid(pid: usize) -> SysResult {
+    Ok(process_or_current(pid)?.inner_exclusive_access().sid)
 }
 
-pub fn sys_sbrk(size: i32) -> isize {
-    let current_task = current_task().unwrap();
-    if let Some(old_brk) = current_task.change_program_brk(size) {
-        old_brk as isize
+/// Make the caller lead a new session and a new process group, both named
+/// after its pid, and return it. Fail with `EPERM` if a process group with
+/// that id exists already, e.g. when the caller leads one.
+pub fn sys_setsid() -> SysResult {
+    let current = current_process();
+    let pid = current.getpid();
+    if !process_group(pid).is_empty() {
+        return Err(Errno::EPERM);
+    }
+    let mut inner = current.inner_exclusive_access();
+    inner.pgid = pid;
+    inner.sid = pid;
+    Ok(pid)
+}
+
+pub fn sys_sbrk(size: i32) -> SysResult {
+    current_process()
+        .inner_exclusive_access()
+        .change_program_brk(size)
+        .ok_or(Errno::ENOMEM)
+}
+
+/// Map `len` bytes of anonymous memory or of the file `fd` from `offset`.
+/// `addr` is only a hint unless `MAP_FIXED` is given.
+/// Return the start address of the mapping.
+pub fn sys_mmap(
+    addr: usize,
+    len: usize,
+    prot: u32,
+    flags: u32,
+    fd: usize,
+    offset: usize,
+) -> SysResult {
+    let prot = MmapProt::from_bits(prot).ok_or(Errno::EINVAL)?;
+    let flags = MmapFlags::from_bits(flags).ok_or(Errno::EINVAL)?;
+    // exactly one of MAP_SHARED and MAP_PRIVATE
+    if len == 0
+        || offset % PAGE_SIZE != 0
+        || flags.contains(MmapFlags::SHARED) == flags.contains(MmapFlags::PRIVATE)
+    {
+        return Err(Errno::EINVAL);
+    }
+    let shared = flags.contains(MmapFlags::SHARED);
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    let backing = if flags.contains(MmapFlags::ANONYMOUS) {
+        MapBacking::Zero
+    } else {
+        let file = inner.get_file(fd)?;
+        // only files on disk can be mapped
+        let inode = file.inode().ok_or(Errno::EINVAL)?;
+        if !file.readable() || (shared && prot.contains(MmapProt::WRITE) && !file.writable()) {
+            return Err(Errno::EBADF);
+        }
+        MapBacking::File { inode, offset }
+    };
+    let start = if flags.contains(MmapFlags::FIXED) {
+        if addr % PAGE_SIZE != 0 {
+            return Err(Errno::EINVAL);
+        }
+        Some(VirtAddr::from(addr))
     } else {
-        -1
+        None
+    };
+    inner
+        .memory_set
+        .mmap(start, len, prot.into(), backing, shared)
+        .map(usize::from)
+        .ok_or(Errno::ENOMEM)
+}
+
+/// Check that `[addr, addr + len)` is a page aligned range of user space
+fn user_page_range(addr: usize, len: usize) -> SysResult<(VirtAddr, VirtAddr)> {
+    let end = addr.checked_add(len).ok_or(Errno::EINVAL)?;
+    if addr % PAGE_SIZE != 0 || len == 0 || end > USER_SPACE_TOP {
+        return Err(Errno::EINVAL);
     }
+    Ok((VirtAddr::from(addr), VirtAddr::from(end)))
+}
+
+/// Unmap the pages in `[addr, addr + len)`
+pub fn sys_munmap(addr: usize, len: usize) -> SysResult {
+    let (start, end) = user_page_range(addr, len)?;
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    inner.memory_set.munmap(start.floor(), end.ceil());
+    Ok(0)
 }
 
-/// Fork a process
-pub fn sys_fork() -> isize {
+/// Change the protection of the pages in `[addr, addr + len)`.
+/// Fail with `ENOMEM` if some of the pages are not mapped.
+pub fn sys_mprotect(addr: usize, len: usize, prot: u32) -> SysResult {
+    let (start, end) = user_page_range(addr, len)?;
+    let prot = MmapProt::from_bits(prot).ok_or(Errno::EINVAL)?;
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    if inner
+        .memory_set
+        .mprotect(start.floor(), end.ceil(), prot.into())
+    {
+        Ok(0)
+    } else {
+        Err(Errno::ENOMEM)
+    }
+}
+
+/// Fork a process, only the calling thread is copied into the child
+pub fn sys_fork() -> SysResult {
     let current_task = current_task().unwrap();
-    let new_task = current_task.fork();
-    let new_pid = new_task.pid.0;
+    let new_task = current_task.process().fork(&current_task);
+    let new_pid = new_task.process().getpid();
     let trap_cx = new_task.inner_exclusive_access().get_trap_cx();
     trap_cx.x[10] = 0; // ra = 0
     add_task(new_task);
-    new_pid as isize
+    Ok(new_pid)
 }
 
-/// Execute a new program
-pub fn sys_exec(path: *const u8, mut args: *const usize) -> isize {
+/// Execute a new program. The other threads of the process exit first,
+/// see [`ProcessControlBlock::exec`].
+pub fn sys_exec(path: *const u8, mut args: *const usize) -> SysResult {
     let token = current_user_token();
-    let path = translated_str(token, path);
+    let path = translated_str(token, path)?;
     let mut args_vec: Vec<String> = Vec::new();
     loop {
-        let args_str_ptr = *translated_ref(token, args);
+        let args_str_ptr = *translated_ref(token, args)?;
         if args_str_ptr == 0 {
             break;
         }
-        args_vec.push(translated_str(token, args_str_ptr as *const u8));
-        unsafe { args = args.add(1) };
-    }
-
-    if let Some(app_inode) = open_file(path.as_str(), OpenFlags::RDONLY) {
-        // FIXME: lazy loading
-        let all_data = app_inode.read_all();
-        let task = current_task().unwrap();
-        let argc = args_vec.len();
-        task.exec(all_data.as_slice(), args_vec);
-        // return argc because cx.x[10] will be covered with it later
-        argc as isize
-    } else {
-        -1
+        args_vec.push(translated_str(token, args_str_ptr as *const u8)?);
+        args = args.wrapping_add(1);
     }
-}
 
-/// If there is not a child process whose pid is same as given, return -1.
-/// Else if there is a child process but it is still running, return -2.
-pub fn sys_waitpid(pid: isize, exit_code_ptr: *mut i32) -> isize {
+    let cwd = current_process().inner_exclusive_access().cwd.clone();
+    let app_inode = open_file(&cwd, path.as_str(), OpenFlags::RDONLY)?;
+    // FIXME: lazy loading
+    let all_data = app_inode.read_all();
     let task = current_task().unwrap();
-    // find a child process
+    let argc = args_vec.len();
+    task.process().exec(&task, all_data.as_slice(), args_vec)?;
+    // return argc because cx.x[10] will be covered with it later
+    Ok(argc)
+}
 
-    // ---- access current TCB exclusively
-    let mut inner = task.inner_exclusive_access();
-    if !inner
-        .children
-        .iter()
-        .any(|p| pid == -1 || pid as usize == p.getpid())
-    {
-        return -1;
-        // ---- release current TCB
-    }
-    let pair = inner.children.iter().enumerate().find(|(_, p)| {
-        // ++++ temporarily access child TCB lock exclusively
-        p.inner_exclusive_access().is_zombie() && (pid == -1 || pid as usize == p.getpid())
-        // ++++ release child TCB automatically
-    });
-    if let Some((idx, _)) = pair {
-        let child = inner.children.remove(idx);
-        // confirm that child will be deallocated after removing from children list
-        assert_eq!(Arc::strong_count(&child), 1);
-        let found_pid = child.getpid();
-        // ++++ temporarily access child TCB exclusively
-        let exit_code = child.inner_exclusive_access().exit_code;
-        // ++++ release child TCB
-        *translated_refmut(inner.memory_set.token(), exit_code_ptr) = exit_code;
-        found_pid as isize
-    } else {
-        -2
+/// `options` of [`sys_wait4`]: return at once if no child has exited
+const WNOHANG: u32 = 1;
+/// `options` of [`sys_wait4`]: also report a child which has stopped
+const WUNTRACED: u32 = 2;
+/// `options` of [`sys_wait4`]: also report a stopped child which has been
+/// continued by `SIGCONT`
+const WCONTINUED: u32 = 8;
+
+/// Wait for a child to exit: child `pid`, any child if `pid` is -1, a child in
+/// the process group of the caller if it is 0, or in group `-pid` below that.
+///
+/// If there is no such child, fail with `ECHILD`. Else if all of them are still
+/// running, block until one exits or, with `WUNTRACED` and `WCONTINUED`, stops
+/// or continues, or fail with `EINTR` if a signal comes first. Return 0 at once
+/// instead with `WNOHANG`.
+///
+/// The wait status of the child, which tells an exit code from a signal that
+/// terminated or stopped it, is saved into `*status_ptr`, and its resource
+/// usage into `*rusage_ptr`. Either pointer may be null.
+pub fn sys_wait4(
+    pid: isize,
+    status_ptr: *mut i32,
+    options: u32,
+    rusage_ptr: *mut RUsage,
+) -> SysResult {
+    if options & !(WNOHANG | WUNTRACED | WCONTINUED) != 0 {
+        return Err(Errno::EINVAL);
+    }
+    let process = current_process();
+    loop {
+        // a child exiting after this wakes us up
+        let ticket = process.child_exit.prepare_to_wait();
+        // ---- access current PCB exclusively
+        let mut inner = process.inner_exclusive_access();
+        let pgid = inner.pgid;
+        let wanted = |child_pid: usize, child_pgid: usize| match pid {
+            -1 => true,
+            0 => child_pgid == pgid,
+            ..=-2 => child_pgid == pid.unsigned_abs(),
+            _ => child_pid == pid as usize,
+        };
+        let mut any_child = false;
+        // (index, status, usage) of a child which has stopped or continued
+        let mut changed = None;
+        // index of a zombie child
+        let mut zombie = None;
+        for (idx, child) in inner.children.iter().enumerate() {
+            // ++++ temporarily access child PCB exclusively
+            let mut child_inner = child.inner_exclusive_access();
+            if !wanted(child.getpid(), child_inner.pgid) {
+                continue;
+            }
+            any_child = true;
+            if child_inner.is_zombie {
+                zombie = Some(idx);
+                break;
+            }
+            let status = if options & WUNTRACED != 0 && child_inner.stop_status.is_some() {
+                child_inner.stop_status.take()
+            } else if options & WCONTINUED != 0 && child_inner.continued {
+                child_inner.continued = false;
+                Some(CONTINUED_STATUS)
+            } else {
+                None
+            };
+            if let Some(status) = status {
+                changed = Some((idx, status, child_inner.total_usage()));
+                break;
+            }
+            // ++++ release child PCB
+        }
+        if !any_child {
+            return Err(Errno::ECHILD);
+            // ---- release current PCB
+        }
+        let found = if let Some(idx) = zombie {
+            let child = inner.children.remove(idx);
+            // the last thread of the child may still be switching away from
+            // its kernel stack on another hart
+            child.wait_off_cpu();
+            // confirm that child will be deallocated after removing from children list
+            assert_eq!(Arc::strong_count(&child), 1);
+            // ++++ temporarily access child PCB exclusively
+            let child_inner = child.inner_exclusive_access();
+            let usage = child_inner.total_usage();
+            // the time of a child counts for its parent once it is waited for
+            inner.children_usage += usage;
+            Some((child.getpid(), child_inner.exit_status, usage))
+            // ++++ release child PCB
+        } else {
+            // a stopped child stays until it exits
+            changed.map(|(idx, status, usage)| (inner.children[idx].getpid(), status, usage))
+        };
+        if let Some((found_pid, status, usage)) = found {
+            let token = inner.memory_set.token();
+            // accessing user memory may need to access the memory set
+            drop(inner);
+            if !status_ptr.is_null() {
+                copy_to_user(token, status_ptr, &status)?;
+            }
+            if !rusage_ptr.is_null() {
+                copy_to_user(token, rusage_ptr, &usage.to_rusage())?;
+            }
+            return Ok(found_pid);
+        }
+        if options & WNOHANG != 0 {
+            return Ok(0);
+        }
+        drop(inner);
+        // ---- release current PCB lock
+        if current_interrupted() {
+            return Err(Errno::EINTR);
+        }
+        process.child_exit.wait_interruptible(ticket);
     }
-    // ---- release current TCB lock automatically
 }
 
 pub fn sys_reboot() -> ! {
@@ -0,0 +1,42 @@
+use core::sync::atomic::AtomicU32;
+
+use crate::errno::{Errno, SysResult};
+use crate::mm::{copy_from_user, translated_refmut};
+use crate::sync::{futex_wait, futex_wake};
+use crate::task::current_user_token;
+use crate::timer::{get_time, TimeSpec};
+
+/// Block while the word still holds `val`
+const FUTEX_WAIT: u32 = 0;
+/// Wake up at most `val` waiters
+const FUTEX_WAKE: u32 = 1;
+/// Futexes are keyed by physical address either way, so this is ignored
+const FUTEX_PRIVATE_FLAG: u32 = 128;
+
+/// Operate on the futex of the word at `uaddr`, see [`futex_wait`] and
+/// [`futex_wake`]. For `FUTEX_WAIT`, `timeout` is relative and may be null
+/// to block without one. `FUTEX_WAKE` returns how many tasks were woken up.
+pub fn sys_futex(uaddr: *mut u32, op: u32, val: u32, timeout: *const TimeSpec) -> SysResult {
+    if uaddr as usize % core::mem::align_of::<u32>() != 0 {
+        return Err(Errno::EINVAL);
+    }
+    let token = current_user_token();
+    // resolved for writing, so that a copy-on-write page gets its own frame
+    // before its address is taken as the key
+    let word = translated_refmut(token, uaddr as *mut AtomicU32)?;
+    match op & !FUTEX_PRIVATE_FLAG {
+        FUTEX_WAIT => {
+            let deadline = if timeout.is_null() {
+                None
+            } else {
+                let ticks = copy_from_user(token, timeout)?
+                    .to_ticks()
+                    .ok_or(Errno::EINVAL)?;
+                Some(get_time().saturating_add(ticks))
+            };
+            futex_wait(word, val, deadline)
+        }
+        FUTEX_WAKE => Ok(futex_wake(word, val as usize)),
+        _ => Err(Errno::ENOSYS),
+    }
+}
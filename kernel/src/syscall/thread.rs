@@ -0,0 +1,69 @@
+use alloc::sync::Arc;
+
+use crate::errno::{Errno, SysResult};
+use crate::mm::copy_to_user;
+use crate::task::{add_task, current_interrupted, current_task};
+
+/// Create a thread in the current process which runs `entry(arg)` on a new
+/// user stack. Return its tid, or `ENOMEM` if there is no room for its stack.
+pub fn sys_thread_create(entry: usize, arg: usize) -> SysResult {
+    let task = current_task().unwrap();
+    // threads start with the priority and signal mask of their creator
+    let task_inner = task.inner_exclusive_access();
+    let (sched, signal_mask) = (task_inner.sched.fork(), task_inner.signal_mask);
+    drop(task_inner);
+    let new_task = task
+        .process()
+        .create_thread(entry, arg, sched, signal_mask)?;
+    let tid = new_task.gettid();
+    add_task(new_task);
+    Ok(tid)
+}
+
+pub fn sys_gettid() -> SysResult {
+    Ok(current_task().unwrap().gettid())
+}
+
+/// Block until thread `tid` of the current process exits, then write its exit
+/// code into `*exit_code_ptr` unless it is null and return `tid`.
+/// Fail with `ESRCH` if there is no such thread, `EDEADLK` for the calling one,
+/// or `EINTR` if a signal comes first.
+pub fn sys_waittid(tid: usize, exit_code_ptr: *mut i32) -> SysResult {
+    let task = current_task().unwrap();
+    if task.gettid() == tid {
+        return Err(Errno::EDEADLK);
+    }
+    let process = task.process();
+    loop {
+        // a thread exiting after this wakes us up
+        let ticket = process.thread_exit.prepare_to_wait();
+        // ---- access current PCB exclusively
+        let mut inner = process.inner_exclusive_access();
+        let waited = inner.get_task(tid).ok_or(Errno::ESRCH)?;
+        // ++++ temporarily access waited TCB exclusively
+        let exit_code = waited.inner_exclusive_access().exit_code;
+        // ++++ release waited TCB
+        if let Some(exit_code) = exit_code {
+            inner.tasks[tid] = None;
+            inner.dealloc_tid(tid);
+            let token = inner.get_user_token();
+            // accessing user memory may need to access the memory set
+            drop(inner);
+            // ---- release current PCB
+            // it may still be switching away from its kernel stack on another hart
+            waited.wait_off_cpu();
+            // confirm that the thread will be deallocated
+            assert_eq!(Arc::strong_count(&waited), 1);
+            if !exit_code_ptr.is_null() {
+                copy_to_user(token, exit_code_ptr, &exit_code)?;
+            }
+            return Ok(tid);
+        }
+        drop(inner);
+        // ---- release current PCB
+        if current_interrupted() {
+            return Err(Errno::EINTR);
+        }
+        process.thread_exit.wait_interruptible(ticket);
+    }
+}
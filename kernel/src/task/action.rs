@@ -1,18 +1,43 @@
 use crate::task::{SignalFlags, MAX_SIG};
+use bitflags::*;
+
+bitflags! {
+    /// `flags` of [`SignalAction`], the same values as Linux
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct SignalActionFlags: u32 {
+        /// For `SIGCHLD`: do not send it when a child stops
+        const SA_NOCLDSTOP = 1;
+        /// Pass `SignalInfo` and `UContext` to the handler
+        const SA_SIGINFO = 4;
+        /// Restart a syscall interrupted by the signal instead of failing with `EINTR`
+        const SA_RESTART = 0x1000_0000;
+        /// Do not block the signal while its handler runs
+        const SA_NODEFER = 0x4000_0000;
+    }
+}
+
+/// `handler` of [`SignalAction`] for the default action of the signal
+pub const SIG_DFL: usize = 0;
+/// `handler` of [`SignalAction`] to discard the signal
+pub const SIG_IGN: usize = 1;
 
 /// Action for a signal
 #[repr(C, align(16))]
 #[derive(Debug, Clone, Copy)]
 pub struct SignalAction {
+    /// Address of the handler, or [`SIG_DFL`] or [`SIG_IGN`]
     pub handler: usize,
+    /// Blocked while the handler runs, besides the signal itself
     pub mask: SignalFlags,
+    pub flags: SignalActionFlags,
 }
 
 impl Default for SignalAction {
     fn default() -> Self {
         Self {
-            handler: 0,
+            handler: SIG_DFL,
             mask: SignalFlags::from_bits(40).unwrap(),
+            flags: SignalActionFlags::empty(),
         }
     }
 }
@@ -0,0 +1,201 @@
+use alloc::vec::Vec;
+use lazy_static::lazy_static;
+
+use crate::config::*;
+use crate::errno::{Errno, SysResult};
+use crate::mm::{flush_tlb, MapPermission, MemorySet, PhysPageNum, VirtAddr, KERNEL_SPACE};
+use crate::sync::SpinLock;
+
+/// Hands out ids, reusing the ones given back first
+#[derive(Debug)]
+pub struct RecycleAllocator {
+    current: usize,
+    recycled: Vec<usize>,
+}
+
+impl RecycleAllocator {
+    pub fn new() -> Self {
+        RecycleAllocator {
+            current: 0,
+            recycled: Vec::new(),
+        }
+    }
+    pub fn alloc(&mut self) -> usize {
+        if let Some(id) = self.recycled.pop() {
+            id
+        } else {
+            self.current += 1;
+            self.current - 1
+        }
+    }
+    pub fn dealloc(&mut self, id: usize) {
+        assert!(id < self.current);
+        assert!(
+            !self.recycled.iter().any(|i| *i == id),
+            "id {} has been deallocated!",
+            id
+        );
+        self.recycled.push(id);
+    }
+}
+
+lazy_static! {
+    static ref PID_ALLOCATOR: SpinLock<RecycleAllocator> = SpinLock::new(RecycleAllocator::new());
+    static ref KSTACK_ALLOCATOR: SpinLock<RecycleAllocator> =
+        SpinLock::new(RecycleAllocator::new());
+}
+
+/// RAII PID
+#[derive(Debug)]
+pub struct PidHandle(pub usize);
+
+impl Drop for PidHandle {
+    fn drop(&mut self) {
+        PID_ALLOCATOR.exclusive_access().dealloc(self.0);
+    }
+}
+
+pub fn pid_alloc() -> PidHandle {
+    PidHandle(PID_ALLOCATOR.exclusive_access().alloc())
+}
+
+/// Return (bottom, top) of a kernel stack in kernel space.
+pub fn kernel_stack_position(kstack_id: usize) -> (usize, usize) {
+    let top = TRAMPOLINE - kstack_id * (KERNEL_STACK_SIZE + PAGE_SIZE);
+    let bottom = top - KERNEL_STACK_SIZE;
+    (bottom, top)
+}
+
+/// RAII kernel stack of a thread, mapped in kernel space
+#[derive(Debug)]
+pub struct KernelStack(pub usize);
+
+/// Allocate and map a kernel stack
+pub fn kstack_alloc() -> KernelStack {
+    let kstack_id = KSTACK_ALLOCATOR.exclusive_access().alloc();
+    let (kernel_stack_bottom, kernel_stack_top) = kernel_stack_position(kstack_id);
+    KERNEL_SPACE.exclusive_access().insert_framed_area(
+        kernel_stack_bottom.into(),
+        kernel_stack_top.into(),
+        MapPermission::R | MapPermission::W,
+    );
+    KernelStack(kstack_id)
+}
+
+impl KernelStack {
+    /// Push a value on top of kernel stack
+    #[allow(unused)]
+    pub fn push_on_top<T>(&self, value: T) -> *mut T
+    where
+        T: Sized,
+    {
+        let kernel_stack_top = self.get_top();
+        let ptr_mut = (kernel_stack_top - core::mem::size_of::<T>()) as *mut T;
+        unsafe { *ptr_mut = value };
+        ptr_mut
+    }
+    pub fn get_top(&self) -> usize {
+        let (_, kernel_stack_top) = kernel_stack_position(self.0);
+        kernel_stack_top
+    }
+}
+
+impl Drop for KernelStack {
+    fn drop(&mut self) {
+        let (kernel_stack_bottom, _) = kernel_stack_position(self.0);
+        let kernel_stack_bottom_va: VirtAddr = kernel_stack_bottom.into();
+        KERNEL_SPACE
+            .exclusive_access()
+            .remove_area_with_start_vpn(kernel_stack_bottom_va.into());
+        KSTACK_ALLOCATOR.exclusive_access().dealloc(self.0);
+    }
+}
+
+/// Address of the trap context of thread `tid` in user space
+pub fn trap_cx_bottom_from_tid(tid: usize) -> usize {
+    TRAP_CONTEXT_BASE - tid * PAGE_SIZE
+}
+
+/// Bottom of the `slot`-th stack for threads, counted downwards from the
+/// top of user space with a guard page above each stack
+fn thread_stack_bottom(slot: usize) -> usize {
+    USER_SPACE_TOP - (slot + 1) * (USER_STACK_SIZE + PAGE_SIZE)
+}
+
+/// Resources of a thread in the memory set of its process.
+///
+/// They are not freed on drop, since the memory set may be gone already
+/// (for example after `exec`). Call [`TaskUserRes::dealloc`] explicitly.
+#[derive(Debug)]
+pub struct TaskUserRes {
+    pub tid: usize,
+    /// Bottom of the user stack mapped for this thread, `None` if it
+    /// runs on the stack set up by `exec`
+    pub ustack_bottom: Option<usize>,
+}
+
+impl TaskUserRes {
+    /// Map the trap context and a user stack of thread `tid` into `memory_set`.
+    /// Fail with `ENOMEM` if there is no room left for another stack.
+    pub fn alloc(memory_set: &mut MemorySet, tid: usize) -> SysResult<Self> {
+        let trap_cx_bottom = VirtAddr::from(trap_cx_bottom_from_tid(tid));
+        let trap_cx_top = VirtAddr::from(trap_cx_bottom_from_tid(tid) + PAGE_SIZE);
+        if !memory_set.is_unmapped(trap_cx_bottom.floor(), trap_cx_top.ceil()) {
+            return Err(Errno::ENOMEM);
+        }
+        // the lowest free slot, the user may have mapped something over the others
+        let ustack_bottom = (0..)
+            .map(thread_stack_bottom)
+            .take_while(|bottom| *bottom >= MMAP_BASE)
+            .find(|bottom| {
+                let start = VirtAddr::from(*bottom).floor();
+                let end = VirtAddr::from(*bottom + USER_STACK_SIZE).ceil();
+                memory_set.is_unmapped(start, end)
+            })
+            .ok_or(Errno::ENOMEM)?;
+        memory_set.insert_framed_area(
+            ustack_bottom.into(),
+            (ustack_bottom + USER_STACK_SIZE).into(),
+            MapPermission::R | MapPermission::W | MapPermission::U,
+        );
+        let res = Self {
+            tid,
+            ustack_bottom: Some(ustack_bottom),
+        };
+        res.map_trap_cx(memory_set);
+        Ok(res)
+    }
+    /// Map the trap context page of this thread into `memory_set`
+    pub fn map_trap_cx(&self, memory_set: &mut MemorySet) {
+        let trap_cx_bottom = self.trap_cx_user_va();
+        memory_set.insert_framed_area(
+            trap_cx_bottom.into(),
+            (trap_cx_bottom + PAGE_SIZE).into(),
+            MapPermission::R | MapPermission::W,
+        );
+    }
+    /// Unmap the trap context and the user stack of this thread from `memory_set`.
+    /// The tid stays in use until the thread is waited for.
+    pub fn dealloc(&mut self, memory_set: &mut MemorySet) {
+        memory_set.remove_area_with_start_vpn(VirtAddr::from(self.trap_cx_user_va()).into());
+        if let Some(ustack_bottom) = self.ustack_bottom.take() {
+            memory_set.remove_area_with_start_vpn(VirtAddr::from(ustack_bottom).into());
+        }
+        // other threads may have used the stack on other harts
+        flush_tlb();
+    }
+    pub fn trap_cx_user_va(&self) -> usize {
+        trap_cx_bottom_from_tid(self.tid)
+    }
+    /// Physical page of the trap context of this thread in `memory_set`
+    pub fn trap_cx_ppn(&self, memory_set: &MemorySet) -> PhysPageNum {
+        memory_set
+            .translate(VirtAddr::from(self.trap_cx_user_va()).into())
+            .unwrap()
+            .ppn()
+    }
+    /// Top of the user stack mapped for this thread
+    pub fn ustack_top(&self) -> Option<usize> {
+        self.ustack_bottom.map(|bottom| bottom + USER_STACK_SIZE)
+    }
+}
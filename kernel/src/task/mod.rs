@@ -6,42 +6,56 @@
 //! A single global instance of [`TaskManager`] called `TASK_MANAGER` controls
 //! all the tasks in the whole operating system.
 //!
-//! A single global instance of [`Processor`] called `PROCESSOR` monitors running
-//! task(s) for each core.
+//! One instance of [`Processor`] per hart in `PROCESSORS` monitors the task
+//! running on that hart. Every hart takes ready tasks from the shared `TASK_MANAGER`.
 //!
-//! A single global instance of [`PidAllocator`] called `PID_ALLOCATOR` allocates
-//! pid for user apps.
+//! A task is a thread. The threads of a process share its address space, files
+//! and signal state, kept in a [`ProcessControlBlock`].
+//!
+//! Pids, kernel stacks and the tids in each process are handed out by
+//! `RecycleAllocator`s.
 //!
 //! Be careful when you see `__switch` ASM function in `switch.S`. Control flow around this function
 //! might not be what you expect.
 mod action;
 mod context;
+mod id;
 mod manager;
-mod pid;
+mod process;
 mod processor;
+mod scheduler;
 mod signal;
 mod switch;
 #[allow(clippy::module_inception)]
 mod task;
 
-use crate::fs::inode::open_file;
+use crate::config::SIGRETURN_TRAMPOLINE;
 use crate::fs::inode::OpenFlags;
+use crate::fs::inode::{open_file, ROOT_INODE};
+use crate::mm::copy_to_user;
 use crate::sbi::shutdown;
+use crate::timer::{get_time, ITIMER_PROF, ITIMER_VIRTUAL};
+use crate::trap::TrapContext;
 use alloc::sync::Arc;
+use core::mem::offset_of;
 use lazy_static::*;
 pub use manager::fetch_task;
 use switch::__switch;
-use task::{TaskControlBlock, TaskStatus};
+pub use process::{exit_status, signal_status, stop_status, ProcessControlBlock, CONTINUED_STATUS};
+pub use task::TaskControlBlock;
+use task::TaskStatus;
 
 pub use action::*;
 pub use context::TaskContext;
 #[allow(unused)]
 pub use manager::inspect_kernel_stack;
-pub use manager::{add_task, pid2task, remove_from_pid2task};
-pub use pid::{pid_alloc, KernelStack, PidHandle};
+pub use id::{kstack_alloc, pid_alloc, KernelStack, PidHandle, TaskUserRes};
+pub use manager::{add_task, pid2process, process_group, remove_from_pid2process};
 pub use processor::{
-    current_task, current_trap_cx, current_user_token, run_tasks, schedule, take_current_task,
+    current_handle_page_fault, current_process, current_task, current_trap_cx,
+    current_trap_cx_user_va, current_user_token, hart_id, run_tasks, schedule, take_current_task,
 };
+pub use scheduler::{CpuUsage, RUsage, SchedEntity, NICE_MAX, NICE_MIN};
 pub use signal::*;
 /// Suspend the current 'Running' task and run the next task in task list.
 pub fn suspend_current_and_run_next() {
@@ -53,6 +67,9 @@ pub fn suspend_current_and_run_next() {
     let task_cx_ptr = &mut task_inner.task_cx as *mut TaskContext;
     // Change status to Ready
     task_inner.task_status = TaskStatus::Ready;
+    // account the time slice just used
+    task_inner.sched.charge(get_time());
+    task_inner.sched.usage.involuntary_switches += 1;
     drop(task_inner);
     // ---- release current PCB
 
@@ -62,21 +79,137 @@ pub fn suspend_current_and_run_next() {
     schedule(task_cx_ptr);
 }
 
+/// Block the current 'Running' task and run the next task in task list.
+/// The caller must have arranged for someone to call [`wakeup_task`] on it later.
+pub fn block_current_and_run_next() {
+    let task = take_current_task().unwrap();
+    let mut task_inner = task.inner_exclusive_access();
+    let task_cx_ptr = &mut task_inner.task_cx as *mut TaskContext;
+    task_inner.task_status = TaskStatus::Blocked;
+    task_inner.sched.charge(get_time());
+    task_inner.sched.usage.voluntary_switches += 1;
+    drop(task_inner);
+    // not pushed back to the ready queue
+    schedule(task_cx_ptr);
+}
+
+/// Send `SIGVTALRM` and `SIGPROF` to the current process when its cpu time
+/// interval timers expire, on every timer interrupt
+pub fn check_cpu_timers() {
+    let task = current_task().unwrap();
+    // count the time slice so far
+    task.inner_exclusive_access().sched.charge(get_time());
+    let process = task.process();
+    drop(task);
+    let mut process_inner = process.inner_exclusive_access();
+    let mut expired = SignalFlags::empty();
+    for (which, signal) in [
+        (ITIMER_VIRTUAL, SignalFlags::SIGVTALRM),
+        (ITIMER_PROF, SignalFlags::SIGPROF),
+    ] {
+        if process_inner.itimers[which].expire == 0 {
+            continue;
+        }
+        let now = process_inner.itimer_clock(which);
+        if process_inner.itimers[which].expired(now) {
+            expired |= signal;
+        }
+    }
+    drop(process_inner);
+    for signal in expired.iter() {
+        process.send_signal(SignalInfo::kernel(signal));
+    }
+}
+
+/// Make a blocked task ready to run again.
+pub fn wakeup_task(task: Arc<TaskControlBlock>) {
+    let mut task_inner = task.inner_exclusive_access();
+    task_inner.task_status = TaskStatus::Ready;
+    drop(task_inner);
+    add_task(task);
+}
+
 /// pid of usertests app in make run TEST=1
 pub const IDLE_PID: usize = 0;
 
-/// Exit the current 'Running' task and run the next task in task list.
+/// Exit the current process with all its threads and run the next task in
+/// task list. The other threads exit the next time they are about to return
+/// to user mode.
 pub fn exit_current_and_run_next(exit_code: i32) {
+    exit_current(exit_code, Some(exit_status(exit_code)));
+}
+
+/// Exit the current thread and run the next task in task list.
+/// The process exits as well if it is the main thread.
+pub fn exit_current_thread_and_run_next(exit_code: i32) {
+    let main_thread = current_task().unwrap().gettid() == 0;
+    exit_current(exit_code, main_thread.then(|| exit_status(exit_code)));
+}
+
+/// Terminate the current process by signal `sig` and run the next task in task list
+fn kill_current_and_run_next(sig: usize) {
+    kprintln!(
+        "[kernel] Process {} killed by signal {}",
+        current_process().getpid(),
+        sig
+    );
+    exit_current(-(sig as i32), Some(signal_status(sig)));
+}
+
+/// Exit the current thread with `exit_code`, and the process with the wait
+/// status `process_status` if it is set. The last thread to exit turns the
+/// process into a zombie, which exits with `exit_code` unless the process is
+/// exiting already.
+fn exit_current(exit_code: i32, process_status: Option<i32>) {
     // take from Processor
     let task = take_current_task().unwrap();
+    let process = task.process();
+
+    // Other harts lock a process before its threads, and a parent before its
+    // children (see `sys_wait4`), never the other way around.
+
+    // **** access current PCB exclusively
+    let mut process_inner = process.inner_exclusive_access();
+    // ++++ access current TCB exclusively
+    let mut task_inner = task.inner_exclusive_access();
+    // Change status to Zombie
+    task_inner.task_status = TaskStatus::Zombie;
+    // Record exit code
+    task_inner.exit_code = Some(exit_code);
+    // deallocate user stack and trap context, the tid is kept until waited for
+    task_inner.res.dealloc(&mut process_inner.memory_set);
+    task_inner.sched.charge(get_time());
+    process_inner.usage += task_inner.sched.usage;
+    drop(task_inner);
+    // ++++ release current TCB
+    let last_thread = process_inner.alive_threads() == 0;
+    if (process_status.is_some() || last_thread) && !process_inner.exiting {
+        process_inner.exiting = true;
+        process_inner.exit_status = process_status.unwrap_or(exit_status(exit_code));
+    }
+    if !last_thread {
+        let exiting = process_inner.exiting;
+        drop(process_inner);
+        // wake up the threads blocked in waittid or exec
+        process.thread_exit.wake_all();
+        if exiting {
+            // the others exit once they return from the kernel
+            process.interrupt_threads();
+        }
+        drop(process);
+        drop(task);
+        let mut _unused = TaskContext::zero_init();
+        schedule(&mut _unused as *mut _);
+        return;
+    }
 
-    let pid = task.getpid();
+    let pid = process.getpid();
     if pid == IDLE_PID {
         kprintln!(
-            "[kernel] Idle process exit with exit_code {} ...",
-            exit_code
+            "[kernel] Idle process exit with status {:#x} ...",
+            process_inner.exit_status
         );
-        if exit_code != 0 {
+        if process_inner.exit_status != 0 {
             //crate::sbi::shutdown(255); //255 == -1 for err hint
             shutdown(true)
         } else {
@@ -84,35 +217,36 @@ pub fn exit_current_and_run_next(exit_code: i32) {
             shutdown(false)
         }
     }
-    
-    // remove from pid2task
-    remove_from_pid2task(task.getpid());
 
-    // **** access current TCB exclusively
-    let mut inner = task.inner_exclusive_access();
-    // Change status to Zombie
-    inner.task_status = TaskStatus::Zombie;
-    // Record exit code
-    inner.exit_code = exit_code;
-    // do not move to its parent but under initproc
+    // remove from pid2process
+    remove_from_pid2process(pid);
+    process_inner.is_zombie = true;
+    let parent = process_inner.parent.as_ref().and_then(|p| p.upgrade());
+    let children = core::mem::take(&mut process_inner.children);
+    let status = process_inner.exit_status;
+    // deallocate user space
+    process_inner.memory_set.recycle_data_pages();
+    drop(process_inner);
+    // **** release current PCB
 
-    // ++++++ access initproc TCB exclusively
-    {
-        let mut initproc_inner = INITPROC.inner_exclusive_access();
-        for child in inner.children.iter() {
+    // tell the parent, and wake it up if it is blocked in waitpid
+    if let Some(parent) = parent {
+        parent.send_signal(SignalInfo::child(pid, status));
+        parent.child_exit.wake_all();
+    }
+    // do not move to its parent but under initproc
+    if !children.is_empty() {
+        for child in children.iter() {
             child.inner_exclusive_access().parent = Some(Arc::downgrade(&INITPROC));
-            initproc_inner.children.push(child.clone());
         }
-        // stop holding TCB inner automatically
+        // ++++++ access initproc PCB exclusively
+        INITPROC.inner_exclusive_access().children.extend(children);
+        // ++++++ release initproc PCB
+        // some of the adopted children may have exited already
+        INITPROC.child_exit.wake_all();
     }
-    // ++++++ release parent PCB
-
-    inner.children.clear();
-    // deallocate user space
-    inner.memory_set.recycle_data_pages();
-    drop(inner);
-    // **** release current PCB
-    // drop task manually to maintain rc correctly
+    // drop process and task manually to maintain rc correctly
+    drop(process);
     drop(task);
     // we do not have to save task context
     let mut _unused = TaskContext::zero_init();
@@ -121,120 +255,180 @@ pub fn exit_current_and_run_next(exit_code: i32) {
 
 lazy_static! {
     ///Globle process that init user shell
-    pub static ref INITPROC: Arc<TaskControlBlock> = Arc::new({
-        let inode = open_file("initproc", OpenFlags::RDONLY).unwrap();
+    pub static ref INITPROC: Arc<ProcessControlBlock> = {
+        let inode = open_file(&ROOT_INODE, "initproc", OpenFlags::RDONLY).unwrap();
         let v = inode.read_all();
-        let tcb = TaskControlBlock::new(v.as_slice());
-        tcb
-    });
+        ProcessControlBlock::new(v.as_slice())
+    };
 }
 ///Add init process to the manager
 pub fn add_initproc() {
-    add_task(INITPROC.clone());
+    // the main thread of initproc is put into the ready queue when it is created
+    lazy_static::initialize(&INITPROC);
 }
 
-pub fn check_signals_error_of_current() -> Option<(i32, &'static str)> {
+/// Whether another thread is exiting the current process or replacing its program
+pub fn current_process_exiting() -> bool {
+    current_process().inner_exclusive_access().exiting
+}
+
+/// Whether a blocking syscall should fail with `EINTR`: the current thread has
+/// a pending signal which it neither blocks nor ignores, or its process is exiting
+pub fn current_interrupted() -> bool {
     let task = current_task().unwrap();
-    let task_inner = task.inner_exclusive_access();
-    task_inner.signals.check_error()
+    let signal_mask = task.inner_exclusive_access().signal_mask;
+    let process = task.process();
+    let inner = process.inner_exclusive_access();
+    inner.exiting || !inner.interrupting_signals(signal_mask).is_empty()
 }
 
-pub fn handle_signals() {
+/// Handle the pending signals of the current thread before it returns to
+/// user mode, calling at most one user handler. A stopped process waits here
+/// until it is continued.
+///
+/// `interrupted` is the first argument of a syscall which has just failed with
+/// `EINTR`. It starts over, unless a handler without `SA_RESTART` is called.
+pub fn handle_signals(interrupted: Option<usize>) {
     loop {
-        check_pending_signals();
-        let (frozen, killed) = {
-            let task = current_task().unwrap();
-            let task_inner = task.inner_exclusive_access();
-            (task_inner.frozen, task_inner.killed)
-        };
-        if !frozen | killed {
+        match check_pending_signals() {
+            Some(PendingSignal::Handler(sig)) => {
+                call_user_signal_handler(sig, interrupted);
+                return;
+            }
+            Some(PendingSignal::Terminate(sig)) => {
+                kill_current_and_run_next(sig);
+                return;
+            }
+            Some(PendingSignal::Stop(sig)) => current_process().notify_parent(stop_status(sig)),
+            None => {}
+        }
+        let process = current_process();
+        let process_inner = process.inner_exclusive_access();
+        // an exiting process does not wait to be continued
+        if !process_inner.frozen || process_inner.exiting {
             break;
         }
+        drop(process_inner);
+        drop(process);
         suspend_current_and_run_next();
     }
+    if let Some(a0) = interrupted {
+        restart_syscall(current_trap_cx(), a0);
+    }
+}
+
+/// Make the thread issue the syscall it has just returned from once more
+fn restart_syscall(trap_cx: &mut TrapContext, a0: usize) {
+    trap_cx.sepc -= 4;
+    trap_cx.x[10] = a0;
+}
+
+/// A pending signal which the kernel cannot handle by itself
+enum PendingSignal {
+    /// Call the user handler of the signal
+    Handler(usize),
+    /// Terminate the process by the signal
+    Terminate(usize),
+    /// The process has just been stopped by the signal
+    Stop(usize),
 }
 
-fn check_pending_signals() {
+/// Take the default action of the pending signals which the current thread
+/// does not block, until a signal is found which has a user handler or
+/// terminates or stops the process
+fn check_pending_signals() -> Option<PendingSignal> {
+    let task = current_task().unwrap();
+    let signal_mask = task.inner_exclusive_access().signal_mask;
+    let process = task.process();
+    let mut process_inner = process.inner_exclusive_access();
     for sig in 0..(MAX_SIG + 1) {
-        let task = current_task().unwrap();
-        let task_inner = task.inner_exclusive_access();
         let signal = SignalFlags::from_bits(1 << sig).unwrap();
-        if task_inner.signals.contains(signal) && (!task_inner.signal_mask.contains(signal)) {
-            let mut masked = true;
-            let handling_sig = task_inner.handling_sig;
-            if handling_sig == -1 {
-                masked = false;
-            } else {
-                let handling_sig = handling_sig as usize;
-                if !task_inner.signal_actions.table[handling_sig]
-                    .mask
-                    .contains(signal)
-                {
-                    masked = false;
-                }
+        if !process_inner.signals.contains(signal) || signal_mask.contains(signal) {
+            continue;
+        }
+        match process_inner.signal_actions.table[sig].handler {
+            SIG_DFL => {}
+            SIG_IGN => {
+                process_inner.signals.remove(signal);
+                continue;
             }
-            if !masked {
-                drop(task_inner);
-                drop(task);
-                if signal == SignalFlags::SIGKILL
-                    || signal == SignalFlags::SIGSTOP
-                    || signal == SignalFlags::SIGCONT
-                    || signal == SignalFlags::SIGDEF
-                {
-                    // signal is a kernel signal
-                    call_kernel_signal_handler(signal);
-                } else {
-                    // signal is a user signal
-                    call_user_signal_handler(sig, signal);
-                    return;
-                }
+            _ => return Some(PendingSignal::Handler(sig)),
+        }
+        process_inner.signals.remove(signal);
+        match signal.default_action() {
+            SignalDefault::Terminate | SignalDefault::Core => {
+                return Some(PendingSignal::Terminate(sig));
+            }
+            SignalDefault::Stop => {
+                process_inner.frozen = true;
+                process_inner.stop_status = Some(stop_status(sig));
+                return Some(PendingSignal::Stop(sig));
             }
+            // a process is continued as soon as `SIGCONT` is sent
+            SignalDefault::Ignore | SignalDefault::Continue => {}
         }
     }
+    None
 }
 
-fn call_kernel_signal_handler(signal: SignalFlags) {
+/// Call the user handler of `sig` on a signal frame pushed onto the user stack.
+/// The handler returns to `SIGRETURN_TRAMPOLINE`, see `sys_sigreturn`.
+fn call_user_signal_handler(sig: usize, interrupted: Option<usize>) {
     let task = current_task().unwrap();
-    let mut task_inner = task.inner_exclusive_access();
-    match signal {
-        SignalFlags::SIGSTOP => {
-            task_inner.frozen = true;
-            task_inner.signals ^= SignalFlags::SIGSTOP;
-        }
-        SignalFlags::SIGCONT => {
-            if task_inner.signals.contains(SignalFlags::SIGCONT) {
-                task_inner.signals ^= SignalFlags::SIGCONT;
-                task_inner.frozen = false;
-            }
-        }
-        _ => {
-            task_inner.killed = true;
+    let process = task.process();
+    let signal = SignalFlags::from_bits(1 << sig).unwrap();
+
+    let mut process_inner = process.inner_exclusive_access();
+    process_inner.signals.remove(signal);
+    let action = process_inner.signal_actions.table[sig];
+    let info = process_inner.signal_info[sig];
+    let token = process_inner.get_user_token();
+    drop(process_inner);
+
+    let trap_cx = current_trap_cx();
+    if let Some(a0) = interrupted {
+        if action.flags.contains(SignalActionFlags::SA_RESTART) {
+            restart_syscall(trap_cx, a0);
         }
     }
-}
+    let signal_mask = task.inner_exclusive_access().signal_mask;
+    let frame = SignalFrame {
+        info,
+        ucontext: UContext {
+            flags: 0,
+            sigmask: signal_mask,
+            mcontext: MContext::save(trap_cx),
+        },
+    };
+    // below the stack of the interrupted code, which may be another handler
+    let sp = trap_cx.x[2];
+    let frame_va = sp.wrapping_sub(core::mem::size_of::<SignalFrame>()) & !0xf;
+    if frame_va > sp || copy_to_user(token, frame_va as *mut SignalFrame, &frame).is_err() {
+        // no room for the frame, the process dies of `SIGSEGV`: a handler
+        // would need a frame as well, and a blocked one would stay pending
+        let info = SignalInfo::fault(SignalFlags::SIGSEGV, sp);
+        let mut process_inner = process.inner_exclusive_access();
+        process_inner.signal_actions.table[info.signo as usize] = SignalAction::default();
+        task.inner_exclusive_access()
+            .signal_mask
+            .remove(SignalFlags::SIGSEGV);
+        process_inner.add_signal(info);
+        return;
+    }
 
-fn call_user_signal_handler(sig: usize, signal: SignalFlags) {
-    let task = current_task().unwrap();
     let mut task_inner = task.inner_exclusive_access();
+    task_inner.signal_mask |= action.mask;
+    if !action.flags.contains(SignalActionFlags::SA_NODEFER) {
+        task_inner.signal_mask |= signal;
+    }
+    drop(task_inner);
 
-    let handler = task_inner.signal_actions.table[sig].handler;
-    if handler != 0 {
-        // user handler
-        // handle flag
-        task_inner.handling_sig = sig as isize;
-        task_inner.signals ^= signal;
-
-        // backup trapframe
-        let trap_ctx = task_inner.get_trap_cx();
-        task_inner.trap_ctx_backup = Some(*trap_ctx);
-
-        // modify trapframe
-        trap_ctx.sepc = handler;
-
-        // put args (a0)
-        trap_ctx.x[10] = sig;
-    } else {
-        // default action
-        kprintln!("[K] task/call_user_signal_handler: default action: ignore it or kill process");
+    trap_cx.sepc = action.handler;
+    trap_cx.x[1] = SIGRETURN_TRAMPOLINE;
+    trap_cx.x[2] = frame_va;
+    trap_cx.x[10] = sig;
+    if action.flags.contains(SignalActionFlags::SA_SIGINFO) {
+        trap_cx.x[11] = frame_va + offset_of!(SignalFrame, info);
+        trap_cx.x[12] = frame_va + offset_of!(SignalFrame, ucontext);
     }
 }
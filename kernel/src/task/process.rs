@@ -0,0 +1,639 @@
+//!Implementation of [`ProcessControlBlock`]
+use super::id::RecycleAllocator;
+use super::manager::insert_into_pid2process;
+use super::{add_task, pid_alloc, PidHandle, TaskControlBlock, TaskUserRes};
+use super::{CpuUsage, SchedEntity, SignalActionFlags, SignalDefault, SignalFlags, SignalInfo};
+use super::{SignalAction, SignalActions, MAX_SIG, SIG_DFL, SIG_IGN};
+use crate::config::USER_STACK_SIZE;
+use crate::errno::{Errno, SysResult};
+use crate::fs::inode::ROOT_INODE;
+use crate::fs::File;
+use crate::fs::{Stderr, Stdin, Stdout};
+use crate::mm::{kernel_token, translated_refmut, MemorySet, VirtAddr};
+use crate::sync::{futex_wake_process, SpinLock, WaitQueue};
+use crate::timer::{add_alarm_timer, get_time, IntervalTimer, ITIMER_REAL, ITIMER_VIRTUAL};
+use crate::trap::{trap_handler, TrapContext};
+use alloc::string::String;
+use alloc::sync::{Arc, Weak};
+use alloc::vec;
+use alloc::vec::Vec;
+use easy_fs::Inode;
+use spin::MutexGuard;
+
+/// Status reported by `waitpid` for a process which exited with `exit_code`
+pub fn exit_status(exit_code: i32) -> i32 {
+    (exit_code & 0xff) << 8
+}
+
+/// Status reported by `waitpid` for a process terminated by signal `signo`
+pub fn signal_status(signo: usize) -> i32 {
+    signo as i32 & 0x7f
+}
+
+/// Status reported by `waitpid` for a process stopped by signal `signo`
+pub fn stop_status(signo: usize) -> i32 {
+    (signo as i32) << 8 | 0x7f
+}
+
+/// Status reported by `waitpid` for a stopped process continued by `SIGCONT`
+pub const CONTINUED_STATUS: i32 = 0xffff;
+
+/// A process: the address space, files and signal state shared by its threads
+#[derive(Debug)]
+pub struct ProcessControlBlock {
+    // immutable
+    pub pid: PidHandle,
+    /// Woken up when a child exits or stops
+    pub child_exit: WaitQueue,
+    /// Woken up when a thread of this process exits
+    pub thread_exit: WaitQueue,
+    // mutable
+    inner: SpinLock<ProcessControlBlockInner>,
+}
+
+#[derive(Debug)]
+pub struct ProcessControlBlockInner {
+    /// Set when the last thread has exited
+    pub is_zombie: bool,
+    /// Set when the process exits or replaces its program. The other threads
+    /// exit the next time they are about to return to user mode.
+    pub exiting: bool,
+    #[allow(unused)]
+    pub base_size: usize,
+    pub heap_bottom: usize,
+    pub program_brk: usize,
+    pub memory_set: MemorySet,
+    pub parent: Option<Weak<ProcessControlBlock>>,
+    pub children: Vec<Arc<ProcessControlBlock>>,
+    /// Status reported by `waitpid`, see [`exit_status`] and [`signal_status`]
+    pub exit_status: i32,
+    pub fd_table: Vec<Option<Arc<dyn File + Send + Sync>>>,
+    /// Current working directory
+    pub cwd: Arc<Inode>,
+    /// Pending signals, each thread blocks some of them with its own mask
+    pub signals: SignalFlags,
+    /// What handlers learn about each pending signal
+    pub signal_info: [SignalInfo; MAX_SIG + 1],
+    /// Signal actions
+    pub signal_actions: SignalActions,
+    /// If the process is stopped by a signal until `SIGCONT` comes
+    pub frozen: bool,
+    /// A stop not yet reported by `waitpid`, see [`stop_status`]
+    pub stop_status: Option<i32>,
+    /// Continued by `SIGCONT` since `waitpid` last reported it
+    pub continued: bool,
+    /// Cpu usage of the exited threads
+    pub usage: CpuUsage,
+    /// Cpu usage of the children waited for, including their own children
+    pub children_usage: CpuUsage,
+    /// Interval timers indexed by `ITIMER_REAL`, `ITIMER_VIRTUAL` and
+    /// `ITIMER_PROF`, which are not inherited by children
+    pub itimers: [IntervalTimer; 3],
+    /// Process group, for job control
+    pub pgid: usize,
+    /// Session, which holds process groups
+    pub sid: usize,
+    /// Threads indexed by tid, kept after they exit until they are waited for
+    pub tasks: Vec<Option<Arc<TaskControlBlock>>>,
+    task_res_allocator: RecycleAllocator,
+}
+
+impl ProcessControlBlockInner {
+    pub fn get_user_token(&self) -> usize {
+        self.memory_set.token()
+    }
+    /// Make the signal of `info` pending. `SIGCONT` continues the process at
+    /// once and discards the pending stop signals, which discard it in turn.
+    pub fn add_signal(&mut self, info: SignalInfo) {
+        let signal = SignalFlags::from_bits_truncate(1 << info.signo);
+        match signal.default_action() {
+            SignalDefault::Continue => {
+                self.continued |= self.frozen;
+                self.frozen = false;
+                self.stop_status = None;
+                let stops: SignalFlags = self
+                    .signals
+                    .iter()
+                    .filter(|pending| pending.default_action() == SignalDefault::Stop)
+                    .collect();
+                self.signals -= stops;
+            }
+            SignalDefault::Stop => self.signals.remove(SignalFlags::SIGCONT),
+            _ => {}
+        }
+        self.signals |= signal;
+        self.signal_info[info.signo as usize] = info;
+    }
+    /// Pending signals which `mask` does not block and which are not just
+    /// discarded when taken, so that they interrupt a blocking syscall
+    pub fn interrupting_signals(&self, mask: SignalFlags) -> SignalFlags {
+        (self.signals - mask)
+            .iter()
+            .filter(|signal| {
+                let sig = signal.bits().trailing_zeros() as usize;
+                match self.signal_actions.table[sig].handler {
+                    SIG_IGN => false,
+                    // a process is continued as soon as `SIGCONT` is sent
+                    SIG_DFL => !matches!(
+                        signal.default_action(),
+                        SignalDefault::Ignore | SignalDefault::Continue
+                    ),
+                    _ => true,
+                }
+            })
+            .collect()
+    }
+    pub fn change_program_brk(&mut self, size: i32) -> Option<usize> {
+        assert!((size as isize) < isize::MAX);
+        let old_break = self.program_brk;
+        let new_brk = self.program_brk as isize + size as isize;
+        if new_brk < self.heap_bottom as isize {
+            return None;
+        }
+        let result = if size < 0 {
+            self.memory_set
+                .shrink_to(VirtAddr(self.heap_bottom), VirtAddr(new_brk as usize))
+        } else {
+            self.memory_set
+                .append_to(VirtAddr(self.heap_bottom), VirtAddr(new_brk as usize))
+        };
+        if result {
+            self.program_brk = new_brk as usize;
+            Some(old_break)
+        } else {
+            None
+        }
+    }
+    /// The file opened as `fd`, or `EBADF`
+    pub fn get_file(&self, fd: usize) -> SysResult<Arc<dyn File + Send + Sync>> {
+        match self.fd_table.get(fd) {
+            Some(Some(file)) => Ok(file.clone()),
+            _ => Err(Errno::EBADF),
+        }
+    }
+    pub fn alloc_fd(&mut self) -> usize {
+        if let Some(fd) = (0..self.fd_table.len()).find(|fd| self.fd_table[*fd].is_none()) {
+            fd
+        } else {
+            self.fd_table.push(None);
+            self.fd_table.len() - 1
+        }
+    }
+    pub fn alloc_tid(&mut self) -> usize {
+        self.task_res_allocator.alloc()
+    }
+    pub fn dealloc_tid(&mut self, tid: usize) {
+        self.task_res_allocator.dealloc(tid)
+    }
+    pub fn get_task(&self, tid: usize) -> Option<Arc<TaskControlBlock>> {
+        self.tasks.get(tid).cloned().flatten()
+    }
+    /// Put `task` into the slot of its tid
+    fn insert_task(&mut self, tid: usize, task: Arc<TaskControlBlock>) {
+        if self.tasks.len() <= tid {
+            self.tasks.resize(tid + 1, None);
+        }
+        self.tasks[tid] = Some(task);
+    }
+    /// Cpu usage of the threads of the process so far
+    pub fn cpu_usage(&self) -> CpuUsage {
+        let mut usage = self.usage;
+        for task in self.tasks.iter().flatten() {
+            let task_inner = task.inner_exclusive_access();
+            // an exited thread is counted already
+            if !task_inner.is_zombie() {
+                usage += task_inner.sched.usage;
+            }
+        }
+        usage
+    }
+    /// Cpu usage of the process, including its children waited for
+    pub fn total_usage(&self) -> CpuUsage {
+        let mut usage = self.cpu_usage();
+        usage += self.children_usage;
+        usage
+    }
+    /// Current time on the clock of interval timer `which`: real time, or cpu
+    /// time of the process in user mode only or in both modes
+    pub fn itimer_clock(&self, which: usize) -> usize {
+        match which {
+            ITIMER_REAL => get_time(),
+            ITIMER_VIRTUAL => self.cpu_usage().user_time,
+            _ => self.cpu_usage().runtime,
+        }
+    }
+    /// Number of threads which have not exited yet
+    pub fn alive_threads(&self) -> usize {
+        self.tasks
+            .iter()
+            .flatten()
+            .filter(|task| !task.inner_exclusive_access().is_zombie())
+            .count()
+    }
+}
+
+impl ProcessControlBlock {
+    pub fn inner_exclusive_access(&self) -> MutexGuard<'_, ProcessControlBlockInner> {
+        self.inner.exclusive_access()
+    }
+    /// Make the signal of `info` pending, and interrupt the blocked threads
+    /// so that they take it at once
+    pub fn send_signal(&self, info: SignalInfo) {
+        let mut inner = self.inner_exclusive_access();
+        let was_continued = inner.continued;
+        inner.add_signal(info);
+        let continued = inner.continued && !was_continued;
+        drop(inner);
+        self.interrupt_threads();
+        if continued {
+            self.notify_parent(CONTINUED_STATUS);
+        }
+    }
+    /// Wake up the threads blocked on futexes or sleeping interruptibly, so
+    /// that they notice a new signal or that the process is exiting
+    pub fn interrupt_threads(&self) {
+        futex_wake_process(self);
+        let inner = self.inner_exclusive_access();
+        let waiters: Vec<_> = inner
+            .tasks
+            .iter()
+            .flatten()
+            .filter_map(|task| task.inner_exclusive_access().waiter.clone())
+            .collect();
+        drop(inner);
+        for waiter in waiters {
+            waiter.wake();
+        }
+    }
+    /// Arm the real time interval timer to expire at `expire_time`
+    pub fn add_real_timer(self: &Arc<Self>, expire_time: usize) {
+        add_alarm_timer(expire_time, Arc::downgrade(self));
+    }
+    /// Send `SIGALRM` if the real time interval timer has expired, and rearm it
+    pub fn check_real_timer(self: &Arc<Self>) {
+        let now = get_time();
+        let mut inner = self.inner_exclusive_access();
+        if inner.is_zombie || !inner.itimers[ITIMER_REAL].expired(now) {
+            return;
+        }
+        let expire = inner.itimers[ITIMER_REAL].expire;
+        drop(inner);
+        if expire != 0 {
+            self.add_real_timer(expire);
+        }
+        self.send_signal(SignalInfo::kernel(SignalFlags::SIGALRM));
+    }
+    /// Tell the parent that this process has stopped or continued with
+    /// `wait_status`, so that it may find out with `waitpid`. `SIGCHLD` is not
+    /// sent if the parent has set `SA_NOCLDSTOP`.
+    pub fn notify_parent(&self, wait_status: i32) {
+        let parent = self.inner_exclusive_access().parent.clone();
+        // a parent is locked before its children
+        let Some(parent) = parent.and_then(|parent| parent.upgrade()) else {
+            return;
+        };
+        let sigchld = SignalFlags::SIGCHLD.bits().trailing_zeros() as usize;
+        let action = parent.inner_exclusive_access().signal_actions.table[sigchld];
+        if !action.flags.contains(SignalActionFlags::SA_NOCLDSTOP) {
+            parent.send_signal(SignalInfo::child(self.getpid(), wait_status));
+        }
+        parent.child_exit.wake_all();
+    }
+    /// Create a process running `elf_data` with a single thread, which is put
+    /// into the ready queue
+    pub fn new(elf_data: &[u8]) -> Arc<Self> {
+        // memory_set with elf program headers/trampoline/trap context/user stack
+        let (memory_set, user_sp, entry_point) = MemorySet::from_elf(elf_data).unwrap();
+        let pid = pid_alloc();
+        // the first process leads its own session and process group
+        let (pgid, sid) = (pid.0, pid.0);
+        let process = Arc::new(Self {
+            pid,
+            child_exit: WaitQueue::new(),
+            thread_exit: WaitQueue::new(),
+            inner: SpinLock::new(ProcessControlBlockInner {
+                is_zombie: false,
+                exiting: false,
+                base_size: user_sp,
+                heap_bottom: user_sp,
+                program_brk: user_sp,
+                memory_set,
+                parent: None,
+                children: Vec::new(),
+                exit_status: 0,
+                fd_table: vec![
+                    // 0 -> stdin
+                    Some(Arc::new(Stdin)),
+                    // 1 -> stdout
+                    Some(Arc::new(Stdout)),
+                    // 2 -> stderr
+                    Some(Arc::new(Stderr)),
+                ],
+                cwd: ROOT_INODE.clone(),
+                signals: SignalFlags::empty(),
+                signal_info: [SignalInfo::default(); MAX_SIG + 1],
+                signal_actions: SignalActions::default(),
+                frozen: false,
+                stop_status: None,
+                continued: false,
+                usage: CpuUsage::default(),
+                children_usage: CpuUsage::default(),
+                itimers: Default::default(),
+                pgid,
+                sid,
+                tasks: Vec::new(),
+                task_res_allocator: RecycleAllocator::new(),
+            }),
+        });
+        // the main thread runs on the user stack of the program
+        let mut inner = process.inner_exclusive_access();
+        let res = TaskUserRes {
+            tid: inner.alloc_tid(),
+            ustack_bottom: None,
+        };
+        let tid = res.tid;
+        let trap_cx_ppn = res.trap_cx_ppn(&inner.memory_set);
+        let task = Arc::new(TaskControlBlock::new(
+            &process,
+            res,
+            trap_cx_ppn,
+            SchedEntity::default(),
+            SignalFlags::empty(),
+        ));
+        // prepare TrapContext in user space
+        *task.inner_exclusive_access().get_trap_cx() = TrapContext::init_context(
+            entry_point,
+            user_sp,
+            kernel_token(),
+            task.kernel_stack.get_top(),
+            trap_handler as usize,
+        );
+        inner.insert_task(tid, task.clone());
+        drop(inner);
+        insert_into_pid2process(process.getpid(), process.clone());
+        add_task(task);
+        process
+    }
+    /// Create a thread which runs `entry(arg)` on a new user stack, blocking
+    /// `signal_mask`. The caller puts it into the ready queue.
+    pub fn create_thread(
+        self: &Arc<Self>,
+        entry: usize,
+        arg: usize,
+        sched: SchedEntity,
+        signal_mask: SignalFlags,
+    ) -> SysResult<Arc<TaskControlBlock>> {
+        let mut inner = self.inner_exclusive_access();
+        let tid = inner.alloc_tid();
+        let res = match TaskUserRes::alloc(&mut inner.memory_set, tid) {
+            Ok(res) => res,
+            Err(errno) => {
+                inner.dealloc_tid(tid);
+                return Err(errno);
+            }
+        };
+        let trap_cx_ppn = res.trap_cx_ppn(&inner.memory_set);
+        let ustack_top = res.ustack_top().unwrap();
+        let task = Arc::new(TaskControlBlock::new(
+            self,
+            res,
+            trap_cx_ppn,
+            sched,
+            signal_mask,
+        ));
+        let mut trap_cx = TrapContext::init_context(
+            entry,
+            ustack_top,
+            kernel_token(),
+            task.kernel_stack.get_top(),
+            trap_handler as usize,
+        );
+        trap_cx.x[10] = arg;
+        *task.inner_exclusive_access().get_trap_cx() = trap_cx;
+        inner.insert_task(tid, task.clone());
+        Ok(task)
+    }
+    /// Replace the program of this process, `task` is the calling thread.
+    /// Fail with `E2BIG` if `args` do not fit into the user stack, `ENOEXEC`
+    /// if `elf_data` is not a valid ELF, or `EINTR` if another thread is
+    /// exiting the process.
+    pub fn exec(
+        &self,
+        task: &Arc<TaskControlBlock>,
+        elf_data: &[u8],
+        args: Vec<String>,
+    ) -> SysResult<()> {
+        let args_size: usize = args
+            .iter()
+            .map(|arg| arg.len() + 1 + core::mem::size_of::<usize>())
+            .sum();
+        if args_size > USER_STACK_SIZE / 2 {
+            return Err(Errno::E2BIG);
+        }
+        // memory_set with elf program headers/trampoline/trap context/user stack
+        let (memory_set, mut user_sp, entry_point) =
+            MemorySet::from_elf(elf_data).ok_or(Errno::ENOEXEC)?;
+        // the heap starts out empty right above the user stack
+        let heap_bottom = user_sp;
+
+        // push arguments on user stack
+        user_sp -= (args.len() + 1) * core::mem::size_of::<usize>();
+        let argv_base = user_sp;
+        let mut argv: Vec<_> = (0..=args.len())
+            .map(|arg| {
+                translated_refmut(
+                    memory_set.token(),
+                    (argv_base + arg * core::mem::size_of::<usize>()) as *mut usize,
+                )
+            })
+            .collect::<SysResult<_>>()?;
+        *argv[args.len()] = 0;
+        for i in 0..args.len() {
+            user_sp -= args[i].len() + 1;
+            *argv[i] = user_sp;
+            let mut p = user_sp;
+            // FIXME: maybe slow here
+            for c in args[i].as_bytes() {
+                *translated_refmut(memory_set.token(), p as *mut u8)? = *c;
+                p += 1;
+            }
+            *translated_refmut(memory_set.token(), p as *mut u8)? = 0;
+        }
+        // make the user_sp aligned to 16 bytes
+        user_sp -= user_sp % 16;
+
+        // no other thread may run on the old memory set
+        self.exit_other_threads()?;
+
+        // **** access inner exclusively
+        let mut inner = self.inner_exclusive_access();
+        // substitute memory_set
+        inner.memory_set = memory_set;
+        // initialize base_size
+        inner.base_size = user_sp;
+        inner.heap_bottom = heap_bottom;
+        inner.program_brk = heap_bottom;
+        // the handlers are gone with the old program, ignored signals stay ignored
+        for action in inner.signal_actions.table.iter_mut() {
+            if action.handler != SIG_IGN {
+                *action = SignalAction::default();
+            }
+        }
+        // the calling thread becomes the main thread of the new program
+        let exited = core::mem::take(&mut inner.tasks);
+        inner.task_res_allocator = RecycleAllocator::new();
+        let res = TaskUserRes {
+            tid: inner.alloc_tid(),
+            ustack_bottom: None,
+        };
+        let tid = res.tid;
+        let mut task_inner = task.inner_exclusive_access();
+        task_inner.trap_cx_ppn = res.trap_cx_ppn(&inner.memory_set);
+        task_inner.res = res;
+        // initialize trap_cx
+        let mut trap_cx = TrapContext::init_context(
+            entry_point,
+            user_sp,
+            kernel_token(),
+            task.kernel_stack.get_top(),
+            trap_handler as usize,
+        );
+        // int main(int argc, char *argv[]);
+        trap_cx.x[10] = args.len();
+        trap_cx.x[11] = argv_base;
+        *task_inner.get_trap_cx() = trap_cx;
+        drop(task_inner);
+        inner.insert_task(tid, task.clone());
+        drop(inner);
+        // **** release inner
+        for exited in exited.into_iter().flatten() {
+            if !Arc::ptr_eq(&exited, task) {
+                // it may still be switching away from its kernel stack
+                exited.wait_off_cpu();
+            }
+        }
+        Ok(())
+    }
+    /// Make the other threads exit the next time they are about to return to
+    /// user mode, and wait until they have all exited. Threads blocked in the
+    /// kernel are waited for until they wake up.
+    /// Fail with `EINTR` if another thread is exiting the process already.
+    fn exit_other_threads(&self) -> SysResult<()> {
+        let mut inner = self.inner_exclusive_access();
+        if inner.exiting {
+            return Err(Errno::EINTR);
+        }
+        inner.exiting = true;
+        drop(inner);
+        self.interrupt_threads();
+        loop {
+            // a thread exiting after this wakes us up
+            let ticket = self.thread_exit.prepare_to_wait();
+            if self.inner_exclusive_access().alive_threads() == 1 {
+                break;
+            }
+            self.thread_exit.wait(ticket);
+        }
+        self.inner_exclusive_access().exiting = false;
+        Ok(())
+    }
+    /// Fork a process with a copy of the calling thread `task` only.
+    /// Return the thread of the child, which is not in the ready queue yet.
+    pub fn fork(self: &Arc<Self>, task: &Arc<TaskControlBlock>) -> Arc<TaskControlBlock> {
+        // ---- access parent PCB exclusively
+        let mut parent_inner = self.inner_exclusive_access();
+        // copy user space(without trap contexts)
+        let mut memory_set = MemorySet::from_existed_user(&mut parent_inner.memory_set);
+        // the stacks of the other threads are not copied
+        for other in parent_inner.tasks.iter().flatten() {
+            if Arc::ptr_eq(other, task) {
+                continue;
+            }
+            if let Some(ustack_bottom) = other.inner_exclusive_access().res.ustack_bottom {
+                memory_set.remove_area_with_start_vpn(VirtAddr::from(ustack_bottom).into());
+            }
+        }
+        // the copied thread becomes the main thread of the child, on the same user stack
+        let mut task_res_allocator = RecycleAllocator::new();
+        let task_inner = task.inner_exclusive_access();
+        let res = TaskUserRes {
+            tid: task_res_allocator.alloc(),
+            ustack_bottom: task_inner.res.ustack_bottom,
+        };
+        res.map_trap_cx(&mut memory_set);
+        let trap_cx_ppn = res.trap_cx_ppn(&memory_set);
+        *trap_cx_ppn.get_mut::<TrapContext>() = *task_inner.get_trap_cx();
+        let sched = task_inner.sched.fork();
+        let signal_mask = task_inner.signal_mask;
+        drop(task_inner);
+        // copy fd table
+        // manually control the behavior of cloning
+        let mut new_fd_table: Vec<Option<Arc<dyn File + Send + Sync>>> = Vec::new();
+        for fd in parent_inner.fd_table.iter() {
+            if let Some(file) = fd {
+                new_fd_table.push(Some(file.clone()));
+            } else {
+                new_fd_table.push(None);
+            }
+        }
+        let child = Arc::new(ProcessControlBlock {
+            pid: pid_alloc(),
+            child_exit: WaitQueue::new(),
+            thread_exit: WaitQueue::new(),
+            inner: SpinLock::new(ProcessControlBlockInner {
+                is_zombie: false,
+                exiting: false,
+                base_size: parent_inner.base_size,
+                heap_bottom: parent_inner.heap_bottom,
+                program_brk: parent_inner.program_brk,
+                memory_set,
+                parent: Some(Arc::downgrade(self)),
+                children: Vec::new(),
+                exit_status: 0,
+                fd_table: new_fd_table,
+                cwd: parent_inner.cwd.clone(),
+                signals: SignalFlags::empty(),
+                signal_info: [SignalInfo::default(); MAX_SIG + 1],
+                // inherit the signal_action, the thread inherits the signal mask
+                signal_actions: parent_inner.signal_actions.clone(),
+                frozen: false,
+                stop_status: None,
+                continued: false,
+                usage: CpuUsage::default(),
+                children_usage: CpuUsage::default(),
+                itimers: Default::default(),
+                pgid: parent_inner.pgid,
+                sid: parent_inner.sid,
+                tasks: Vec::new(),
+                task_res_allocator,
+            }),
+        });
+        // add child
+        parent_inner.children.push(child.clone());
+        drop(parent_inner);
+        // ---- release parent PCB
+        let tid = res.tid;
+        let child_task = Arc::new(TaskControlBlock::new(
+            &child,
+            res,
+            trap_cx_ppn,
+            sched,
+            signal_mask,
+        ));
+        // modify kernel_sp in trap_cx
+        trap_cx_ppn.get_mut::<TrapContext>().kernel_sp = child_task.kernel_stack.get_top();
+        child.inner_exclusive_access().insert_task(tid, child_task.clone());
+        insert_into_pid2process(child.getpid(), child.clone());
+        child_task
+    }
+    pub fn getpid(&self) -> usize {
+        self.pid.0
+    }
+    /// Spin until no hart runs on the kernel stack of any thread of this process
+    pub fn wait_off_cpu(&self) {
+        let tasks = self.inner_exclusive_access().tasks.clone();
+        for task in tasks.into_iter().flatten() {
+            task.wait_off_cpu();
+        }
+    }
+}
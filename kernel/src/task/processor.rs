@@ -1,18 +1,41 @@
 use super::__switch;
 use super::{fetch_task, TaskStatus};
-use super::{TaskContext, TaskControlBlock};
+use super::{ProcessControlBlock, TaskContext, TaskControlBlock};
+use crate::board::MAX_HARTS;
+use crate::errno::SysResult;
+use crate::mm::{MapPermission, VirtAddr};
 use crate::sync::UPSafeCell;
+use crate::timer::{check_timer, get_time};
 use crate::trap::TrapContext;
 use alloc::sync::Arc;
+use core::arch::asm;
 use lazy_static::*;
+
+/// What a hart is running
 pub struct Processor {
     current: Option<Arc<TaskControlBlock>>,
     idle_task_cx: TaskContext,
 }
 
-// FIXME: multiple cores implementation
 lazy_static! {
-    pub static ref PROCESSOR: UPSafeCell<Processor> = unsafe { UPSafeCell::new(Processor::new()) };
+    /// One [`Processor`] per hart, only accessed by the hart itself
+    static ref PROCESSORS: [UPSafeCell<Processor>; MAX_HARTS] =
+        core::array::from_fn(|_| unsafe { UPSafeCell::new(Processor::new()) });
+}
+
+/// Id of the hart running this code, kept in `tp` while in the kernel
+pub fn hart_id() -> usize {
+    let id: usize;
+    unsafe { asm!("mv {}, tp", out(reg) id) };
+    id
+}
+
+/// [`Processor`] of the hart running this code.
+///
+/// A task may move to another hart whenever it is switched out,
+/// so never keep the result across a call to [`schedule`].
+fn current_processor() -> &'static UPSafeCell<Processor> {
+    &PROCESSORS[hart_id()]
 }
 
 impl Processor {
@@ -35,16 +58,21 @@ impl Processor {
 }
 
 pub fn take_current_task() -> Option<Arc<TaskControlBlock>> {
-    PROCESSOR.exclusive_access().take_current()
+    current_processor().exclusive_access().take_current()
 }
 
 pub fn current_task() -> Option<Arc<TaskControlBlock>> {
-    PROCESSOR.exclusive_access().current()
+    current_processor().exclusive_access().current()
+}
+
+/// The process of the current task
+pub fn current_process() -> Arc<ProcessControlBlock> {
+    current_task().unwrap().process()
 }
 
 pub fn current_user_token() -> usize {
-    let task = current_task().unwrap();
-    let token = task.inner_exclusive_access().get_user_token();
+    let process = current_process();
+    let token = process.inner_exclusive_access().get_user_token();
     token
 }
 
@@ -55,31 +83,61 @@ pub fn current_trap_cx() -> &'static mut TrapContext {
         .get_trap_cx()
 }
 
-/// The main part of process execution and scheduling
+/// Address of the trap context of the current task in user space
+pub fn current_trap_cx_user_va() -> usize {
+    current_task()
+        .unwrap()
+        .inner_exclusive_access()
+        .res
+        .trap_cx_user_va()
+}
+
+/// Resolve a page fault at `va` of current task, see [`crate::mm::MemorySet::handle_page_fault`].
+pub fn current_handle_page_fault(va: VirtAddr, access: MapPermission) -> SysResult<()> {
+    current_process()
+        .inner_exclusive_access()
+        .memory_set
+        .handle_page_fault(va.floor(), access)
+}
+
+/// The main part of process execution and scheduling, every hart runs it
 pub fn run_tasks() {
     loop {
-        let mut processor = PROCESSOR.exclusive_access();
         if let Some(task) = fetch_task() {
+            // the task may have been put back by another hart which is still switching away from it
+            task.wait_off_cpu();
+            task.set_on_cpu(true);
+            let mut processor = current_processor().exclusive_access();
             let idle_task_cx_ptr = processor.get_idle_task_cx_ptr();
             // access coming task TCB exclusively
             let mut task_inner = task.inner_exclusive_access();
             let next_task_cx_ptr = &task_inner.task_cx as *const TaskContext;
             task_inner.task_status = TaskStatus::Running;
+            task_inner.sched.exec_start = get_time();
             // stop exclusively accessing coming task TCB manually
             drop(task_inner);
+            // the task is not freed before it is off this hart, see `sys_wait4`
+            let prev_task = Arc::as_ptr(&task);
             processor.current = Some(task);
             // stop exclusively accessing processor manually
             drop(processor);
             unsafe {
+                // kernel stacks are remapped when they are reused, drop stale translations
+                asm!("sfence.vma");
                 __switch(idle_task_cx_ptr, next_task_cx_ptr);
+                // back to the idle control flow, the context of the task has been saved
+                (*prev_task).set_on_cpu(false);
             }
+        } else {
+            // timer interrupts are off in kernel, so sleeping tasks have to be polled
+            check_timer();
         }
     }
 }
 
 /// Schedule back to idle control flow for new scheduling
 pub fn schedule(switched_task_cx_ptr: *mut TaskContext) {
-    let mut processor = PROCESSOR.exclusive_access();
+    let mut processor = current_processor().exclusive_access();
     let idle_task_cx_ptr = processor.get_idle_task_cx_ptr();
     drop(processor);
     unsafe {
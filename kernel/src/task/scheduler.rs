@@ -0,0 +1,211 @@
+//! Implementation of [`Scheduler`] which
+//! decides which ready task runs next.
+
+use super::TaskControlBlock;
+use crate::board::CLOCK_FREQ;
+use crate::timer::TimeVal;
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::sync::Arc;
+use core::ops::AddAssign;
+
+/// Lowest (most favourable) nice value
+pub const NICE_MIN: i32 = -20;
+/// Highest (least favourable) nice value
+pub const NICE_MAX: i32 = 19;
+
+/// Weight of a task with nice 0
+const NICE_0_WEIGHT: usize = 1024;
+
+/// Weight for nice values -20..=19, every step is about 1.25 times its neighbour,
+/// so that one nice level makes ~10% difference of cpu time.
+#[rustfmt::skip]
+const NICE_TO_WEIGHT: [usize; 40] = [
+    /* -20 */ 88761, 71755, 56483, 46273, 36291,
+    /* -15 */ 29154, 23254, 18705, 14949, 11916,
+    /* -10 */  9548,  7620,  6100,  4904,  3906,
+    /*  -5 */  3121,  2501,  1991,  1586,  1277,
+    /*   0 */  1024,   820,   655,   526,   423,
+    /*   5 */   335,   272,   215,   172,   137,
+    /*  10 */   110,    87,    70,    56,    45,
+    /*  15 */    36,    29,    23,    18,    15,
+];
+
+/// How far (in `mtime` ticks of virtual runtime) a task which has been away from the
+/// ready queue may lag behind the others, so that it runs soon after waking up
+/// but cannot monopolize the cpu to catch up.
+const WAKEUP_GRANULARITY: usize = CLOCK_FREQ / 100;
+
+/// Cpu time (in `mtime` ticks) and context switches of a task, or summed up
+/// over the threads of a process
+#[derive(Debug, Default, Clone, Copy)]
+pub struct CpuUsage {
+    /// Time on the cpu, in user and kernel mode
+    pub runtime: usize,
+    /// Part of `runtime` spent in user mode
+    pub user_time: usize,
+    /// Times the task blocked to wait for something
+    pub voluntary_switches: usize,
+    /// Times the task gave up the cpu while it could still run
+    pub involuntary_switches: usize,
+}
+
+impl AddAssign for CpuUsage {
+    fn add_assign(&mut self, other: Self) {
+        self.runtime += other.runtime;
+        self.user_time += other.user_time;
+        self.voluntary_switches += other.voluntary_switches;
+        self.involuntary_switches += other.involuntary_switches;
+    }
+}
+
+impl CpuUsage {
+    /// The part of `struct rusage` which is tracked, the rest is left zero
+    pub fn to_rusage(self) -> RUsage {
+        RUsage {
+            utime: TimeVal::from_ticks(self.user_time),
+            stime: TimeVal::from_ticks(self.runtime.saturating_sub(self.user_time)),
+            nvcsw: self.voluntary_switches,
+            nivcsw: self.involuntary_switches,
+            ..Default::default()
+        }
+    }
+}
+
+/// Resource usage reported by `wait4`, same layout as `struct rusage`
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RUsage {
+    pub utime: TimeVal,
+    pub stime: TimeVal,
+    pub maxrss: usize,
+    pub ixrss: usize,
+    pub idrss: usize,
+    pub isrss: usize,
+    pub minflt: usize,
+    pub majflt: usize,
+    pub nswap: usize,
+    pub inblock: usize,
+    pub oublock: usize,
+    pub msgsnd: usize,
+    pub msgrcv: usize,
+    pub nsignals: usize,
+    pub nvcsw: usize,
+    pub nivcsw: usize,
+}
+
+/// Scheduling state of a task
+#[derive(Debug, Default)]
+pub struct SchedEntity {
+    /// Nice value in `NICE_MIN..=NICE_MAX`
+    pub nice: i32,
+    /// Weighted cpu time this task has consumed
+    pub vruntime: usize,
+    /// Time when the task was last put on the cpu
+    pub exec_start: usize,
+    /// Time when the task last returned to user mode
+    pub user_start: usize,
+    /// Cpu time and context switches so far
+    pub usage: CpuUsage,
+}
+
+impl SchedEntity {
+    /// Create the scheduling state for a child, which inherits the nice value of `self`.
+    pub fn fork(&self) -> Self {
+        Self {
+            nice: self.nice,
+            vruntime: self.vruntime,
+            ..Default::default()
+        }
+    }
+    pub fn weight(&self) -> usize {
+        NICE_TO_WEIGHT[(self.nice - NICE_MIN) as usize]
+    }
+    /// Charge the cpu time used since `exec_start` to the virtual runtime.
+    pub fn charge(&mut self, now: usize) {
+        let delta = now.saturating_sub(self.exec_start);
+        self.vruntime += delta * NICE_0_WEIGHT / self.weight();
+        self.exec_start = now;
+        self.usage.runtime += delta;
+    }
+    /// Charge the time since the task returned to user mode as user time, on
+    /// a trap at `now`. It cannot be switched away from in user mode.
+    pub fn enter_kernel(&mut self, now: usize) {
+        self.usage.user_time += now.saturating_sub(self.user_start);
+    }
+}
+
+pub trait Scheduler {
+    fn new() -> Self;
+    /// Put a ready task into the scheduler
+    fn add(&mut self, task: Arc<TaskControlBlock>);
+    /// Take the task which should run next
+    fn fetch(&mut self) -> Option<Arc<TaskControlBlock>>;
+    /// Visit every ready task
+    fn for_each(&self, f: &mut dyn FnMut(&Arc<TaskControlBlock>));
+}
+
+/// A simple FIFO scheduler, which ignores priorities
+#[allow(unused)]
+pub struct FifoScheduler {
+    ready_queue: VecDeque<Arc<TaskControlBlock>>,
+}
+
+impl Scheduler for FifoScheduler {
+    fn new() -> Self {
+        Self {
+            ready_queue: VecDeque::new(),
+        }
+    }
+    fn add(&mut self, task: Arc<TaskControlBlock>) {
+        self.ready_queue.push_back(task);
+    }
+    fn fetch(&mut self) -> Option<Arc<TaskControlBlock>> {
+        self.ready_queue.pop_front()
+    }
+    fn for_each(&self, f: &mut dyn FnMut(&Arc<TaskControlBlock>)) {
+        self.ready_queue.iter().for_each(f);
+    }
+}
+
+/// A fair-share scheduler in the manner of CFS: always run the task with the smallest
+/// virtual runtime, where virtual runtime grows slower for tasks of higher priority.
+pub struct FairScheduler {
+    /// Ready tasks ordered by (vruntime, arrival sequence)
+    ready_queue: BTreeMap<(usize, usize), Arc<TaskControlBlock>>,
+    /// Monotonic lower bound of vruntime of all runnable tasks
+    min_vruntime: usize,
+    /// Breaks ties between equal vruntime in FIFO order
+    seq: usize,
+}
+
+impl Scheduler for FairScheduler {
+    fn new() -> Self {
+        Self {
+            ready_queue: BTreeMap::new(),
+            min_vruntime: 0,
+            seq: 0,
+        }
+    }
+    fn add(&mut self, task: Arc<TaskControlBlock>) {
+        let mut inner = task.inner_exclusive_access();
+        // a task coming back from sleep (or a new one) must not drag too far behind
+        let floor = self.min_vruntime.saturating_sub(WAKEUP_GRANULARITY);
+        if inner.sched.vruntime < floor {
+            inner.sched.vruntime = floor;
+        }
+        let key = (inner.sched.vruntime, self.seq);
+        drop(inner);
+        self.seq += 1;
+        self.ready_queue.insert(key, task);
+    }
+    fn fetch(&mut self) -> Option<Arc<TaskControlBlock>> {
+        let ((vruntime, _), task) = self.ready_queue.pop_first()?;
+        self.min_vruntime = self.min_vruntime.max(vruntime);
+        Some(task)
+    }
+    fn for_each(&self, f: &mut dyn FnMut(&Arc<TaskControlBlock>)) {
+        self.ready_queue.values().for_each(f);
+    }
+}
+
+pub type SchedulerImpl = FairScheduler;
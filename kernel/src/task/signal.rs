@@ -1,9 +1,11 @@
+use super::CONTINUED_STATUS;
+use crate::trap::TrapContext;
 use bitflags::*;
 
 pub const MAX_SIG: usize = 31;
 
 bitflags! {
-    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
     pub struct SignalFlags: u32 {
         const SIGDEF = 1; // Default signal handling
         const SIGHUP = 1 << 1;
@@ -40,23 +42,164 @@ bitflags! {
     }
 }
 
+/// What happens to a process when a signal comes without a handler
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignalDefault {
+    /// Terminate the process
+    Terminate,
+    /// Discard the signal
+    Ignore,
+    /// Stop the process until `SIGCONT` comes
+    Stop,
+    /// Continue the process if it is stopped
+    Continue,
+    /// Terminate the process with a core dump, which is not written
+    Core,
+}
+
 impl SignalFlags {
-    pub fn check_error(&self) -> Option<(i32, &'static str)> {
-        if self.contains(Self::SIGINT) {
-            Some((-2, "Killed, SIGINT=2"))
-        } else if self.contains(Self::SIGILL) {
-            Some((-4, "Illegal Instruction, SIGILL=4"))
-        } else if self.contains(Self::SIGABRT) {
-            Some((-6, "Aborted, SIGABRT=6"))
-        } else if self.contains(Self::SIGFPE) {
-            Some((-8, "Erroneous Arithmetic Operation, SIGFPE=8"))
-        } else if self.contains(Self::SIGKILL) {
-            Some((-9, "Killed, SIGKILL=9"))
-        } else if self.contains(Self::SIGSEGV) {
-            Some((-11, "Segmentation Fault, SIGSEGV=11"))
-        } else {
-            //println!("[K] signalflags check_error  {:?}", self);
-            None
+    /// The default action of a single signal, the same as Linux
+    pub fn default_action(&self) -> SignalDefault {
+        match *self {
+            // signal 0 is never delivered, `kill` only checks the process
+            Self::SIGDEF | Self::SIGCHLD | Self::SIGURG | Self::SIGWINCH => SignalDefault::Ignore,
+            Self::SIGSTOP | Self::SIGTSTP | Self::SIGTTIN | Self::SIGTTOU => SignalDefault::Stop,
+            Self::SIGCONT => SignalDefault::Continue,
+            Self::SIGQUIT
+            | Self::SIGILL
+            | Self::SIGTRAP
+            | Self::SIGABRT
+            | Self::SIGBUS
+            | Self::SIGFPE
+            | Self::SIGSEGV
+            | Self::SIGXCPU
+            | Self::SIGXFSZ
+            | Self::SIGSYS => SignalDefault::Core,
+            _ => SignalDefault::Terminate,
+        }
+    }
+}
+
+/// `code` of a signal sent with `kill`
+pub const SI_USER: i32 = 0;
+/// `code` of a signal raised by the kernel
+pub const SI_KERNEL: i32 = 0x80;
+/// `code` of `SIGCHLD` for a child which has exited
+pub const CLD_EXITED: i32 = 1;
+/// `code` of `SIGCHLD` for a child killed by a signal
+pub const CLD_KILLED: i32 = 2;
+/// `code` of `SIGCHLD` for a child stopped by a signal
+pub const CLD_STOPPED: i32 = 5;
+/// `code` of `SIGCHLD` for a stopped child continued by `SIGCONT`
+pub const CLD_CONTINUED: i32 = 6;
+
+/// What a handler with `SA_SIGINFO` learns about its signal,
+/// a simplified `siginfo_t`
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SignalInfo {
+    pub signo: i32,
+    pub errno: i32,
+    /// Who raised the signal, `SI_USER` or `SI_KERNEL`, or what happened
+    /// to the child for `SIGCHLD`
+    pub code: i32,
+    /// Sender of a signal from `kill`, or the child for `SIGCHLD`
+    pub pid: i32,
+    /// Exit code, or the signal which killed or stopped the child for `SIGCHLD`
+    pub status: i32,
+    /// Address which caused a fault
+    pub addr: usize,
+}
+
+impl SignalInfo {
+    /// `signal` raised by the kernel because of an access to `addr`
+    pub fn fault(signal: SignalFlags, addr: usize) -> Self {
+        Self {
+            signo: signal.bits().trailing_zeros() as i32,
+            code: SI_KERNEL,
+            addr,
+            ..Default::default()
+        }
+    }
+    /// `signal` raised by the kernel for no fault, e.g. from the console
+    pub fn kernel(signal: SignalFlags) -> Self {
+        Self::fault(signal, 0)
+    }
+    /// `signal` sent by process `pid`
+    pub fn user(signal: SignalFlags, pid: usize) -> Self {
+        Self {
+            signo: signal.bits().trailing_zeros() as i32,
+            code: SI_USER,
+            pid: pid as i32,
+            ..Default::default()
+        }
+    }
+    /// `SIGCHLD` for child `pid` which has turned into a zombie, stopped or
+    /// continued with `wait_status`, see `sys_wait4`
+    pub fn child(pid: usize, wait_status: i32) -> Self {
+        let sigcont = SignalFlags::SIGCONT.bits().trailing_zeros() as i32;
+        let (code, status) = match wait_status & 0x7f {
+            _ if wait_status == CONTINUED_STATUS => (CLD_CONTINUED, sigcont),
+            0 => (CLD_EXITED, wait_status >> 8),
+            0x7f => (CLD_STOPPED, wait_status >> 8),
+            signo => (CLD_KILLED, signo),
+        };
+        Self {
+            signo: SignalFlags::SIGCHLD.bits().trailing_zeros() as i32,
+            code,
+            pid: pid as i32,
+            status,
+            ..Default::default()
+        }
+    }
+}
+
+/// User registers saved in a signal frame
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MContext {
+    /// `pc` in place of `x0`, then `x1` to `x31`
+    pub regs: [usize; 32],
+    #[cfg(feature = "D_EXTENSION_ENABLED")]
+    pub fregs: [u64; 32],
+}
+
+impl MContext {
+    pub fn save(cx: &TrapContext) -> Self {
+        let mut regs = cx.x;
+        regs[0] = cx.sepc;
+        Self {
+            regs,
+            #[cfg(feature = "D_EXTENSION_ENABLED")]
+            fregs: cx.f,
         }
     }
+    /// Put the saved registers back, the privileged state of `cx` is kept
+    pub fn restore(&self, cx: &mut TrapContext) {
+        cx.sepc = self.regs[0];
+        cx.x[1..].copy_from_slice(&self.regs[1..]);
+        #[cfg(feature = "D_EXTENSION_ENABLED")]
+        {
+            cx.f = self.fregs;
+        }
+    }
+}
+
+/// The context a signal handler interrupted, passed as its third argument
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct UContext {
+    pub flags: usize,
+    /// Signal mask restored when the handler returns
+    pub sigmask: SignalFlags,
+    pub mcontext: MContext,
+}
+
+/// Pushed onto the user stack when a handler is called, and popped by
+/// `sigreturn` when the handler returns to `SIGRETURN_TRAMPOLINE`
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SignalFrame {
+    pub info: SignalInfo,
+    pub ucontext: UContext,
 }
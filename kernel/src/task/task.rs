@@ -1,296 +1,116 @@
 //!Implementation of [`TaskControlBlock`]
-use super::{pid_alloc, KernelStack, PidHandle};
-use super::{SignalActions, SignalFlags, TaskContext};
-use crate::config::TRAP_CONTEXT;
-use crate::fs::File;
-use crate::fs::{Stderr, Stdin, Stdout};
-use crate::mm::{translated_refmut, MemorySet, PhysPageNum, VirtAddr, KERNEL_SPACE};
-use crate::sync::UPSafeCell;
-use crate::trap::{trap_handler, TrapContext};
-use alloc::string::String;
+use super::{kstack_alloc, KernelStack, ProcessControlBlock, TaskUserRes};
+use super::{SchedEntity, SignalFlags, TaskContext};
+use crate::mm::PhysPageNum;
+use crate::sync::{SpinLock, Waiter};
+use crate::trap::TrapContext;
 use alloc::sync::{Arc, Weak};
-use alloc::vec;
-use alloc::vec::Vec;
-use core::cell::RefMut;
+use core::sync::atomic::{AtomicBool, Ordering};
+use spin::MutexGuard;
 
+/// A thread, the unit of scheduling. Everything else is shared with
+/// the other threads of its [`ProcessControlBlock`].
 #[derive(Debug)]
 pub struct TaskControlBlock {
     // immutable
-    pub pid: PidHandle,
+    pub process: Weak<ProcessControlBlock>,
     pub kernel_stack: KernelStack,
+    /// Set while a hart runs on the kernel stack of this task,
+    /// until its context is saved by `__switch`
+    on_cpu: AtomicBool,
     // mutable
-    inner: UPSafeCell<TaskControlBlockInner>,
+    inner: SpinLock<TaskControlBlockInner>,
 }
 
 #[derive(Debug)]
 pub struct TaskControlBlockInner {
+    pub res: TaskUserRes,
     pub trap_cx_ppn: PhysPageNum,
-    #[allow(unused)]
-    pub base_size: usize,
-    pub heap_bottom: usize,
-    pub program_brk: usize,
     pub task_cx: TaskContext,
     pub task_status: TaskStatus,
-    pub memory_set: MemorySet,
-    pub parent: Option<Weak<TaskControlBlock>>,
-    pub children: Vec<Arc<TaskControlBlock>>,
-    pub exit_code: i32,
-    pub fd_table: Vec<Option<Arc<dyn File + Send + Sync>>>,
-    pub signals: SignalFlags,
+    /// Priority and cpu time accounting used by the scheduler
+    pub sched: SchedEntity,
+    /// Set when the thread exits
+    pub exit_code: Option<i32>,
+    /// Signals blocked from being delivered to this thread
     pub signal_mask: SignalFlags,
-    /// The signal which is being handling
-    pub handling_sig: isize,
-    /// Signal actions
-    pub signal_actions: SignalActions,
-    /// If the task is killed
-    pub killed: bool,
-    /// If the task if frozen by a signal
-    pub frozen: bool,
-    pub trap_ctx_backup: Option<TrapContext>,
+    /// Set while the thread sleeps interruptibly, see [`Waiter`]
+    pub waiter: Option<Arc<Waiter>>,
 }
 
 impl TaskControlBlockInner {
-    /*
-    pub fn get_task_cx_ptr2(&self) -> *const usize {
-        &self.task_cx_ptr as *const usize
-    }
-    */
     pub fn get_trap_cx(&self) -> &'static mut TrapContext {
         self.trap_cx_ppn.get_mut()
     }
-    pub fn get_user_token(&self) -> usize {
-        self.memory_set.token()
-    }
     fn get_status(&self) -> TaskStatus {
         self.task_status
     }
     pub fn is_zombie(&self) -> bool {
         self.get_status() == TaskStatus::Zombie
     }
-    pub fn change_program_brk(&mut self, size: i32) -> Option<usize> {
-        assert!((size as isize) < isize::MAX);
-        let old_break = self.program_brk;
-        let new_brk = self.program_brk as isize + size as isize;
-        if new_brk < self.heap_bottom as isize {
-            return None;
-        }
-        let result = if size < 0 {
-            self.memory_set
-                .shrink_to(VirtAddr(self.heap_bottom), VirtAddr(new_brk as usize))
-        } else {
-            self.memory_set
-                .append_to(VirtAddr(self.heap_bottom), VirtAddr(new_brk as usize))
-        };
-        if result {
-            self.program_brk = new_brk as usize;
-            Some(old_break)
-        } else {
-            None
-        }
-    }
-    pub fn alloc_fd(&mut self) -> usize {
-        if let Some(fd) = (0..self.fd_table.len()).find(|fd| self.fd_table[*fd].is_none()) {
-            fd
-        } else {
-            self.fd_table.push(None);
-            self.fd_table.len() - 1
-        }
-    }
 }
 
 impl TaskControlBlock {
-    pub fn inner_exclusive_access(&self) -> RefMut<'_, TaskControlBlockInner> {
+    pub fn inner_exclusive_access(&self) -> MutexGuard<'_, TaskControlBlockInner> {
         self.inner.exclusive_access()
     }
-    pub fn new(elf_data: &[u8]) -> Self {
-        // memory_set with elf program headers/trampoline/trap context/user stack
-        let (memory_set, user_sp, entry_point) = MemorySet::from_elf(elf_data);
-        let trap_cx_ppn = memory_set
-            .translate(VirtAddr::from(TRAP_CONTEXT).into())
-            .unwrap()
-            .ppn();
-        // alloc a pid and a kernel stack in kernel space
-        let pid_handle = pid_alloc();
-        let kernel_stack = KernelStack::new(&pid_handle);
-        let kernel_stack_top = kernel_stack.get_top();
-        // push a task context which goes to trap_return to the top of kernel stack
-        let task_control_block = Self {
-            pid: pid_handle,
-            kernel_stack,
-            inner: unsafe {
-                UPSafeCell::new(TaskControlBlockInner {
-                    trap_cx_ppn,
-                    base_size: user_sp,
-                    heap_bottom: user_sp,
-                    program_brk: user_sp,
-                    task_cx: TaskContext::goto_trap_return(kernel_stack_top),
-                    task_status: TaskStatus::Ready,
-                    memory_set,
-                    parent: None,
-                    children: Vec::new(),
-                    exit_code: 0,
-                    fd_table: vec![
-                        // 0 -> stdin
-                        Some(Arc::new(Stdin)),
-                        // 1 -> stdout
-                        Some(Arc::new(Stdout)),
-                        // 2 -> stderr
-                        Some(Arc::new(Stderr)),
-                    ],
-                    signals: SignalFlags::empty(),
-                    signal_mask: SignalFlags::empty(),
-                    handling_sig: -1,
-                    signal_actions: SignalActions::default(),
-                    killed: false,
-                    frozen: false,
-                    trap_ctx_backup: None,
-                })
-            },
-        };
-        // prepare TrapContext in user space
-        let trap_cx = task_control_block.inner_exclusive_access().get_trap_cx();
-        *trap_cx = TrapContext::init_context(
-            entry_point,
-            user_sp,
-            KERNEL_SPACE.exclusive_access().token(),
-            kernel_stack_top,
-            trap_handler as usize,
-        );
-        task_control_block
-    }
-    pub fn change_program_brk(self: &Arc<Self>, size: i32) -> Option<usize> {
-        self.inner_exclusive_access().change_program_brk(size)
+    /// Mark whether a hart is running on the kernel stack of this task
+    pub fn set_on_cpu(&self, on_cpu: bool) {
+        self.on_cpu.store(on_cpu, Ordering::Release);
     }
-    pub fn exec(&self, elf_data: &[u8], args: Vec<String>) {
-        // memory_set with elf program headers/trampoline/trap context/user stack
-        let (memory_set, mut user_sp, entry_point) = MemorySet::from_elf(elf_data);
-        let trap_cx_ppn = memory_set
-            .translate(VirtAddr::from(TRAP_CONTEXT).into())
-            .unwrap()
-            .ppn();
-
-        // push arguments on user stack
-        user_sp -= (args.len() + 1) * core::mem::size_of::<usize>();
-        let argv_base = user_sp;
-        let mut argv: Vec<_> = (0..=args.len())
-            .map(|arg| {
-                translated_refmut(
-                    memory_set.token(),
-                    (argv_base + arg * core::mem::size_of::<usize>()) as *mut usize,
-                )
-            })
-            .collect();
-        *argv[args.len()] = 0;
-        for i in 0..args.len() {
-            user_sp -= args[i].len() + 1;
-            *argv[i] = user_sp;
-            let mut p = user_sp;
-            // FIXME: maybe slow here
-            for c in args[i].as_bytes() {
-                *translated_refmut(memory_set.token(), p as *mut u8) = *c;
-                p += 1;
-            }
-            *translated_refmut(memory_set.token(), p as *mut u8) = 0;
+    /// Spin until no hart runs on the kernel stack of this task, so that its
+    /// saved context is complete and it is safe to run or free it
+    pub fn wait_off_cpu(&self) {
+        while self.on_cpu.load(Ordering::Acquire) {
+            core::hint::spin_loop();
         }
-        // make the user_sp aligned to 16 bytes
-        user_sp -= user_sp % 16;
-
-        // **** access inner exclusively
-        let mut inner = self.inner_exclusive_access();
-        // substitute memory_set
-        inner.memory_set = memory_set;
-        // update trap_cx ppn
-        inner.trap_cx_ppn = trap_cx_ppn;
-        // initialize base_size
-        inner.base_size = user_sp;
-        // initialize trap_cx
-        let mut trap_cx = TrapContext::init_context(
-            entry_point,
-            user_sp,
-            KERNEL_SPACE.exclusive_access().token(),
-            self.kernel_stack.get_top(),
-            trap_handler as usize,
-        );
-        // int main(int argc, char *argv[]);
-        trap_cx.x[10] = args.len();
-        trap_cx.x[11] = argv_base;
-        *inner.get_trap_cx() = trap_cx;
-        // **** release inner automatically
     }
-    pub fn fork(self: &Arc<Self>) -> Arc<Self> {
-        // ---- access parent PCB exclusively
-        let mut parent_inner = self.inner_exclusive_access();
-        // copy user space(include trap context)
-        let memory_set = MemorySet::from_existed_user(&parent_inner.memory_set);
-        let trap_cx_ppn = memory_set
-            .translate(VirtAddr::from(TRAP_CONTEXT).into())
-            .unwrap()
-            .ppn();
-        // alloc a pid and a kernel stack in kernel space
-        let pid_handle = pid_alloc();
-        let kernel_stack = KernelStack::new(&pid_handle);
+    /// Create a thread of `process` on the resources `res`, which starts
+    /// with returning to user mode. The caller fills in the trap context.
+    pub fn new(
+        process: &Arc<ProcessControlBlock>,
+        res: TaskUserRes,
+        trap_cx_ppn: PhysPageNum,
+        sched: SchedEntity,
+        signal_mask: SignalFlags,
+    ) -> Self {
+        // alloc a kernel stack in kernel space
+        let kernel_stack = kstack_alloc();
         let kernel_stack_top = kernel_stack.get_top();
-        // copy fd table
-        // manually control the behavior of cloning
-        let mut new_fd_table: Vec<Option<Arc<dyn File + Send + Sync>>> = Vec::new();
-        for fd in parent_inner.fd_table.iter() {
-            if let Some(file) = fd {
-                new_fd_table.push(Some(file.clone()));
-            } else {
-                new_fd_table.push(None);
-            }
-        }
-        let task_control_block = Arc::new(TaskControlBlock {
-            pid: pid_handle,
+        Self {
+            process: Arc::downgrade(process),
             kernel_stack,
-            inner: unsafe {
-                UPSafeCell::new(TaskControlBlockInner {
-                    trap_cx_ppn,
-                    base_size: parent_inner.base_size,
-                    heap_bottom: parent_inner.heap_bottom,
-                    program_brk: parent_inner.program_brk,
-                    task_cx: TaskContext::goto_trap_return(kernel_stack_top),
-                    task_status: TaskStatus::Ready,
-                    memory_set,
-                    parent: Some(Arc::downgrade(self)),
-                    children: Vec::new(),
-                    exit_code: 0,
-                    fd_table: new_fd_table,
-                    signals: SignalFlags::empty(),
-                    // inherit the signal_mask and signal_action
-                    signal_mask: parent_inner.signal_mask,
-                    handling_sig: -1,
-                    signal_actions: parent_inner.signal_actions.clone(),
-                    killed: false,
-                    frozen: false,
-                    trap_ctx_backup: None,
-                })
-            },
-        });
-        // add child
-        parent_inner.children.push(task_control_block.clone());
-        // modify kernel_sp in trap_cx
-        // **** access children PCB exclusively
-        let trap_cx = task_control_block.inner_exclusive_access().get_trap_cx();
-        trap_cx.kernel_sp = kernel_stack_top;
-        // return
-        task_control_block
-        // ---- release parent PCB automatically
-        // **** release children PCB automatically
+            on_cpu: AtomicBool::new(false),
+            inner: SpinLock::new(TaskControlBlockInner {
+                res,
+                trap_cx_ppn,
+                // push a task context which goes to trap_return to the top of kernel stack
+                task_cx: TaskContext::goto_trap_return(kernel_stack_top),
+                task_status: TaskStatus::Ready,
+                sched,
+                exit_code: None,
+                signal_mask,
+                waiter: None,
+            }),
+        }
+    }
+    /// The process of this thread, which is not freed before all its threads exit
+    pub fn process(&self) -> Arc<ProcessControlBlock> {
+        self.process.upgrade().unwrap()
     }
-    pub fn getpid(&self) -> usize {
-        self.pid.0
+    pub fn gettid(&self) -> usize {
+        self.inner_exclusive_access().res.tid
     }
     pub fn inspect_kernel_stack(&self) {
         let guard = self.inner_exclusive_access();
         let sp: usize = guard.task_cx.sp();
-        let (bottom, top) = super::pid::kernel_stack_position(self.pid.0);
+        let (bottom, top) = super::id::kernel_stack_position(self.kernel_stack.0);
         kprintln!(
-            r"Kernel stack of pid {}:
+            r"Kernel stack of tid {}:
 bottom = {:#x}
 top    = {:#x}
 sp     = {:#x}",
-            self.pid.0,
+            guard.res.tid,
             bottom,
             top,
             sp
@@ -303,5 +123,7 @@ sp     = {:#x}",
 pub enum TaskStatus {
     Ready,
     Running,
+    /// Waiting for an event, not in the ready queue
+    Blocked,
     Zombie,
 }
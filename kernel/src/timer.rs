@@ -1,10 +1,21 @@
+use alloc::collections::BinaryHeap;
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+use lazy_static::*;
 use riscv::register::time;
 
-use crate::{board::CLOCK_FREQ, sbi::set_timer};
+use crate::sync::{futex_timeout, FutexWaiter, SpinLock, Waiter};
+use crate::task::ProcessControlBlock;
+use crate::{
+    board::{CLOCK_FREQ, RTC_BASE},
+    sbi::set_timer,
+};
 
 const TICKS_PER_SEC: usize = 100; // Timer interrupt every 10ms.
 const MICRO_PER_SEC: usize = 1_000_000; // 1 millon microseconds per second.
 const MSEC_PER_SEC: usize = 1000; // 1 thousand milliseconds per second.
+const NSEC_PER_SEC: usize = 1_000_000_000; // 1 billion nanoseconds per second.
 
 /// Get value from `mtime` register.
 pub fn get_time() -> usize {
@@ -28,3 +39,249 @@ pub fn get_time_us() -> usize {
 pub fn get_time_ms() -> usize {
     time::read() / (CLOCK_FREQ / MSEC_PER_SEC)
 }
+
+/// Get wall clock time in seconds since the Unix epoch from the RTC.
+pub fn get_wall_time_sec() -> usize {
+    // reading the low half latches the high half
+    let low = unsafe { (RTC_BASE as *const u32).read_volatile() } as u64;
+    let high = unsafe { ((RTC_BASE + 4) as *const u32).read_volatile() } as u64;
+    ((high << 32 | low) / NSEC_PER_SEC as u64) as usize
+}
+
+/// Time interval used by `nanosleep`, same layout as `struct timespec`.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct TimeSpec {
+    pub tv_sec: usize,
+    pub tv_nsec: usize,
+}
+
+impl TimeSpec {
+    /// Convert `ticks` of `mtime`
+    pub fn from_ticks(ticks: usize) -> Self {
+        Self {
+            tv_sec: ticks / CLOCK_FREQ,
+            tv_nsec: ticks % CLOCK_FREQ * NSEC_PER_SEC / CLOCK_FREQ,
+        }
+    }
+    /// Return `None` if `tv_nsec` is out of range. A time too long to count
+    /// saturates, and never comes.
+    pub fn to_ticks(self) -> Option<usize> {
+        if self.tv_nsec >= NSEC_PER_SEC {
+            return None;
+        }
+        let ticks = self.tv_sec.saturating_mul(CLOCK_FREQ);
+        Some(ticks.saturating_add(self.tv_nsec * CLOCK_FREQ / NSEC_PER_SEC))
+    }
+}
+
+/// Time in seconds and microseconds, same layout as `struct timeval`.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct TimeVal {
+    pub tv_sec: usize,
+    pub tv_usec: usize,
+}
+
+impl TimeVal {
+    /// Convert `ticks` of `mtime`
+    pub fn from_ticks(ticks: usize) -> Self {
+        Self {
+            tv_sec: ticks / CLOCK_FREQ,
+            tv_usec: ticks % CLOCK_FREQ * MICRO_PER_SEC / CLOCK_FREQ,
+        }
+    }
+    /// Return `None` if `tv_usec` is out of range. A nonzero time is at least
+    /// one tick, and a time too long to count saturates.
+    pub fn to_ticks(self) -> Option<usize> {
+        if self.tv_usec >= MICRO_PER_SEC {
+            return None;
+        }
+        let ticks = self
+            .tv_sec
+            .saturating_mul(CLOCK_FREQ)
+            .saturating_add(self.tv_usec * CLOCK_FREQ / MICRO_PER_SEC);
+        Some(ticks.max((self.tv_usec != 0) as usize))
+    }
+}
+
+/// `which` of `setitimer`: counts down in real time, then sends `SIGALRM`
+pub const ITIMER_REAL: usize = 0;
+/// `which` of `setitimer`: counts down in the cpu time of the process spent in
+/// user mode, then sends `SIGVTALRM`
+pub const ITIMER_VIRTUAL: usize = 1;
+/// `which` of `setitimer`: counts down in the cpu time of the process, then
+/// sends `SIGPROF`
+pub const ITIMER_PROF: usize = 2;
+
+/// Value of an interval timer, same layout as `struct itimerval`
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct ITimerVal {
+    /// Period after the first expiry, or zero for a single shot
+    pub it_interval: TimeVal,
+    /// Time until the next expiry, or zero if disarmed
+    pub it_value: TimeVal,
+}
+
+/// An interval timer of a process, in `mtime` ticks of its clock, see
+/// [`ITIMER_REAL`], [`ITIMER_VIRTUAL`] and [`ITIMER_PROF`]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct IntervalTimer {
+    /// When it expires, or 0 if it is disarmed
+    pub expire: usize,
+    /// Rearmed with this period when it expires, unless it is 0
+    pub interval: usize,
+}
+
+impl IntervalTimer {
+    /// Arm it to expire `value` ticks after `now`, or disarm it if `value` is 0
+    pub fn set(&mut self, now: usize, value: usize, interval: usize) {
+        self.expire = if value == 0 {
+            0
+        } else {
+            now.saturating_add(value)
+        };
+        self.interval = interval;
+    }
+    /// The time left until it expires, and the period
+    pub fn get(&self, now: usize) -> ITimerVal {
+        // an armed timer which is due has not been disarmed yet
+        let left = match self.expire {
+            0 => 0,
+            expire => expire.saturating_sub(now).max(1),
+        };
+        ITimerVal {
+            it_interval: TimeVal::from_ticks(self.interval),
+            it_value: TimeVal::from_ticks(left),
+        }
+    }
+    /// Return whether it has expired at `now`, rearming it then. The periods
+    /// which have been missed entirely are skipped.
+    pub fn expired(&mut self, now: usize) -> bool {
+        if self.expire == 0 || self.expire > now {
+            return false;
+        }
+        self.expire = match self.interval {
+            0 => 0,
+            interval => {
+                let periods = (now - self.expire) / interval + 1;
+                self.expire.saturating_add(periods.saturating_mul(interval))
+            }
+        };
+        true
+    }
+}
+
+/// What happens when a timer expires
+pub enum TimerEvent {
+    /// Wake up a sleeping task
+    Wakeup(Arc<Waiter>),
+    /// Give up waiting on a futex
+    FutexTimeout(Arc<FutexWaiter>),
+    /// Check the real time interval timer of a process
+    Alarm(Weak<ProcessControlBlock>),
+}
+
+/// An event happening at `expire_time` (in `mtime` ticks)
+pub struct TimerCondVar {
+    pub expire_time: usize,
+    pub event: TimerEvent,
+}
+
+impl PartialEq for TimerCondVar {
+    fn eq(&self, other: &Self) -> bool {
+        self.expire_time == other.expire_time
+    }
+}
+impl Eq for TimerCondVar {}
+impl PartialOrd for TimerCondVar {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl Ord for TimerCondVar {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // reversed, so that `BinaryHeap` pops the earliest timer first
+        other.expire_time.cmp(&self.expire_time)
+    }
+}
+
+lazy_static! {
+    static ref TIMERS: SpinLock<BinaryHeap<TimerCondVar>> =
+        SpinLock::new(BinaryHeap::<TimerCondVar>::new());
+}
+
+/// Wake `waiter` up when `get_time()` reaches `expire_time`.
+pub fn add_timer(expire_time: usize, waiter: Arc<Waiter>) {
+    let mut timers = TIMERS.exclusive_access();
+    timers.push(TimerCondVar {
+        expire_time,
+        event: TimerEvent::Wakeup(waiter),
+    });
+}
+
+/// Time `waiter` out when `get_time()` reaches `expire_time`.
+pub fn add_futex_timer(expire_time: usize, waiter: Arc<FutexWaiter>) {
+    let mut timers = TIMERS.exclusive_access();
+    timers.push(TimerCondVar {
+        expire_time,
+        event: TimerEvent::FutexTimeout(waiter),
+    });
+}
+
+/// Check the real time interval timer of `process` when `get_time()` reaches
+/// `expire_time`. The timer may have been changed in the meantime.
+pub fn add_alarm_timer(expire_time: usize, process: Weak<ProcessControlBlock>) {
+    let mut timers = TIMERS.exclusive_access();
+    timers.push(TimerCondVar {
+        expire_time,
+        event: TimerEvent::Alarm(process),
+    });
+}
+
+/// Cancel the timeout of `waiter`, if it has not expired yet.
+pub fn remove_futex_timer(waiter: &Arc<FutexWaiter>) {
+    let mut timers = TIMERS.exclusive_access();
+    timers.retain(|timer| match &timer.event {
+        TimerEvent::FutexTimeout(other) => !Arc::ptr_eq(other, waiter),
+        TimerEvent::Wakeup(_) | TimerEvent::Alarm(_) => true,
+    });
+}
+
+/// Cancel the wakeup of `waiter`, if it has not expired yet.
+pub fn remove_timer(waiter: &Arc<Waiter>) {
+    let mut timers = TIMERS.exclusive_access();
+    timers.retain(|timer| match &timer.event {
+        TimerEvent::Wakeup(other) => !Arc::ptr_eq(other, waiter),
+        TimerEvent::FutexTimeout(_) | TimerEvent::Alarm(_) => true,
+    });
+}
+
+/// Handle all the timers that have expired.
+pub fn check_timer() {
+    let current = get_time();
+    let mut timers = TIMERS.exclusive_access();
+    let mut expired = Vec::new();
+    while let Some(timer) = timers.peek() {
+        if timer.expire_time > current {
+            break;
+        }
+        expired.push(timers.pop().unwrap().event);
+    }
+    // futexes are locked before timers, see `futex_wake`
+    drop(timers);
+    for event in expired {
+        match event {
+            TimerEvent::Wakeup(waiter) => {
+                waiter.wake();
+            }
+            TimerEvent::FutexTimeout(waiter) => futex_timeout(waiter),
+            TimerEvent::Alarm(process) => {
+                if let Some(process) = process.upgrade() {
+                    process.check_real_timer();
+                }
+            }
+        }
+    }
+}
@@ -24,6 +24,10 @@ pub struct TrapContext {
 
     /// Trap handler
     pub trap_handler: usize,
+
+    /// Id of the hart the task last returned to user mode from,
+    /// loaded into `tp` on trap
+    pub hart_id: usize,
 }
 
 #[allow(unused)]
@@ -52,6 +56,7 @@ impl TrapContext {
             kernel_satp,
             kernel_sp,
             trap_handler,
+            hart_id: 0,
         };
         cx.set_sp(sp);
         cx
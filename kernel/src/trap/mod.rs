@@ -6,17 +6,21 @@ use riscv::register::{
     stvec::{self, TrapMode},
 };
 
+use crate::fs::poll_console;
+use crate::mm::MapPermission;
 use crate::task::{
-    current_trap_cx, current_user_token, exit_current_and_run_next, suspend_current_and_run_next,
+    check_cpu_timers, current_handle_page_fault, current_trap_cx, current_trap_cx_user_va,
+    current_user_token, exit_current_and_run_next, hart_id, suspend_current_and_run_next,
 };
-use crate::timer::set_next_trigger;
+use crate::timer::{check_timer, get_time, set_next_trigger};
 use crate::{
-    config::{TRAMPOLINE, TRAP_CONTEXT},
-    task::{current_task, SignalFlags},
+    config::TRAMPOLINE,
+    errno::Errno,
+    task::{current_task, SignalAction, SignalFlags, SignalInfo, SIG_IGN},
 };
 use crate::{
-    syscall::syscall,
-    task::{check_signals_error_of_current, handle_signals},
+    syscall::{syscall, SYSCALL_SIGRETURN},
+    task::{current_process_exiting, handle_signals},
 };
 
 mod context;
@@ -26,6 +30,17 @@ pub use context::TrapContext;
 global_asm!(include_str!("trap_d_ext.S"));
 #[cfg(not(feature = "D_EXTENSION_ENABLED"))]
 global_asm!(include_str!("trap.S"));
+global_asm!(include_str!("sigreturn.S"));
+
+/// Code of the page at `SIGRETURN_TRAMPOLINE`, to which signal handlers return
+pub fn sigreturn_trampoline() -> &'static [u8] {
+    extern "C" {
+        fn __sigreturn_start();
+        fn __sigreturn_end();
+    }
+    let start = __sigreturn_start as usize;
+    unsafe { core::slice::from_raw_parts(start as *const u8, __sigreturn_end as usize - start) }
+}
 
 pub fn init() {
     set_kernel_trap_entry();
@@ -53,7 +68,12 @@ pub fn trap_from_kernel() -> ! {
 #[no_mangle]
 pub fn trap_return() -> ! {
     set_user_trap_entry();
-    let trap_cx_ptr = TRAP_CONTEXT;
+    // the next trap may come on this hart only
+    current_trap_cx().hart_id = hart_id();
+    let task = current_task().unwrap();
+    task.inner_exclusive_access().sched.user_start = get_time();
+    drop(task);
+    let trap_cx_user_va = current_trap_cx_user_va();
     let user_satp = current_user_token();
     extern "C" {
         fn __alltraps();
@@ -65,7 +85,7 @@ pub fn trap_return() -> ! {
             "fence.i",
             "jr {restore_va}",
             restore_va = in(reg) restore_va,
-            in("a0") trap_cx_ptr,
+            in("a0") trap_cx_user_va,
             in("a1") user_satp,
             options(noreturn)
         );
@@ -75,34 +95,74 @@ pub fn trap_return() -> ! {
 #[no_mangle]
 pub fn trap_handler() -> ! {
     set_kernel_trap_entry();
+    let task = current_task().unwrap();
+    task.inner_exclusive_access().sched.enter_kernel(get_time());
+    drop(task);
     let scause = scause::read();
     let stval = stval::read();
+    // first argument of a syscall interrupted by a signal
+    let mut interrupted = None;
     match scause.cause() {
         Trap::Exception(Exception::UserEnvCall) => {
             let mut cx = current_trap_cx();
             cx.sepc += 4;
-            let result = syscall(cx.x[17], [cx.x[10], cx.x[11], cx.x[12]]);
+            let (syscall_id, a0) = (cx.x[17], cx.x[10]);
+            let result = syscall(
+                syscall_id,
+                [cx.x[10], cx.x[11], cx.x[12], cx.x[13], cx.x[14], cx.x[15]],
+            );
             // cx is changed during sys_exec, so we have to call it again
             cx = current_trap_cx();
             cx.x[10] = result as usize;
+            // `sigreturn` returns whatever a0 the handler interrupted
+            if result == -(Errno::EINTR as isize) && syscall_id != SYSCALL_SIGRETURN {
+                interrupted = Some(a0);
+            }
+        }
+        Trap::Exception(
+            fault @ (Exception::StorePageFault
+            | Exception::LoadPageFault
+            | Exception::InstructionPageFault),
+        ) => {
+            let access = match fault {
+                Exception::StorePageFault => MapPermission::W,
+                Exception::LoadPageFault => MapPermission::R,
+                _ => MapPermission::X,
+            };
+            match current_handle_page_fault(stval.into(), access) {
+                // page loaded or copied, retry the access
+                Ok(()) => {}
+                // only the process needing the page goes
+                Err(Errno::ENOMEM) => {
+                    kprintln!("[kernel] Out of memory in application");
+                    current_add_signal(SignalInfo::kernel(SignalFlags::SIGKILL));
+                }
+                Err(_) => {
+                    kprintln!("[kernel] PageFault in application");
+                    current_add_signal(SignalInfo::fault(SignalFlags::SIGSEGV, stval));
+                }
+            }
         }
         Trap::Exception(Exception::StoreFault)
-        | Trap::Exception(Exception::StorePageFault)
         | Trap::Exception(Exception::InstructionFault)
-        | Trap::Exception(Exception::InstructionPageFault)
-        | Trap::Exception(Exception::LoadFault)
-        | Trap::Exception(Exception::LoadPageFault) => {
+        | Trap::Exception(Exception::LoadFault) => {
             kprintln!("[kernel] PageFault in application");
             // exit_current_and_run_next(-2);
-            current_add_signal(SignalFlags::SIGSEGV);
+            current_add_signal(SignalInfo::fault(SignalFlags::SIGSEGV, stval));
         }
         Trap::Exception(Exception::IllegalInstruction) => {
             kprintln!("[kernel] IllegalInstruction in application");
             // exit_current_and_run_next(-3);
-            current_add_signal(SignalFlags::SIGSEGV);
+            let sepc = current_trap_cx().sepc;
+            current_add_signal(SignalInfo::fault(SignalFlags::SIGSEGV, sepc));
         }
         Trap::Interrupt(Interrupt::SupervisorTimer) => {
             set_next_trigger();
+            // wake up sleeping tasks whose deadline has come
+            check_timer();
+            check_cpu_timers();
+            // deliver ^C and ^Z even if no one reads the console
+            poll_console();
             // Schedule next task to run
             suspend_current_and_run_next();
         }
@@ -116,12 +176,11 @@ pub fn trap_handler() -> ! {
     }
 
     // handle signals (handle the sent signal)
-    handle_signals();
+    handle_signals(interrupted);
 
-    // check error signals (if error then exit)
-    if let Some((errno, msg)) = check_signals_error_of_current() {
-        kprintln!("[kernel] {}", msg);
-        exit_current_and_run_next(errno);
+    // another thread is exiting the process or replacing its program
+    if current_process_exiting() {
+        exit_current_and_run_next(0);
     }
 
     trap_return();
@@ -133,8 +192,20 @@ pub fn enable_timer_interrupt() {
     unsafe { sie::set_stimer() }
 }
 
-pub fn current_add_signal(signal: SignalFlags) {
+/// Raise the signal of `info` for a fault of the current thread. Returning to
+/// the faulting instruction would only fault again, so the signal cannot be
+/// blocked or ignored: its default action is taken instead.
+pub fn current_add_signal(info: SignalInfo) {
     let task = current_task().unwrap();
+    let process = task.process();
+    let signal = SignalFlags::from_bits_truncate(1 << info.signo);
+    let mut process_inner = process.inner_exclusive_access();
     let mut task_inner = task.inner_exclusive_access();
-    task_inner.signals |= signal;
+    let action = &mut process_inner.signal_actions.table[info.signo as usize];
+    if task_inner.signal_mask.contains(signal) || action.handler == SIG_IGN {
+        *action = SignalAction::default();
+        task_inner.signal_mask.remove(signal);
+    }
+    drop(task_inner);
+    process_inner.add_signal(info);
 }
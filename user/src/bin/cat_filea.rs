@@ -10,7 +10,7 @@ use user_lib::{close, open, read, OpenFlags};
 #[no_mangle]
 pub fn main() -> i32 {
     let fd = open("filea\0", OpenFlags::RDONLY);
-    if fd == -1 {
+    if fd < 0 {
         panic!("Error occured when opening file");
     }
     let fd = fd as usize;
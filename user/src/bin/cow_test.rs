@@ -0,0 +1,68 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{close, exit, fork, pipe, read, waitpid};
+
+const PAGE_SIZE: usize = 4096;
+const PAGES: usize = 16;
+
+static mut DATA: [u8; PAGES * PAGE_SIZE] = [0; PAGES * PAGE_SIZE];
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let data = unsafe { &mut *core::ptr::addr_of_mut!(DATA) };
+    data.fill(1);
+    let mut stack_value = 1usize;
+
+    let pid = fork();
+    if pid == 0 {
+        // the child sees the parent's memory and gets private copies on write
+        assert!(data.iter().all(|&b| b == 1));
+        for page in data.chunks_mut(PAGE_SIZE) {
+            page[0] = 2;
+        }
+        stack_value = 2;
+        assert_eq!(data[PAGE_SIZE], 2);
+        assert_eq!(stack_value, 2);
+        // the kernel writes into a shared page as well
+        let mut pipe_fd = [0usize; 2];
+        pipe(&mut pipe_fd);
+        close(pipe_fd[0]);
+        close(pipe_fd[1]);
+        exit(0);
+    }
+    assert!(pid > 0);
+    let mut exit_code: i32 = -1;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    // writes of the child never reach the parent
+    assert!(data.iter().all(|&b| b == 1));
+    assert_eq!(stack_value, 1);
+
+    // after the child exits the parent owns its pages alone again
+    data.fill(3);
+    assert!(data.iter().all(|&b| b == 3));
+
+    // the kernel writes into a page still shared with a child
+    let mut pipe_fd = [0usize; 2];
+    pipe(&mut pipe_fd);
+    let pid = fork();
+    if pid == 0 {
+        let mut buf = [0u8; 8];
+        close(pipe_fd[1]);
+        assert_eq!(read(pipe_fd[0], &mut buf), 3);
+        assert_eq!(&buf[..3], b"cow");
+        assert!(data.iter().all(|&b| b == 3));
+        exit(0);
+    }
+    close(pipe_fd[0]);
+    user_lib::write(pipe_fd[1], b"cow");
+    close(pipe_fd[1]);
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    println!("cow_test passed!");
+    0
+}
@@ -0,0 +1,85 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    chdir, close, getcwd, mkdir, open, read, rmdir, unlink, write, OpenFlags, ENOENT, ENOTDIR,
+    ERANGE,
+};
+
+/// Assert that the working directory is `expected`
+fn assert_cwd(expected: &str) {
+    let mut buf = [0u8; 64];
+    let len = getcwd(&mut buf);
+    // the returned length includes the trailing NUL
+    assert_eq!(len, expected.len() as isize + 1);
+    assert_eq!(&buf[..expected.len()], expected.as_bytes());
+    assert_eq!(buf[expected.len()], 0);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let test_str = "Hello from the working directory!";
+    assert_cwd("/");
+    assert_eq!(mkdir("/cwdtest\0"), 0);
+    assert_eq!(chdir("/cwdtest\0"), 0);
+    assert_cwd("/cwdtest");
+
+    // the buffer must hold the path and its NUL
+    let mut buf = [0u8; 9];
+    assert_eq!(getcwd(&mut buf[..8]), -ERANGE);
+    assert_eq!(getcwd(&mut buf), 9);
+
+    // relative paths start from the working directory
+    assert_eq!(mkdir("sub\0"), 0);
+    let fd = open("sub/file\0", OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd > 0);
+    write(fd as usize, test_str.as_bytes());
+    close(fd as usize);
+    let fd = open("/cwdtest/sub/file\0", OpenFlags::RDONLY);
+    assert!(fd > 0);
+    close(fd as usize);
+
+    // `..` leads back up, and stops at the root
+    assert_eq!(chdir("sub\0"), 0);
+    assert_cwd("/cwdtest/sub");
+    let fd = open("../sub/file\0", OpenFlags::RDONLY);
+    assert!(fd > 0);
+    let mut buffer = [0u8; 100];
+    let read_len = read(fd as usize, &mut buffer) as usize;
+    close(fd as usize);
+    assert_eq!(test_str, core::str::from_utf8(&buffer[..read_len]).unwrap());
+    assert_eq!(chdir("..\0"), 0);
+    assert_cwd("/cwdtest");
+    assert_eq!(chdir("../..\0"), 0);
+    assert_cwd("/");
+    assert_eq!(chdir("cwdtest/sub/..\0"), 0);
+    assert_cwd("/cwdtest");
+
+    // only existing directories can be entered
+    assert_eq!(chdir("sub/file\0"), -ENOTDIR);
+    assert_eq!(chdir("none\0"), -ENOENT);
+    assert_cwd("/cwdtest");
+
+    // a removed working directory has no path, and takes no new entries
+    assert_eq!(mkdir("gone\0"), 0);
+    assert_eq!(chdir("gone\0"), 0);
+    assert_eq!(rmdir("/cwdtest/gone\0"), 0);
+    assert_eq!(getcwd(&mut buffer), -ENOENT);
+    assert_eq!(mkdir("dir\0"), -ENOENT);
+    assert_eq!(
+        open("file\0", OpenFlags::CREATE | OpenFlags::WRONLY),
+        -ENOENT
+    );
+
+    // clean up so that the test can be run again
+    assert_eq!(chdir("/\0"), 0);
+    assert_eq!(unlink("/cwdtest/sub/file\0"), 0);
+    assert_eq!(rmdir("/cwdtest/sub\0"), 0);
+    assert_eq!(rmdir("/cwdtest\0"), 0);
+    assert_cwd("/");
+    println!("cwd_test passed!");
+    0
+}
@@ -0,0 +1,50 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{close, mkdir, open, read, rmdir, unlink, write, OpenFlags, EEXIST, ENOENT};
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let test_str = "Hello from a nested directory!";
+    assert_eq!(mkdir("/dirtest\0"), 0);
+    assert_eq!(mkdir("/dirtest/sub\0"), 0);
+    // creating an existing directory fails
+    assert_eq!(mkdir("/dirtest/sub\0"), -EEXIST);
+    // parent does not exist
+    assert_eq!(mkdir("/dirtest/none/sub\0"), -ENOENT);
+
+    let fd = open("/dirtest/sub/file\0", OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd > 0);
+    let fd = fd as usize;
+    write(fd, test_str.as_bytes());
+    close(fd);
+
+    // walk through `.` and `..`
+    let fd = open("/dirtest/./sub/../sub//file\0", OpenFlags::RDONLY);
+    assert!(fd > 0);
+    let fd = fd as usize;
+    let mut buffer = [0u8; 100];
+    let read_len = read(fd, &mut buffer) as usize;
+    close(fd);
+    assert_eq!(test_str, core::str::from_utf8(&buffer[..read_len]).unwrap());
+
+    // relative paths, `..` of root is root itself
+    for path in ["dirtest/sub/file\0", "/../dirtest/sub/file\0"] {
+        let fd = open(path, OpenFlags::RDONLY);
+        assert!(fd > 0);
+        close(fd as usize);
+    }
+    // directories cannot be opened as files, files cannot be walked through
+    assert_eq!(open("/dirtest/sub\0", OpenFlags::RDONLY), -ENOENT);
+    assert_eq!(open("/dirtest/sub/file/x\0", OpenFlags::RDONLY), -ENOENT);
+
+    // clean up so that the test can be run again
+    assert_eq!(unlink("/dirtest/sub/file\0"), 0);
+    assert_eq!(rmdir("/dirtest/sub\0"), 0);
+    assert_eq!(rmdir("/dirtest\0"), 0);
+    println!("dirtest passed!");
+    0
+}
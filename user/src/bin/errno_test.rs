@@ -0,0 +1,39 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::arch::asm;
+use user_lib::{close, exec, pipe, read, write, EBADF, EFAULT, ENOENT, ENOSYS};
+
+/// Below the program, never mapped
+const BAD_ADDR: usize = 0x1000;
+
+fn raw_syscall(id: usize) -> isize {
+    let mut ret: isize;
+    unsafe {
+        asm!("ecall", inlateout("x10") 0usize => ret, in("x17") id);
+    }
+    ret
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    // bad user pointers fail the syscall instead of the kernel
+    let bad = unsafe { core::slice::from_raw_parts_mut(BAD_ADDR as *mut u8, 16) };
+    assert_eq!(write(1, bad), -EFAULT);
+    assert_eq!(read(0, bad), -EFAULT);
+    let bad_fds = unsafe { core::slice::from_raw_parts_mut(BAD_ADDR as *mut usize, 2) };
+    assert_eq!(pipe(bad_fds), -EFAULT);
+
+    assert_eq!(close(100), -EBADF);
+    assert_eq!(write(0, b"x"), -EBADF);
+    assert_eq!(
+        exec("errno_test_none\0", &[core::ptr::null::<u8>()]),
+        -ENOENT
+    );
+    assert_eq!(raw_syscall(9999), -ENOSYS);
+    println!("errno_test passed!");
+    0
+}
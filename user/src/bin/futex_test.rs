@@ -0,0 +1,148 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+use user_lib::{
+    exit, fork, futex_wait, futex_wake, get_time, mmap, sleep, thread_create, waitpid, waittid,
+    wexitstatus, yield_, Condvar, MmapFlags, MmapProt, Mutex, Semaphore, TimeSpec, EAGAIN,
+    ETIMEDOUT,
+};
+
+const THREADS: usize = 4;
+const ROUNDS: usize = 200;
+const ITEMS: usize = 100;
+
+static COUNTER: Mutex<usize> = Mutex::new(0);
+
+/// (the next item, whether the consumer has taken it)
+static SLOT: Mutex<(usize, bool)> = Mutex::new((0, true));
+static SLOT_CHANGED: Condvar = Condvar::new();
+
+static EMPTY: Semaphore = Semaphore::new(1);
+static FULL: Semaphore = Semaphore::new(0);
+static ITEM: AtomicUsize = AtomicUsize::new(0);
+
+fn add(_arg: usize) -> ! {
+    for _ in 0..ROUNDS {
+        let mut counter = COUNTER.lock();
+        let value = *counter;
+        // let the others run into the lock
+        yield_();
+        *counter = value + 1;
+    }
+    exit(0)
+}
+
+fn consume_with_condvar(_arg: usize) -> ! {
+    for i in 1..=ITEMS {
+        let mut slot = SLOT.lock();
+        while slot.1 {
+            slot = SLOT_CHANGED.wait(slot);
+        }
+        assert_eq!(slot.0, i);
+        slot.1 = true;
+        SLOT_CHANGED.notify_all();
+    }
+    exit(0)
+}
+
+fn consume_with_semaphore(_arg: usize) -> ! {
+    for i in 1..=ITEMS {
+        FULL.down();
+        assert_eq!(ITEM.load(Ordering::Relaxed), i);
+        EMPTY.up();
+    }
+    exit(0)
+}
+
+fn wait_forever(_arg: usize) -> ! {
+    let word = AtomicU32::new(0);
+    loop {
+        futex_wait(&word, 0, None);
+    }
+}
+
+fn join(tid: isize) {
+    let mut exit_code = -1;
+    assert_eq!(waittid(tid as usize, &mut exit_code), tid);
+    assert_eq!(exit_code, 0);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let word = AtomicU32::new(1);
+    assert_eq!(futex_wait(&word, 0, None), -EAGAIN);
+    assert_eq!(futex_wake(&word, 1), 0);
+    let timeout = TimeSpec {
+        tv_sec: 0,
+        tv_nsec: 50_000_000,
+    };
+    let start = get_time();
+    assert_eq!(futex_wait(&word, 1, Some(&timeout)), -ETIMEDOUT);
+    assert!(get_time() - start >= 50);
+
+    let tids: [isize; THREADS] = core::array::from_fn(|_| thread_create(add as usize, 0));
+    tids.into_iter().for_each(join);
+    assert_eq!(*COUNTER.lock(), THREADS * ROUNDS);
+
+    let tid = thread_create(consume_with_condvar as usize, 0);
+    for i in 1..=ITEMS {
+        let mut slot = SLOT.lock();
+        while !slot.1 {
+            slot = SLOT_CHANGED.wait(slot);
+        }
+        *slot = (i, false);
+        SLOT_CHANGED.notify_all();
+    }
+    join(tid);
+    let (_slot, timed_out) = SLOT_CHANGED.wait_timeout(SLOT.lock(), Some(&timeout));
+    assert!(timed_out);
+
+    let tid = thread_create(consume_with_semaphore as usize, 0);
+    for i in 1..=ITEMS {
+        EMPTY.down();
+        ITEM.store(i, Ordering::Relaxed);
+        FULL.up();
+    }
+    join(tid);
+
+    // a futex in a shared page is shared by both processes
+    let addr = mmap(
+        0,
+        4096,
+        MmapProt::READ | MmapProt::WRITE,
+        MmapFlags::SHARED | MmapFlags::ANONYMOUS,
+        0,
+        0,
+    );
+    assert!(addr > 0);
+    let shared = unsafe { &*(addr as *const AtomicU32) };
+    let pid = fork();
+    if pid == 0 {
+        while shared.load(Ordering::Acquire) == 0 {
+            futex_wait(shared, 0, None);
+        }
+        exit(0);
+    }
+    sleep(10);
+    shared.store(1, Ordering::Release);
+    futex_wake(shared, 1);
+    let mut exit_code = -1;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+
+    // sleepers on a futex leave with the process
+    let pid = fork();
+    if pid == 0 {
+        thread_create(wait_forever as usize, 0);
+        sleep(10);
+        exit(5);
+    }
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(wexitstatus(exit_code), 5);
+    println!("futex_test passed!");
+    0
+}
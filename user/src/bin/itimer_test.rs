@@ -0,0 +1,120 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+use user_lib::{
+    alarm, exit, fork, get_time, getitimer, setitimer, sigaction, waitpid, wtermsig, ITimerVal,
+    SignalAction, TimeVal, EINVAL, ITIMER_PROF, ITIMER_REAL, ITIMER_VIRTUAL, SIGALRM, SIGPROF,
+    SIGVTALRM,
+};
+
+static ALARMS: AtomicUsize = AtomicUsize::new(0);
+
+extern "C" fn on_alarm(_sig: i32) {
+    ALARMS.fetch_add(1, Ordering::Relaxed);
+}
+
+fn set_handler(signum: i32) {
+    let action = SignalAction {
+        handler: on_alarm as usize,
+        ..Default::default()
+    };
+    assert_eq!(sigaction(signum, Some(&action), None), 0);
+}
+
+fn timer(value_ms: usize, interval_ms: usize) -> ITimerVal {
+    let ms = |ms: usize| TimeVal {
+        tv_sec: ms / 1000,
+        tv_usec: ms % 1000 * 1000,
+    };
+    ITimerVal {
+        it_interval: ms(interval_ms),
+        it_value: ms(value_ms),
+    }
+}
+
+/// Spin in user mode until `count` signals have come, or fail after `limit_ms`
+fn spin_until(count: usize, limit_ms: isize) {
+    let start = get_time();
+    while ALARMS.load(Ordering::Relaxed) < count {
+        assert!(get_time() - start < limit_ms, "no signal in time");
+        for i in 0..1000 {
+            core::hint::black_box(i);
+        }
+    }
+}
+
+fn to_ms(value: &TimeVal) -> usize {
+    value.tv_sec * 1000 + value.tv_usec / 1000
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut value = ITimerVal::default();
+    assert_eq!(getitimer(3, &mut value), -EINVAL);
+    let mut bad = timer(10, 0);
+    bad.it_value.tv_usec = 1_000_000;
+    assert_eq!(setitimer(ITIMER_REAL, &bad, None), -EINVAL);
+
+    // a single shot, then disarmed
+    set_handler(SIGALRM);
+    assert_eq!(setitimer(ITIMER_REAL, &timer(30, 0), None), 0);
+    spin_until(1, 1000);
+    assert_eq!(getitimer(ITIMER_REAL, &mut value), 0);
+    assert_eq!(to_ms(&value.it_value), 0);
+
+    // periodic, and the time left is reported
+    ALARMS.store(0, Ordering::Relaxed);
+    assert_eq!(setitimer(ITIMER_REAL, &timer(20, 20), None), 0);
+    spin_until(3, 1000);
+    let mut old = ITimerVal::default();
+    assert_eq!(setitimer(ITIMER_REAL, &timer(0, 0), Some(&mut old)), 0);
+    assert_eq!(to_ms(&old.it_interval), 20);
+    assert!(to_ms(&old.it_value) <= 20);
+    assert_eq!(setitimer(ITIMER_REAL, &timer(5000, 0), None), 0);
+    assert_eq!(getitimer(ITIMER_REAL, &mut value), 0);
+    assert!((4900..=5000).contains(&to_ms(&value.it_value)));
+
+    // alarm reports the seconds left, rounded up
+    assert_eq!(alarm(0), 5);
+    assert_eq!(alarm(0), 0);
+
+    // cpu time clocks
+    for (which, signum) in [(ITIMER_VIRTUAL, SIGVTALRM), (ITIMER_PROF, SIGPROF)] {
+        ALARMS.store(0, Ordering::Relaxed);
+        set_handler(signum);
+        assert_eq!(setitimer(which, &timer(30, 0), None), 0);
+        spin_until(1, 2000);
+    }
+
+    // not inherited by a child
+    assert_eq!(setitimer(ITIMER_REAL, &timer(5000, 0), None), 0);
+    let pid = fork();
+    if pid == 0 {
+        assert_eq!(getitimer(ITIMER_REAL, &mut value), 0);
+        assert_eq!(to_ms(&value.it_value), 0);
+        exit(0);
+    }
+    let mut status = 0;
+    assert_eq!(waitpid(pid as usize, &mut status), pid);
+    assert_eq!(status, 0);
+    alarm(0);
+
+    // a watchdog ends a stuck child
+    let pid = fork();
+    if pid == 0 {
+        // the handler is inherited
+        sigaction(SIGALRM, Some(&SignalAction::default()), None);
+        alarm(1);
+        loop {
+            core::hint::spin_loop();
+        }
+    }
+    assert_eq!(waitpid(pid as usize, &mut status), pid);
+    assert_eq!(wtermsig(status), SIGALRM);
+    println!("itimer_test passed!");
+    0
+}
@@ -0,0 +1,113 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    exit, fork, getpgid, getpid, getsid, kill, killpg, pipe, read, setpgid, setsid, sleep,
+    tcgetpgrp, waitpid, waitpid_options, wexitstatus, wifexited, wifstopped, wstopsig, wtermsig,
+    ENOTTY, EPERM, ESRCH, SIGCONT, SIGKILL, SIGSTOP, SIGTERM, SIGTTIN, WNOHANG, WUNTRACED,
+};
+
+/// Fork a child which runs `f` and then exits with 0
+fn spawn(f: fn()) -> usize {
+    let pid = fork();
+    if pid == 0 {
+        f();
+        exit(0);
+    }
+    pid as usize
+}
+
+fn new_session() {
+    let pid = getpid();
+    assert_eq!(setsid(), pid);
+    assert_eq!(getsid(0), pid);
+    assert_eq!(getpgid(0), pid);
+    // it leads a group already, and cannot leave it
+    assert_eq!(setsid(), -EPERM);
+    assert_eq!(setpgid(0, 0), -EPERM);
+}
+
+fn pause() {
+    loop {
+        sleep(1000);
+    }
+}
+
+fn stop_self() {
+    kill(getpid() as usize, SIGSTOP);
+    exit(5);
+}
+
+fn read_in_background() {
+    assert_eq!(setpgid(0, 0), 0);
+    let mut buf = [0u8; 1];
+    read(0, &mut buf);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let pgid = getpgid(0);
+    assert!(pgid >= 0);
+    assert_eq!(getsid(0), getsid(getpid() as usize));
+
+    let mut status = 0;
+    let pid = spawn(new_session);
+    assert_eq!(waitpid(pid, &mut status), pid as isize);
+    assert_eq!(status, 0);
+
+    // two children in a group of their own, killed together
+    let leader = spawn(pause);
+    assert_eq!(setpgid(leader, 0), 0);
+    let member = spawn(pause);
+    assert_eq!(setpgid(member, leader), 0);
+    assert_eq!(getpgid(leader), leader as isize);
+    assert_eq!(getpgid(member), leader as isize);
+    assert_eq!(getpgid(0), pgid);
+    // signal 0 only checks that the group exists
+    assert_eq!(killpg(leader, 0), 0);
+    assert_eq!(killpg(leader, SIGTERM), 0);
+    for pid in [leader, member] {
+        assert_eq!(waitpid(pid, &mut status), pid as isize);
+        assert_eq!(wtermsig(status), SIGTERM);
+    }
+    assert_eq!(killpg(leader, 0), -ESRCH);
+
+    // a stopped child is reported with WUNTRACED, and only once
+    let pid = spawn(stop_self);
+    assert_eq!(
+        waitpid_options(pid as isize, &mut status, WUNTRACED),
+        pid as isize
+    );
+    assert!(wifstopped(status) && !wifexited(status));
+    assert_eq!(wstopsig(status), SIGSTOP);
+    assert_eq!(
+        waitpid_options(pid as isize, &mut status, WUNTRACED | WNOHANG),
+        0
+    );
+    kill(pid, SIGCONT);
+    assert_eq!(waitpid(pid, &mut status), pid as isize);
+    assert!(wifexited(status));
+    assert_eq!(wexitstatus(status), 5);
+
+    // reading the terminal from a background group stops the reader
+    let pid = spawn(read_in_background);
+    assert_eq!(
+        waitpid_options(pid as isize, &mut status, WUNTRACED),
+        pid as isize
+    );
+    assert_eq!(wstopsig(status), SIGTTIN);
+    kill(pid, SIGKILL);
+    assert_eq!(waitpid(pid, &mut status), pid as isize);
+    assert_eq!(wtermsig(status), SIGKILL);
+
+    // only the console is a terminal
+    let mut pipe_fd = [0usize; 2];
+    pipe(&mut pipe_fd);
+    assert_eq!(tcgetpgrp(pipe_fd[0]), -ENOTTY);
+    assert!(tcgetpgrp(0) >= 0);
+    println!("jobctl_test passed!");
+    0
+}
@@ -0,0 +1,91 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+use user_lib::{exit, fork, sbrk, thread_create, waitpid, waittid, wifsignaled, wtermsig, SIGKILL};
+
+const PAGE_SIZE: usize = 0x1000;
+/// Far more than the physical memory of the machine
+const HEAP_SIZE: usize = 0x1000_0000;
+const THREADS: usize = 4;
+/// Pages all threads touch at once
+const SHARED_PAGES: usize = 256;
+
+static SHARED_START: AtomicUsize = AtomicUsize::new(0);
+
+fn touch_shared(i: usize) -> ! {
+    let start = SHARED_START.load(Ordering::Relaxed);
+    for page in 0..SHARED_PAGES {
+        // losing the race for a page to another thread is no fault
+        unsafe { ((start + page * PAGE_SIZE + i) as *mut u8).write_volatile(1) };
+    }
+    exit(0)
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let heap_bottom = sbrk(0);
+    assert!(heap_bottom > 0);
+    assert_eq!(sbrk(HEAP_SIZE as i32), heap_bottom);
+    let heap = unsafe { core::slice::from_raw_parts_mut(heap_bottom as *mut u8, HEAP_SIZE) };
+    // untouched pages read as zero
+    assert_eq!(heap[HEAP_SIZE / 2], 0);
+    // only the pages touched here get frames
+    for offset in (0..HEAP_SIZE).step_by(HEAP_SIZE / 16) {
+        heap[offset] = 1;
+    }
+    heap[HEAP_SIZE - 1] = 2;
+
+    let pid = fork();
+    if pid == 0 {
+        // touched pages are inherited, untouched ones still come up empty
+        assert_eq!(heap[HEAP_SIZE / 16], 1);
+        assert_eq!(heap[HEAP_SIZE - 1], 2);
+        assert_eq!(heap[PAGE_SIZE], 0);
+        heap[PAGE_SIZE] = 3;
+        exit(0);
+    }
+    let mut exit_code: i32 = -1;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    assert_eq!(heap[PAGE_SIZE], 0);
+
+    // threads faulting on the same untouched pages at once all go on
+    SHARED_START.store(heap_bottom as usize + HEAP_SIZE / 2, Ordering::Relaxed);
+    let mut tids = [0usize; THREADS];
+    for (i, tid) in tids.iter_mut().enumerate() {
+        let ret = thread_create(touch_shared as usize, i);
+        assert!(ret > 0);
+        *tid = ret as usize;
+    }
+    for tid in tids {
+        assert_eq!(waittid(tid, &mut exit_code), tid as isize);
+        assert_eq!(exit_code, 0);
+    }
+    for page in 0..SHARED_PAGES {
+        let offset = HEAP_SIZE / 2 + page * PAGE_SIZE;
+        assert_eq!(heap[offset..offset + THREADS], [1; THREADS]);
+    }
+
+    // running out of memory kills the process needing the page, not the kernel
+    let pid = fork();
+    if pid == 0 {
+        for offset in (0..HEAP_SIZE).step_by(PAGE_SIZE) {
+            heap[offset] = 4;
+        }
+        exit(0);
+    }
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert!(wifsignaled(exit_code));
+    assert_eq!(wtermsig(exit_code), SIGKILL);
+
+    // give the heap back, growing it again gives fresh pages
+    assert_eq!(sbrk(-(HEAP_SIZE as i32)), heap_bottom + HEAP_SIZE as isize);
+    assert_eq!(sbrk(PAGE_SIZE as i32), heap_bottom);
+    assert_eq!(heap[0], 0);
+    println!("lazy_test passed!");
+    0
+}
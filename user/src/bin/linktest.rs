@@ -0,0 +1,90 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, fstat, link, mkdir, open, read, rename, renameat2, rmdir, unlink, write, OpenFlags,
+    Stat, EEXIST, EINVAL, EISDIR, ENOENT, ENOTDIR, ENOTEMPTY, EPERM, RENAME_EXCHANGE,
+    RENAME_NOREPLACE,
+};
+
+/// Create `path` holding `content`
+fn write_file(path: &str, content: &[u8]) {
+    let fd = open(path, OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd > 0);
+    assert_eq!(write(fd as usize, content), content.len() as isize);
+    close(fd as usize);
+}
+
+/// Contents of `path`, which must be at most 16 bytes, and its status
+fn read_file(path: &str) -> ([u8; 16], usize, Stat) {
+    let fd = open(path, OpenFlags::RDONLY);
+    assert!(fd > 0);
+    let mut buf = [0u8; 16];
+    let len = read(fd as usize, &mut buf) as usize;
+    let mut st = Stat::default();
+    assert_eq!(fstat(fd as usize, &mut st), 0);
+    close(fd as usize);
+    (buf, len, st)
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    // the file lives on under its second name
+    write_file("linktest_a\0", b"hello");
+    assert_eq!(link("linktest_a\0", "linktest_b\0"), 0);
+    assert_eq!(link("linktest_a\0", "linktest_b\0"), -EEXIST);
+    assert_eq!(link("linktest_none\0", "linktest_c\0"), -ENOENT);
+    let (_, _, st) = read_file("linktest_a\0");
+    assert_eq!(st.st_nlink, 2);
+    assert_eq!(unlink("linktest_a\0"), 0);
+    let (buf, len, st2) = read_file("linktest_b\0");
+    assert_eq!(&buf[..len], b"hello");
+    assert_eq!(st2.st_ino, st.st_ino);
+    assert_eq!(st2.st_nlink, 1);
+
+    // directories cannot be linked or moved below themselves
+    assert_eq!(mkdir("linktest_dir\0"), 0);
+    assert_eq!(link("linktest_dir\0", "linktest_dir2\0"), -EPERM);
+    assert_eq!(mkdir("linktest_dir/sub\0"), 0);
+    assert_eq!(rename("linktest_dir\0", "linktest_dir/sub/x\0"), -EINVAL);
+    assert_eq!(rename("linktest_dir\0", "linktest_dir\0"), 0);
+
+    // moving and replacing files
+    assert_eq!(rename("linktest_b\0", "linktest_dir/c\0"), 0);
+    assert_eq!(open("linktest_b\0", OpenFlags::RDONLY), -ENOENT);
+    write_file("linktest_d\0", b"world");
+    assert_eq!(
+        renameat2("linktest_d\0", "linktest_dir/c\0", RENAME_NOREPLACE),
+        -EEXIST
+    );
+    assert_eq!(rename("linktest_d\0", "linktest_dir/c\0"), 0);
+    let (buf, len, _) = read_file("linktest_dir/c\0");
+    assert_eq!(&buf[..len], b"world");
+
+    // types must match, and a replaced directory must be empty
+    assert_eq!(rename("linktest_dir/c\0", "linktest_dir/sub\0"), -EISDIR);
+    assert_eq!(rename("linktest_dir/sub\0", "linktest_dir/c\0"), -ENOTDIR);
+    assert_eq!(mkdir("linktest_empty\0"), 0);
+    assert_eq!(rename("linktest_empty\0", "linktest_dir\0"), -ENOTEMPTY);
+    assert_eq!(rename("linktest_dir/sub\0", "linktest_empty\0"), 0);
+
+    // exchange swaps a file and a directory
+    assert_eq!(
+        renameat2("linktest_dir/c\0", "linktest_empty\0", RENAME_EXCHANGE),
+        0
+    );
+    let (buf, len, _) = read_file("linktest_empty\0");
+    assert_eq!(&buf[..len], b"world");
+    assert_eq!(rmdir("linktest_dir/c\0"), 0);
+    assert_eq!(
+        renameat2("linktest_empty\0", "linktest_none\0", RENAME_EXCHANGE),
+        -ENOENT
+    );
+    assert_eq!(unlink("linktest_empty\0"), 0);
+    assert_eq!(rmdir("linktest_dir\0"), 0);
+    println!("linktest passed!");
+    0
+}
@@ -0,0 +1,33 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::string::String;
+use user_lib::{link, symlink};
+
+#[no_mangle]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    let symbolic = argc > 1 && argv[1] == "-s";
+    let args = if symbolic { &argv[2..] } else { &argv[1..] };
+    if args.len() != 2 {
+        println!("usage: ln [-s] TARGET LINK_NAME");
+        return -1;
+    }
+    let mut target = String::from(args[0]);
+    target.push('\0');
+    let mut link_name = String::from(args[1]);
+    link_name.push('\0');
+    let ret = if symbolic {
+        symlink(target.as_str(), link_name.as_str())
+    } else {
+        link(target.as_str(), link_name.as_str())
+    };
+    if ret != 0 {
+        println!("ln: cannot create link '{}' to '{}'", args[1], args[0]);
+        return -1;
+    }
+    0
+}
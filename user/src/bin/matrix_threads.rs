@@ -0,0 +1,72 @@
+#![no_std]
+#![no_main]
+#![allow(clippy::needless_range_loop)]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{exit, get_time, thread_create, waittid};
+
+const THREADS: usize = 4;
+const N: usize = 40;
+const P: u64 = 10007;
+type Arr = [[u64; N]; N];
+
+static mut A: Arr = [[0; N]; N];
+static mut B: Arr = [[0; N]; N];
+static mut C: Arr = [[0; N]; N];
+
+/// Compute the rows `row, row + THREADS, ...` of `C = A * B`
+fn work(row: usize) -> ! {
+    let (a, b, c) = unsafe {
+        (
+            &*core::ptr::addr_of!(A),
+            &*core::ptr::addr_of!(B),
+            &mut *core::ptr::addr_of_mut!(C),
+        )
+    };
+    for i in (row..N).step_by(THREADS) {
+        for j in 0..N {
+            c[i][j] = (0..N).map(|k| a[i][k] * b[k][j]).sum::<u64>() % P;
+        }
+    }
+    exit(0)
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let (a, b) = unsafe {
+        (
+            &mut *core::ptr::addr_of_mut!(A),
+            &mut *core::ptr::addr_of_mut!(B),
+        )
+    };
+    for i in 0..N {
+        for j in 0..N {
+            a[i][j] = ((i * N + j) as u64) % P;
+            b[i][j] = ((i + j * 3) as u64) % P;
+        }
+    }
+    let start = get_time();
+    let mut tids = [0; THREADS];
+    for (row, tid) in tids.iter_mut().enumerate() {
+        *tid = thread_create(work as usize, row);
+        assert!(*tid > 0);
+    }
+    for tid in tids {
+        let mut exit_code = -1;
+        assert_eq!(waittid(tid as usize, &mut exit_code), tid);
+        assert_eq!(exit_code, 0);
+    }
+    println!("{} threads done in {}ms.", THREADS, get_time() - start);
+    // the threads wrote their results into memory of this process
+    let c = unsafe { &*core::ptr::addr_of!(C) };
+    for i in 0..N {
+        for j in 0..N {
+            let expected = (0..N).map(|k| a[i][k] * b[k][j]).sum::<u64>() % P;
+            assert_eq!(c[i][j], expected);
+        }
+    }
+    println!("matrix_threads passed.");
+    0
+}
@@ -0,0 +1,27 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::string::String;
+use user_lib::mkdir;
+
+#[no_mangle]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    if argc < 2 {
+        println!("usage: mkdir DIRECTORY...");
+        return -1;
+    }
+    let mut exit_code = 0;
+    for dir in &argv[1..] {
+        let mut path = String::from(*dir);
+        path.push('\0');
+        if mkdir(path.as_str()) != 0 {
+            println!("mkdir: cannot create directory '{}'", dir);
+            exit_code = -1;
+        }
+    }
+    exit_code
+}
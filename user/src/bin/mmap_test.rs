@@ -0,0 +1,164 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, exit, fork, mmap, mprotect, munmap, open, read, unlink, waitpid, wexitstatus,
+    wifsignaled, write, wtermsig, MmapFlags, MmapProt, OpenFlags, ENOMEM,
+};
+
+const PAGE_SIZE: usize = 0x1000;
+const LEN: usize = 4 * PAGE_SIZE;
+
+fn map_slice(addr: isize, len: usize) -> &'static mut [u8] {
+    assert!(addr > 0);
+    unsafe { core::slice::from_raw_parts_mut(addr as usize as *mut u8, len) }
+}
+
+/// Run `f` in a child and return its exit code, or minus the signal which killed it
+fn in_child(f: impl FnOnce()) -> i32 {
+    let pid = fork();
+    if pid == 0 {
+        f();
+        exit(0);
+    }
+    let mut status: i32 = 0;
+    assert_eq!(waitpid(pid as usize, &mut status), pid);
+    if wifsignaled(status) {
+        -wtermsig(status)
+    } else {
+        wexitstatus(status)
+    }
+}
+
+fn anonymous() {
+    let rw = MmapProt::READ | MmapProt::WRITE;
+    let private = MmapFlags::PRIVATE | MmapFlags::ANONYMOUS;
+    let addr = mmap(0, LEN, rw, private, 0, 0);
+    let data = map_slice(addr, LEN);
+    assert!(data.iter().all(|&b| b == 0));
+    data.fill(1);
+    // private memory is copied on write in the child
+    assert_eq!(in_child(|| data.fill(2)), 0);
+    assert!(data.iter().all(|&b| b == 1));
+
+    // shared memory is seen by both processes
+    let shared = MmapFlags::SHARED | MmapFlags::ANONYMOUS;
+    let shared_data = map_slice(mmap(0, LEN, rw, shared, 0, 0), LEN);
+    assert_eq!(in_child(|| shared_data.fill(3)), 0);
+    assert!(shared_data.iter().all(|&b| b == 3));
+
+    // read-only pages fault on write, the rest stays writable
+    assert_eq!(
+        mprotect(addr as usize + PAGE_SIZE, PAGE_SIZE, MmapProt::READ),
+        0
+    );
+    assert_eq!(data[PAGE_SIZE], 1);
+    assert_eq!(in_child(|| data[PAGE_SIZE] = 4), -11);
+    data[0] = 5;
+    data[2 * PAGE_SIZE] = 5;
+
+    // inaccessible pages fault, and a child inherits them as they are
+    let none = mmap(0, PAGE_SIZE, MmapProt::empty(), private, 0, 0);
+    assert!(none > 0);
+    assert_eq!(in_child(|| map_slice(none, 1)[0] = 6), -11);
+    assert_eq!(
+        in_child(|| {
+            assert_eq!(mprotect(none as usize, PAGE_SIZE, rw), 0);
+            map_slice(none, 1)[0] = 6;
+        }),
+        0
+    );
+    assert_eq!(munmap(none as usize, PAGE_SIZE), 0);
+
+    // unmap a hole in the middle
+    assert_eq!(munmap(addr as usize + 2 * PAGE_SIZE, PAGE_SIZE), 0);
+    assert_eq!(in_child(|| assert_eq!(data[2 * PAGE_SIZE], 0)), -11);
+    assert_eq!(data[0], 5);
+    assert_eq!(data[3 * PAGE_SIZE], 1);
+    // a fixed mapping fills the hole again
+    let fixed = mmap(
+        addr as usize + 2 * PAGE_SIZE,
+        PAGE_SIZE,
+        rw,
+        private | MmapFlags::FIXED,
+        0,
+        0,
+    );
+    assert_eq!(fixed, addr + 2 * PAGE_SIZE as isize);
+    assert_eq!(data[2 * PAGE_SIZE], 0);
+    assert_eq!(munmap(addr as usize, LEN), 0);
+    assert_eq!(munmap(shared_data.as_ptr() as usize, LEN), 0);
+    assert_eq!(mprotect(addr as usize, LEN, MmapProt::READ), -ENOMEM);
+}
+
+fn file() {
+    let path = "mmap_test_file\0";
+    let fd = open(path, OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd > 0);
+    // the file is one page and 16 bytes long
+    let chunk = [b'a'; 16];
+    for _ in 0..PAGE_SIZE / 16 + 1 {
+        write(fd as usize, &chunk);
+    }
+    close(fd as usize);
+    let file_len = PAGE_SIZE + 16;
+
+    let fd = open(path, OpenFlags::RDWR);
+    assert!(fd > 0);
+    let fd = fd as usize;
+    let rw = MmapProt::READ | MmapProt::WRITE;
+    // private file mappings never reach the file
+    let private = map_slice(mmap(0, LEN, rw, MmapFlags::PRIVATE, fd, 0), LEN);
+    assert!(private[..file_len].iter().all(|&b| b == b'a'));
+    assert!(private[file_len..].iter().all(|&b| b == 0));
+    private[0] = b'p';
+    assert_eq!(munmap(private.as_ptr() as usize, LEN), 0);
+
+    // shared file mappings are written back when unmapped, without growing the file
+    let shared = map_slice(mmap(0, LEN, rw, MmapFlags::SHARED, fd, 0), LEN);
+    shared[0] = b's';
+    shared[PAGE_SIZE + 15] = b's';
+    shared[PAGE_SIZE + 16] = b'x';
+    assert_eq!(munmap(shared.as_ptr() as usize, LEN), 0);
+    // mapping from an offset
+    let second_page = map_slice(
+        mmap(0, PAGE_SIZE, rw, MmapFlags::PRIVATE, fd, PAGE_SIZE),
+        16,
+    );
+    assert_eq!(second_page[15], b's');
+    close(fd);
+
+    let fd = open(path, OpenFlags::RDONLY) as usize;
+    let mut buf = [0u8; 16];
+    let mut chunks = 0;
+    loop {
+        match read(fd, &mut buf) {
+            0 => break,
+            16 => {}
+            _ => panic!("file has grown"),
+        }
+        let expected = if chunks == 0 {
+            *b"saaaaaaaaaaaaaaa"
+        } else if chunks == PAGE_SIZE / 16 {
+            *b"aaaaaaaaaaaaaaas"
+        } else {
+            chunk
+        };
+        assert_eq!(buf, expected);
+        chunks += 1;
+    }
+    assert_eq!(chunks, file_len / 16);
+    close(fd);
+    assert_eq!(unlink(path), 0);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    anonymous();
+    file();
+    println!("mmap_test passed!");
+    0
+}
@@ -0,0 +1,55 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::string::String;
+use user_lib::{rename, renameat2, EEXIST, ENOENT, RENAME_NOREPLACE};
+
+/// `path` with a terminating NUL for the syscalls
+fn c_path(path: &str) -> String {
+    let mut path = String::from(path);
+    path.push('\0');
+    path
+}
+
+/// Move `source` to `dest`, or into `dest` if it is a directory
+fn move_file(source: &str, dest: &str) -> isize {
+    let source_path = c_path(source);
+    let ret = renameat2(
+        source_path.as_str(),
+        c_path(dest).as_str(),
+        RENAME_NOREPLACE,
+    );
+    if ret != -EEXIST {
+        return ret;
+    }
+    let name = source.trim_end_matches('/').rsplit('/').next().unwrap();
+    let mut inner = String::from(dest.trim_end_matches('/'));
+    inner.push('/');
+    inner.push_str(name);
+    match rename(source_path.as_str(), c_path(&inner).as_str()) {
+        // `dest` is not a directory, so it is replaced
+        ret if ret == -ENOENT => rename(source_path.as_str(), c_path(dest).as_str()),
+        ret => ret,
+    }
+}
+
+#[no_mangle]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    if argc != 3 {
+        println!("usage: mv SOURCE DEST");
+        return -1;
+    }
+    let ret = move_file(argv[1], argv[2]);
+    if ret != 0 {
+        println!(
+            "mv: cannot move '{}' to '{}': error {}",
+            argv[1], argv[2], -ret
+        );
+        return -1;
+    }
+    0
+}
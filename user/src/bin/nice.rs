@@ -0,0 +1,37 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use user_lib::{exec, setpriority};
+
+#[no_mangle]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    let nice = if argc > 2 {
+        argv[1].parse::<i32>().ok()
+    } else {
+        None
+    };
+    let Some(nice) = nice else {
+        println!("usage: nice N COMMAND [ARG]...");
+        return -1;
+    };
+    setpriority(0, nice);
+    let args: Vec<String> = argv[2..]
+        .iter()
+        .map(|arg| {
+            let mut arg = String::from(*arg);
+            arg.push('\0');
+            arg
+        })
+        .collect();
+    let mut args_addr: Vec<*const u8> = args.iter().map(|arg| arg.as_ptr()).collect();
+    args_addr.push(core::ptr::null::<u8>());
+    exec(args[0].as_str(), args_addr.as_slice());
+    println!("nice: cannot run '{}'", argv[2]);
+    -1
+}
@@ -0,0 +1,76 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::sync::atomic::{AtomicIsize, AtomicUsize, Ordering};
+use user_lib::{
+    exit, fork, mmap, munmap, pipe, read, sleep, thread_create, waitpid, waittid, write, MmapFlags,
+    MmapProt, EFAULT,
+};
+
+const PAGE_SIZE: usize = 4096;
+const DATA: &[u8; 16] = b"0123456789abcdef";
+
+static mut BUF: [u8; 16] = [0; 16];
+static PAGE: AtomicUsize = AtomicUsize::new(0);
+static READ: AtomicIsize = AtomicIsize::new(0);
+
+fn read_into_buf(fd: usize) -> ! {
+    let buf = unsafe { &mut *core::ptr::addr_of_mut!(BUF) };
+    READ.store(read(fd, buf), Ordering::Relaxed);
+    exit(0)
+}
+
+fn read_into_page(fd: usize) -> ! {
+    let page = PAGE.load(Ordering::Relaxed);
+    let buf = unsafe { core::slice::from_raw_parts_mut(page as *mut u8, DATA.len()) };
+    READ.store(read(fd, buf), Ordering::Relaxed);
+    exit(0)
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut pipe_fd = [0usize; 2];
+    assert_eq!(pipe(&mut pipe_fd), 0);
+
+    // a fork while a reader sleeps makes its buffer copy-on-write, and the
+    // content which comes later belongs to the parent only
+    let tid = thread_create(read_into_buf as usize, pipe_fd[0]);
+    sleep(20);
+    let pid = fork();
+    if pid == 0 {
+        sleep(40);
+        let buf = unsafe { &*core::ptr::addr_of!(BUF) };
+        exit((buf != &[0; 16]) as i32);
+    }
+    assert_eq!(write(pipe_fd[1], DATA), DATA.len() as isize);
+    let mut exit_code = -1;
+    assert_eq!(waittid(tid as usize, &mut exit_code), tid);
+    assert_eq!(READ.load(Ordering::Relaxed), DATA.len() as isize);
+    assert!(unsafe { &*core::ptr::addr_of!(BUF) } == DATA);
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+
+    // nor is a buffer unmapped while the reader sleeps written to
+    let page = mmap(
+        0,
+        PAGE_SIZE,
+        MmapProt::READ | MmapProt::WRITE,
+        MmapFlags::PRIVATE | MmapFlags::ANONYMOUS,
+        0,
+        0,
+    );
+    assert!(page > 0);
+    unsafe { (page as *mut u8).write_volatile(1) };
+    PAGE.store(page as usize, Ordering::Relaxed);
+    let tid = thread_create(read_into_page as usize, pipe_fd[0]);
+    sleep(20);
+    assert_eq!(munmap(page as usize, PAGE_SIZE), 0);
+    assert_eq!(write(pipe_fd[1], DATA), DATA.len() as isize);
+    assert_eq!(waittid(tid as usize, &mut exit_code), tid);
+    assert_eq!(READ.load(Ordering::Relaxed), -EFAULT);
+    println!("pipe_block_test passed!");
+    0
+}
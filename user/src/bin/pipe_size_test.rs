@@ -0,0 +1,49 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{close, fcntl, pipe, read, write, EBADF, EBUSY, EPERM, F_GETPIPE_SZ, F_SETPIPE_SZ};
+
+const PAGE_SIZE: usize = 4096;
+const SIZE: usize = 3 * PAGE_SIZE;
+
+static mut SRC: [u8; SIZE] = [0; SIZE];
+static mut DST: [u8; SIZE] = [0; SIZE];
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let src = unsafe { &mut *core::ptr::addr_of_mut!(SRC) };
+    let dst = unsafe { &mut *core::ptr::addr_of_mut!(DST) };
+    for (i, byte) in src.iter_mut().enumerate() {
+        *byte = (i % 251) as u8;
+    }
+    let mut pipe_fd = [0usize; 2];
+    assert_eq!(pipe(&mut pipe_fd), 0);
+    assert_eq!(fcntl(pipe_fd[0], F_GETPIPE_SZ, 0), PAGE_SIZE as isize);
+    // rounded up to whole pages
+    assert_eq!(fcntl(pipe_fd[1], F_SETPIPE_SZ, SIZE - 100), SIZE as isize);
+    assert_eq!(fcntl(pipe_fd[0], F_GETPIPE_SZ, 0), SIZE as isize);
+    assert_eq!(fcntl(pipe_fd[1], F_SETPIPE_SZ, 1 << 30), -EPERM);
+    // not a pipe
+    assert_eq!(fcntl(1, F_GETPIPE_SZ, 0), -EBADF);
+
+    // the whole buffer fits, so a single process does not block
+    assert_eq!(write(pipe_fd[1], src), SIZE as isize);
+    // cannot shrink below the content
+    assert_eq!(fcntl(pipe_fd[1], F_SETPIPE_SZ, PAGE_SIZE), -EBUSY);
+    assert_eq!(read(pipe_fd[0], &mut dst[..100]), 100);
+    // wrap around the end of the ring
+    assert_eq!(write(pipe_fd[1], &src[..100]), 100);
+    assert_eq!(read(pipe_fd[0], &mut dst[100..]), (SIZE - 100) as isize);
+    assert!(src == dst);
+    close(pipe_fd[1]);
+    assert_eq!(read(pipe_fd[0], &mut dst[..100]), 100);
+    assert!(src[..100] == dst[..100]);
+    // end of file
+    assert_eq!(read(pipe_fd[0], &mut dst[..100]), 0);
+    close(pipe_fd[0]);
+    println!("pipe_size_test passed!");
+    0
+}
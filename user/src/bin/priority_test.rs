@@ -0,0 +1,38 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{exit, fork, getpid, getpriority, setpriority, waitpid, ESRCH};
+
+#[no_mangle]
+pub fn main() -> i32 {
+    assert_eq!(getpriority(0), Some(0));
+    assert_eq!(setpriority(0, 5), 0);
+    assert_eq!(getpriority(0), Some(5));
+    assert_eq!(getpriority(getpid() as usize), Some(5));
+    // out of range values are clamped
+    assert_eq!(setpriority(0, 100), 0);
+    assert_eq!(getpriority(0), Some(19));
+    assert_eq!(setpriority(0, -100), 0);
+    assert_eq!(getpriority(0), Some(-20));
+    assert_eq!(setpriority(0, 5), 0);
+
+    let pid = fork();
+    if pid == 0 {
+        // the child inherits the nice value
+        assert_eq!(getpriority(0), Some(5));
+        exit(0);
+    }
+    assert_eq!(getpriority(pid as usize), Some(5));
+    assert_eq!(setpriority(pid as usize, 10), 0);
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    // the child is gone
+    assert_eq!(getpriority(pid as usize), None);
+    assert_eq!(setpriority(pid as usize, 0), -ESRCH);
+    println!("priority_test passed!");
+    0
+}
@@ -0,0 +1,23 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::getcwd;
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut buf = [0u8; 256];
+    let len = getcwd(&mut buf);
+    if len < 0 {
+        println!("pwd: cannot get current directory");
+        return -1;
+    }
+    // the returned length includes the trailing NUL
+    println!(
+        "{}",
+        core::str::from_utf8(&buf[..len as usize - 1]).unwrap()
+    );
+    0
+}
@@ -0,0 +1,34 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::string::String;
+use user_lib::{rmdir, unlink};
+
+#[no_mangle]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    let remove_dir = argc > 1 && argv[1] == "-d";
+    let files = if remove_dir { &argv[2..] } else { &argv[1..] };
+    if files.is_empty() {
+        println!("usage: rm [-d] FILE...");
+        return -1;
+    }
+    let mut exit_code = 0;
+    for file in files {
+        let mut path = String::from(*file);
+        path.push('\0');
+        let ret = if remove_dir {
+            rmdir(path.as_str())
+        } else {
+            unlink(path.as_str())
+        };
+        if ret != 0 {
+            println!("rm: cannot remove '{}'", file);
+            exit_code = -1;
+        }
+    }
+    exit_code
+}
@@ -5,7 +5,7 @@
 extern crate user_lib;
 
 use core::ptr::slice_from_raw_parts_mut;
-use user_lib::sbrk;
+use user_lib::{sbrk, ENOMEM};
 
 #[no_mangle]
 fn main() -> i32 {
@@ -35,7 +35,7 @@ fn main() -> i32 {
     println!("11 page DEALLOCATED,  break point = {:x}", brk);
     println!("try DEALLOCATED more one page, should be failed.");
     let ret = sbrk(PAGE_SIZE as i32 * -1);
-    if ret != -1 {
+    if ret != -ENOMEM {
         println!("Test sbrk failed!");
         return -1;
     }
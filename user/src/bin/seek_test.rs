@@ -0,0 +1,70 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, fstat, lseek, open, pread, pwrite, read, unlink, write, OpenFlags, Stat, EBADF, EFBIG,
+    EINVAL, ESPIPE, SEEK_CUR, SEEK_END, SEEK_SET,
+};
+
+const PAGE_SIZE: usize = 4096;
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let fd = open("seek_test_file\0", OpenFlags::CREATE | OpenFlags::RDWR);
+    assert!(fd > 0);
+    let fd = fd as usize;
+    assert_eq!(write(fd, b"hello, world"), 12);
+    assert_eq!(lseek(fd, 0, SEEK_CUR), 12);
+    assert_eq!(lseek(fd, 7, SEEK_SET), 7);
+    let mut buf = [0u8; 16];
+    assert_eq!(read(fd, &mut buf), 5);
+    assert_eq!(&buf[..5], b"world");
+    assert_eq!(lseek(fd, -5, SEEK_END), 7);
+    assert_eq!(lseek(fd, -8, SEEK_CUR), -EINVAL);
+    assert_eq!(lseek(fd, 0, 3), -EINVAL);
+
+    // positional I/O leaves the offset alone
+    assert_eq!(pwrite(fd, b"WORLD", 7), 5);
+    assert_eq!(pread(fd, &mut buf[..5], 0), 5);
+    assert_eq!(&buf[..5], b"hello");
+    assert_eq!(lseek(fd, 0, SEEK_CUR), 7);
+    assert_eq!(read(fd, &mut buf), 5);
+    assert_eq!(&buf[..5], b"WORLD");
+    // nothing beyond the end
+    assert_eq!(pread(fd, &mut buf, 100), 0);
+
+    // writing past the end grows the file
+    assert_eq!(pwrite(fd, b"!", PAGE_SIZE * 2), 1);
+    let mut st = Stat::default();
+    assert_eq!(fstat(fd, &mut st), 0);
+    assert!(st.is_file());
+    assert_eq!(st.st_size, (PAGE_SIZE * 2 + 1) as i64);
+    assert_eq!(st.st_blocks, (3 * PAGE_SIZE / 512) as i64);
+    assert_eq!(lseek(fd, 0, SEEK_END), (PAGE_SIZE * 2 + 1) as isize);
+
+    // no file grows past what easy-fs can index
+    assert_eq!(lseek(fd, 1 << 40, SEEK_SET), -EFBIG);
+    assert_eq!(lseek(fd, 0, SEEK_CUR), (PAGE_SIZE * 2 + 1) as isize);
+    assert_eq!(pwrite(fd, b"x", 1 << 40), -EFBIG);
+    assert_eq!(pwrite(fd, b"x", usize::MAX), -EFBIG);
+    assert_eq!(fstat(fd, &mut st), 0);
+    assert_eq!(st.st_size, (PAGE_SIZE * 2 + 1) as i64);
+    close(fd);
+
+    // another descriptor of the same file shares the inode number
+    let fd2 = open("seek_test_file\0", OpenFlags::RDONLY) as usize;
+    let mut st2 = Stat::default();
+    assert_eq!(fstat(fd2, &mut st2), 0);
+    assert_eq!(st.st_ino, st2.st_ino);
+    assert_eq!(pwrite(fd2, b"x", 0), -EBADF);
+    close(fd2);
+    // pipes and consoles are not seekable
+    assert_eq!(lseek(1, 0, SEEK_SET), -ESPIPE);
+    assert_eq!(fstat(1, &mut st), -EINVAL);
+    assert_eq!(unlink("seek_test_file\0"), 0);
+    println!("seek_test passed!");
+    0
+}
@@ -7,7 +7,6 @@ use user_lib::*;
 
 fn func() {
     println!("user_sig_test passed");
-    sigreturn();
 }
 
 #[no_mangle]
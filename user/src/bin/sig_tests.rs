@@ -7,7 +7,6 @@ use user_lib::*;
 
 fn func() {
     println!("func triggered");
-    sigreturn();
 }
 
 fn user_sig_test_failsignum() {
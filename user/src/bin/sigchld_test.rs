@@ -0,0 +1,134 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::sync::atomic::{AtomicI32, Ordering};
+use user_lib::{
+    exit, fork, getpid, kill, sigaction, sigprocmask, sleep, waitpid, wexitstatus, wifexited,
+    wifsignaled, wtermsig, SignalAction, SignalActionFlags, SignalFlags, SignalInfo, CLD_EXITED,
+    CLD_KILLED, ESRCH, SIGCHLD, SIGCONT, SIGKILL, SIGSEGV, SIGSTOP, SIGTERM, SIGUSR1, SIGWINCH,
+    SIG_IGN,
+};
+
+static CHILD_PID: AtomicI32 = AtomicI32::new(0);
+static CHILD_CODE: AtomicI32 = AtomicI32::new(0);
+static CHILD_STATUS: AtomicI32 = AtomicI32::new(0);
+
+extern "C" fn on_child(_sig: i32, info: &SignalInfo) {
+    CHILD_PID.store(info.pid, Ordering::Relaxed);
+    CHILD_CODE.store(info.code, Ordering::Relaxed);
+    CHILD_STATUS.store(info.status, Ordering::Relaxed);
+}
+
+/// Run `f` in a child, return its wait status after checking the `SIGCHLD`
+/// which came with it
+fn run_child(f: fn()) -> i32 {
+    let pid = fork();
+    if pid == 0 {
+        f();
+        exit(0);
+    }
+    let mut status = 0;
+    assert_eq!(waitpid(pid as usize, &mut status), pid);
+    assert_eq!(CHILD_PID.load(Ordering::Relaxed) as isize, pid);
+    let (code, child_status) = if wifexited(status) {
+        (CLD_EXITED, wexitstatus(status))
+    } else {
+        (CLD_KILLED, wtermsig(status))
+    };
+    assert_eq!(CHILD_CODE.load(Ordering::Relaxed), code);
+    assert_eq!(CHILD_STATUS.load(Ordering::Relaxed), child_status);
+    status
+}
+
+fn exit_with_3() {
+    exit(3);
+}
+
+fn segfault() {
+    unsafe { core::ptr::null_mut::<u8>().write_volatile(1) };
+}
+
+fn segfault_blocked() {
+    // a fault cannot be blocked
+    sigprocmask(SignalFlags::SIGSEGV.bits() as u32);
+    segfault();
+}
+
+fn kill_self(sig: i32) {
+    kill(getpid() as usize, sig);
+    sleep(10);
+}
+
+fn terminated_by_default() {
+    kill_self(SIGUSR1);
+    exit(1);
+}
+
+fn ignored_by_default() {
+    kill_self(SIGWINCH);
+}
+
+fn ignored() {
+    let action = SignalAction {
+        handler: SIG_IGN,
+        ..Default::default()
+    };
+    sigaction(SIGTERM, Some(&action), None);
+    kill_self(SIGTERM);
+}
+
+fn stopped() {
+    kill_self(SIGSTOP);
+    exit(4);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let action = SignalAction {
+        handler: on_child as usize,
+        flags: SignalActionFlags::SA_SIGINFO,
+        ..Default::default()
+    };
+    assert_eq!(sigaction(SIGCHLD, Some(&action), None), 0);
+
+    let status = run_child(exit_with_3);
+    assert!(wifexited(status) && !wifsignaled(status));
+    assert_eq!(wexitstatus(status), 3);
+
+    let status = run_child(segfault);
+    assert!(wifsignaled(status) && !wifexited(status));
+    assert_eq!(wtermsig(status), SIGSEGV);
+    assert_eq!(wtermsig(run_child(segfault_blocked)), SIGSEGV);
+    assert_eq!(wtermsig(run_child(terminated_by_default)), SIGUSR1);
+    assert_eq!(run_child(ignored_by_default), 0);
+    assert_eq!(run_child(ignored), 0);
+
+    // a stopped child goes on with SIGCONT
+    let pid = fork();
+    if pid == 0 {
+        stopped();
+    }
+    sleep(50);
+    // signal 0 only checks that the process exists
+    assert_eq!(kill(pid as usize, 0), 0);
+    assert_eq!(kill(pid as usize, SIGCONT), 0);
+    let mut status = 0;
+    assert_eq!(waitpid(pid as usize, &mut status), pid);
+    assert_eq!(wexitstatus(status), 4);
+    assert_eq!(kill(pid as usize, 0), -ESRCH);
+
+    // SIGKILL ends a stopped child
+    let pid = fork();
+    if pid == 0 {
+        stopped();
+    }
+    sleep(50);
+    kill(pid as usize, SIGKILL);
+    assert_eq!(waitpid(pid as usize, &mut status), pid);
+    assert_eq!(wtermsig(status), SIGKILL);
+    println!("sigchld_test passed!");
+    0
+}
@@ -0,0 +1,207 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::arch::asm;
+use core::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+use user_lib::{
+    exit, fork, futex_wait, futex_wake, getpid, kill, mmap, mprotect, nanosleep, pipe, read,
+    sigaction, sigprocmask, sleep, thread_create, waitpid, waittid, wexitstatus, wifexited,
+    wifsignaled, wtermsig, MmapFlags, MmapProt, SignalAction, SignalActionFlags, SignalFlags,
+    SignalInfo, TimeSpec, UContext, EINTR, SIGALRM, SIGSEGV, SIGUSR1, SIGUSR2, SI_KERNEL, SI_USER,
+};
+
+const PAGE_SIZE: usize = 4096;
+const SYSCALL_KILL: usize = 129;
+/// Below the program, never mapped
+const BAD_SP: usize = 0x1000;
+
+static PAGE: AtomicUsize = AtomicUsize::new(0);
+static IN_ALARM: AtomicUsize = AtomicUsize::new(0);
+static SEGV_IN_ALARM: AtomicUsize = AtomicUsize::new(0);
+static DEPTH: AtomicUsize = AtomicUsize::new(0);
+static MAX_DEPTH: AtomicUsize = AtomicUsize::new(0);
+static CALLS: AtomicUsize = AtomicUsize::new(0);
+static WORD: AtomicU32 = AtomicU32::new(0);
+
+fn set_handler(signum: i32, handler: usize, flags: SignalActionFlags) {
+    let action = SignalAction {
+        handler,
+        flags,
+        ..Default::default()
+    };
+    assert_eq!(sigaction(signum, Some(&action), None), 0);
+}
+
+extern "C" fn on_usr1(sig: i32, info: &SignalInfo, _ctx: &mut UContext) {
+    assert_eq!(sig, SIGUSR1);
+    assert_eq!(info.signo, SIGUSR1);
+    assert_eq!(info.code, SI_USER);
+    assert_eq!(info.pid as isize, getpid());
+    let depth = DEPTH.fetch_add(1, Ordering::Relaxed) + 1;
+    MAX_DEPTH.fetch_max(depth, Ordering::Relaxed);
+    // raise it again once from inside the handler
+    if CALLS.fetch_add(1, Ordering::Relaxed) == 0 {
+        kill(getpid() as usize, SIGUSR1);
+    }
+    DEPTH.fetch_sub(1, Ordering::Relaxed);
+}
+
+extern "C" fn on_alarm(_sig: i32) {
+    IN_ALARM.store(1, Ordering::Relaxed);
+    // the page is read-only until the SIGSEGV handler changes it
+    let page = PAGE.load(Ordering::Relaxed) as *mut u32;
+    unsafe { page.write_volatile(42) };
+    IN_ALARM.store(0, Ordering::Relaxed);
+}
+
+extern "C" fn on_segv(sig: i32, info: &SignalInfo, ctx: &mut UContext) {
+    assert_eq!(sig, SIGSEGV);
+    assert_eq!(info.code, SI_KERNEL);
+    let page = PAGE.load(Ordering::Relaxed);
+    assert_eq!(info.addr, page);
+    // the interrupted store is retried after this returns
+    assert_ne!(ctx.mcontext.regs[0], 0);
+    SEGV_IN_ALARM.store(IN_ALARM.load(Ordering::Relaxed), Ordering::Relaxed);
+    let prot = MmapProt::READ | MmapProt::WRITE;
+    assert_eq!(mprotect(page, PAGE_SIZE, prot), 0);
+}
+
+extern "C" fn on_usr2(_sig: i32) {
+    CALLS.fetch_add(1, Ordering::Relaxed);
+}
+
+fn signal_later(_arg: usize) -> ! {
+    // leave the signal to the main thread
+    sigprocmask(SignalFlags::SIGUSR2.bits() as u32);
+    sleep(20);
+    kill(getpid() as usize, SIGUSR2);
+    sleep(20);
+    WORD.store(1, Ordering::Release);
+    futex_wake(&WORD, 1);
+    exit(0)
+}
+
+/// Run `block` while another thread sends `SIGUSR2` and then sets `WORD`
+fn interrupt(block: impl FnOnce() -> isize) -> isize {
+    WORD.store(0, Ordering::Relaxed);
+    CALLS.store(0, Ordering::Relaxed);
+    let tid = thread_create(signal_later as usize, 0);
+    let ret = block();
+    let mut exit_code = -1;
+    assert_eq!(waittid(tid as usize, &mut exit_code), tid);
+    assert_eq!(CALLS.load(Ordering::Relaxed), 1);
+    ret
+}
+
+/// Wait on `WORD` while another thread sends `SIGUSR2` and then sets it
+fn wait_for_word() -> isize {
+    interrupt(|| futex_wait(&WORD, 0, None))
+}
+
+fn read_forever(fd: usize) -> ! {
+    let mut buf = [0u8; 1];
+    read(fd, &mut buf);
+    exit(1)
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    // a handler blocks its own signal, unless SA_NODEFER
+    set_handler(SIGUSR1, on_usr1 as usize, SignalActionFlags::SA_SIGINFO);
+    kill(getpid() as usize, SIGUSR1);
+    assert_eq!(CALLS.load(Ordering::Relaxed), 2);
+    assert_eq!(MAX_DEPTH.load(Ordering::Relaxed), 1);
+    // the mask is restored after the handlers
+    assert_eq!(sigprocmask(0), 0);
+
+    CALLS.store(0, Ordering::Relaxed);
+    MAX_DEPTH.store(0, Ordering::Relaxed);
+    let flags = SignalActionFlags::SA_SIGINFO | SignalActionFlags::SA_NODEFER;
+    set_handler(SIGUSR1, on_usr1 as usize, flags);
+    kill(getpid() as usize, SIGUSR1);
+    assert_eq!(CALLS.load(Ordering::Relaxed), 2);
+    assert_eq!(MAX_DEPTH.load(Ordering::Relaxed), 2);
+
+    // SIGSEGV inside a SIGALRM handler
+    let page = mmap(
+        0,
+        PAGE_SIZE,
+        MmapProt::READ,
+        MmapFlags::PRIVATE | MmapFlags::ANONYMOUS,
+        0,
+        0,
+    );
+    assert!(page > 0);
+    PAGE.store(page as usize, Ordering::Relaxed);
+    set_handler(SIGALRM, on_alarm as usize, SignalActionFlags::empty());
+    set_handler(SIGSEGV, on_segv as usize, SignalActionFlags::SA_SIGINFO);
+    kill(getpid() as usize, SIGALRM);
+    assert_eq!(SEGV_IN_ALARM.load(Ordering::Relaxed), 1);
+    assert_eq!(unsafe { (page as *const u32).read_volatile() }, 42);
+
+    // a blocking syscall fails with EINTR, or starts over with SA_RESTART
+    set_handler(SIGUSR2, on_usr2 as usize, SignalActionFlags::empty());
+    assert_eq!(wait_for_word(), -EINTR);
+    set_handler(SIGUSR2, on_usr2 as usize, SignalActionFlags::SA_RESTART);
+    assert_eq!(wait_for_word(), 0);
+
+    // so do sleeps, which tell the time left, and reads from an empty pipe
+    set_handler(SIGUSR2, on_usr2 as usize, SignalActionFlags::empty());
+    let req = TimeSpec {
+        tv_sec: 10,
+        tv_nsec: 0,
+    };
+    let mut rem = TimeSpec::default();
+    assert_eq!(interrupt(|| nanosleep(&req, Some(&mut rem))), -EINTR);
+    assert_eq!(rem.tv_sec, 9);
+    // a sleep too long to count lasts until a signal comes
+    let req = TimeSpec {
+        tv_sec: usize::MAX,
+        tv_nsec: 0,
+    };
+    assert_eq!(interrupt(|| nanosleep(&req, None)), -EINTR);
+    let mut pipe_fd = [0usize; 2];
+    assert_eq!(pipe(&mut pipe_fd), 0);
+    let mut buf = [0u8; 1];
+    assert_eq!(interrupt(|| read(pipe_fd[0], &mut buf)), -EINTR);
+
+    // a thread blocked on the pipe does not keep its process from exiting
+    let pid = fork();
+    if pid == 0 {
+        thread_create(read_forever as usize, pipe_fd[0]);
+        sleep(20);
+        exit(7);
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert!(wifexited(exit_code));
+    assert_eq!(wexitstatus(exit_code), 7);
+
+    // with no stack for the frame, even a SIGSEGV handler cannot save the process
+    let pid = fork();
+    if pid == 0 {
+        set_handler(SIGUSR2, on_usr2 as usize, SignalActionFlags::empty());
+        set_handler(SIGSEGV, on_usr2 as usize, SignalActionFlags::empty());
+        unsafe {
+            asm!(
+                "mv sp, {sp}",
+                "ecall",
+                "1: j 1b",
+                sp = in(reg) BAD_SP,
+                in("a0") getpid(),
+                in("a1") SIGUSR2,
+                in("a7") SYSCALL_KILL,
+                options(noreturn),
+            );
+        }
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert!(wifsignaled(exit_code));
+    assert_eq!(wtermsig(exit_code), SIGSEGV);
+    println!("sigframe_test passed!");
+    0
+}
@@ -0,0 +1,51 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, fstat, lseek, open, read, sleep, unlink, write, OpenFlags, Stat, StatMode, SEEK_SET,
+};
+
+/// Status of the open file `fd`
+fn stat(fd: usize) -> Stat {
+    let mut st = Stat::default();
+    assert_eq!(fstat(fd, &mut st), 0);
+    st
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let fd = open("stat_test_file\0", OpenFlags::CREATE | OpenFlags::RDWR);
+    assert!(fd > 0);
+    let fd = fd as usize;
+    let st = stat(fd);
+    assert!(st.is_file());
+    assert_eq!(st.st_mode & 0o7777, 0o644);
+    assert_eq!(st.mode() & StatMode::S_IFMT, StatMode::S_IFREG);
+    assert_eq!((st.st_uid, st.st_gid), (0, 0));
+    // stamped from the RTC, so well after 2020
+    assert!(st.st_mtime > 1_577_836_800);
+    assert_eq!(st.st_atime, st.st_mtime);
+    assert_eq!(st.st_ctime, st.st_mtime);
+
+    // timestamps have whole seconds
+    sleep(1100);
+    assert_eq!(write(fd, b"data"), 4);
+    let written = stat(fd);
+    assert!(written.st_mtime > st.st_mtime);
+    assert_eq!(written.st_ctime, written.st_mtime);
+    assert_eq!(written.st_atime, st.st_atime);
+    sleep(1100);
+    lseek(fd, 0, SEEK_SET);
+    let mut buf = [0u8; 4];
+    assert_eq!(read(fd, &mut buf), 4);
+    let read_st = stat(fd);
+    assert!(read_st.st_atime > written.st_mtime);
+    assert_eq!(read_st.st_mtime, written.st_mtime);
+    close(fd);
+    assert_eq!(unlink("stat_test_file\0"), 0);
+    println!("stat_test passed!");
+    0
+}
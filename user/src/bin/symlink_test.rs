@@ -0,0 +1,98 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    chdir, close, exec, fork, link, mkdir, open, read, readlink, rmdir, symlink, unlink, waitpid,
+    write, OpenFlags, EEXIST, EINVAL, ELOOP, ENOENT,
+};
+
+/// Contents of `path`, which must be at most 16 bytes
+fn read_file(path: &str) -> ([u8; 16], usize) {
+    let fd = open(path, OpenFlags::RDONLY);
+    assert!(fd > 0);
+    let mut buf = [0u8; 16];
+    let len = read(fd as usize, &mut buf) as usize;
+    close(fd as usize);
+    (buf, len)
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    assert_eq!(mkdir("/symlink_test\0"), 0);
+    assert_eq!(chdir("/symlink_test\0"), 0);
+    let fd = open("file\0", OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd > 0);
+    assert_eq!(write(fd as usize, b"data"), 4);
+    close(fd as usize);
+
+    // relative targets start at the directory of the link
+    assert_eq!(mkdir("dir\0"), 0);
+    assert_eq!(symlink("../file\0", "dir/up\0"), 0);
+    assert_eq!(symlink("/symlink_test/dir\0", "abs\0"), 0);
+    assert_eq!(symlink("x\0", "abs\0"), -EEXIST);
+    assert_eq!(symlink("\0", "empty\0"), -ENOENT);
+    let (buf, len) = read_file("abs/up\0");
+    assert_eq!(&buf[..len], b"data");
+    let mut buf = [0u8; 32];
+    assert_eq!(readlink("dir/up\0", &mut buf), 7);
+    assert_eq!(&buf[..7], b"../file");
+    // the target is cut to the buffer
+    assert_eq!(readlink("dir/up\0", &mut buf[..3]), 3);
+    assert_eq!(readlink("file\0", &mut buf), -EINVAL);
+
+    // NOFOLLOW refuses a link at the end of the path only
+    assert_eq!(
+        open("dir/up\0", OpenFlags::RDONLY | OpenFlags::NOFOLLOW),
+        -ELOOP
+    );
+    let fd = open("abs/../file\0", OpenFlags::RDONLY | OpenFlags::NOFOLLOW);
+    assert!(fd > 0);
+    close(fd as usize);
+
+    // dangling links and loops
+    assert_eq!(symlink("missing\0", "dangling\0"), 0);
+    assert_eq!(open("dangling\0", OpenFlags::RDONLY), -ENOENT);
+    assert_eq!(symlink("loop_b\0", "loop_a\0"), 0);
+    assert_eq!(symlink("loop_a\0", "loop_b\0"), 0);
+    assert_eq!(open("loop_a\0", OpenFlags::RDONLY), -ELOOP);
+    assert_eq!(mkdir("dangling\0"), -EEXIST);
+
+    // a hard link to a symlink is another symlink
+    assert_eq!(link("dir/up\0", "up2\0"), 0);
+    assert_eq!(readlink("up2\0", &mut buf), 7);
+
+    // exec goes through links, like `sh` pointing at the shell
+    assert_eq!(symlink("/hello_world\0", "hello\0"), 0);
+    let pid = fork();
+    if pid == 0 {
+        exec("hello\0", &[core::ptr::null::<u8>()]);
+        panic!("exec through a symlink failed");
+    }
+    let mut exit_code = -1;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+
+    // removing links leaves their targets alone
+    for name in [
+        "hello\0",
+        "up2\0",
+        "loop_a\0",
+        "loop_b\0",
+        "dangling\0",
+        "abs\0",
+        "dir/up\0",
+    ] {
+        assert_eq!(unlink(name), 0);
+    }
+    let (buf, len) = read_file("file\0");
+    assert_eq!(&buf[..len], b"data");
+    assert_eq!(unlink("file\0"), 0);
+    assert_eq!(rmdir("dir\0"), 0);
+    assert_eq!(chdir("/\0"), 0);
+    assert_eq!(rmdir("/symlink_test\0"), 0);
+    println!("symlink_test passed!");
+    0
+}
@@ -0,0 +1,85 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+use user_lib::{
+    exit, fork, getpid, gettid, thread_create, waitpid, waittid, wexitstatus, yield_, EDEADLK,
+    ESRCH,
+};
+
+const THREADS: usize = 4;
+const ROUNDS: usize = 1000;
+
+static COUNTER: AtomicUsize = AtomicUsize::new(0);
+static TIDS: [AtomicUsize; THREADS] = [const { AtomicUsize::new(0) }; THREADS];
+static PID: AtomicUsize = AtomicUsize::new(0);
+
+fn add(i: usize) -> ! {
+    // threads share the address space and the pid
+    assert_eq!(getpid() as usize, PID.load(Ordering::Relaxed));
+    TIDS[i].store(gettid() as usize, Ordering::Relaxed);
+    for _ in 0..ROUNDS {
+        COUNTER.fetch_add(1, Ordering::Relaxed);
+    }
+    exit(i as i32 + 10)
+}
+
+fn spin(_arg: usize) -> ! {
+    loop {
+        yield_();
+    }
+}
+
+fn fork_in_thread(_arg: usize) -> ! {
+    let pid = fork();
+    if pid == 0 {
+        // the child only has a copy of this thread
+        assert_eq!(gettid(), 0);
+        exit(7)
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(wexitstatus(exit_code), 7);
+    exit(0)
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    PID.store(getpid() as usize, Ordering::Relaxed);
+    assert_eq!(gettid(), 0);
+    let mut tids = [0; THREADS];
+    for (i, tid) in tids.iter_mut().enumerate() {
+        let ret = thread_create(add as usize, i);
+        assert!(ret > 0);
+        *tid = ret as usize;
+    }
+    for (i, tid) in tids.iter().enumerate() {
+        let mut exit_code = 0;
+        assert_eq!(waittid(*tid, &mut exit_code), *tid as isize);
+        assert_eq!(exit_code, i as i32 + 10);
+        assert_eq!(TIDS[i].load(Ordering::Relaxed), *tid);
+    }
+    assert_eq!(COUNTER.load(Ordering::Relaxed), THREADS * ROUNDS);
+    let mut exit_code = 0;
+    // already waited for
+    assert_eq!(waittid(tids[0], &mut exit_code), -ESRCH);
+    assert_eq!(waittid(0, &mut exit_code), -EDEADLK);
+
+    let tid = thread_create(fork_in_thread as usize, 0);
+    assert_eq!(waittid(tid as usize, &mut exit_code), tid);
+    assert_eq!(exit_code, 0);
+
+    // the process ends with its main thread
+    let pid = fork();
+    if pid == 0 {
+        thread_create(spin as usize, 0);
+        exit(3);
+    }
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(wexitstatus(exit_code), 3);
+    println!("thread_test passed!");
+    0
+}
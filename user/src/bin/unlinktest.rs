@@ -0,0 +1,60 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, mkdir, open, read, rmdir, unlink, write, OpenFlags, EINVAL, EISDIR, ENOENT, ENOTDIR,
+    ENOTEMPTY,
+};
+
+const ROUNDS: usize = 64;
+const FILE_SIZE: usize = 64 * 1024;
+
+#[no_mangle]
+pub fn main() -> i32 {
+    // without reclamation the image would run out of blocks long before this finishes
+    let buffer = [0x5au8; 4096];
+    for _ in 0..ROUNDS {
+        let fd = open("unlinktest_file\0", OpenFlags::CREATE | OpenFlags::WRONLY);
+        assert!(fd > 0);
+        let fd = fd as usize;
+        for _ in 0..FILE_SIZE / buffer.len() {
+            assert_eq!(write(fd, &buffer), buffer.len() as isize);
+        }
+        close(fd);
+        assert_eq!(unlink("unlinktest_file\0"), 0);
+        assert_eq!(open("unlinktest_file\0", OpenFlags::RDONLY), -ENOENT);
+    }
+    assert_eq!(unlink("unlinktest_file\0"), -ENOENT);
+
+    assert_eq!(mkdir("unlinktest_dir\0"), 0);
+    let fd = open(
+        "unlinktest_dir/file\0",
+        OpenFlags::CREATE | OpenFlags::WRONLY,
+    );
+    assert!(fd > 0);
+    close(fd as usize);
+    // directories need AT_REMOVEDIR, files must not have it
+    assert_eq!(unlink("unlinktest_dir\0"), -EISDIR);
+    assert_eq!(rmdir("unlinktest_dir/file\0"), -ENOTDIR);
+    // only empty directories can be removed
+    assert_eq!(rmdir("unlinktest_dir\0"), -ENOTEMPTY);
+    assert_eq!(unlink("unlinktest_dir/./file\0"), 0);
+    assert_eq!(rmdir("unlinktest_dir/.\0"), -EINVAL);
+    assert_eq!(rmdir("unlinktest_dir\0"), 0);
+
+    // the freed entry is reused and the file starts out empty
+    let fd = open("unlinktest_file\0", OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd > 0);
+    close(fd as usize);
+    let fd = open("unlinktest_file\0", OpenFlags::RDONLY);
+    assert!(fd > 0);
+    let mut buf = [0u8; 16];
+    assert_eq!(read(fd as usize, &mut buf), 0);
+    close(fd as usize);
+    assert_eq!(unlink("unlinktest_file\0"), 0);
+    println!("unlinktest passed!");
+    0
+}
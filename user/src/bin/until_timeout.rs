@@ -4,7 +4,7 @@
 #[macro_use]
 extern crate user_lib;
 
-use user_lib::{exec, fork, get_time, kill, waitpid, waitpid_nb, SignalFlags};
+use user_lib::{exec, fork, get_time, kill, sleep, waitpid, waitpid_nb, SignalFlags};
 
 #[no_mangle]
 pub fn main(argc: usize, argv: &[&str]) -> i32 {
@@ -34,6 +34,7 @@ pub fn main(argc: usize, argv: &[&str]) -> i32 {
                     exit_code,
                 );
             }
+            sleep(1);
         }
         if !child_exited {
             println!("child has run for {}ms, kill it!", timeout_ms);
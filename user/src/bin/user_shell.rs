@@ -16,7 +16,11 @@ const LINE_START: &str = ">> ";
 use alloc::string::String;
 use alloc::vec::Vec;
 use user_lib::console::getchar;
-use user_lib::{close, dup, exec, fork, open, pipe, waitpid, OpenFlags};
+use user_lib::{
+    chdir, close, dup, exec, exit, fork, getpid, killpg, open, pipe, setpgid, setsid, sigaction,
+    tcsetpgrp, waitpid_options, wifstopped, OpenFlags, SignalAction, SIGCONT, SIGINT, SIGQUIT,
+    SIGTSTP, SIGTTIN, SIGTTOU, SIG_DFL, SIG_IGN, WNOHANG, WUNTRACED,
+};
 
 #[derive(Debug)]
 struct ProcessArguments {
@@ -74,9 +78,273 @@ impl ProcessArguments {
     }
 }
 
+/// Signals which the shell ignores so that only the foreground job gets them
+/// from the terminal
+const JOB_CONTROL_SIGNALS: [i32; 5] = [SIGINT, SIGQUIT, SIGTSTP, SIGTTIN, SIGTTOU];
+
+fn set_job_control_signals(handler: usize) {
+    let action = SignalAction {
+        handler,
+        ..Default::default()
+    };
+    for signum in JOB_CONTROL_SIGNALS {
+        sigaction(signum, Some(&action), None);
+    }
+}
+
+/// A pipeline running in its own process group
+struct Job {
+    id: usize,
+    pgid: usize,
+    /// Processes which have not exited yet
+    pids: Vec<usize>,
+    command: String,
+    stopped: bool,
+}
+
+#[derive(Default)]
+struct Jobs {
+    list: Vec<Job>,
+}
+
+impl Jobs {
+    fn add(&mut self, mut job: Job) -> &Job {
+        job.id = self.list.iter().map(|job| job.id).max().unwrap_or(0) + 1;
+        self.list.push(job);
+        self.list.last().unwrap()
+    }
+    /// The job of `%n`, or the latest one if `spec` is `None`
+    fn take(&mut self, spec: Option<&str>) -> Option<Job> {
+        let idx = match spec {
+            Some(spec) => {
+                let id: usize = spec.trim_start_matches('%').parse().ok()?;
+                self.list.iter().position(|job| job.id == id)?
+            }
+            None => self.list.len().checked_sub(1)?,
+        };
+        Some(self.list.remove(idx))
+    }
+    /// Collect the background jobs which have exited or stopped
+    fn reap(&mut self) {
+        let mut status = 0;
+        loop {
+            let pid = waitpid_options(-1, &mut status, WNOHANG | WUNTRACED);
+            if pid <= 0 {
+                break;
+            }
+            let pid = pid as usize;
+            let Some(idx) = self.list.iter().position(|job| job.pids.contains(&pid)) else {
+                continue;
+            };
+            let job = &mut self.list[idx];
+            if wifstopped(status) {
+                job.stopped = true;
+                continue;
+            }
+            job.pids.retain(|&alive| alive != pid);
+            if job.pids.is_empty() {
+                let job = self.list.remove(idx);
+                println!("[{}] Done\t{}", job.id, job.command);
+            }
+        }
+    }
+}
+
+/// Hand the terminal to `job` and wait until it exits or stops. A stopped job
+/// is put back into `jobs`.
+fn wait_foreground(mut job: Job, jobs: &mut Jobs) {
+    tcsetpgrp(0, job.pgid);
+    let mut status = 0;
+    while !job.pids.is_empty() {
+        let pid = waitpid_options(-(job.pgid as isize), &mut status, WUNTRACED);
+        if pid < 0 {
+            break;
+        }
+        if wifstopped(status) {
+            job.stopped = true;
+            println!("");
+            let job = jobs.add(job);
+            println!("[{}] Stopped\t{}", job.id, job.command);
+            break;
+        }
+        job.pids.retain(|&alive| alive != pid as usize);
+    }
+    tcsetpgrp(0, getpid() as usize);
+}
+
+/// Run `command` if it is a shell builtin, returning whether it was one.
+fn run_builtin(command: &str, jobs: &mut Jobs) -> bool {
+    let mut words = command.split(' ').filter(|word| !word.is_empty());
+    match words.next() {
+        Some("cd") => {
+            let dir = words.next().unwrap_or("/");
+            let mut path = String::from(dir);
+            path.push('\0');
+            if chdir(path.as_str()) != 0 {
+                println!("cd: {}: No such directory", dir);
+            }
+            true
+        }
+        Some("jobs") => {
+            for job in jobs.list.iter() {
+                let state = if job.stopped { "Stopped" } else { "Running" };
+                println!("[{}] {}\t{}", job.id, state, job.command);
+            }
+            true
+        }
+        Some(builtin @ ("fg" | "bg")) => {
+            let Some(mut job) = jobs.take(words.next()) else {
+                println!("{}: no such job", builtin);
+                return true;
+            };
+            job.stopped = false;
+            if builtin == "fg" {
+                println!("{}", job.command);
+                tcsetpgrp(0, job.pgid);
+                killpg(job.pgid, SIGCONT);
+                wait_foreground(job, jobs);
+            } else {
+                killpg(job.pgid, SIGCONT);
+                let job = jobs.add(job);
+                println!("[{}] {} &", job.id, job.command);
+            }
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Start the pipeline of `command` as a new job
+fn run_pipeline(command: &str, background: bool, jobs: &mut Jobs) {
+    let splited: Vec<_> = command.split('|').collect();
+    let process_arguments_list: Vec<_> = splited
+        .iter()
+        .map(|&cmd| ProcessArguments::new(cmd))
+        .collect();
+    let mut valid = true;
+    for (i, process_args) in process_arguments_list.iter().enumerate() {
+        if i == 0 {
+            if !process_args.output.is_empty() {
+                valid = false;
+            }
+        } else if i == process_arguments_list.len() - 1 {
+            if !process_args.input.is_empty() {
+                valid = false;
+            }
+        } else if !process_args.output.is_empty() || !process_args.input.is_empty() {
+            valid = false;
+        }
+    }
+    if process_arguments_list.len() == 1 {
+        valid = true;
+    }
+    if !valid {
+        println!("Invalid command: Inputs/Outputs cannot be correctly binded!");
+        return;
+    }
+    // create pipes
+    let mut pipes_fd: Vec<[usize; 2]> = Vec::new();
+    if !process_arguments_list.is_empty() {
+        for _ in 0..process_arguments_list.len() - 1 {
+            let mut pipe_fd = [0usize; 2];
+            pipe(&mut pipe_fd);
+            pipes_fd.push(pipe_fd);
+        }
+    }
+    // the first process leads the process group of the job
+    let mut pgid = 0;
+    let mut children: Vec<usize> = Vec::new();
+    for (i, process_argument) in process_arguments_list.iter().enumerate() {
+        let pid = fork();
+        if pid == 0 {
+            setpgid(0, pgid);
+            set_job_control_signals(SIG_DFL);
+            let input = &process_argument.input;
+            let output = &process_argument.output;
+            let args_copy = &process_argument.args_copy;
+            let args_addr = &process_argument.args_addr;
+            // redirect input
+            if !input.is_empty() {
+                let input_fd = open(input.as_str(), OpenFlags::RDONLY);
+                if input_fd < 0 {
+                    println!("Error when opening file {}", input);
+                    exit(-4);
+                }
+                let input_fd = input_fd as usize;
+                close(0);
+                assert_eq!(dup(input_fd), 0);
+                close(input_fd);
+            }
+            // redirect output
+            if !output.is_empty() {
+                let output_fd = open(output.as_str(), OpenFlags::CREATE | OpenFlags::WRONLY);
+                if output_fd < 0 {
+                    println!("Error when opening file {}", output);
+                    exit(-4);
+                }
+                let output_fd = output_fd as usize;
+                close(1);
+                assert_eq!(dup(output_fd), 1);
+                close(output_fd);
+            }
+            // receive input from the previous process
+            if i > 0 {
+                close(0);
+                let read_end = pipes_fd.get(i - 1).unwrap()[0];
+                assert_eq!(dup(read_end), 0);
+            }
+            // send output to the next process
+            if i < process_arguments_list.len() - 1 {
+                close(1);
+                let write_end = pipes_fd.get(i).unwrap()[1];
+                assert_eq!(dup(write_end), 1);
+            }
+            // close all pipe ends inherited from the parent process
+            for pipe_fd in pipes_fd.iter() {
+                close(pipe_fd[0]);
+                close(pipe_fd[1]);
+            }
+            // execute new application
+            if exec(args_copy[0].as_str(), args_addr.as_slice()) < 0 {
+                println!("Error when executing!");
+                exit(-4);
+            }
+            unreachable!();
+        }
+        // also set here, as the child may not have run yet
+        if pgid == 0 {
+            pgid = pid as usize;
+        }
+        setpgid(pid as usize, pgid);
+        children.push(pid as usize);
+    }
+    for pipe_fd in pipes_fd.iter() {
+        close(pipe_fd[0]);
+        close(pipe_fd[1]);
+    }
+    let job = Job {
+        id: 0,
+        pgid,
+        pids: children,
+        command: String::from(command.trim()),
+        stopped: false,
+    };
+    if background {
+        let job = jobs.add(job);
+        println!("[{}] {}", job.id, job.pgid);
+    } else {
+        wait_foreground(job, jobs);
+    }
+}
+
 #[no_mangle]
 pub fn main() -> i32 {
     println!("Rust user shell");
+    // lead a session owning the terminal, and leave ^C and ^Z to the jobs
+    setsid();
+    tcsetpgrp(0, getpid() as usize);
+    set_job_control_signals(SIG_IGN);
+    let mut jobs = Jobs::default();
     let mut line: String = String::new();
     print!("{}", LINE_START);
     loop {
@@ -84,117 +352,18 @@ pub fn main() -> i32 {
         match c {
             LF | CR => {
                 println!("");
-                if !line.is_empty() {
-                    let splited: Vec<_> = line.as_str().split('|').collect();
-                    let process_arguments_list: Vec<_> = splited
-                        .iter()
-                        .map(|&cmd| ProcessArguments::new(cmd))
-                        .collect();
-                    let mut valid = true;
-                    for (i, process_args) in process_arguments_list.iter().enumerate() {
-                        if i == 0 {
-                            if !process_args.output.is_empty() {
-                                valid = false;
-                            }
-                        } else if i == process_arguments_list.len() - 1 {
-                            if !process_args.input.is_empty() {
-                                valid = false;
-                            }
-                        } else if !process_args.output.is_empty() || !process_args.input.is_empty()
-                        {
-                            valid = false;
-                        }
-                    }
-                    if process_arguments_list.len() == 1 {
-                        valid = true;
-                    }
-                    if !valid {
-                        println!("Invalid command: Inputs/Outputs cannot be correctly binded!");
-                    } else {
-                        // create pipes
-                        let mut pipes_fd: Vec<[usize; 2]> = Vec::new();
-                        if !process_arguments_list.is_empty() {
-                            for _ in 0..process_arguments_list.len() - 1 {
-                                let mut pipe_fd = [0usize; 2];
-                                pipe(&mut pipe_fd);
-                                pipes_fd.push(pipe_fd);
-                            }
-                        }
-                        let mut children: Vec<_> = Vec::new();
-                        for (i, process_argument) in process_arguments_list.iter().enumerate() {
-                            let pid = fork();
-                            if pid == 0 {
-                                let input = &process_argument.input;
-                                let output = &process_argument.output;
-                                let args_copy = &process_argument.args_copy;
-                                let args_addr = &process_argument.args_addr;
-                                // redirect input
-                                if !input.is_empty() {
-                                    let input_fd = open(input.as_str(), OpenFlags::RDONLY);
-                                    if input_fd == -1 {
-                                        println!("Error when opening file {}", input);
-                                        return -4;
-                                    }
-                                    let input_fd = input_fd as usize;
-                                    close(0);
-                                    assert_eq!(dup(input_fd), 0);
-                                    close(input_fd);
-                                }
-                                // redirect output
-                                if !output.is_empty() {
-                                    let output_fd = open(
-                                        output.as_str(),
-                                        OpenFlags::CREATE | OpenFlags::WRONLY,
-                                    );
-                                    if output_fd == -1 {
-                                        println!("Error when opening file {}", output);
-                                        return -4;
-                                    }
-                                    let output_fd = output_fd as usize;
-                                    close(1);
-                                    assert_eq!(dup(output_fd), 1);
-                                    close(output_fd);
-                                }
-                                // receive input from the previous process
-                                if i > 0 {
-                                    close(0);
-                                    let read_end = pipes_fd.get(i - 1).unwrap()[0];
-                                    assert_eq!(dup(read_end), 0);
-                                }
-                                // send output to the next process
-                                if i < process_arguments_list.len() - 1 {
-                                    close(1);
-                                    let write_end = pipes_fd.get(i).unwrap()[1];
-                                    assert_eq!(dup(write_end), 1);
-                                }
-                                // close all pipe ends inherited from the parent process
-                                for pipe_fd in pipes_fd.iter() {
-                                    close(pipe_fd[0]);
-                                    close(pipe_fd[1]);
-                                }
-                                // execute new application
-                                if exec(args_copy[0].as_str(), args_addr.as_slice()) == -1 {
-                                    println!("Error when executing!");
-                                    return -4;
-                                }
-                                unreachable!();
-                            } else {
-                                children.push(pid);
-                            }
-                        }
-                        for pipe_fd in pipes_fd.iter() {
-                            close(pipe_fd[0]);
-                            close(pipe_fd[1]);
-                        }
-                        let mut exit_code: i32 = 0;
-                        for pid in children.into_iter() {
-                            let exit_pid = waitpid(pid as usize, &mut exit_code);
-                            assert_eq!(pid, exit_pid);
-                            //println!("Shell: Process {} exited with code {}", pid, exit_code);
-                        }
-                    }
-                    line.clear();
+                let mut command = line.trim_end();
+                let background = command.ends_with('&');
+                if background {
+                    command = command[..command.len() - 1].trim_end();
+                }
+                if !command.is_empty()
+                    && (command.contains('|') || !run_builtin(command, &mut jobs))
+                {
+                    run_pipeline(command, background, &mut jobs);
                 }
+                line.clear();
+                jobs.reap();
                 print!("{}", LINE_START);
             }
             BS | DL => {
@@ -8,23 +8,54 @@ extern crate user_lib;
 // count_lines, infloop, user_shell, usertests
 
 // item of TESTS : app_name(argv_0), argv_1, argv_2, argv_3, exit_code
+// (minus the signal for an app killed by a signal)
 static SUCC_TESTS: &[(&str, &str, &str, &str, i32)] = &[
+    ("cow_test\0", "\0", "\0", "\0", 0),
+    ("cwd_test\0", "\0", "\0", "\0", 0),
+    ("dirtest\0", "\0", "\0", "\0", 0),
+    ("errno_test\0", "\0", "\0", "\0", 0),
     ("exit\0", "\0", "\0", "\0", 0),
     ("fantastic_text\0", "\0", "\0", "\0", 0),
     ("forktest_simple\0", "\0", "\0", "\0", 0),
     ("forktest\0", "\0", "\0", "\0", 0),
     ("forktest2\0", "\0", "\0", "\0", 0),
     ("forktree\0", "\0", "\0", "\0", 0),
+    ("futex_test\0", "\0", "\0", "\0", 0),
     ("hello_world\0", "\0", "\0", "\0", 0),
+    ("itimer_test\0", "\0", "\0", "\0", 0),
+    ("jobctl_test\0", "\0", "\0", "\0", 0),
+    ("lazy_test\0", "\0", "\0", "\0", 0),
+    ("linktest\0", "\0", "\0", "\0", 0),
     ("matrix\0", "\0", "\0", "\0", 0),
+    ("matrix_threads\0", "\0", "\0", "\0", 0),
+    ("mmap_test\0", "\0", "\0", "\0", 0),
+    ("pipe_block_test\0", "\0", "\0", "\0", 0),
+    ("pipe_large_test\0", "\0", "\0", "\0", 0),
+    ("pipe_size_test\0", "\0", "\0", "\0", 0),
+    ("pipetest\0", "\0", "\0", "\0", 0),
+    ("priority_test\0", "\0", "\0", "\0", 0),
+    ("seek_test\0", "\0", "\0", "\0", 0),
+    ("sigchld_test\0", "\0", "\0", "\0", 0),
+    ("sigframe_test\0", "\0", "\0", "\0", 0),
     ("sleep_simple\0", "\0", "\0", "\0", 0),
     ("sleep\0", "\0", "\0", "\0", 0),
+    ("stat_test\0", "\0", "\0", "\0", 0),
+    ("symlink_test\0", "\0", "\0", "\0", 0),
+    ("thread_test\0", "\0", "\0", "\0", 0),
+    ("unlinktest\0", "\0", "\0", "\0", 0),
+    ("wait4_test\0", "\0", "\0", "\0", 0),
     ("yield\0", "\0", "\0", "\0", 0),
 ];
 
-static FAIL_TESTS: &[(&str, &str, &str, &str, i32)] = &[("stack_overflow\0", "\0", "\0", "\0", -2)];
+static FAIL_TESTS: &[(&str, &str, &str, &str, i32)] = &[
+    ("sbrk_test\0", "\0", "\0", "\0", -11),
+    ("stack_overflow\0", "\0", "\0", "\0", -11),
+];
 
-use user_lib::{exec, fork, waitpid};
+use user_lib::{
+    exec, fork, kill, waitpid, waitpid_options, wexitstatus, wifsignaled, wifstopped, wstopsig,
+    wtermsig, SIGKILL, WUNTRACED,
+};
 
 fn run_tests(tests: &[(&str, &str, &str, &str, i32)]) -> i32 {
     let mut pass_num = 0;
@@ -65,9 +96,24 @@ fn run_tests(tests: &[(&str, &str, &str, &str, i32)]) -> i32 {
             exec(test.0, &[core::ptr::null::<u8>()]);
             panic!("unreachable!");
         } else {
-            let mut exit_code: i32 = Default::default();
-            let wait_pid = waitpid(pid as usize, &mut exit_code);
+            let mut status: i32 = Default::default();
+            let mut wait_pid = waitpid_options(pid, &mut status, WUNTRACED);
+            // a stopped test would hang the others
+            if wifstopped(status) {
+                println!(
+                    "Usertests: Test {} stopped by signal {}, killing it",
+                    test.0,
+                    wstopsig(status)
+                );
+                kill(pid as usize, SIGKILL);
+                wait_pid = waitpid(pid as usize, &mut status);
+            }
             assert_eq!(pid, wait_pid);
+            let exit_code = if wifsignaled(status) {
+                -wtermsig(status)
+            } else {
+                wexitstatus(status)
+            };
             if exit_code == test.4 {
                 // summary apps with  exit_code
                 pass_num = pass_num + 1;
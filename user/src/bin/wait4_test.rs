@@ -0,0 +1,112 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::sync::atomic::{AtomicI32, Ordering};
+use user_lib::{
+    exit, fork, get_time, getpid, kill, sigaction, sleep, wait4, waitpid_options, wexitstatus,
+    wifcontinued, wifexited, wifstopped, wstopsig, RUsage, SignalAction, SignalActionFlags,
+    SignalInfo, CLD_CONTINUED, CLD_STOPPED, EINVAL, SIGCHLD, SIGCONT, SIGSTOP, WCONTINUED, WNOHANG,
+    WUNTRACED,
+};
+
+static CHILD_CODE: AtomicI32 = AtomicI32::new(0);
+static CHILD_STATUS: AtomicI32 = AtomicI32::new(0);
+
+extern "C" fn on_child(_sig: i32, info: &SignalInfo) {
+    CHILD_CODE.store(info.code, Ordering::Relaxed);
+    CHILD_STATUS.store(info.status, Ordering::Relaxed);
+}
+
+fn spawn(f: fn()) -> isize {
+    let pid = fork();
+    if pid == 0 {
+        f();
+        exit(7);
+    }
+    pid
+}
+
+fn spin() {
+    let start = get_time();
+    // mostly in user mode, not in `get_time`
+    while get_time() - start < 100 {
+        for i in 0..10000 {
+            core::hint::black_box(i);
+        }
+    }
+}
+
+fn nap() {
+    for _ in 0..3 {
+        sleep(5);
+    }
+}
+
+fn stop_then_exit() {
+    kill(getpid() as usize, SIGSTOP);
+    sleep(20);
+}
+
+fn to_ms(sec: usize, usec: usize) -> usize {
+    sec * 1000 + usec / 1000
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut status = 0;
+    let mut usage = RUsage::default();
+    assert_eq!(waitpid_options(-1, &mut status, 0x100), -EINVAL);
+
+    // time spent in user mode
+    let pid = spawn(spin);
+    assert_eq!(wait4(pid, &mut status, 0, &mut usage), pid);
+    assert_eq!(wexitstatus(status), 7);
+    let utime = to_ms(usage.utime.tv_sec, usage.utime.tv_usec);
+    assert!(utime >= 50, "utime {} ms", utime);
+
+    // a sleeping child gives up the cpu
+    let pid = spawn(nap);
+    assert_eq!(waitpid_options(pid, &mut status, WNOHANG), 0);
+    assert_eq!(wait4(pid, &mut status, 0, &mut usage), pid);
+    assert!(usage.nvcsw >= 3);
+    let utime = to_ms(usage.utime.tv_sec, usage.utime.tv_usec);
+    assert!(utime < 50, "utime {} ms", utime);
+
+    // stopped and continued, told by SIGCHLD as well
+    let action = SignalAction {
+        handler: on_child as usize,
+        flags: SignalActionFlags::SA_SIGINFO,
+        ..Default::default()
+    };
+    assert_eq!(sigaction(SIGCHLD, Some(&action), None), 0);
+    let pid = spawn(stop_then_exit);
+    assert_eq!(waitpid_options(pid, &mut status, WUNTRACED), pid);
+    assert!(wifstopped(status) && !wifcontinued(status));
+    assert_eq!(wstopsig(status), SIGSTOP);
+    assert_eq!(CHILD_CODE.load(Ordering::Relaxed), CLD_STOPPED);
+    // nothing more to report yet
+    assert_eq!(
+        waitpid_options(pid, &mut status, WUNTRACED | WCONTINUED | WNOHANG),
+        0
+    );
+    kill(pid as usize, SIGCONT);
+    assert_eq!(CHILD_CODE.load(Ordering::Relaxed), CLD_CONTINUED);
+    assert_eq!(CHILD_STATUS.load(Ordering::Relaxed), SIGCONT);
+    assert_eq!(waitpid_options(pid, &mut status, WCONTINUED), pid);
+    assert!(wifcontinued(status) && !wifexited(status) && !wifstopped(status));
+    // reported only once, then the exit
+    assert_eq!(waitpid_options(pid, &mut status, WCONTINUED), pid);
+    assert!(wifexited(status));
+    assert_eq!(wexitstatus(status), 7);
+
+    // SIGCONT to a running child is not reported
+    let pid = spawn(nap);
+    kill(pid as usize, SIGCONT);
+    assert_eq!(waitpid_options(pid, &mut status, WCONTINUED), pid);
+    assert!(wifexited(status));
+    println!("wait4_test passed!");
+    0
+}
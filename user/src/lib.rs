@@ -13,6 +13,9 @@ mod syscall;
 use alloc::vec::Vec;
 use bitflags::bitflags;
 use buddy_system_allocator::LockedHeap;
+use core::cell::UnsafeCell;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicU32, Ordering};
 use syscall::*;
 const POINTER_SIZE: usize = core::mem::size_of::<usize>() * 8;
 const USER_HEAP_SIZE: usize = 16384;
@@ -89,6 +92,36 @@ pub const SIGIO: i32 = 29;
 pub const SIGPWR: i32 = 30;
 pub const SIGSYS: i32 = 31;
 
+// A failed syscall returns the negated error number
+pub const EPERM: isize = 1;
+pub const ENOENT: isize = 2;
+pub const ESRCH: isize = 3;
+pub const EINTR: isize = 4;
+pub const EIO: isize = 5;
+pub const E2BIG: isize = 7;
+pub const ENOEXEC: isize = 8;
+pub const EBADF: isize = 9;
+pub const ECHILD: isize = 10;
+pub const EAGAIN: isize = 11;
+pub const ENOMEM: isize = 12;
+pub const EFAULT: isize = 14;
+pub const EBUSY: isize = 16;
+pub const EEXIST: isize = 17;
+pub const ENOTDIR: isize = 20;
+pub const EISDIR: isize = 21;
+pub const EINVAL: isize = 22;
+pub const ENOTTY: isize = 25;
+pub const EFBIG: isize = 27;
+pub const ENOSPC: isize = 28;
+pub const ESPIPE: isize = 29;
+pub const ERANGE: isize = 34;
+pub const EDEADLK: isize = 35;
+pub const ENAMETOOLONG: isize = 36;
+pub const ENOSYS: isize = 38;
+pub const ENOTEMPTY: isize = 39;
+pub const ELOOP: isize = 40;
+pub const ETIMEDOUT: isize = 110;
+
 bitflags! {
     #[derive(Debug, Clone, Copy)]
     pub struct SignalFlags: i32 {
@@ -127,22 +160,167 @@ bitflags! {
     }
 }
 
+bitflags! {
+    /// `flags` of [`SignalAction`]
+    #[derive(Debug, Clone, Copy)]
+    pub struct SignalActionFlags: u32 {
+        /// Do not send `SIGCHLD` when a child stops
+        const SA_NOCLDSTOP = 1;
+        /// Call the handler as `extern "C" fn(i32, &SignalInfo, &mut UContext)`
+        const SA_SIGINFO = 4;
+        /// Restart a syscall interrupted by the signal instead of failing with `EINTR`
+        const SA_RESTART = 0x1000_0000;
+        /// Do not block the signal while its handler runs
+        const SA_NODEFER = 0x4000_0000;
+    }
+}
+
+/// `handler` of [`SignalAction`] for the default action of the signal
+pub const SIG_DFL: usize = 0;
+/// `handler` of [`SignalAction`] to discard the signal
+pub const SIG_IGN: usize = 1;
+
+/// The handler is called as `extern "C" fn(i32)`, or with more arguments for
+/// `SA_SIGINFO`. When it returns, the interrupted code goes on.
 #[repr(C, align(16))]
 #[derive(Debug, Clone, Copy)]
 pub struct SignalAction {
+    /// Address of the handler, or [`SIG_DFL`] or [`SIG_IGN`]
     pub handler: usize,
+    /// Blocked while the handler runs, besides the signal itself
     pub mask: SignalFlags,
+    pub flags: SignalActionFlags,
 }
 
 impl Default for SignalAction {
     fn default() -> Self {
         Self {
-            handler: 0,
+            handler: SIG_DFL,
             mask: SignalFlags::empty(),
+            flags: SignalActionFlags::empty(),
         }
     }
 }
 
+/// `code` of a signal sent with [`kill`]
+pub const SI_USER: i32 = 0;
+/// `code` of a signal raised by the kernel
+pub const SI_KERNEL: i32 = 0x80;
+/// `code` of `SIGCHLD` for a child which has exited
+pub const CLD_EXITED: i32 = 1;
+/// `code` of `SIGCHLD` for a child killed by a signal
+pub const CLD_KILLED: i32 = 2;
+/// `code` of `SIGCHLD` for a child stopped by a signal
+pub const CLD_STOPPED: i32 = 5;
+/// `code` of `SIGCHLD` for a stopped child continued by `SIGCONT`
+pub const CLD_CONTINUED: i32 = 6;
+
+/// Passed to a handler with `SA_SIGINFO`
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct SignalInfo {
+    pub signo: i32,
+    pub errno: i32,
+    /// Who raised the signal, [`SI_USER`] or [`SI_KERNEL`], or what happened
+    /// to the child for `SIGCHLD`, [`CLD_EXITED`] or [`CLD_KILLED`]
+    pub code: i32,
+    /// Sender of a signal from [`kill`], or the child for `SIGCHLD`
+    pub pid: i32,
+    /// Exit code or signal of the child for `SIGCHLD`
+    pub status: i32,
+    /// Address which caused a fault
+    pub addr: usize,
+}
+
+/// Registers of the interrupted code
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct MContext {
+    /// `pc` in place of `x0`, then `x1` to `x31`
+    pub regs: [usize; 32],
+}
+
+/// Passed to a handler with `SA_SIGINFO`, the interrupted code resumes with
+/// whatever it holds when the handler returns
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct UContext {
+    pub flags: usize,
+    /// Signal mask restored when the handler returns
+    pub sigmask: SignalFlags,
+    pub mcontext: MContext,
+}
+
+/// Time interval of [`nanosleep`], same layout as `struct timespec`
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TimeSpec {
+    pub tv_sec: usize,
+    pub tv_nsec: usize,
+}
+
+/// Same layout as `struct timeval`
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TimeVal {
+    pub tv_sec: usize,
+    pub tv_usec: usize,
+}
+
+/// `which` of [`setitimer`]: counts down in real time, then sends `SIGALRM`
+pub const ITIMER_REAL: usize = 0;
+/// `which` of [`setitimer`]: counts down in the cpu time of the process spent
+/// in user mode, then sends `SIGVTALRM`
+pub const ITIMER_VIRTUAL: usize = 1;
+/// `which` of [`setitimer`]: counts down in the cpu time of the process, then
+/// sends `SIGPROF`
+pub const ITIMER_PROF: usize = 2;
+
+/// Same layout as `struct itimerval`
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ITimerVal {
+    /// Period after the first expiry, or zero for a single shot
+    pub it_interval: TimeVal,
+    /// Time until the next expiry, or zero to disarm the timer
+    pub it_value: TimeVal,
+}
+
+/// Resource usage of a child reported by [`wait4`], same layout as
+/// `struct rusage`. Only the cpu times and context switches are tracked.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RUsage {
+    /// Time spent in user mode
+    pub utime: TimeVal,
+    /// Time spent in the kernel
+    pub stime: TimeVal,
+    pub maxrss: usize,
+    pub ixrss: usize,
+    pub idrss: usize,
+    pub isrss: usize,
+    pub minflt: usize,
+    pub majflt: usize,
+    pub nswap: usize,
+    pub inblock: usize,
+    pub oublock: usize,
+    pub msgsnd: usize,
+    pub msgrcv: usize,
+    pub nsignals: usize,
+    /// Times it blocked to wait for something
+    pub nvcsw: usize,
+    /// Times it gave up the cpu while it could still run
+    pub nivcsw: usize,
+}
+
+/// Special `dirfd` value: resolve relative paths against the working directory
+pub const AT_FDCWD: isize = -100;
+pub const AT_REMOVEDIR: u32 = 0x200;
+/// Flag of [`renameat2`]: fail with `EEXIST` instead of replacing the new path
+pub const RENAME_NOREPLACE: u32 = 1;
+/// Flag of [`renameat2`]: swap the two paths, both of which must exist
+pub const RENAME_EXCHANGE: u32 = 2;
+
 bitflags! {
     pub struct OpenFlags: u32 {
         const RDONLY = 0;
@@ -150,6 +328,63 @@ bitflags! {
         const RDWR = 1 << 1;
         const CREATE = 1 << 9;
         const TRUNC = 1 << 10;
+        const NOFOLLOW = 1 << 17;
+    }
+}
+
+/// `whence` of [`lseek`]
+pub const SEEK_SET: usize = 0;
+pub const SEEK_CUR: usize = 1;
+pub const SEEK_END: usize = 2;
+
+bitflags! {
+    /// File type and permission bits of [`Stat`]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct StatMode: u32 {
+        const S_IFMT = 0o170_000;
+        const S_IFDIR = 0o040_000;
+        const S_IFREG = 0o100_000;
+        const S_IFLNK = 0o120_000;
+        const S_IRWXU = 0o700;
+        const S_IRWXG = 0o070;
+        const S_IRWXO = 0o007;
+    }
+}
+
+/// File status of [`fstat`], same layout as `struct stat`
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Stat {
+    pub st_dev: u64,
+    pub st_ino: u64,
+    pub st_mode: u32,
+    pub st_nlink: u32,
+    pub st_uid: u32,
+    pub st_gid: u32,
+    pub st_rdev: u64,
+    __pad1: u64,
+    pub st_size: i64,
+    pub st_blksize: i32,
+    __pad2: i32,
+    pub st_blocks: i64,
+    pub st_atime: i64,
+    pub st_atime_nsec: i64,
+    pub st_mtime: i64,
+    pub st_mtime_nsec: i64,
+    pub st_ctime: i64,
+    pub st_ctime_nsec: i64,
+    __unused: [u32; 2],
+}
+
+impl Stat {
+    pub fn mode(&self) -> StatMode {
+        StatMode::from_bits_truncate(self.st_mode)
+    }
+    pub fn is_dir(&self) -> bool {
+        self.mode() & StatMode::S_IFMT == StatMode::S_IFDIR
+    }
+    pub fn is_file(&self) -> bool {
+        self.mode() & StatMode::S_IFMT == StatMode::S_IFREG
     }
 }
 
@@ -157,6 +392,78 @@ pub fn dup(fd: usize) -> isize {
     sys_dup(fd)
 }
 
+/// `cmd` of [`fcntl`]: resize a pipe, return the new capacity
+pub const F_SETPIPE_SZ: u32 = 1031;
+/// `cmd` of [`fcntl`]: get the capacity of a pipe
+pub const F_GETPIPE_SZ: u32 = 1032;
+
+pub fn fcntl(fd: usize, cmd: u32, arg: usize) -> isize {
+    sys_fcntl(fd, cmd, arg)
+}
+
+/// `request` of `ioctl`: get the foreground process group of a terminal
+const TIOCGPGRP: usize = 0x540f;
+/// `request` of `ioctl`: set the foreground process group of a terminal
+const TIOCSPGRP: usize = 0x5410;
+
+/// The foreground process group of the terminal `fd`
+pub fn tcgetpgrp(fd: usize) -> isize {
+    let mut pgid: i32 = 0;
+    match sys_ioctl(fd, TIOCGPGRP, &mut pgid as *mut i32 as usize) {
+        0 => pgid as isize,
+        err => err,
+    }
+}
+
+/// Make `pgid`, which must be in the session of the caller, the foreground
+/// process group of the terminal `fd`. It is the one which may read the
+/// terminal, and gets `SIGINT` and `SIGTSTP` when ^C and ^Z are typed.
+pub fn tcsetpgrp(fd: usize, pgid: usize) -> isize {
+    let pgid = pgid as i32;
+    sys_ioctl(fd, TIOCSPGRP, &pgid as *const i32 as usize)
+}
+
+pub fn mkdir(path: &str) -> isize {
+    sys_mkdirat(AT_FDCWD, path, 0o755)
+}
+
+pub fn unlink(path: &str) -> isize {
+    sys_unlinkat(AT_FDCWD, path, 0)
+}
+
+pub fn rmdir(path: &str) -> isize {
+    sys_unlinkat(AT_FDCWD, path, AT_REMOVEDIR)
+}
+
+pub fn link(old_path: &str, new_path: &str) -> isize {
+    sys_linkat(AT_FDCWD, old_path, AT_FDCWD, new_path, 0)
+}
+
+pub fn symlink(target: &str, link_path: &str) -> isize {
+    sys_symlinkat(target, AT_FDCWD, link_path)
+}
+
+/// Read the target of the symbolic link `path` into `buf`, return its length
+pub fn readlink(path: &str, buf: &mut [u8]) -> isize {
+    sys_readlinkat(AT_FDCWD, path, buf)
+}
+
+pub fn rename(old_path: &str, new_path: &str) -> isize {
+    sys_renameat2(AT_FDCWD, old_path, AT_FDCWD, new_path, 0)
+}
+
+pub fn renameat2(old_path: &str, new_path: &str, flags: u32) -> isize {
+    sys_renameat2(AT_FDCWD, old_path, AT_FDCWD, new_path, flags)
+}
+
+pub fn getcwd(buf: &mut [u8]) -> isize {
+    sys_getcwd(buf)
+}
+
+pub fn chdir(path: &str) -> isize {
+    sys_chdir(path)
+}
+
 pub fn open(path: &str, flags: OpenFlags) -> isize {
     sys_open(path, flags.bits())
 }
@@ -169,6 +476,10 @@ pub fn pipe(pipe_fd: &mut [usize]) -> isize {
     sys_pipe(pipe_fd)
 }
 
+pub fn lseek(fd: usize, offset: isize, whence: usize) -> isize {
+    sys_lseek(fd, offset, whence)
+}
+
 pub fn read(fd: usize, buf: &mut [u8]) -> isize {
     sys_read(fd, buf)
 }
@@ -177,6 +488,18 @@ pub fn write(fd: usize, buf: &[u8]) -> isize {
     sys_write(fd, buf)
 }
 
+pub fn pread(fd: usize, buf: &mut [u8], offset: usize) -> isize {
+    sys_pread64(fd, buf, offset)
+}
+
+pub fn pwrite(fd: usize, buf: &[u8], offset: usize) -> isize {
+    sys_pwrite64(fd, buf, offset)
+}
+
+pub fn fstat(fd: usize, st: &mut Stat) -> isize {
+    sys_fstat(fd, st)
+}
+
 pub fn exit(exit_code: i32) -> ! {
     sys_exit(exit_code);
 }
@@ -186,7 +509,13 @@ pub fn yield_() -> isize {
 }
 
 pub fn kill(pid: usize, signum: i32) -> isize {
-    sys_kill(pid, signum)
+    sys_kill(pid as isize, signum)
+}
+
+/// Send `signum` to every process in group `pgid`, or in the group of the
+/// caller if it is 0
+pub fn killpg(pgid: usize, signum: i32) -> isize {
+    sys_kill(-(pgid as isize), signum)
 }
 
 pub fn sigaction(
@@ -205,8 +534,21 @@ pub fn sigprocmask(mask: u32) -> isize {
     sys_sigprocmask(mask)
 }
 
-pub fn sigreturn() -> isize {
-    sys_sigreturn()
+/// `which` of priority syscalls: `who` is a pid
+const PRIO_PROCESS: usize = 0;
+
+/// Set nice value of process `pid` (0 for the caller), lower value gets more cpu time.
+pub fn setpriority(pid: usize, nice: i32) -> isize {
+    sys_setpriority(PRIO_PROCESS, pid, nice)
+}
+
+/// Get nice value of process `pid` (0 for the caller).
+pub fn getpriority(pid: usize) -> Option<i32> {
+    match sys_getpriority(PRIO_PROCESS, pid) {
+        ret if ret < 0 => None,
+        // the kernel returns `20 - nice`
+        ret => Some(20 - ret as i32),
+    }
 }
 
 pub fn reboot() -> ! {
@@ -221,10 +563,67 @@ pub fn getpid() -> isize {
     sys_getpid()
 }
 
+/// Move process `pid` (0 for the caller) into process group `pgid`, or into
+/// a new group led by it if `pgid` is 0
+pub fn setpgid(pid: usize, pgid: usize) -> isize {
+    sys_setpgid(pid, pgid)
+}
+
+pub fn getpgid(pid: usize) -> isize {
+    sys_getpgid(pid)
+}
+
+pub fn getsid(pid: usize) -> isize {
+    sys_getsid(pid)
+}
+
+/// Lead a new session and a new process group, return the session id
+pub fn setsid() -> isize {
+    sys_setsid()
+}
+
 pub fn sbrk(size: i32) -> isize {
     sys_sbrk(size)
 }
 
+bitflags! {
+    #[derive(Debug, Clone, Copy)]
+    pub struct MmapProt: u32 {
+        const READ = 1 << 0;
+        const WRITE = 1 << 1;
+        const EXEC = 1 << 2;
+    }
+}
+
+bitflags! {
+    #[derive(Debug, Clone, Copy)]
+    pub struct MmapFlags: u32 {
+        const SHARED = 1 << 0;
+        const PRIVATE = 1 << 1;
+        const FIXED = 1 << 4;
+        const ANONYMOUS = 1 << 5;
+    }
+}
+
+pub fn mmap(
+    addr: usize,
+    len: usize,
+    prot: MmapProt,
+    flags: MmapFlags,
+    fd: usize,
+    offset: usize,
+) -> isize {
+    sys_mmap(addr, len, prot.bits(), flags.bits(), fd, offset)
+}
+
+pub fn munmap(addr: usize, len: usize) -> isize {
+    sys_munmap(addr, len)
+}
+
+pub fn mprotect(addr: usize, len: usize, prot: MmapProt) -> isize {
+    sys_mprotect(addr, len, prot.bits())
+}
+
 pub fn fork() -> isize {
     sys_fork()
 }
@@ -233,35 +632,301 @@ pub fn exec(path: &str, args: &[*const u8]) -> isize {
     sys_exec(path, args)
 }
 
-pub fn wait(exit_code: &mut i32) -> isize {
-    loop {
-        match sys_waitpid(-1, exit_code as *mut _) {
-            -2 => {
-                yield_();
+/// `options` of [`waitpid_options`]: return 0 at once if no child has exited
+pub const WNOHANG: u32 = 1;
+/// `options` of [`waitpid_options`]: also report a child which has stopped
+pub const WUNTRACED: u32 = 2;
+/// `options` of [`waitpid_options`]: also report a stopped child which has
+/// been continued by `SIGCONT`
+pub const WCONTINUED: u32 = 8;
+
+/// Wait for any child to exit, return its pid. `status` tells how it exited,
+/// see [`wifexited`] and [`wifsignaled`].
+pub fn wait(status: &mut i32) -> isize {
+    waitpid_options(-1, status, 0)
+}
+
+pub fn waitpid(pid: usize, status: &mut i32) -> isize {
+    waitpid_options(pid as isize, status, 0)
+}
+
+pub fn waitpid_nb(pid: usize, status: &mut i32) -> isize {
+    waitpid_options(pid as isize, status, WNOHANG)
+}
+
+/// Wait for child `pid`, any child if it is -1, or any child in process group
+/// `-pid` (the one of the caller if 0) with `options`
+pub fn waitpid_options(pid: isize, status: &mut i32, options: u32) -> isize {
+    sys_wait4(pid, status as *mut _, options, core::ptr::null_mut())
+}
+
+/// Like [`waitpid_options`], also saving the resource usage of the child into
+/// `rusage`. That of an exited child includes its own children waited for.
+pub fn wait4(pid: isize, status: &mut i32, options: u32, rusage: &mut RUsage) -> isize {
+    sys_wait4(pid, status as *mut _, options, rusage as *mut _)
+}
+
+/// `WIFEXITED`: whether the child of `status` exited by itself
+pub fn wifexited(status: i32) -> bool {
+    status & 0x7f == 0
+}
+
+/// `WEXITSTATUS`: the lowest 8 bits of the exit code of an exited child
+pub fn wexitstatus(status: i32) -> i32 {
+    (status >> 8) & 0xff
+}
+
+/// `WIFSIGNALED`: whether the child of `status` was terminated by a signal
+pub fn wifsignaled(status: i32) -> bool {
+    let signo = status & 0x7f;
+    signo != 0 && signo != 0x7f
+}
+
+/// `WTERMSIG`: the signal which terminated the child
+pub fn wtermsig(status: i32) -> i32 {
+    status & 0x7f
+}
+
+/// `WIFSTOPPED`: whether the child of `status` has been stopped by a signal
+pub fn wifstopped(status: i32) -> bool {
+    status & 0xff == 0x7f
+}
+
+/// `WSTOPSIG`: the signal which stopped the child
+pub fn wstopsig(status: i32) -> i32 {
+    (status >> 8) & 0xff
+}
+
+/// `WIFCONTINUED`: whether the stopped child of `status` has been continued
+pub fn wifcontinued(status: i32) -> bool {
+    status == 0xffff
+}
+
+/// Set interval timer `which` to `new_value`, saving the previous value into
+/// `old_value`. Timers are kept by `exec` but not inherited by `fork`.
+pub fn setitimer(which: usize, new_value: &ITimerVal, old_value: Option<&mut ITimerVal>) -> isize {
+    sys_setitimer(
+        which,
+        new_value,
+        old_value.map_or(core::ptr::null_mut(), |v| v),
+    )
+}
+
+pub fn getitimer(which: usize, curr_value: &mut ITimerVal) -> isize {
+    sys_getitimer(which, curr_value)
+}
+
+/// Send `SIGALRM` after `seconds`, or cancel the alarm if it is 0. Return the
+/// seconds left of the previous alarm, rounded up. There is no syscall for it
+/// on RISC-V, so it sets [`ITIMER_REAL`].
+pub fn alarm(seconds: usize) -> usize {
+    let new_value = ITimerVal {
+        it_value: TimeVal {
+            tv_sec: seconds,
+            tv_usec: 0,
+        },
+        ..Default::default()
+    };
+    let mut old_value = ITimerVal::default();
+    setitimer(ITIMER_REAL, &new_value, Some(&mut old_value));
+    old_value.it_value.tv_sec + (old_value.it_value.tv_usec != 0) as usize
+}
+
+/// Create a thread in this process running `entry(arg)` on its own stack,
+/// return its tid. The thread must end with [`exit`] instead of returning.
+pub fn thread_create(entry: usize, arg: usize) -> isize {
+    sys_thread_create(entry, arg)
+}
+
+pub fn gettid() -> isize {
+    sys_gettid()
+}
+
+/// Wait for thread `tid` of this process to exit, return `tid`
+pub fn waittid(tid: usize, exit_code: &mut i32) -> isize {
+    sys_waittid(tid, exit_code as *mut _)
+}
+
+/// `op` of `futex`: sleep while the word holds the expected value
+const FUTEX_WAIT: u32 = 0;
+/// `op` of `futex`: wake up sleepers
+const FUTEX_WAKE: u32 = 1;
+
+/// Sleep until woken up by [`futex_wake`] on `word`, unless `word` does not hold
+/// `expected` (`-EAGAIN`) or `timeout` passes first (`-ETIMEDOUT`).
+/// Processes sharing the page of `word` share the futex as well.
+pub fn futex_wait(word: &AtomicU32, expected: u32, timeout: Option<&TimeSpec>) -> isize {
+    sys_futex(
+        word.as_ptr(),
+        FUTEX_WAIT,
+        expected,
+        timeout.map_or(core::ptr::null(), |t| t),
+    )
+}
+
+/// Wake up at most `count` sleepers on `word`, return how many were woken up
+pub fn futex_wake(word: &AtomicU32, count: u32) -> isize {
+    sys_futex(word.as_ptr(), FUTEX_WAKE, count, core::ptr::null())
+}
+
+/// A mutual exclusion lock protecting a `T`, blocking in the kernel while
+/// another thread holds it
+pub struct Mutex<T> {
+    /// 0: unlocked, 1: locked, 2: locked and others may be sleeping
+    state: AtomicU32,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: Send> Sync for Mutex<T> {}
+
+impl<T> Mutex<T> {
+    pub const fn new(data: T) -> Self {
+        Self {
+            state: AtomicU32::new(0),
+            data: UnsafeCell::new(data),
+        }
+    }
+    pub fn lock(&self) -> MutexGuard<'_, T> {
+        if self
+            .state
+            .compare_exchange(0, 1, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            // whoever unlocks it now has to wake us up
+            while self.state.swap(2, Ordering::Acquire) != 0 {
+                futex_wait(&self.state, 2, None);
             }
-            exit_pid => return exit_pid,
         }
+        MutexGuard { mutex: self }
+    }
+    /// Lock it only if no one holds it
+    pub fn try_lock(&self) -> Option<MutexGuard<'_, T>> {
+        self.state
+            .compare_exchange(0, 1, Ordering::Acquire, Ordering::Relaxed)
+            .ok()
+            .map(|_| MutexGuard { mutex: self })
+    }
+    fn unlock(&self) {
+        if self.state.swap(0, Ordering::Release) == 2 {
+            futex_wake(&self.state, 1);
+        }
+    }
+}
+
+/// Access to the data of a locked [`Mutex`], unlocking it on drop
+pub struct MutexGuard<'a, T> {
+    mutex: &'a Mutex<T>,
+}
+
+impl<T> Deref for MutexGuard<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        unsafe { &*self.mutex.data.get() }
+    }
+}
+
+impl<T> DerefMut for MutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.mutex.data.get() }
     }
 }
 
-pub fn waitpid(pid: usize, exit_code: &mut i32) -> isize {
-    loop {
-        match sys_waitpid(pid as isize, exit_code as *mut _) {
-            -2 => {
-                yield_();
+impl<T> Drop for MutexGuard<'_, T> {
+    fn drop(&mut self) {
+        self.mutex.unlock();
+    }
+}
+
+/// A condition variable, waited on with a locked [`Mutex`].
+/// Waiters may wake up spuriously, so they check their condition in a loop.
+pub struct Condvar {
+    /// Bumped on every notification
+    seq: AtomicU32,
+}
+
+impl Condvar {
+    pub const fn new() -> Self {
+        Self {
+            seq: AtomicU32::new(0),
+        }
+    }
+    /// Unlock `guard` and sleep until notified, then lock it again
+    pub fn wait<'a, T>(&self, guard: MutexGuard<'a, T>) -> MutexGuard<'a, T> {
+        self.wait_timeout(guard, None).0
+    }
+    /// Like [`Condvar::wait`], but give up once `timeout` passes.
+    /// Also return whether it timed out.
+    pub fn wait_timeout<'a, T>(
+        &self,
+        guard: MutexGuard<'a, T>,
+        timeout: Option<&TimeSpec>,
+    ) -> (MutexGuard<'a, T>, bool) {
+        // a notification after this changes `seq`, so the futex does not sleep
+        let seq = self.seq.load(Ordering::Relaxed);
+        let mutex = guard.mutex;
+        drop(guard);
+        let timed_out = futex_wait(&self.seq, seq, timeout) == -ETIMEDOUT;
+        (mutex.lock(), timed_out)
+    }
+    /// Wake up one waiter
+    pub fn notify_one(&self) {
+        self.seq.fetch_add(1, Ordering::Release);
+        futex_wake(&self.seq, 1);
+    }
+    /// Wake up all the waiters
+    pub fn notify_all(&self) {
+        self.seq.fetch_add(1, Ordering::Release);
+        futex_wake(&self.seq, u32::MAX);
+    }
+}
+
+impl Default for Condvar {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A counting semaphore
+pub struct Semaphore {
+    count: AtomicU32,
+}
+
+impl Semaphore {
+    pub const fn new(count: u32) -> Self {
+        Self {
+            count: AtomicU32::new(count),
+        }
+    }
+    /// Take one from the count, sleeping while it is 0
+    pub fn down(&self) {
+        loop {
+            let count = self.count.load(Ordering::Relaxed);
+            if count == 0 {
+                futex_wait(&self.count, 0, None);
+            } else if self
+                .count
+                .compare_exchange(count, count - 1, Ordering::Acquire, Ordering::Relaxed)
+                .is_ok()
+            {
+                return;
             }
-            exit_pid => return exit_pid,
         }
     }
+    /// Add one to the count, waking up a sleeper
+    pub fn up(&self) {
+        self.count.fetch_add(1, Ordering::Release);
+        futex_wake(&self.count, 1);
+    }
 }
 
-pub fn waitpid_nb(pid: usize, exit_code: &mut i32) -> isize {
-    sys_waitpid(pid as isize, exit_code as *mut _)
+pub fn nanosleep(req: &TimeSpec, rem: Option<&mut TimeSpec>) -> isize {
+    sys_nanosleep(req, rem.map_or(core::ptr::null_mut(), |r| r))
 }
 
 pub fn sleep(period_ms: usize) {
-    let start = sys_get_time();
-    while sys_get_time() < start + period_ms as isize {
-        sys_yield();
-    }
+    let req = TimeSpec {
+        tv_sec: period_ms / 1000,
+        tv_nsec: period_ms % 1000 * 1_000_000,
+    };
+    sys_nanosleep(&req, core::ptr::null_mut());
 }